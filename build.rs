@@ -17,16 +17,25 @@
 // * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
 // *************************************************************************
 
+use std::env;
 use std::process::Command;
-use std::path::Path;
 
 fn main() {
-  let py_file = Path::new(&"src").join("permutations.py");
-  let out_file = Path::new(&"src").join("permutations.in");
+  // The commit is merely informational. When not building from a git
+  // repository, it is omitted from the version information.
+  let output = Command::new("git").args(["rev-parse", "--short", "HEAD"]).output();
+  if let Ok(output) = output {
+    if output.status.success() {
+      let commit = String::from_utf8_lossy(&output.stdout);
+      println!("cargo:rustc-env=DICTCC_CLI_COMMIT={}", commit.trim());
+    }
+  }
+  println!("cargo:rerun-if-changed=.git/HEAD");
+  println!("cargo:rerun-if-changed=.git/refs");
 
-  Command::new("python3")
-    .arg(py_file)
-    .arg(out_file)
-    .status()
-    .unwrap();
+  // SQLCipher is linked in place of SQLite, which the library makes
+  // sure is not linked as well.
+  if env::var_os("CARGO_FEATURE_SQLCIPHER").is_some() {
+    println!("cargo:rustc-link-lib=sqlcipher");
+  }
 }
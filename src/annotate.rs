@@ -0,0 +1,189 @@
+// annotate.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Token level language identification of (possibly code-switched)
+//! texts.
+//!
+//! Each word of a text is looked up on both sides of the dictionary.
+//! Words found on only one side are attributed to the respective
+//! language. Words found on both sides (or on neither) are attributed
+//! to the language of the closest preceding word that could be
+//! attributed unambiguously, if any.
+
+use json::Value;
+use spell::Checker;
+
+
+/// The language a token was attributed to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Language {
+  /// The language of the dictionary's first side.
+  Lang1,
+  /// The language of the dictionary's second side.
+  Lang2,
+  /// The language could not be determined.
+  Unknown,
+}
+
+impl Language {
+  /// Retrieve the name of the language as used in our output.
+  pub fn name(self) -> &'static str {
+    match self {
+      Language::Lang1 => "lang1",
+      Language::Lang2 => "lang2",
+      Language::Unknown => "unknown",
+    }
+  }
+}
+
+
+/// A token of a text along with the language decision made for it.
+#[derive(Debug, PartialEq)]
+pub struct Token {
+  /// The (1-based) line the token appeared in.
+  pub line: usize,
+  /// The byte offset of the token in its line.
+  pub offset: usize,
+  /// The token itself.
+  pub text: String,
+  /// Whether the token was found on the first side of the dictionary.
+  pub in_lang1: bool,
+  /// Whether the token was found on the second side of the dictionary.
+  pub in_lang2: bool,
+  /// The language the token got attributed to.
+  pub language: Language,
+}
+
+impl Token {
+  /// Convert the token into its JSON representation.
+  pub fn to_json(&self) -> Value {
+    let mut matched = Vec::new();
+    if self.in_lang1 {
+      matched.push(Value::from(Language::Lang1.name()));
+    }
+    if self.in_lang2 {
+      matched.push(Value::from(Language::Lang2.name()));
+    }
+
+    Value::Object(vec![
+      ("line".to_string(), Value::Number(self.line as f64)),
+      ("offset".to_string(), Value::Number(self.offset as f64)),
+      ("token".to_string(), Value::from(self.text.as_str())),
+      ("matched".to_string(), Value::Array(matched)),
+      ("language".to_string(), Value::from(self.language.name())),
+    ])
+  }
+}
+
+
+/// Split a line into its words, as (byte offset, word) pairs.
+fn split(line: &str) -> Vec<(usize, &str)> {
+  let mut words = Vec::new();
+  let mut start = None;
+
+  for (i, c) in line.char_indices().chain(Some((line.len(), ' '))) {
+    match (start, c.is_alphanumeric()) {
+      (None, true) => start = Some(i),
+      (Some(s), false) => {
+        let word = &line[s..i];
+        if word.chars().any(|c| c.is_alphabetic()) {
+          words.push((s, word));
+        }
+        start = None;
+      },
+      _ => (),
+    }
+  }
+  words
+}
+
+/// Annotate each word of a text with the language it is in.
+pub fn annotate(text: &str, lang1: &Checker, lang2: &Checker) -> Vec<Token> {
+  let mut tokens = Vec::new();
+  let mut last = Language::Unknown;
+
+  for (i, line) in text.lines().enumerate() {
+    for (offset, word) in split(line) {
+      let in_lang1 = lang1.is_known(word);
+      let in_lang2 = lang2.is_known(word);
+      let language = match (in_lang1, in_lang2) {
+        (true, false) => Language::Lang1,
+        (false, true) => Language::Lang2,
+        _ => last,
+      };
+      if in_lang1 != in_lang2 {
+        last = language;
+      }
+
+      tokens.push(Token {
+        line: i + 1,
+        offset,
+        text: word.to_string(),
+        in_lang1,
+        in_lang2,
+        language,
+      });
+    }
+  }
+  tokens
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::path;
+
+  use open;
+  use TERM1_COL;
+  use TERM2_COL;
+
+  #[test]
+  fn split_line() {
+    assert_eq!(split("Die Liebe, 2 Mal!"), vec![(0, "Die"), (4, "Liebe"), (13, "Mal")]);
+    assert_eq!(split("Ärger über"), vec![(0, "Ärger"), (7, "über")]);
+  }
+
+  #[test]
+  fn annotate_code_switched() {
+    let connection = open(path::Path::new("./test/test.db")).unwrap();
+    let lang1 = Checker::new(&connection, TERM1_COL).unwrap();
+    let lang2 = Checker::new(&connection, TERM2_COL).unwrap();
+
+    let tokens = annotate("Zorn and anger\nWut", &lang1, &lang2);
+    let languages = tokens
+      .iter()
+      .map(|t| (t.text.as_str(), t.language))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      languages,
+      vec![
+        ("Zorn", Language::Lang1),
+        ("and", Language::Lang1),
+        ("anger", Language::Lang2),
+        ("Wut", Language::Lang1),
+      ]
+    );
+    assert_eq!(
+      tokens[2].to_json().to_string(),
+      r#"{"line":1,"offset":9,"token":"anger","matched":["lang2"],"language":"lang2"}"#
+    );
+  }
+}
@@ -0,0 +1,267 @@
+// asynchronous.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+
+//! An asynchronous interface to lookups.
+//!
+//! SQLite works synchronously, so lookups are performed on threads of
+//! their own and their results are made available as futures. As such,
+//! they can be awaited by asynchronous applications without blocking
+//! the executor, whichever one is used.
+//!
+//! An asynchronous [`Database`] keeps its (synchronous) counterpart on
+//! a dedicated thread, which performs all lookups in order.
+
+use std::future;
+use std::path;
+use std::pin;
+use std::sync;
+use std::sync::mpsc;
+use std::task;
+use std::thread;
+
+use Direction;
+use Entry;
+use Error;
+use Query;
+use Result;
+
+
+/// The state shared between a `Task` and the thread producing its
+/// result.
+struct Shared<T> {
+  /// The result, once available.
+  value: Option<T>,
+  /// The waker to notify once the result is available.
+  waker: Option<task::Waker>,
+}
+
+/// A future resolving to the result of work performed on another
+/// thread.
+pub struct Task<T> {
+  shared: sync::Arc<sync::Mutex<Shared<T>>>,
+}
+
+impl<T> future::Future for Task<T> {
+  type Output = T;
+
+  fn poll(self: pin::Pin<&mut Self>, cx: &mut task::Context) -> task::Poll<T> {
+    let mut shared = self.shared.lock().unwrap();
+    match shared.value.take() {
+      Some(value) => task::Poll::Ready(value),
+      None => {
+        shared.waker = Some(cx.waker().clone());
+        task::Poll::Pending
+      },
+    }
+  }
+}
+
+/// The producing end of a `Task`.
+///
+/// If dropped without a result being provided, e.g., because the
+/// thread performing the work panicked, the task fails.
+struct Completer<T> {
+  shared: sync::Arc<sync::Mutex<Shared<Result<T>>>>,
+  done: bool,
+}
+
+impl<T> Completer<T> {
+  /// Complete the task with the given result.
+  fn complete(mut self, value: Result<T>) {
+    self.set(value)
+  }
+
+  fn set(&mut self, value: Result<T>) {
+    self.done = true;
+    let mut shared = self.shared.lock().unwrap();
+    shared.value = Some(value);
+    if let Some(waker) = shared.waker.take() {
+      waker.wake();
+    }
+  }
+}
+
+impl<T> Drop for Completer<T> {
+  fn drop(&mut self) {
+    if !self.done {
+      self.set(Err(Error::Error("Lookup was aborted".to_string())));
+    }
+  }
+}
+
+/// Create a task along with the means to complete it.
+fn task<T>() -> (Task<Result<T>>, Completer<T>) {
+  let shared = sync::Arc::new(sync::Mutex::new(Shared {
+    value: None,
+    waker: None,
+  }));
+  let completer = Completer {
+    shared: shared.clone(),
+    done: false,
+  };
+  (Task { shared }, completer)
+}
+
+/// Perform a lookup, collecting the entries found.
+fn collect(database: &::Database, query: &Query) -> Result<Vec<Entry>> {
+  let mut entries = Vec::new();
+  query.run_on(database, |entry| {
+    entries.push(entry.clone());
+    Ok(())
+  })?;
+  Ok(entries)
+}
+
+
+/// Translate the given terms in the database at the given path on a
+/// thread of its own.
+///
+/// This is the asynchronous counterpart to [`translate`](::translate),
+/// resolving to all entries found.
+pub fn translate<S>(db: &path::Path, terms: &[S], direction: &Direction) -> Task<Result<Vec<Entry>>>
+where
+  S: AsRef<str>,
+{
+  let db = db.to_path_buf();
+  let query = Query::with_terms(terms).direction(*direction);
+  let (task, completer) = task();
+  thread::spawn(move || {
+    let result = ::Database::open(&db).and_then(|database| collect(&database, &query));
+    completer.complete(result)
+  });
+  task
+}
+
+
+/// A request to the thread owning a database.
+type Request = Box<dyn FnOnce(&::Database) + Send>;
+
+/// An open dictionary database, usable from asynchronous code.
+///
+/// The database can be cloned cheaply, with all clones sharing the
+/// same connection. It is closed once the last clone is dropped.
+#[derive(Clone)]
+pub struct Database {
+  requests: mpsc::Sender<Request>,
+}
+
+impl Database {
+  /// Open a database for reading.
+  pub fn open(db: &path::Path) -> Task<Result<Database>> {
+    let db = db.to_path_buf();
+    let (task, completer) = task();
+    thread::spawn(move || {
+      let database = match ::Database::open(&db) {
+        Ok(database) => database,
+        Err(err) => return completer.complete(Err(err)),
+      };
+      let (sender, receiver) = mpsc::channel::<Request>();
+      completer.complete(Ok(Database { requests: sender }));
+      // We serve requests until all handles are gone.
+      for request in receiver {
+        request(&database);
+      }
+    });
+    task
+  }
+
+  /// Translate the given terms, resolving to all entries found.
+  pub fn translate<S>(&self, terms: &[S], direction: &Direction) -> Task<Result<Vec<Entry>>>
+  where
+    S: AsRef<str>,
+  {
+    self.query(Query::with_terms(terms).direction(*direction))
+  }
+
+  /// Perform a lookup, resolving to all entries found.
+  pub fn query(&self, query: Query) -> Task<Result<Vec<Entry>>> {
+    let (task, completer) = task();
+    let request = Box::new(move |database: &::Database| {
+      completer.complete(collect(database, &query))
+    });
+    // If the thread is gone, the request along with its completer is
+    // dropped, failing the task.
+    let _ = self.requests.send(request);
+    task
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::future::Future;
+
+  use entry::Type;
+
+  /// A waker unparking the thread waiting for a future.
+  struct Unpark(thread::Thread);
+
+  impl task::Wake for Unpark {
+    fn wake(self: sync::Arc<Self>) {
+      self.0.unpark()
+    }
+  }
+
+  /// Wait for a future to resolve.
+  fn block_on<F>(future: F) -> F::Output
+  where
+    F: Future,
+  {
+    let mut future = Box::pin(future);
+    let waker = task::Waker::from(sync::Arc::new(Unpark(thread::current())));
+    let mut cx = task::Context::from_waker(&waker);
+    loop {
+      match future.as_mut().poll(&mut cx) {
+        task::Poll::Ready(output) => break output,
+        task::Poll::Pending => thread::park(),
+      }
+    }
+  }
+
+  fn headwords(entries: Vec<Entry>) -> Vec<String> {
+    entries.into_iter().map(|e| e.target.headword).collect()
+  }
+
+  #[test]
+  fn translate_async() {
+    let db = path::Path::new("./test/test.db");
+    let found = block_on(translate(db, &["nauseating"], &Direction::Lang2ToLang1)).unwrap();
+    assert_eq!(headwords(found), vec!["ekelerregend", "widerlich"]);
+
+    let db = path::Path::new("./test/does_not_exist.db");
+    match block_on(translate(db, &["nauseating"], &Direction::Lang2ToLang1)) {
+      Err(Error::DatabaseNotFound(_)) => (),
+      result => panic!("Unexpected result: {:?}", result),
+    }
+  }
+
+  #[test]
+  fn database_async() {
+    let db = block_on(Database::open(path::Path::new("./test/test.db"))).unwrap();
+    // Lookups can be issued concurrently and are performed in order.
+    let first = db.translate(&["nauseating"], &Direction::Lang2ToLang1);
+    let query = Query::new("love").direction(Direction::Lang2ToLang1).types([Type::Noun]);
+    let second = db.clone().query(query);
+    assert_eq!(headwords(block_on(second).unwrap()), vec!["Liebe"]);
+    assert_eq!(headwords(block_on(first).unwrap()), vec!["ekelerregend", "widerlich"]);
+  }
+}
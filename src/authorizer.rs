@@ -0,0 +1,124 @@
+// authorizer.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Restriction of database connections to reading.
+//!
+//! Lookups never modify the database. To keep the damage a crafted
+//! query could do to a minimum, connections used for them only permit
+//! reading statements, as enforced by an SQLite authorizer callback.
+
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::os::raw::c_int;
+use std::os::raw::c_void;
+use std::ptr;
+
+use sqlite;
+use sqlite3_sys as ffi;
+
+use Error;
+use Result;
+
+/// The actions permitted on a read-only connection.
+const PERMITTED: [c_int; 4] = [
+  ffi::SQLITE_SELECT,
+  ffi::SQLITE_READ,
+  ffi::SQLITE_FUNCTION,
+  ffi::SQLITE_RECURSIVE,
+];
+
+/// The pragmas taking an argument that merely inspect the schema.
+const INSPECTING_PRAGMAS: [&[u8]; 4] = [b"table_info", b"table_xinfo", b"index_list", b"index_info"];
+
+
+extern "C" fn authorize(_: *mut c_void,
+                        action: c_int,
+                        arg1: *const c_char,
+                        arg2: *const c_char,
+                        _: *const c_char,
+                        _: *const c_char) -> c_int {
+  let arg = |arg: *const c_char| if arg.is_null() {
+    None
+  } else {
+    Some(unsafe { CStr::from_ptr(arg) }.to_bytes())
+  };
+
+  let permitted = match action {
+    _ if PERMITTED.contains(&action) => true,
+    // Reading pragmas (as opposed to setting them) is harmless. FTS
+    // tables query the page size, for example. Some pragmas take an
+    // argument to inspect the schema, though.
+    ffi::SQLITE_PRAGMA => {
+      arg(arg2).is_none() || arg(arg1).is_some_and(|p| INSPECTING_PRAGMAS.contains(&p))
+    },
+    // Connecting to a virtual table makes SQLite check for the right to
+    // update the schema table. Statements cannot modify that table
+    // directly, though.
+    ffi::SQLITE_UPDATE => arg(arg1) == Some(b"sqlite_master"),
+    _ => false,
+  };
+  if permitted {
+    ffi::SQLITE_OK
+  } else {
+    ffi::SQLITE_DENY
+  }
+}
+
+/// Restrict a connection to statements reading data.
+pub fn read_only(connection: &sqlite::Connection) -> Result<()> {
+  let result = unsafe {
+    ffi::sqlite3_set_authorizer(connection.as_raw(), Some(authorize), ptr::null_mut())
+  };
+
+  if result != ffi::SQLITE_OK {
+    let message = unsafe {
+      CStr::from_ptr(ffi::sqlite3_errmsg(connection.as_raw())).to_string_lossy().into_owned()
+    };
+    return Err(Error::Error(format!("Failed to install authorizer: {}", message)));
+  }
+  Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn deny_writes() {
+    let connection = sqlite::open(":memory:").unwrap();
+    connection.execute("CREATE TABLE words (word VARCHAR); \
+                        INSERT INTO words VALUES ('Zorn');").unwrap();
+    read_only(&connection).unwrap();
+
+    let mut statement = connection
+      .prepare("WITH RECURSIVE n(i) AS (SELECT 1) SELECT upper(word) FROM words, n")
+      .unwrap();
+    statement.next().unwrap();
+    assert_eq!(statement.read::<String>(0).unwrap(), "ZORN");
+
+    assert!(connection.execute("INSERT INTO words VALUES ('Wut')").is_err());
+    assert!(connection.execute("DELETE FROM words").is_err());
+    assert!(connection.execute("DROP TABLE words").is_err());
+    assert!(connection.execute("ATTACH ':memory:' AS other").is_err());
+    assert!(connection.execute("PRAGMA table_info(words)").is_ok());
+    assert!(connection.execute("PRAGMA journal_mode = OFF").is_err());
+    assert!(connection.execute("UPDATE sqlite_master SET type = 'view'").is_err());
+  }
+}
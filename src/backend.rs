@@ -0,0 +1,43 @@
+// backend.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+
+//! The abstraction over the storage lookups are performed against.
+//!
+//! A [`Query`](::Query) describes what to look up, a [`Backend`]
+//! performs the lookup. The [`Database`](::Database) backend assembles
+//! SQL and runs it against a dict.cc SQLite database. With the `memory`
+//! feature enabled, the `memory::Index` backend matches entries held
+//! in memory instead.
+
+use Direction;
+use Entry;
+use Options;
+use Result;
+
+
+/// Storage capable of performing lookups.
+pub trait Backend {
+  /// Look up the entries matching any of the given (non-empty) terms,
+  /// as dictated by `options`, reporting each to `callback` in order.
+  ///
+  /// At most `limit` entries are reported, if set.
+  fn lookup(&self, terms: &[String], direction: &Direction, options: &Options,
+            limit: Option<usize>, callback: &mut dyn FnMut(&Entry) -> Result<()>) -> Result<()>;
+}
@@ -0,0 +1,200 @@
+// band.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Classification of entries into usage bands.
+//!
+//! The database records how commonly each entry is used. To give an
+//! intuition of which translation to prefer, we classify entries by
+//! where their usage ranks among all entries of the database: the top
+//! tenth is very common, the rest of the top half common, and all
+//! others rare. The thresholds are determined once per database and
+//! cached in `$XDG_CACHE_HOME/dictcc-cli/usage-bands`.
+
+#[cfg(feature = "sqlite")]
+use std::fmt;
+#[cfg(feature = "sqlite")]
+use std::path;
+
+#[cfg(feature = "sqlite")]
+use sqlite;
+
+#[cfg(feature = "sqlite")]
+use cache;
+#[cfg(feature = "sqlite")]
+use open;
+use Error;
+use Result;
+#[cfg(feature = "sqlite")]
+use SEARCH_TBL;
+#[cfg(feature = "sqlite")]
+use USAGE_COL;
+
+/// The name of the file caching the thresholds.
+#[cfg(feature = "sqlite")]
+const CACHE_FILE: &str = "usage-bands";
+/// The percentile of usage from which on entries are common.
+#[cfg(feature = "sqlite")]
+const COMMON_PERCENTILE: i64 = 50;
+/// The percentile of usage from which on entries are very common.
+#[cfg(feature = "sqlite")]
+const VERY_COMMON_PERCENTILE: i64 = 90;
+
+
+/// A band of usage.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Band {
+  /// Among the most commonly used entries.
+  VeryCommon,
+  /// Used more commonly than most entries.
+  Common,
+  /// Used less commonly than most entries.
+  Rare,
+}
+
+impl Band {
+  /// Retrieve the name of the band.
+  pub fn name(self) -> &'static str {
+    match self {
+      Band::VeryCommon => "very common",
+      Band::Common => "common",
+      Band::Rare => "rare",
+    }
+  }
+
+  /// Parse a band from its name.
+  pub fn from_name(name: &str) -> Result<Band> {
+    match name {
+      "very common" => Ok(Band::VeryCommon),
+      "common" => Ok(Band::Common),
+      "rare" => Ok(Band::Rare),
+      _ => Err(Error::Error(format!("Invalid usage band: {}", name))),
+    }
+  }
+
+  /// Retrieve the badge representing the band.
+  pub fn badge(self) -> &'static str {
+    match self {
+      Band::VeryCommon => "●●●",
+      Band::Common => "●●○",
+      Band::Rare => "●○○",
+    }
+  }
+}
+
+
+/// The usage thresholds separating the bands of a database.
+#[cfg(feature = "sqlite")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Thresholds {
+  /// The minimum usage of common entries.
+  common: i64,
+  /// The minimum usage of very common entries.
+  very_common: i64,
+}
+
+#[cfg(feature = "sqlite")]
+impl Thresholds {
+  /// Determine the band of an entry with the given usage.
+  pub fn band(&self, usage: i64) -> Band {
+    if usage >= self.very_common {
+      Band::VeryCommon
+    } else if usage >= self.common {
+      Band::Common
+    } else {
+      Band::Rare
+    }
+  }
+}
+
+#[cfg(feature = "sqlite")]
+impl fmt::Display for Thresholds {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}\t{}", self.common, self.very_common)
+  }
+}
+
+
+/// Determine the thresholds from the usage of all entries.
+#[cfg(feature = "sqlite")]
+fn compute(connection: &sqlite::Connection) -> Result<Thresholds> {
+  let mut statement = connection.prepare(format!("SELECT COUNT(*) FROM {}", SEARCH_TBL))?;
+  statement.next()?;
+  let count = statement.read::<i64>(0)?;
+
+  let select = format!(
+    "SELECT CAST({use} AS INTEGER) AS usage FROM {tbl} ORDER BY usage ASC LIMIT 1 OFFSET ?",
+    use = USAGE_COL, tbl = SEARCH_TBL,
+  );
+  let percentile = |percentile: i64| -> Result<i64> {
+    let mut cursor = connection.prepare(&select)?.cursor();
+    cursor.bind(&[sqlite::Value::Integer(count * percentile / 100)])?;
+    Ok(cursor.next()?.and_then(|row| row[0].as_integer()).unwrap_or(0))
+  };
+
+  Ok(Thresholds {
+    common: percentile(COMMON_PERCENTILE)?,
+    very_common: percentile(VERY_COMMON_PERCENTILE)?,
+  })
+}
+
+/// Parse cached thresholds.
+#[cfg(feature = "sqlite")]
+fn parse(value: &str) -> Option<Thresholds> {
+  let mut fields = value.split('\t').map(|f| f.parse().ok());
+  match (fields.next(), fields.next(), fields.next()) {
+    (Some(Some(common)), Some(Some(very_common)), None) => Some(Thresholds { common, very_common }),
+    _ => None,
+  }
+}
+
+/// Retrieve the thresholds of a database, consulting the cache first.
+#[cfg(feature = "sqlite")]
+pub fn thresholds(db: &path::Path) -> Result<Thresholds> {
+  cache::cached(CACHE_FILE, db, || compute(&*open(db)?), parse)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  #[cfg(feature = "sqlite")]
+  fn classify_usage() {
+    let connection = open(path::Path::new("./test/test.db")).unwrap();
+    let thresholds = compute(&connection).unwrap();
+    assert_eq!(thresholds, Thresholds { common: 31, very_common: 42 });
+    assert_eq!(thresholds.band(45), Band::VeryCommon);
+    assert_eq!(thresholds.band(42), Band::VeryCommon);
+    assert_eq!(thresholds.band(41), Band::Common);
+    assert_eq!(thresholds.band(23), Band::Rare);
+
+    assert_eq!(parse(&thresholds.to_string()), Some(thresholds));
+    assert_eq!(parse("31"), None);
+    assert_eq!(parse("a\tb"), None);
+  }
+
+  #[test]
+  fn band_names() {
+    for band in &[Band::VeryCommon, Band::Common, Band::Rare] {
+      assert_eq!(Band::from_name(band.name()).unwrap(), *band);
+    }
+    assert!(Band::from_name("frequent").is_err());
+  }
+}
@@ -0,0 +1,332 @@
+// cache.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Caching of facts derived from databases.
+//!
+//! Some facts about a database are costly to determine, e.g., because
+//! they require looking at many of its entries. We cache them in files
+//! below `$XDG_CACHE_HOME/dictcc-cli/`, one file per kind of fact, with
+//! one line per database of the form `<path>\t<version>\t<value>`,
+//! where the version is made up of the database's modification time in
+//! nanoseconds and its size. An entry is only used as long as the
+//! database was not modified.
+//!
+//! The results of recent queries are cached the same way, in a file
+//! with one line per query of the form
+//! `<path>\t<version>\t<query>\t<results>`, ordered from the least to
+//! the most recently used one.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path;
+use std::process;
+use std::sync::atomic;
+use std::time;
+
+use Error;
+use Result;
+
+/// The name of the directory containing our cache.
+const CACHE_DIR: &str = "dictcc-cli";
+/// The name of the cache file containing the results of queries.
+const RESULTS_FILE: &str = "results";
+/// The maximum number of queries whose results are kept.
+const MAX_RESULTS: usize = 64;
+
+/// The number telling apart the temporary file of the next write.
+static NEXT_TMP: atomic::AtomicUsize = atomic::AtomicUsize::new(0);
+
+
+/// Retrieve the path to the directory containing the cache.
+fn dir() -> Option<path::PathBuf> {
+  let dir = match env::var_os("XDG_CACHE_HOME") {
+    Some(ref dir) if !dir.is_empty() => path::PathBuf::from(dir),
+    _ => path::PathBuf::from(env::var_os("HOME")?).join(".cache"),
+  };
+  Some(dir.join(CACHE_DIR))
+}
+
+/// Retrieve the path to the cache file with the given name.
+fn path(name: &str) -> Option<path::PathBuf> {
+  dir().map(|dir| dir.join(name))
+}
+
+/// Write a cache file, replacing it as a whole, so that concurrent
+/// readers never see it partially written.
+fn write(path: &path::Path, content: &str) -> io::Result<()> {
+  if let Some(dir) = path.parent() {
+    fs::create_dir_all(dir)?;
+  }
+  // Each write uses a temporary file of its own, as threads of the
+  // same process may write concurrently, e.g., while serving.
+  let id = NEXT_TMP.fetch_add(1, atomic::Ordering::Relaxed);
+  let tmp = path.with_extension(format!("{}.{}.tmp", process::id(), id));
+  fs::write(&tmp, content)?;
+  fs::rename(&tmp, path)
+}
+
+/// Determine the key identifying a database in the cache along with
+/// its current version.
+///
+/// Modifications within the same second are common, e.g., when adding
+/// entries in a script, so the modification time is used with its full
+/// precision. The size catches modifications on file systems with a
+/// coarser one.
+fn database_key(db: &path::Path) -> Result<(String, String)> {
+  let key = fs::canonicalize(db)
+    .map_err(|e| Error::Io(format!("Failed to access {}", db.display()), e))?;
+  let version = fs::metadata(db)
+    .map(|m| {
+      let mtime = m
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(time::UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+      format!("{}:{}", mtime, m.len())
+    })
+    .unwrap_or_default();
+  Ok((key.to_string_lossy().into_owned(), version))
+}
+
+/// Look up the value cached for the database with the given key and
+/// version in the contents of a cache file.
+fn lookup<'c>(content: &'c str, key: &str, version: &str) -> Option<&'c str> {
+  content.lines().find_map(|line| {
+    let mut fields = line.splitn(3, '\t');
+    match (fields.next(), fields.next(), fields.next()) {
+      (Some(k), Some(m), Some(value)) if k == key && m == version => Some(value),
+      _ => None,
+    }
+  })
+}
+
+/// Record the value for a database in the contents of a cache file,
+/// replacing any previous entry for it.
+fn update(content: &str, key: &str, version: &str, value: &str) -> String {
+  let mut lines = content
+    .lines()
+    .filter(|line| line.split('\t').next() != Some(key))
+    .map(|line| line.to_string())
+    .collect::<Vec<_>>();
+  lines.push(format!("{}\t{}\t{}", key, version, value));
+  lines.join("\n") + "\n"
+}
+
+/// Retrieve the value cached in the file with the given name for a
+/// database, computing (and caching) it if there is no valid one.
+///
+/// `parse` checks a cached value, with invalid ones being recomputed.
+pub fn cached<T, C, P>(name: &str, db: &path::Path, compute: C, parse: P) -> Result<T>
+where
+  T: ToString,
+  C: FnOnce() -> Result<T>,
+  P: FnOnce(&str) -> Option<T>,
+{
+  let (key, version) = database_key(db)?;
+  let cache = path(name);
+  let content = cache
+    .as_ref()
+    .and_then(|path| fs::read_to_string(path).ok())
+    .unwrap_or_default();
+  if let Some(value) = lookup(&content, &key, &version).and_then(parse) {
+    return Ok(value);
+  }
+
+  let value = compute()?;
+  // Caching is merely an optimization, so failure to do so is not
+  // fatal.
+  if let Some(path) = cache {
+    let _ = write(&path, &update(&content, &key, &version, &value.to_string()));
+  }
+  Ok(value)
+}
+
+/// Find the line with the results of a query against the database with
+/// the given key and version in the contents of the results cache,
+/// returning its index along with the results.
+fn find_results<'c>(content: &'c str, key: &str, version: &str,
+                    query: &str) -> Option<(usize, &'c str)> {
+  content.lines().enumerate().find_map(|(index, line)| {
+    let mut fields = line.splitn(4, '\t');
+    match (fields.next(), fields.next(), fields.next(), fields.next()) {
+      (Some(k), Some(m), Some(q), Some(results))
+        if k == key && m == version && q == query => Some((index, results)),
+      _ => None,
+    }
+  })
+}
+
+/// Record the results of a query as the most recently used ones in the
+/// contents of the results cache, replacing the line at `replace`, if
+/// any, and evicting the least recently used results beyond
+/// `MAX_RESULTS`, as well as those for earlier versions of the
+/// database.
+fn add_results(content: &str, key: &str, version: &str, query: &str, results: &str,
+               replace: Option<usize>) -> String {
+  let mut lines = content
+    .lines()
+    .enumerate()
+    .filter(|&(index, line)| {
+      let mut fields = line.splitn(3, '\t');
+      let stale = fields.next() == Some(key) && fields.next() != Some(version);
+      Some(index) != replace && !stale
+    })
+    .map(|(_, line)| line)
+    .collect::<Vec<_>>();
+  let skip = (lines.len() + 1).saturating_sub(MAX_RESULTS);
+  lines.drain(..skip);
+
+  let mut content = lines.join("\n");
+  if !content.is_empty() {
+    content += "\n";
+  }
+  content + &format!("{}\t{}\t{}\t{}\n", key, version, query, results)
+}
+
+/// Retrieve the results cached for a query against a database, marking
+/// them as the most recently used ones.
+///
+/// The query has to identify everything influencing the results, and
+/// neither it nor the results may contain tabs or line breaks.
+pub fn query_results(db: &path::Path, query: &str) -> Option<String> {
+  let (key, version) = database_key(db).ok()?;
+  let path = path(RESULTS_FILE)?;
+  let content = fs::read_to_string(&path).ok()?;
+  let (index, results) = find_results(&content, &key, &version, query)?;
+  // Most of the time the results are the most recently used ones
+  // already, in which case there is nothing to update.
+  if index + 1 < content.lines().count() {
+    let _ = write(&path, &add_results(&content, &key, &version, query, results, Some(index)));
+  }
+  Some(results.to_string())
+}
+
+/// Cache the results of a query against a database.
+pub fn cache_query_results(db: &path::Path, query: &str, results: &str) {
+  debug_assert!(!query.contains(['\t', '\n']) && !results.contains(['\t', '\n']));
+
+  let (key, version) = match database_key(db) {
+    Ok(key) => key,
+    Err(_) => return,
+  };
+  if let Some(path) = path(RESULTS_FILE) {
+    let content = fs::read_to_string(&path).unwrap_or_default();
+    let replace = find_results(&content, &key, &version, query).map(|(index, _)| index);
+    let _ = write(&path, &add_results(&content, &key, &version, query, results, replace));
+  }
+}
+
+/// Remove the cache altogether, returning whether there was anything to
+/// remove.
+pub fn clear() -> Result<bool> {
+  let dir = match dir() {
+    Some(dir) => dir,
+    None => return Ok(false),
+  };
+  match fs::remove_dir_all(&dir) {
+    Ok(()) => Ok(true),
+    Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+    Err(e) => Err(Error::Io(format!("Failed to remove {}", dir.display()), e)),
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::thread;
+
+  #[test]
+  fn cache_entries() {
+    let content = update("", "/a.db", "42", "term2");
+    assert_eq!(content, "/a.db\t42\tterm2\n");
+    assert_eq!(lookup(&content, "/a.db", "42"), Some("term2"));
+    assert_eq!(lookup(&content, "/a.db", "43"), None);
+    assert_eq!(lookup(&content, "/b.db", "42"), None);
+
+    let content = update(&content, "/b.db", "1", "term1");
+    let content = update(&content, "/a.db", "43", "1\t2");
+    assert_eq!(content, "/b.db\t1\tterm1\n/a.db\t43\t1\t2\n");
+    assert_eq!(lookup(&content, "/a.db", "43"), Some("1\t2"));
+  }
+
+  #[test]
+  fn cache_results() {
+    let content = add_results("", "/a.db", "1", "Zorn", "[1]", None);
+    let content = add_results(&content, "/b.db", "1", "Zorn", "[2]", None);
+    let content = add_results(&content, "/a.db", "1", "Wut", "[3]", None);
+    assert_eq!(find_results(&content, "/a.db", "1", "Zorn"), Some((0, "[1]")));
+    assert_eq!(find_results(&content, "/b.db", "1", "Zorn"), Some((1, "[2]")));
+    assert_eq!(find_results(&content, "/a.db", "2", "Zorn"), None);
+    assert_eq!(find_results(&content, "/a.db", "1", "Ärger"), None);
+
+    // Using results makes them the most recently used ones.
+    let content = add_results(&content, "/a.db", "1", "Zorn", "[1]", Some(0));
+    assert_eq!(content, "/b.db\t1\tZorn\t[2]\n/a.db\t1\tWut\t[3]\n/a.db\t1\tZorn\t[1]\n");
+
+    // Results for earlier versions of a database are dropped.
+    let content = add_results(&content, "/a.db", "2", "Zorn", "[4]", None);
+    assert_eq!(content, "/b.db\t1\tZorn\t[2]\n/a.db\t2\tZorn\t[4]\n");
+
+    // The least recently used results are evicted.
+    let mut content = String::new();
+    for i in 0..MAX_RESULTS + 2 {
+      content = add_results(&content, "/a.db", "1", &i.to_string(), "[]", None);
+    }
+    assert_eq!(content.lines().count(), MAX_RESULTS);
+    assert_eq!(find_results(&content, "/a.db", "1", "1"), None);
+    assert_eq!(find_results(&content, "/a.db", "1", "2"), Some((0, "[]")));
+  }
+
+  #[test]
+  fn database_versions() {
+    let db = env::temp_dir().join(format!("dictcc-cli-version-{}.db", process::id()));
+    fs::write(&db, "1").unwrap();
+    let (key1, version1) = database_key(&db).unwrap();
+    // Modifications in quick succession are told apart.
+    fs::write(&db, "12").unwrap();
+    let (key2, version2) = database_key(&db).unwrap();
+    fs::remove_file(&db).unwrap();
+    assert_eq!(key1, key2);
+    assert_ne!(version1, version2);
+  }
+
+  #[test]
+  fn concurrent_writes() {
+    let dir = env::temp_dir().join(format!("dictcc-cli-cache-{}", process::id()));
+    let path = dir.join(RESULTS_FILE);
+    let contents = (0..8).map(|i| i.to_string().repeat(4096)).collect::<Vec<_>>();
+    thread::scope(|scope| {
+      for content in &contents {
+        let path = &path;
+        scope.spawn(move || {
+          for _ in 0..16 {
+            write(path, content).unwrap();
+          }
+        });
+      }
+    });
+    assert!(contents.contains(&fs::read_to_string(&path).unwrap()));
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
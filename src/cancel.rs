@@ -0,0 +1,125 @@
+// cancel.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+
+//! Cancellation of lookups in progress.
+//!
+//! A [`CancelToken`] is shared between a lookup and whoever may want to
+//! abort it, e.g., the thread handling user input in an interactive
+//! frontend. Cancellation takes effect in between rows as well as while
+//! SQLite is busy searching for the next one.
+
+#[cfg(feature = "sqlite")]
+use std::os::raw::c_int;
+#[cfg(feature = "sqlite")]
+use std::os::raw::c_void;
+#[cfg(feature = "sqlite")]
+use std::ptr;
+use std::sync;
+use std::sync::atomic;
+
+#[cfg(feature = "sqlite")]
+use sqlite;
+#[cfg(feature = "sqlite")]
+use sqlite3_sys as ffi;
+
+/// The number of virtual machine instructions SQLite executes in
+/// between checks for cancellation.
+#[cfg(feature = "sqlite")]
+const CHECK_INTERVAL: c_int = 1000;
+
+
+/// A token for cancelling a lookup.
+///
+/// Clones of a token share their state, i.e., cancelling one cancels
+/// all of them. Cancellation cannot be undone, so each search needs a
+/// token of its own.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+  cancelled: sync::Arc<atomic::AtomicBool>,
+}
+
+impl CancelToken {
+  /// Create a token that is not cancelled.
+  pub fn new() -> CancelToken {
+    CancelToken::default()
+  }
+
+  /// Cancel the lookups using this token.
+  pub fn cancel(&self) {
+    self.cancelled.store(true, atomic::Ordering::SeqCst)
+  }
+
+  /// Check whether the token was cancelled.
+  pub fn is_cancelled(&self) -> bool {
+    self.cancelled.load(atomic::Ordering::SeqCst)
+  }
+}
+
+
+#[cfg(feature = "sqlite")]
+extern "C" fn progress_fn(arg: *mut c_void) -> c_int {
+  // SAFETY: The argument is the flag of the token `interruptible` was
+  //         invoked with, which outlives the handler's registration.
+  let cancelled = unsafe { &*(arg as *const atomic::AtomicBool) };
+  cancelled.load(atomic::Ordering::SeqCst) as c_int
+}
+
+/// Run a function, interrupting whatever statement it executes on the
+/// given connection once the token is cancelled.
+///
+/// Interrupted statements fail with `SQLITE_INTERRUPT`.
+#[cfg(feature = "sqlite")]
+pub fn interruptible<F, T>(connection: &sqlite::Connection, token: &CancelToken, f: F) -> T
+where
+  F: FnOnce() -> T,
+{
+  let cancelled = &*token.cancelled as *const atomic::AtomicBool as *mut c_void;
+  unsafe {
+    ffi::sqlite3_progress_handler(connection.as_raw(), CHECK_INTERVAL, Some(progress_fn), cancelled)
+  };
+  let result = f();
+  unsafe { ffi::sqlite3_progress_handler(connection.as_raw(), 0, None, ptr::null_mut()) };
+  result
+}
+
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn interrupt_statement() {
+    let connection = sqlite::open(":memory:").unwrap();
+    let query = "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 100000) \
+                 SELECT COUNT(*) FROM n";
+
+    let token = CancelToken::new();
+    let count = interruptible(&connection, &token, || {
+      let mut statement = connection.prepare(query).unwrap();
+      statement.next().map(|_| statement.read::<i64>(0).unwrap())
+    });
+    assert_eq!(count.unwrap(), 100000);
+
+    token.clone().cancel();
+    assert!(token.is_cancelled());
+    let result = interruptible(&connection, &token, || connection.prepare(query)?.next());
+    assert_eq!(result.unwrap_err().code, Some(ffi::SQLITE_INTERRUPT as isize));
+  }
+}
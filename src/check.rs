@@ -0,0 +1,319 @@
+// check.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Diagnosis of problems with a database.
+//!
+//! Lookups in a database that deviates from what we expect, be it due
+//! to corruption or because it was created by other means, may simply
+//! find nothing. The checks here point out such deviations: missing
+//! tables or columns, a corrupted database file or full-text index,
+//! and entries that lack terms or types or whose annotations are not
+//! properly closed.
+
+use std::fmt;
+
+use sqlite;
+
+use subjects::table_columns;
+use term::annotations_balanced;
+use Result;
+use SEARCH_TBL;
+use SUBJ_COL;
+use TERM1_COL;
+use TERM2_COL;
+use TYPE_COL;
+use USAGE_COL;
+
+/// The maximum number of entries given as examples of a problem.
+const MAX_EXAMPLES: usize = 3;
+
+
+/// The severity of a problem.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+  /// The problem prevents lookups from working correctly.
+  Error,
+  /// The problem may affect some results.
+  Warning,
+}
+
+impl fmt::Display for Severity {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Severity::Error => write!(f, "error"),
+      Severity::Warning => write!(f, "warning"),
+    }
+  }
+}
+
+
+/// A problem found in a database.
+#[derive(Debug, PartialEq)]
+pub struct Problem {
+  /// How severe the problem is.
+  pub severity: Severity,
+  /// A description of the problem.
+  pub message: String,
+}
+
+
+/// The outcome of checking a database.
+#[derive(Debug, Default)]
+pub struct Report {
+  /// The number of entries checked.
+  pub entries: usize,
+  /// The problems found.
+  pub problems: Vec<Problem>,
+}
+
+impl Report {
+  fn add<S>(&mut self, severity: Severity, message: S)
+  where
+    S: Into<String>,
+  {
+    self.problems.push(Problem {
+      severity,
+      message: message.into(),
+    })
+  }
+
+  /// Retrieve the number of problems of the given severity.
+  pub fn count(&self, severity: Severity) -> usize {
+    self.problems.iter().filter(|p| p.severity == severity).count()
+  }
+}
+
+
+/// Entries sharing a problem, along with a few examples.
+#[derive(Default)]
+struct Finding {
+  count: usize,
+  examples: Vec<String>,
+}
+
+impl Finding {
+  fn add(&mut self, id: i64, term1: &str, term2: &str) {
+    if self.examples.len() < MAX_EXAMPLES {
+      self.examples.push(format!("#{} ({} / {})", id, term1, term2));
+    }
+    self.count += 1;
+  }
+
+  /// Report the finding, if there are any entries affected.
+  fn report(&self, report: &mut Report, severity: Severity, problem: &str) {
+    if self.count > 0 {
+      let entries = if self.count == 1 { "entry" } else { "entries" };
+      let message = format!(
+        "{} {} {}, e.g., {}",
+        self.count, entries, problem, self.examples.join(", "),
+      );
+      report.add(severity, message)
+    }
+  }
+}
+
+
+/// Retrieve the SQL a table was created with, if it exists.
+pub fn table_sql(connection: &sqlite::Connection, table: &str) -> Result<Option<String>> {
+  let mut statement = connection.prepare(
+    "SELECT sql FROM sqlite_master WHERE type='table' AND name=?"
+  )?;
+  statement.bind(1, table)?;
+  if statement.next()? == sqlite::State::Done {
+    return Ok(None)
+  }
+  Ok(Some(statement.read::<String>(0)?))
+}
+
+/// Check that the search table exists and has the columns we rely on,
+/// returning whether further checks can proceed.
+fn check_schema(connection: &sqlite::Connection, report: &mut Report) -> Result<bool> {
+  let sql = match table_sql(connection, SEARCH_TBL)? {
+    Some(sql) => sql,
+    None => {
+      report.add(Severity::Error, format!("The search table {} does not exist", SEARCH_TBL));
+      return Ok(false)
+    },
+  };
+
+  let columns = table_columns(connection, SEARCH_TBL)?;
+  let mut complete = true;
+  for column in &[TERM1_COL, TERM2_COL, TYPE_COL, USAGE_COL] {
+    if !columns.iter().any(|c| c == column) {
+      report.add(Severity::Error, format!("The search table lacks the {} column", column));
+      complete = false;
+    }
+  }
+  if !columns.iter().any(|c| c == SUBJ_COL) {
+    report.add(Severity::Warning, format!(
+      "The search table lacks the {} column, so subjects are not available",
+      SUBJ_COL,
+    ));
+  }
+
+  if !sql.to_lowercase().contains("using fts") {
+    report.add(Severity::Warning, "The search table is not a full-text table");
+  } else if complete {
+    // The FTS integrity check compares the full-text index against the
+    // content of the table. Despite being an insertion, it does not
+    // modify the database.
+    let check = format!("INSERT INTO {tbl}({tbl}) VALUES('integrity-check')", tbl = SEARCH_TBL);
+    if let Err(err) = connection.execute(check) {
+      report.add(Severity::Error, format!("The full-text index is inconsistent: {}", err));
+    }
+  }
+  Ok(complete)
+}
+
+/// Run SQLite's integrity check of the database file.
+fn check_integrity(connection: &sqlite::Connection, report: &mut Report) -> Result<()> {
+  let mut cursor = match connection.prepare("PRAGMA integrity_check") {
+    Ok(statement) => statement.cursor(),
+    Err(err) => {
+      report.add(Severity::Error, format!("The integrity check failed: {}", err));
+      return Ok(())
+    },
+  };
+
+  loop {
+    match cursor.next() {
+      Ok(Some(row)) => {
+        let message = row[0].as_string().unwrap_or("");
+        if message != "ok" {
+          report.add(Severity::Error, format!("The integrity check failed: {}", message));
+        }
+      },
+      Ok(None) => break,
+      Err(err) => {
+        report.add(Severity::Error, format!("The integrity check failed: {}", err));
+        break
+      },
+    }
+  }
+  Ok(())
+}
+
+/// Check the entries of the search table.
+fn check_entries(connection: &sqlite::Connection, report: &mut Report) -> Result<()> {
+  let select = format!(
+    "SELECT rowid, {term1}, {term2}, {typ}, {use} FROM {tbl}",
+    term1 = TERM1_COL, term2 = TERM2_COL, typ = TYPE_COL, use = USAGE_COL, tbl = SEARCH_TBL,
+  );
+  let mut cursor = connection.prepare(select)?.cursor();
+  let mut no_term = Finding::default();
+  let mut no_type = Finding::default();
+  let mut malformed = Finding::default();
+  let mut no_usage = Finding::default();
+
+  while let Some(row) = cursor.next()? {
+    let id = row[0].as_integer().unwrap_or(0);
+    let term1 = row[1].as_string().unwrap_or("");
+    let term2 = row[2].as_string().unwrap_or("");
+    if term1.trim().is_empty() || term2.trim().is_empty() {
+      no_term.add(id, term1, term2);
+    }
+    if row[3].as_string().unwrap_or("").trim().is_empty() {
+      no_type.add(id, term1, term2);
+    }
+    if !annotations_balanced(term1) || !annotations_balanced(term2) {
+      malformed.add(id, term1, term2);
+    }
+    // Columns of FTS tables store the usage as text.
+    let usage = row[4]
+      .as_integer()
+      .or_else(|| row[4].as_string().and_then(|usage| usage.parse().ok()));
+    if usage.is_none() {
+      no_usage.add(id, term1, term2);
+    }
+    report.entries += 1;
+  }
+
+  no_term.report(report, Severity::Error, "without a term");
+  no_type.report(report, Severity::Warning, "without a type");
+  malformed.report(report, Severity::Warning, "with unclosed annotations");
+  no_usage.report(report, Severity::Warning, "without a valid usage");
+  Ok(())
+}
+
+/// Check a database for problems.
+pub fn check(connection: &sqlite::Connection) -> Result<Report> {
+  let mut report = Report::default();
+  check_integrity(connection, &mut report)?;
+  if check_schema(connection, &mut report)? {
+    check_entries(connection, &mut report)?;
+  }
+  Ok(report)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::path;
+
+  use open_writable;
+
+
+  #[test]
+  fn check_test_db() {
+    let connection = open_writable(path::Path::new("./test/test.db")).unwrap();
+    let report = check(&connection).unwrap();
+    assert_eq!(report.entries, 29);
+    assert_eq!(report.problems, vec![Problem {
+      severity: Severity::Warning,
+      message: "1 entry without a type, e.g., #72218 (null [beim Tennis] / love)".to_string(),
+    }]);
+  }
+
+  #[test]
+  fn check_broken_db() {
+    let connection = sqlite::open(":memory:").unwrap();
+    let report = check(&connection).unwrap();
+    assert_eq!(report.count(Severity::Error), 1);
+    assert_eq!(report.problems[0].message, "The search table main_ft does not exist");
+
+    connection.execute(
+      "CREATE TABLE main_ft (term1 VARCHAR, term2 VARCHAR, vt_usage INTEGER)",
+    ).unwrap();
+    let report = check(&connection).unwrap();
+    let messages = report.problems.iter().map(|p| p.message.as_str()).collect::<Vec<_>>();
+    assert_eq!(messages, vec![
+      "The search table lacks the entry_type column",
+      "The search table lacks the subj_ids column, so subjects are not available",
+      "The search table is not a full-text table",
+    ]);
+
+    connection.execute(
+      "ALTER TABLE main_ft ADD COLUMN entry_type VARCHAR; \
+       INSERT INTO main_ft VALUES ('Zorn {m', 'anger', 42, 'noun'); \
+       INSERT INTO main_ft VALUES ('', 'love', 'many', '');",
+    ).unwrap();
+    let report = check(&connection).unwrap();
+    assert_eq!(report.entries, 2);
+    assert_eq!(report.count(Severity::Error), 1);
+    let messages = report.problems.iter().map(|p| p.message.as_str()).collect::<Vec<_>>();
+    assert_eq!(messages[2..], [
+      "1 entry without a term, e.g., #2 ( / love)",
+      "1 entry without a type, e.g., #2 ( / love)",
+      "1 entry with unclosed annotations, e.g., #1 (Zorn {m / anger)",
+      "1 entry without a valid usage, e.g., #2 ( / love)",
+    ]);
+  }
+}
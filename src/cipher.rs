@@ -0,0 +1,134 @@
+// cipher.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+
+//! Support for databases encrypted with SQLCipher.
+//!
+//! With the `sqlcipher` feature the program links against SQLCipher
+//! instead of SQLite and connections get keyed upon opening, if a key
+//! is set. The key is taken from the `DICTCC_DB_KEY` environment
+//! variable or read from a file (see `read_key_file`). It is passed on
+//! as is, so that it can be a passphrase as well as a raw key in the
+//! form of SQLCipher (`x'...'`).
+
+use std::env;
+use std::fs;
+use std::path;
+use std::sync;
+
+use sqlite;
+
+use quote;
+use Error;
+use Result;
+
+/// The environment variable containing the key of the database.
+pub const ENV_VAR: &str = "DICTCC_DB_KEY";
+
+/// The key applied to connections upon opening.
+static CURRENT: sync::RwLock<Option<String>> = sync::RwLock::new(None);
+
+
+/// Retrieve the key to use by default, as set in the environment.
+pub fn default_key() -> Option<String> {
+  env::var(ENV_VAR).ok().filter(|key| !key.is_empty())
+}
+
+/// Read the key from a file, ignoring a trailing line break.
+pub fn read_key_file(path: &path::Path) -> Result<String> {
+  let content = fs::read_to_string(path)
+    .map_err(|e| Error::Io(format!("Failed to read key file {}", path.display()), e))?;
+  let key = content.trim_end_matches(['\n', '\r']);
+  if key.is_empty() {
+    return Err(Error::Error(format!("Key file {} is empty", path.display())))
+  }
+  Ok(key.to_string())
+}
+
+/// Set the key applied to connections opened from now on, if any.
+pub fn set(key: Option<String>) {
+  *CURRENT.write().unwrap_or_else(|e| e.into_inner()) = key;
+}
+
+/// Retrieve the key applied to connections upon opening, if any.
+pub fn current() -> Option<String> {
+  CURRENT.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Key a freshly opened connection with the current key, if any.
+///
+/// Keying has to happen before anything else accesses the database.
+/// As SQLCipher merely checks the key once data are read, we read the
+/// schema right away to report a wrong key early on.
+pub fn apply(connection: &sqlite::Connection) -> Result<()> {
+  match current() {
+    Some(key) => apply_key(connection, &key),
+    None => Ok(()),
+  }
+}
+
+/// Key a freshly opened connection with the given key.
+fn apply_key(connection: &sqlite::Connection, key: &str) -> Result<()> {
+  // SQLite silently ignores PRAGMAs it does not know, so we have to
+  // check that the library actually is SQLCipher.
+  let mut cursor = connection.prepare("PRAGMA cipher_version")?.cursor();
+  if cursor.next()?.is_none() {
+    return Err(Error::Error("The SQLite library lacks SQLCipher support".to_string()))
+  }
+  drop(cursor);
+
+  connection.execute(format!("PRAGMA key = {}", quote(key)))?;
+  connection
+    .execute("SELECT COUNT(*) FROM sqlite_master")
+    .map_err(|_| Error::Error("Failed to decrypt the database; is the key correct?".to_string()))
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::process;
+
+  #[test]
+  fn read_keys() {
+    let path = env::temp_dir().join(format!("dictcc-cli-key-{}", process::id()));
+    fs::write(&path, "x'2DD29CA851E7B56E4697B0E1F08507293D761A05CE4D1B628663F411A8086D99'\n").unwrap();
+    assert_eq!(
+      read_key_file(&path).unwrap(),
+      "x'2DD29CA851E7B56E4697B0E1F08507293D761A05CE4D1B628663F411A8086D99'"
+    );
+    fs::write(&path, "\n").unwrap();
+    assert!(read_key_file(&path).is_err());
+    fs::remove_file(&path).unwrap();
+    assert!(read_key_file(&path).is_err());
+  }
+
+  /// Check that keying a connection to an unencrypted database fails,
+  /// be it for lack of SQLCipher or because decryption fails.
+  #[test]
+  fn key_unencrypted() {
+    let db = env::temp_dir().join(format!("dictcc-cli-unencrypted-{}.db", process::id()));
+    fs::copy("./test/test.db", &db).unwrap();
+    let connection = sqlite::open(&db).unwrap();
+    assert!(apply_key(&connection, "secret").is_err());
+    drop(connection);
+    fs::remove_file(&db).unwrap();
+  }
+}
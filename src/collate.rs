@@ -0,0 +1,212 @@
+// collate.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Collations for ordering terms.
+//!
+//! SQLite's built-in BINARY collation orders strings by their bytes,
+//! placing "Ärger" after all words starting with 'z'. We register
+//! collations ordering terms the way a reader would expect instead:
+//! `english` ignores case and diacritics and `german` additionally
+//! treats 'ß' like "ss", as per DIN 5007. Ties are broken by comparing
+//! the terms' bytes, so that "Arger" precedes "Ärger".
+
+use std::cmp::Ordering;
+#[cfg(feature = "sqlite")]
+use std::ffi::CStr;
+#[cfg(feature = "sqlite")]
+use std::ffi::CString;
+#[cfg(feature = "sqlite")]
+use std::os::raw::c_int;
+#[cfg(feature = "sqlite")]
+use std::os::raw::c_void;
+#[cfg(feature = "sqlite")]
+use std::ptr;
+#[cfg(feature = "sqlite")]
+use std::slice;
+
+#[cfg(feature = "sqlite")]
+use sqlite;
+#[cfg(feature = "sqlite")]
+use sqlite3_sys as ffi;
+
+use like::unaccent;
+use Error;
+use Result;
+
+
+/// A collation to order terms by.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Collation {
+  /// German dictionary order.
+  #[default]
+  German,
+  /// English dictionary order.
+  English,
+  /// Ordering by bytes.
+  Binary,
+}
+
+impl Collation {
+  /// Parse a collation from its name as used on the command line.
+  pub fn from_name(name: &str) -> Result<Collation> {
+    match name {
+      "de" => Ok(Collation::German),
+      "en" => Ok(Collation::English),
+      "binary" => Ok(Collation::Binary),
+      _ => Err(Error::Error(format!("Unsupported collation: {} (expected de, en, or binary)", name))),
+    }
+  }
+
+  /// Retrieve the name of the collation in SQL.
+  pub fn sql_name(self) -> &'static str {
+    match self {
+      Collation::German => "german",
+      Collation::English => "english",
+      Collation::Binary => "BINARY",
+    }
+  }
+
+  /// Compare two strings according to the collation.
+  pub fn compare(self, string1: &str, string2: &str) -> Ordering {
+    let german = match self {
+      Collation::German => true,
+      Collation::English => false,
+      Collation::Binary => return string1.cmp(string2),
+    };
+    key(string1, german)
+      .cmp(&key(string2, german))
+      .then_with(|| string1.cmp(string2))
+  }
+}
+
+
+/// Retrieve the key to compare a string by.
+fn key(string: &str, german: bool) -> String {
+  let key = unaccent(&string.to_lowercase());
+  if german {
+    key.replace('ß', "ss")
+  } else {
+    key
+  }
+}
+
+/// Compare the strings passed to a collation callback.
+#[cfg(feature = "sqlite")]
+unsafe fn compare(collation: Collation,
+                  len1: c_int, data1: *const c_void,
+                  len2: c_int, data2: *const c_void) -> c_int {
+  let string1 = slice::from_raw_parts(data1 as *const u8, len1 as usize);
+  let string2 = slice::from_raw_parts(data2 as *const u8, len2 as usize);
+  let ordering = collation.compare(&String::from_utf8_lossy(string1),
+                                   &String::from_utf8_lossy(string2));
+  ordering as c_int
+}
+
+#[cfg(feature = "sqlite")]
+extern "C" fn german_fn(_: *mut c_void,
+                        len1: c_int, data1: *const c_void,
+                        len2: c_int, data2: *const c_void) -> c_int {
+  unsafe { compare(Collation::German, len1, data1, len2, data2) }
+}
+
+#[cfg(feature = "sqlite")]
+extern "C" fn english_fn(_: *mut c_void,
+                         len1: c_int, data1: *const c_void,
+                         len2: c_int, data2: *const c_void) -> c_int {
+  unsafe { compare(Collation::English, len1, data1, len2, data2) }
+}
+
+/// Register a collation with the given connection.
+#[cfg(feature = "sqlite")]
+fn create_collation(connection: &sqlite::Connection,
+                    name: &str,
+                    collation: ffi::sqlite3_create_collation_callback) -> Result<()> {
+  let name = CString::new(name).expect("collation name contains NUL byte");
+  let result = unsafe {
+    ffi::sqlite3_create_collation_v2(connection.as_raw(),
+                                     name.as_ptr(),
+                                     ffi::SQLITE_UTF8,
+                                     ptr::null_mut(),
+                                     Some(collation),
+                                     None)
+  };
+
+  if result != ffi::SQLITE_OK {
+    let message = unsafe {
+      CStr::from_ptr(ffi::sqlite3_errmsg(connection.as_raw())).to_string_lossy().into_owned()
+    };
+    return Err(Error::Error(format!("Failed to register collation {:?}: {}", name, message)));
+  }
+  Ok(())
+}
+
+/// Register our collations with a connection.
+#[cfg(feature = "sqlite")]
+pub fn register(connection: &sqlite::Connection) -> Result<()> {
+  create_collation(connection, Collation::German.sql_name(), german_fn)?;
+  create_collation(connection, Collation::English.sql_name(), english_fn)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn sorted(collation: Collation, words: &[&str]) -> Vec<String> {
+    let mut words = words.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+    words.sort_by(|w1, w2| collation.compare(w1, w2));
+    words
+  }
+
+  #[test]
+  fn compare_strings() {
+    let words = ["Zorn", "Ärger", "arg", "Arger", "Maß", "Masse", "Mast"];
+    assert_eq!(
+      sorted(Collation::German, &words),
+      vec!["arg", "Arger", "Ärger", "Maß", "Masse", "Mast", "Zorn"]
+    );
+    assert_eq!(
+      sorted(Collation::English, &words),
+      vec!["arg", "Arger", "Ärger", "Masse", "Mast", "Maß", "Zorn"]
+    );
+    assert_eq!(
+      sorted(Collation::Binary, &words),
+      vec!["Arger", "Masse", "Mast", "Maß", "Zorn", "arg", "Ärger"]
+    );
+    assert_eq!(Collation::from_name("de").unwrap(), Collation::German);
+    assert!(Collation::from_name("fr").is_err());
+  }
+
+  #[test]
+  #[cfg(feature = "sqlite")]
+  fn order_by_collation() {
+    let connection = sqlite::open(":memory:").unwrap();
+    register(&connection).unwrap();
+    connection.execute("CREATE TABLE words (word TEXT); \
+                        INSERT INTO words VALUES ('Zorn'), ('Ärger'), ('Arger');").unwrap();
+
+    let query = "SELECT word FROM words ORDER BY word COLLATE german ASC";
+    let mut cursor = connection.prepare(query).unwrap().cursor();
+    let mut words = Vec::new();
+    while let Some(row) = cursor.next().unwrap() {
+      words.push(row[0].as_string().unwrap().to_string());
+    }
+    assert_eq!(words, vec!["Arger", "Ärger", "Zorn"]);
+  }
+}
@@ -0,0 +1,1256 @@
+// commands.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! The commands of the program other than translating, e.g., for
+//! maintaining databases or for managing entries of one's own.
+
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::io::Write as _;
+use std::net;
+use std::path;
+use std::thread;
+
+use getopts;
+use libc;
+use sqlite;
+
+use annotate;
+use cache;
+use check;
+use columns;
+use config;
+use connection_tuning;
+use corpus;
+use coverage;
+use default_database;
+use default_direction;
+use entry;
+use export;
+use fetch;
+use filter;
+use fulltext;
+use glossary;
+use hunspell;
+use import;
+use json;
+use lacks_database;
+use languages;
+use locate;
+use logger;
+use merge_policy;
+use normalize;
+use open;
+use open_writable;
+use optimize;
+use orient;
+use overlay;
+use parse_options;
+use rank_weights;
+use read_file;
+use results;
+use serve;
+use spell;
+use stats;
+use subjects;
+use suggest;
+use term;
+use translate;
+use tuning;
+use types;
+use usage;
+use write_file;
+use CommandResult;
+use Direction;
+use Entry;
+use Error;
+use Options;
+use Result;
+use SEARCH_TBL;
+use TERM1_COL;
+use TERM2_COL;
+
+
+/// Check whether standard output refers to a terminal.
+fn stdout_is_tty() -> bool {
+  unsafe { libc::isatty(libc::STDOUT_FILENO) != 0 }
+}
+
+/// Check a database for problems, reporting them.
+pub fn check_database(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["check [<database>]"];
+  let mut opts = getopts::Options::new();
+  let mut matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.is_empty() {
+    default_database(&mut matches.free)?;
+  }
+  if matches.free.len() != 1 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+
+  let connection = open_writable(path::Path::new(&matches.free[0]))?;
+  let report = check::check(&connection)?;
+  for problem in &report.problems {
+    println!("{}: {}", problem.severity, problem.message);
+  }
+
+  let errors = report.count(check::Severity::Error);
+  if errors > 0 {
+    Err(Error::Error(format!("{} error(s) found in the database", errors)).into())
+  } else {
+    println!("Checked {} entries", report.entries);
+    Ok(())
+  }
+}
+
+/// Check a translated document against a glossary.
+pub fn check_terms(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["check-terms [options] [<database>] <glossary.tsv> <document>"];
+  let mut opts = getopts::Options::new();
+  opts.optflag("r", "reverse", "The glossary maps from lang2 to lang1 \
+                                instead of from lang1 to lang2");
+
+  let mut matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.len() == 2 {
+    default_database(&mut matches.free)?;
+  }
+  if matches.free.len() != 3 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+  let direction = if matches.opt_present("r") {
+    Direction::Lang2ToLang1
+  } else {
+    Direction::Lang1ToLang2
+  };
+
+  let db = path::Path::new(&matches.free[0]);
+  let glossary = glossary::parse(&read_file(&matches.free[1])?)?;
+  let document = read_file(&matches.free[2])?;
+  let deviations = glossary::check(db, &glossary, &document, &direction)?;
+
+  for deviation in &deviations {
+    println!(
+      "{}:{}: \"{}\" used for \"{}\", glossary prescribes \"{}\"",
+      matches.free[2], deviation.line, deviation.found, deviation.source, deviation.expected,
+    );
+  }
+
+  if deviations.is_empty() {
+    Ok(())
+  } else {
+    Err(Error::Error(format!("{} deviation(s) from the glossary found", deviations.len())).into())
+  }
+}
+
+/// Report how well the dictionary covers a list of words.
+pub fn report_coverage(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["coverage [options] [<database>] <wordlist>"];
+  let mut opts = getopts::Options::new();
+  opts.optflag("r", "reverse", "The words are lang2 words instead of \
+                                lang1 ones");
+
+  let mut matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.len() == 1 {
+    default_database(&mut matches.free)?;
+  }
+  if matches.free.len() != 2 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+  let direction = if matches.opt_present("r") {
+    Direction::Lang2ToLang1
+  } else {
+    Direction::Lang1ToLang2
+  };
+
+  let db = path::Path::new(&matches.free[0]);
+  let words = coverage::parse(&read_file(&matches.free[1])?);
+  let coverage = coverage::check(db, &words, &direction)?;
+
+  println!(
+    "{} of {} words covered ({:.1}%)",
+    coverage.covered, coverage.total, coverage.percentage(),
+  );
+  for (type_, count) in &coverage.types {
+    println!("  {} ({})", type_, count);
+  }
+  if !coverage.missing.is_empty() {
+    println!("Missing:");
+    for word in &coverage.missing {
+      println!("  {}", word);
+    }
+  }
+  Ok(())
+}
+
+/// Annotate each word of a document with the language it is in.
+pub fn annotate_document(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["annotate [options] [<database>] <document>"];
+  let mut opts = getopts::Options::new();
+  opts.optflag("j", "json", "Print the per-token decisions as JSON");
+
+  let mut matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.len() == 1 {
+    default_database(&mut matches.free)?;
+  }
+  if matches.free.len() != 2 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+
+  let connection = open(path::Path::new(&matches.free[0]))?;
+  let lang1 = spell::Checker::new(&connection, TERM1_COL)?;
+  let lang2 = spell::Checker::new(&connection, TERM2_COL)?;
+  let document = read_file(&matches.free[1])?;
+  let tokens = annotate::annotate(&document, &lang1, &lang2);
+
+  if matches.opt_present("j") {
+    let tokens = tokens.iter().map(|t| t.to_json()).collect();
+    println!("{}", json::Value::Array(tokens));
+  } else {
+    // We print one line per line of the document, with each word
+    // followed by the language it was attributed to.
+    let mut line = 0;
+    let mut words = Vec::new();
+    for token in tokens {
+      if token.line != line && !words.is_empty() {
+        println!("{}", words.join(" "));
+        words.clear();
+      }
+      line = token.line;
+      words.push(format!("{}/{}", token.text, token.language.name()));
+    }
+    if !words.is_empty() {
+      println!("{}", words.join(" "));
+    }
+  }
+  Ok(())
+}
+
+/// Answer lookups made over HTTP until interrupted.
+pub fn serve_http(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["serve --http ADDR [options] [<database>]"];
+  let mut opts = getopts::Options::new();
+  opts.optopt("", "http", "The address to listen on, e.g., \
+                           127.0.0.1:8080", "ADDR");
+  opts.optflag("", "allow-remote", "Allow listening on addresses other \
+                                    hosts can reach; the server is not \
+                                    sandboxed, so only use this on \
+                                    trusted networks");
+  opts.optopt("j", "jobs", "The number of requests to answer in parallel \
+                            (default: the number of CPUs)", "N");
+  opts.optopt("", "allow-origin", "Allow web pages and browser extensions \
+                                   from ORIGIN (or any, with '*') to read \
+                                   responses", "ORIGIN");
+  opts.optflagmulti("v", "verbose", "Report more details on requests \
+                                     and lookups");
+
+  let mut matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.is_empty() {
+    default_database(&mut matches.free)?;
+  }
+  let addr = match matches.opt_str("http") {
+    Some(addr) if matches.free.len() == 1 => addr,
+    _ => return Err(Error::Error(usage(&opts, SYNOPSES)).into()),
+  };
+  let jobs = match matches.opt_str("j") {
+    Some(jobs) => jobs
+      .parse::<usize>()
+      .ok()
+      .filter(|jobs| *jobs > 0)
+      .ok_or_else(|| Error::Error(format!("Invalid number of jobs: {}", jobs)))?,
+    None => thread::available_parallelism().map(|n| n.get()).unwrap_or(4),
+  };
+  // Requests are logged by default.
+  logger::init(1 + matches.opt_count("v"));
+
+  // Settings are determined once, before any request is answered, as
+  // they are shared by all workers.
+  let database = path::PathBuf::from(&matches.free[0]);
+  let mut config = config::Config::load()?;
+  config.select_database(&database);
+  tuning::set(connection_tuning(&matches, &config)?);
+  overlay::set(overlay::default_path());
+  // Fail early on a database that cannot be opened, instead of upon
+  // the first request.
+  let _ = open(&database)?;
+  let lookups = serve::Lookups {
+    direction: default_direction(&database, &config)?,
+    languages: languages(&config)?,
+    options: Options {
+      max_results: Some(serve::MAX_RESULTS),
+      type_order: config.get_strings("type-order")?.unwrap_or_default(),
+      weights: rank_weights(&config)?,
+      merge: merge_policy(&config)?,
+      ..Default::default()
+    },
+    database,
+  };
+
+  // The server neither drops privileges nor confines itself to the
+  // database, so being reachable from other hosts has to be asked for
+  // explicitly.
+  let addrs = net::ToSocketAddrs::to_socket_addrs(addr.as_str())
+    .map_err(|e| Error::Io(format!("Failed to resolve {}", addr), e))?
+    .collect::<Vec<_>>();
+  if let Some(public) = addrs.iter().find(|a| !a.ip().is_loopback()) {
+    if !matches.opt_present("allow-remote") {
+      return Err(Error::Error(format!(
+        "Refusing to listen on non-loopback address {} without --allow-remote", public
+      )).into())
+    }
+    warn!("Listening on {}, which other hosts may reach", public);
+  }
+  let listener = net::TcpListener::bind(&addrs[..])
+    .map_err(|e| Error::Io(format!("Failed to listen on {}", addr), e))?;
+  let local = listener
+    .local_addr()
+    .map_err(|e| Error::Io("Failed to retrieve listening address".to_string(), e))?;
+  eprintln!("Listening on http://{}/translate", local);
+
+  let shutdown = serve::shutdown_on_signal();
+  let allow_origin = matches.opt_str("allow-origin");
+  serve::serve(listener, jobs, allow_origin.as_deref(), shutdown, |request| lookups.answer(request))?;
+  eprintln!("Shut down");
+  Ok(())
+}
+
+/// Check the spelling of a document against one side of the dictionary.
+pub fn spellcheck(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["spellcheck [options] [<database>] <document>"];
+  let mut opts = getopts::Options::new();
+  opts.optopt("l", "lang", "The language of the document: 1 or 2 for \
+                            the respective side of the dictionary, or \
+                            a language code, with \"de\" denoting the \
+                            German side (default: 1)", "LANG");
+
+  let mut matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.len() == 1 {
+    default_database(&mut matches.free)?;
+  }
+  if matches.free.len() != 2 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+
+  let db = path::Path::new(&matches.free[0]);
+  let connection = open(db)?;
+  let column = match matches.opt_str("l") {
+    None => TERM1_COL,
+    Some(ref lang) if lang == "1" => TERM1_COL,
+    Some(ref lang) if lang == "2" => TERM2_COL,
+    Some(ref lang) => {
+      let german = spell::german_column(&connection)?;
+      match (lang.to_lowercase() == "de", german) {
+        (true, column) => column,
+        (false, TERM1_COL) => TERM2_COL,
+        (false, _) => TERM1_COL,
+      }
+    },
+  };
+
+  let document = read_file(&matches.free[1])?;
+  let checker = spell::Checker::new(&connection, column)?;
+  let misspellings = checker.check(&document, suggest::MAX_SUGGESTIONS);
+
+  for misspelling in &misspellings {
+    if misspelling.suggestions.is_empty() {
+      println!("{}:{}: {}", matches.free[1], misspelling.line, misspelling.word);
+    } else {
+      println!(
+        "{}:{}: {} (did you mean: {})",
+        matches.free[1], misspelling.line, misspelling.word, misspelling.suggestions.join(", "),
+      );
+    }
+  }
+
+  if misspellings.is_empty() {
+    Ok(())
+  } else {
+    Err(Error::Error(format!("{} unknown word(s) found", misspellings.len())).into())
+  }
+}
+
+/// Show or edit the configuration.
+pub fn configure(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &[
+    "config show [options]",
+    "config get [options] <key>",
+    "config set <key> <value>",
+  ];
+  let mut opts = getopts::Options::new();
+  opts.optflag("e", "effective", "Show the effective configuration, \
+                                  including defaults and overrides, \
+                                  along with the origin of each value");
+  opts.optmulti("", "set", "Override a configuration setting", "KEY=VALUE");
+  opts.optopt("d", "database", "Apply the overrides configured for the \
+                                given database", "DATABASE");
+
+  let matches = parse_options(&mut opts, args, SYNOPSES)?;
+  let load = || -> Result<config::Config> {
+    let mut config = config::Config::load()?;
+    if let Some(database) = matches.opt_str("d") {
+      config.select_database(path::Path::new(&database));
+    }
+    Ok(config)
+  };
+  let free = matches.free.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+  match free[..] {
+    ["show"] => {
+      let mut config = load()?;
+      for setting in matches.opt_strs("set") {
+        config.set_from(&setting, config::Origin::Cli)?;
+      }
+      let effective = matches.opt_present("e");
+      for (key, value, origin) in config.effective() {
+        if effective {
+          println!("{} = {} # {}", key, config::to_toml(&value), origin.name());
+        } else if origin == config::Origin::File {
+          println!("{} = {}", key, config::to_toml(&value));
+        }
+      }
+      Ok(())
+    },
+    ["get", key] => {
+      let config = load()?;
+      match config.get(key) {
+        Some(value) => {
+          println!("{}", config::to_toml(value));
+          Ok(())
+        },
+        None => Err(Error::Error(format!("Setting {} is not set", key)).into()),
+      }
+    },
+    ["set", key, value] => {
+      let path = config::path()
+        .ok_or_else(|| Error::Error("Unable to determine configuration path".to_string()))?;
+      let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(Error::Io(format!("Failed to read {}", path.display()), e).into()),
+      };
+      let content = config::set_in(&content, key, &config::parse_value(value))?;
+      if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+          .map_err(|e| Error::Io(format!("Failed to create {}", dir.display()), e))?;
+      }
+      Ok(write_file(&path.to_string_lossy(), &content)?)
+    },
+    _ => Err(Error::Error(usage(&opts, SYNOPSES)).into()),
+  }
+}
+
+/// List the databases registered in the configuration.
+pub fn list_dicts(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["list-dicts [options]"];
+  let mut opts = getopts::Options::new();
+  opts.optflag("n", "names", "Only print the names of the databases");
+  let matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if !matches.free.is_empty() {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+
+  let config = config::Config::load()?;
+  let databases = config.databases();
+  if matches.opt_present("n") {
+    for (name, _) in databases {
+      println!("{}", name);
+    }
+    return Ok(());
+  }
+  let width = databases.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+  for (name, db) in databases {
+    if !db.exists() {
+      println!("{:width$}  {} (not found)", name, db.display(), width = width);
+      continue;
+    }
+
+    // The configured language names take precedence over what we can
+    // detect, which is only the German side.
+    let mut config = config::Config::load()?;
+    config.select_database(&db);
+    let languages = match config.get_strings("languages")?.as_deref() {
+      Some([lang1, lang2]) => format!("{}-{}", lang1, lang2),
+      _ if orient::german_column(&db)? == TERM1_COL => "de-?".to_string(),
+      _ => "?-de".to_string(),
+    };
+
+    let connection = open(&db)?;
+    let mut statement = connection.prepare(format!("SELECT COUNT(*) FROM {}", SEARCH_TBL))?;
+    statement.next()?;
+    let count = statement.read::<i64>(0)?;
+    println!(
+      "{:width$}  {}  {} entries  {}",
+      name, languages, count, db.display(), width = width,
+    );
+  }
+  Ok(())
+}
+
+/// Render results saved earlier.
+pub fn replay(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["replay [options] <results.json>"];
+  let mut opts = getopts::Options::new();
+  opts.optopt("f", "format", "The format to render the results in: \
+                              plain, compact, one-line, first, \
+                              tsv, or json (default: plain)", "FORMAT");
+
+  let matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.len() != 1 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+  let format = match matches.opt_str("f") {
+    Some(format) => results::Format::from_name(&format)?,
+    None => results::Format::Plain,
+  };
+
+  let path = &matches.free[0];
+  let results = results::Results::parse(&read_file(path)?)
+    .map_err(|e| Error::Error(format!("{}: {}", path, e)))?;
+  print!("{}", results.render(format));
+  Ok(())
+}
+
+/// Render previously captured results read from a file or from
+/// standard input.
+pub fn render(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["render [options]"];
+  let mut opts = getopts::Options::new();
+  opts.optopt("i", "input", "The file to read the results from \
+                             (default: standard input)", "FILE");
+  opts.optopt("f", "format", "The format to render the results in: \
+                              plain, compact, one-line, first, \
+                              tsv, or json (default: plain)", "FORMAT");
+
+  let matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if !matches.free.is_empty() {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+  let format = match matches.opt_str("f") {
+    Some(format) => results::Format::from_name(&format)?,
+    None => results::Format::Plain,
+  };
+
+  let (name, content) = match matches.opt_str("i") {
+    Some(path) => {
+      let content = read_file(&path)?;
+      (path, content)
+    },
+    None => {
+      let mut content = String::new();
+      io::stdin()
+        .read_to_string(&mut content)
+        .map_err(|e| Error::Io("Failed to read standard input".to_string(), e))?;
+      ("<stdin>".to_string(), content)
+    },
+  };
+  let results = results::Results::parse(&content)
+    .map_err(|e| Error::Error(format!("{}: {}", name, e)))?;
+  print!("{}", results.render(format));
+  Ok(())
+}
+
+/// Create a file for writing, buffering writes to it.
+fn create_file(path: &str) -> Result<io::BufWriter<fs::File>> {
+  let file = fs::File::create(path).map_err(|e| Error::Io(format!("Failed to create {}", path), e))?;
+  Ok(io::BufWriter::new(file))
+}
+
+/// Export the dictionary in the format of another dictionary program.
+pub fn export_dictionary(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["export [options] [<database>] <prefix>"];
+  let mut opts = getopts::Options::new();
+  opts.optopt("f", "format", "The format to export into: stardict \
+                              (default), dictd, csv, or jsonl", "FORMAT");
+  opts.optopt("n", "name", "The name of the dictionary (default: the \
+                            file name of the prefix)", "NAME");
+  opts.optflag("r", "reverse", "Export the dictionary from lang2 to \
+                                lang1");
+  opts.optmulti("", "subject", "Only export entries belonging to the \
+                                given subject area, including its \
+                                sub-areas; may be given multiple times", "SUBJECT");
+  opts.optmulti("", "type", "Only export entries of the given type \
+                             (e.g., \"noun\"); may be given multiple \
+                             times", "TYPE");
+  for register in &filter::Register::ALL {
+    opts.optflag("", &format!("no-{}", register.name()), &format!(
+      "Omit {} entries, as identified by their annotations",
+      register.name()
+    ));
+  }
+
+  let mut matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.len() == 1 {
+    default_database(&mut matches.free)?;
+  }
+  if matches.free.len() != 2 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+  let format = matches
+    .opt_str("f")
+    .map(|format| format.parse())
+    .transpose()?
+    .unwrap_or(export::Format::StarDict);
+  let direction = if matches.opt_present("r") {
+    Direction::Lang2ToLang1
+  } else {
+    Direction::Lang1ToLang2
+  };
+  let registers = filter::Register::ALL
+    .iter()
+    .filter(|r| matches.opt_present(&format!("no-{}", r.name())))
+    .cloned()
+    .collect::<Vec<_>>();
+  let filter = filter::Filter::new(&registers, &config::Config::load()?)?;
+
+  let db = path::Path::new(&matches.free[0]);
+  let connection = open(db)?;
+  let mut options = Options {
+    types: matches.opt_strs("type").iter().map(|t| entry::Type::from_name(t)).collect(),
+    ..Default::default()
+  };
+  if matches.opt_present("subject") {
+    let subjects = subjects::subjects(&connection)?;
+    for subject in matches.opt_strs("subject") {
+      options.subjects.extend(subjects::resolve(&subjects, &subject)?);
+    }
+  }
+
+  let prefix = &matches.free[1];
+  let name = matches.opt_str("n").unwrap_or_else(|| {
+    path::Path::new(prefix)
+      .file_name()
+      .map(|name| name.to_string_lossy().into_owned())
+      .unwrap_or_else(|| prefix.clone())
+  });
+
+  match format {
+    export::Format::StarDict => {
+      let articles = export::articles(&connection, &direction, &options, &filter)?;
+      let dict = export::stardict(&articles, &name)?;
+      write_file(&format!("{}.ifo", prefix), &dict.ifo)?;
+      write_file(&format!("{}.idx", prefix), &dict.idx)?;
+      write_file(&format!("{}.dict.dz", prefix), &dict.dict)?;
+      println!("Exported {} articles to {}.ifo", articles.len(), prefix);
+    },
+    export::Format::Dictd => {
+      let path = format!("{}.dict", prefix);
+      let failed = |e| Error::Io(format!("Failed to write {}", path), e);
+      let mut dictd = export::Dictd::new(create_file(&path)?, &name).map_err(failed)?;
+      let count = export::entries(&connection, &direction, &options, &filter, |entry| {
+        dictd.add(&entry).map_err(failed)
+      })?;
+      let index = dictd.finish().map_err(failed)?;
+      write_file(&format!("{}.index", prefix), index)?;
+      println!("Exported {} entries to {}", count, path);
+    },
+    export::Format::Csv | export::Format::Jsonl => {
+      let (extension, record): (_, fn(&Entry) -> String) = match format {
+        export::Format::Csv => ("csv", export::csv_record),
+        _ => ("jsonl", export::jsonl_record),
+      };
+      let path = format!("{}.{}", prefix, extension);
+      let failed = |e| Error::Io(format!("Failed to write {}", path), e);
+      let mut file = create_file(&path)?;
+      if format == export::Format::Csv {
+        file.write_all(export::CSV_HEADER.as_bytes()).map_err(failed)?;
+      }
+      let count = export::entries(&connection, &direction, &options, &filter, |entry| {
+        file.write_all(record(&entry).as_bytes()).map_err(failed)
+      })?;
+      file.flush().map_err(failed)?;
+      println!("Exported {} entries to {}", count, path);
+    },
+  }
+  Ok(())
+}
+
+/// Export the words of one side of the dictionary as a Hunspell
+/// dictionary.
+pub fn export_hunspell(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["export-hunspell [options] [<database>] <prefix>"];
+  let mut opts = getopts::Options::new();
+  opts.optflag("r", "reverse", "Export the lang2 words instead of the \
+                                lang1 ones");
+
+  let mut matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.len() == 1 {
+    default_database(&mut matches.free)?;
+  }
+  if matches.free.len() != 2 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+  let direction = if matches.opt_present("r") {
+    Direction::Lang2ToLang1
+  } else {
+    Direction::Lang1ToLang2
+  };
+
+  let (src_col, _) = columns(&direction);
+  let db = path::Path::new(&matches.free[0]);
+  let words = hunspell::words(&*open(db)?, src_col)?;
+
+  let prefix = &matches.free[1];
+  write_file(&format!("{}.dic", prefix), hunspell::dic(&words))?;
+  write_file(&format!("{}.aff", prefix), hunspell::AFF)?;
+  println!("Exported {} words to {}.dic", words.len(), prefix);
+  Ok(())
+}
+
+/// Download a database or vocabulary export and install it as a
+/// database.
+pub fn fetch_database(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["fetch [options] <url>"];
+  let mut opts = getopts::Options::new();
+  opts.optopt("", "sha256", "The SHA-256 checksum the downloaded file \
+                             has to have", "CHECKSUM");
+  opts.optopt("n", "name", "The file name of the database to install \
+                            (default: derived from the downloaded \
+                            file)", "NAME");
+  opts.optflag("f", "force", "Replace an existing database of the same \
+                              name");
+
+  let matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.len() != 1 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+  let url = &matches.free[0];
+  let dir = locate::data_dir()
+    .ok_or_else(|| Error::Error("Failed to determine the data directory".to_string()))?;
+
+  let data = fetch::download(url)?;
+  match matches.opt_str("sha256") {
+    Some(expected) => fetch::verify(&data, &expected)?,
+    None => println!("Downloaded {} bytes with SHA-256 checksum {}", data.len(), fetch::checksum(&data)),
+  }
+
+  let (file, content) = fetch::unpack(data)?;
+  let name = matches
+    .opt_str("n")
+    .unwrap_or_else(|| fetch::database_name(file.as_deref().unwrap_or(url)));
+  let db = dir.join(name);
+  if let Some(count) = fetch::install(&content, &db, matches.opt_present("f"))? {
+    println!("Imported {} entries", count);
+  }
+  println!("Installed {}", db.display());
+  Ok(())
+}
+
+/// Add an entry of the user's own to the user database.
+pub fn add_entry(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["add [options] <term1> <term2>"];
+  let mut opts = getopts::Options::new();
+  opts.optopt("", "type", "The type of the entry, e.g., 'noun' or \
+                           'verb'", "TYPE");
+
+  let matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.len() != 2 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+  let db = user_database()?;
+  let connection = overlay::open(&db)?;
+  let type_ = matches.opt_str("type").unwrap_or_default();
+  overlay::add(&connection, &matches.free[0], &matches.free[1], &type_)?;
+  println!("Added entry to {}", db.display());
+  Ok(())
+}
+
+/// Remove entries of the user's own from the user database.
+pub fn remove_entry(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["remove <term1> <term2>"];
+  let mut opts = getopts::Options::new();
+  let matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.len() != 2 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+  let db = user_database()?;
+  if !db.is_file() {
+    return Err(Error::Error(format!("No user database at {}", db.display())).into());
+  }
+  let connection = overlay::open(&db)?;
+  match overlay::remove(&connection, &matches.free[0], &matches.free[1])? {
+    0 => Err(Error::Error(format!("No such entry in {}", db.display())).into()),
+    count => {
+      println!("Removed {} entries from {}", count, db.display());
+      Ok(())
+    },
+  }
+}
+
+/// Hide an entry from all future lookups.
+pub fn hide_entry(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["hide <id|term>"];
+  let mut opts = getopts::Options::new();
+  let matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.len() != 1 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+  let hidden = overlay::Hidden::parse(&matches.free[0])?;
+  let db = user_database()?;
+  let connection = overlay::open(&db)?;
+  if overlay::hide(&connection, &hidden)? {
+    println!("Hid {}", hidden);
+  } else {
+    println!("{} is hidden already", hidden);
+  }
+  Ok(())
+}
+
+/// Show an entry hidden before in lookups again.
+pub fn unhide_entry(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["unhide <id|term>"];
+  let mut opts = getopts::Options::new();
+  let matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.len() != 1 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+  let hidden = overlay::Hidden::parse(&matches.free[0])?;
+  let db = user_database()?;
+  if !db.is_file() || !overlay::unhide(&*overlay::open(&db)?, &hidden)? {
+    return Err(Error::Error(format!("{} is not hidden", hidden)).into());
+  }
+  println!("Unhid {}", hidden);
+  Ok(())
+}
+
+/// List the entries hidden from lookups.
+pub fn list_hidden(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["hidden list [<database>]"];
+  let mut opts = getopts::Options::new();
+  let mut matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.first().map(String::as_str) != Some("list") {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+  let _ = matches.free.remove(0);
+  if matches.free.is_empty() {
+    default_database(&mut matches.free)?;
+  }
+  if matches.free.len() != 1 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+
+  let db = user_database()?;
+  if !db.is_file() {
+    return Ok(());
+  }
+  let user = overlay::open(&db)?;
+  let hidden = overlay::hidden(&user)?;
+  // Entries hidden by id are described by their terms, as far as they
+  // can be found. Negative ids are those of the user's entries.
+  let connection = open(path::Path::new(&matches.free[0]))?;
+  let select = format!(
+    "SELECT {term1}, {term2} FROM {tbl} WHERE rowid = ?",
+    term1 = TERM1_COL, term2 = TERM2_COL, tbl = SEARCH_TBL,
+  );
+  let mut main_statement = connection.prepare(select)?;
+  let mut user_statement = user.prepare("SELECT term1, term2 FROM entries WHERE rowid = ?")?;
+  for hidden in hidden {
+    match hidden {
+      overlay::Hidden::Id(id) => {
+        let statement = if id > 0 {
+          &mut main_statement
+        } else {
+          &mut user_statement
+        };
+        statement.reset()?;
+        statement.bind(1, id.abs())?;
+        match statement.next()? {
+          sqlite::State::Row => println!(
+            "{} ({} / {})",
+            hidden,
+            statement.read::<String>(0)?,
+            statement.read::<String>(1)?,
+          ),
+          sqlite::State::Done => println!("{}", hidden),
+        }
+      },
+      overlay::Hidden::Term(_) => println!("{}", hidden),
+    }
+  }
+  Ok(())
+}
+
+/// Retrieve the path of the user database.
+fn user_database() -> Result<path::PathBuf> {
+  overlay::default_path()
+    .ok_or_else(|| Error::Error("Failed to determine the user database".to_string()))
+}
+
+/// Create a database from a vocabulary export of dict.cc.
+pub fn import_export(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["import <export.txt> <database>"];
+  let mut opts = getopts::Options::new();
+  let matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.len() != 2 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+
+  let content = read_file(&matches.free[0])?;
+  let count = import::create(path::Path::new(&matches.free[1]), &content)?;
+  println!("Imported {} entries", count);
+  Ok(())
+}
+
+/// Update a database with the changes of a newer vocabulary export.
+pub fn update_database(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["update [<database>] <export.txt>"];
+  let mut opts = getopts::Options::new();
+  let mut matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.len() == 1 {
+    default_database(&mut matches.free)?;
+  }
+  if matches.free.len() != 2 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+
+  let connection = open_writable(path::Path::new(&matches.free[0]))?;
+  let changes = import::update(&connection, &read_file(&matches.free[1])?)?;
+  println!(
+    "Inserted {} entries, updated {}, and deleted {}",
+    changes.inserted, changes.updated, changes.deleted,
+  );
+  Ok(())
+}
+
+/// Import sentence pairs into the corpus of a database.
+pub fn import_corpus(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["import-corpus [<database>] <pairs.tsv>"];
+  let mut opts = getopts::Options::new();
+  let mut matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.len() == 1 {
+    default_database(&mut matches.free)?;
+  }
+  if matches.free.len() != 2 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+
+  let connection = open_writable(path::Path::new(&matches.free[0]))?;
+  let count = corpus::import(&connection, &read_file(&matches.free[1])?)?;
+  println!("Imported {} sentence pairs", count);
+  Ok(())
+}
+
+/// Remove the cache.
+pub fn clear_cache(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["clear-cache"];
+  let mut opts = getopts::Options::new();
+  let matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if !matches.free.is_empty() {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+
+  if cache::clear()? {
+    println!("Cleared the cache");
+  } else {
+    println!("The cache is empty");
+  }
+  Ok(())
+}
+
+/// Gather statistics on a database for SQLite's query planner.
+pub fn analyze_database(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["analyze [<database>]"];
+  let mut opts = getopts::Options::new();
+  let mut matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.is_empty() {
+    default_database(&mut matches.free)?;
+  }
+  if matches.free.len() != 1 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+
+  let connection = open_writable(path::Path::new(&matches.free[0]))?;
+  let count = optimize::analyze(&connection)?;
+  println!("Gathered statistics on {} tables and indexes", count);
+  Ok(())
+}
+
+/// Rebuild a database to reclaim unused space.
+pub fn compact_database(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["compact [options] [<database>]"];
+  let mut opts = getopts::Options::new();
+  opts.optflag("i", "index", "Merge the segments of the full-text index \
+                              as well");
+
+  let mut matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.is_empty() {
+    default_database(&mut matches.free)?;
+  }
+  if matches.free.len() != 1 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+
+  let connection = open_writable(path::Path::new(&matches.free[0]))?;
+  let (before, after) = optimize::compact(&connection, matches.opt_present("i"))?;
+  println!(
+    "Compacted database from {} to {} ({} bytes saved)",
+    stats::format_size(before),
+    stats::format_size(after),
+    before.saturating_sub(after),
+  );
+  Ok(())
+}
+
+/// Convert the search table of a database into an FTS5 table.
+pub fn migrate_database(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["migrate [options] [<database>]"];
+  let mut opts = getopts::Options::new();
+  opts.optopt("", "remove-diacritics", "Whether to remove diacritics when \
+                                        indexing: 0 (no), 1, or 2 (yes, \
+                                        the default)", "N");
+
+  let mut matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.is_empty() {
+    default_database(&mut matches.free)?;
+  }
+  if matches.free.len() != 1 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+  let remove_diacritics = match matches.opt_str("remove-diacritics") {
+    Some(n) => n.parse().map_err(|_| Error::Error(format!("Invalid number: {}", n)))?,
+    None => 2,
+  };
+
+  let connection = open_writable(path::Path::new(&matches.free[0]))?;
+  let count = fulltext::migrate(&connection, remove_diacritics)?;
+  println!("Migrated {} entries to FTS5", count);
+  Ok(())
+}
+
+/// Add the headwords table speeding up exact lookups and completion to
+/// a database, or remove it again.
+pub fn optimize_database(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["optimize [options] [<database>]"];
+  let mut opts = getopts::Options::new();
+  opts.optflag("", "remove", "Remove the indexes added earlier instead");
+
+  let mut matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.is_empty() {
+    default_database(&mut matches.free)?;
+  }
+  if matches.free.len() != 1 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+
+  let connection = open_writable(path::Path::new(&matches.free[0]))?;
+  if matches.opt_present("remove") {
+    optimize::remove(&connection)?;
+    println!("Removed the headwords table");
+  } else {
+    let count = optimize::build(&connection)?;
+    println!("Indexed {} headwords", count);
+  }
+  Ok(())
+}
+
+/// List the sentence pairs of the corpus containing a word.
+pub fn concord(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["concord [options] [<database>] <word>..."];
+  let mut opts = getopts::Options::new();
+  opts.optflag("r", "reverse", "Search the lang2 sentences instead of \
+                                the lang1 ones");
+
+  let mut matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if lacks_database(&matches.free) {
+    default_database(&mut matches.free)?;
+  }
+  if matches.free.len() < 2 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+  let direction = if matches.opt_present("r") {
+    Direction::Lang2ToLang1
+  } else {
+    Direction::Lang1ToLang2
+  };
+
+  let db = path::Path::new(&matches.free[0]);
+  let word = matches.free[1..].join(" ");
+  let pairs = corpus::concord(&*open(db)?, &word, &direction)?;
+
+  // In the target sentence we highlight all the translations of the
+  // word that the dictionary knows about.
+  let mut translations = Vec::new();
+  translate(db, &[&word], &direction, |entry: &Entry| {
+    translations.push(entry.target.headword.clone());
+    Ok(())
+  })?;
+
+  let (start, end) = if stdout_is_tty() {
+    ("\x1b[1m", "\x1b[0m")
+  } else {
+    ("", "")
+  };
+  for pair in pairs {
+    println!("{}", term::highlight(&pair.source, &[&word], start, end));
+    println!("  {}", term::highlight(&pair.target, &translations, start, end));
+  }
+  Ok(())
+}
+
+/// List the words frequently used together with a word in the corpus.
+pub fn collocations(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["collocations [options] [<database>] <word>"];
+  let mut opts = getopts::Options::new();
+  opts.optflag("r", "reverse", "Search the lang2 sentences instead of \
+                                the lang1 ones");
+  opts.optopt("w", "window", "The maximum distance of collocates to \
+                              the word (default: 2)", "N");
+  opts.optopt("m", "min-count", "The minimum number of times a \
+                                 collocate has to appear next to the \
+                                 word (default: 2)", "N");
+  opts.optopt("n", "count", "The maximum number of collocates to \
+                             display (default: 10)", "N");
+
+  let mut matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.len() == 1 {
+    default_database(&mut matches.free)?;
+  }
+  if matches.free.len() != 2 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+  let direction = if matches.opt_present("r") {
+    Direction::Lang2ToLang1
+  } else {
+    Direction::Lang1ToLang2
+  };
+  let number = |name: &str, default: usize| -> Result<usize> {
+    match matches.opt_str(name) {
+      Some(n) => n.parse().map_err(|_| Error::Error(format!("Invalid number: {}", n))),
+      None => Ok(default),
+    }
+  };
+  let window = number("w", 2)?;
+  let min_count = number("m", 2)?;
+  let count = number("n", 10)?;
+
+  let db = path::Path::new(&matches.free[0]);
+  let found = corpus::collocations(&*open(db)?, &matches.free[1], &direction, window, min_count)?;
+
+  for collocation in found.into_iter().take(count) {
+    let mut translations = Vec::new();
+    translate(db, &[&collocation.word], &direction, |entry: &Entry| {
+      let translation = entry.target.headword.clone();
+      if !translations.contains(&translation) {
+        translations.push(translation);
+      }
+      Ok(())
+    })?;
+
+    println!(
+      "{} ({}x, PMI {:.2}): {}",
+      collocation.word, collocation.count, collocation.pmi, translations.join(", "),
+    );
+  }
+  Ok(())
+}
+
+/// List the subject areas entries are associated with.
+pub fn list_subjects(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &[
+    "subjects [<database>]",
+    "subjects tree [<database>]",
+  ];
+  let mut opts = getopts::Options::new();
+  let mut matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.is_empty() || (matches.free == ["tree"] && !locate::is_database("tree")) {
+    // The database follows "tree", if given.
+    let db = locate::database()?;
+    matches.free.push(db.to_string_lossy().into_owned());
+  }
+  let (tree, db) = match matches.free.len() {
+    1 => (false, &matches.free[0]),
+    2 if matches.free[0] == "tree" => (true, &matches.free[1]),
+    _ => {
+      return Err(Error::Error(usage(&opts, SYNOPSES)).into())
+    },
+  };
+
+  let subjects = subjects::subjects(&*open(path::Path::new(db))?)?;
+  let name = |subject: &subjects::Subject| -> String {
+    match subject.name {
+      Some(ref name) => name.clone(),
+      None => format!("#{}", subject.id),
+    }
+  };
+
+  if tree {
+    for (depth, subject) in subjects::tree(&subjects) {
+      println!("{}{} ({})", "  ".repeat(depth), name(subject), subject.count);
+    }
+  } else {
+    let mut subjects = subjects.iter().collect::<Vec<_>>();
+    subjects.sort_by_key(|s| name(s).to_lowercase());
+    for subject in subjects {
+      println!("{} ({})", name(subject), subject.count);
+    }
+  }
+  Ok(())
+}
+
+/// Print statistics about a database.
+pub fn print_stats(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["stats [<database>]"];
+  let mut opts = getopts::Options::new();
+  let mut matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.is_empty() {
+    default_database(&mut matches.free)?;
+  }
+  if matches.free.len() != 1 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+
+  let db = path::Path::new(&matches.free[0]);
+  print!("{}", stats::Stats::gather(&*open(db)?)?);
+  Ok(())
+}
+
+/// List the types of entries along with some statistics.
+pub fn list_types(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["types [options] [<database>]"];
+  let mut opts = getopts::Options::new();
+  opts.optflag("r", "reverse", "Show lang2 sample terms instead of \
+                                lang1 ones");
+  opts.optopt("n", "samples", "The number of sample terms to show per \
+                               type (default: 3)", "N");
+
+  let mut matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.is_empty() {
+    default_database(&mut matches.free)?;
+  }
+  if matches.free.len() != 1 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+  let direction = if matches.opt_present("r") {
+    Direction::Lang2ToLang1
+  } else {
+    Direction::Lang1ToLang2
+  };
+  let samples = match matches.opt_str("n") {
+    Some(n) => n.parse().map_err(|_| Error::Error(format!("Invalid number: {}", n)))?,
+    None => 3,
+  };
+
+  let (src_col, _) = columns(&direction);
+  let db = path::Path::new(&matches.free[0]);
+  for type_ in types::types(&*open(db)?, src_col, samples)? {
+    println!("{} ({})", type_.name, type_.count);
+    for sample in type_.samples {
+      println!("  {}", normalize(&sample));
+    }
+  }
+  Ok(())
+}
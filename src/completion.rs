@@ -0,0 +1,482 @@
+// completion.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Generation of shell completion scripts.
+//!
+//! The options of each command are extracted from its help, as
+//! generated by `getopts` from the options the command defines, so
+//! that the scripts cannot get out of sync with the commands.
+
+use std::fmt::Write;
+
+use Error;
+use Result;
+
+/// The name of the program to complete.
+const PROGRAM: &str = "dictcc-cli";
+/// The command listing the names of the registered databases.
+const LIST_DICTS: &str = "dictcc-cli list-dicts --names 2>/dev/null";
+/// The command listing the headwords starting with a prefix, to be
+/// followed by the database and `-r` if given on the command line as
+/// well as by "--" and the prefix.
+const COMPLETE_TERM: &str = "dictcc-cli __complete-term";
+/// The name of the command run if none is given.
+const DEFAULT_COMMAND: &str = "translate";
+
+
+/// A shell to generate a completion script for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shell {
+  Bash,
+  Zsh,
+  Fish,
+}
+
+impl Shell {
+  /// Parse a shell from its name.
+  pub fn from_name(name: &str) -> Result<Shell> {
+    match name {
+      "bash" => Ok(Shell::Bash),
+      "zsh" => Ok(Shell::Zsh),
+      "fish" => Ok(Shell::Fish),
+      _ => Err(Error::Error(format!("Unsupported shell: {} (use bash, zsh, or fish)", name))),
+    }
+  }
+}
+
+
+/// An option of a command.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Opt {
+  pub short: Option<char>,
+  pub long: String,
+  /// The name of the option's argument, if it takes one.
+  pub arg: Option<String>,
+  pub description: String,
+}
+
+impl Opt {
+  /// Check whether the option's argument is the name of a database.
+  fn takes_dict(&self) -> bool {
+    self.long == "dict"
+  }
+
+  /// Check whether the option's argument is a file.
+  fn takes_file(&self) -> bool {
+    self.arg.as_deref() == Some("FILE")
+  }
+}
+
+/// A command along with its options.
+#[derive(Debug)]
+pub struct Command {
+  pub name: String,
+  pub summary: String,
+  pub options: Vec<Opt>,
+}
+
+
+/// Parse the options listed in the help of a command.
+///
+/// Options are listed one per line, indented by four spaces, with
+/// their descriptions wrapped onto lines indented further.
+pub fn parse_options(help: &str) -> Vec<Opt> {
+  let mut options = Vec::<Opt>::new();
+  for line in help.lines() {
+    let spec = if let Some(spec) = line.strip_prefix("        --") {
+      Some((None, spec))
+    } else {
+      line.strip_prefix("    -").and_then(|spec| {
+        let mut chars = spec.chars();
+        let short = chars.next()?;
+        chars.as_str().strip_prefix(", --").map(|spec| (Some(short), spec))
+      })
+    };
+
+    match spec {
+      Some((short, spec)) => {
+        // The option is separated from its description by at least two
+        // spaces, if both are on the same line.
+        let (names, description) = match spec.find("  ") {
+          Some(i) => (&spec[..i], spec[i..].trim()),
+          None => (spec, ""),
+        };
+        let mut names = names.split_whitespace();
+        options.push(Opt {
+          short,
+          long: names.next().unwrap_or_default().to_string(),
+          arg: names.next().map(|arg| arg.to_string()),
+          description: description.to_string(),
+        });
+      },
+      None => {
+        let text = line.trim();
+        match options.last_mut() {
+          Some(option) if line.starts_with("          ") && !text.is_empty() => {
+            if !option.description.is_empty() {
+              option.description.push(' ');
+            }
+            option.description.push_str(text);
+          },
+          _ => (),
+        }
+      },
+    }
+  }
+  options
+}
+
+
+/// Generate the completion script for the given shell.
+pub fn script(shell: Shell, commands: &[Command]) -> String {
+  match shell {
+    Shell::Bash => bash(commands),
+    Shell::Zsh => zsh(commands),
+    Shell::Fish => fish(commands),
+  }
+}
+
+/// Iterate over the names (short and long) of an option.
+fn names(option: &Opt) -> impl Iterator<Item = String> + '_ {
+  let short = option.short.map(|c| format!("-{}", c));
+  short.into_iter().chain(Some(format!("--{}", option.long)))
+}
+
+fn bash(commands: &[Command]) -> String {
+  let command_names = commands.iter().map(|c| c.name.as_str()).collect::<Vec<_>>();
+
+  let mut s = String::new();
+  let _ = writeln!(s, "# bash completion for {}", PROGRAM);
+  let _ = writeln!(s, "_dictcc_cli_terms() {{");
+  let _ = writeln!(s, "  local word args=()");
+  let _ = writeln!(s, "  for word in \"${{COMP_WORDS[@]:1:COMP_CWORD-1}}\"; do");
+  let _ = writeln!(s, "    case \"$word\" in");
+  let _ = writeln!(s, "      *.db) args+=(\"$word\");;");
+  let _ = writeln!(s, "      -r|--reverse) args+=(-r);;");
+  let _ = writeln!(s, "    esac");
+  let _ = writeln!(s, "  done");
+  let _ = writeln!(s, "  {} \"${{args[@]}}\" -- \"$cur\" 2>/dev/null", COMPLETE_TERM);
+  let _ = writeln!(s, "}}");
+  let _ = writeln!(s);
+  let _ = writeln!(s, "_dictcc_cli() {{");
+  let _ = writeln!(s, "  local cur prev cmd opts");
+  let _ = writeln!(s, "  cur=\"${{COMP_WORDS[COMP_CWORD]}}\"");
+  let _ = writeln!(s, "  prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"");
+  let _ = writeln!(s, "  cmd={}", DEFAULT_COMMAND);
+  let _ = writeln!(s, "  if [[ $COMP_CWORD -gt 1 ]]; then");
+  let _ = writeln!(s, "    case \"${{COMP_WORDS[1]}}\" in");
+  let _ = writeln!(s, "      {}) cmd=\"${{COMP_WORDS[1]}}\";;", command_names.join("|"));
+  let _ = writeln!(s, "    esac");
+  let _ = writeln!(s, "  fi");
+  let _ = writeln!(s);
+  let _ = writeln!(s, "  case \"$cmd\" in");
+  for command in commands {
+    let _ = writeln!(s, "    {})", command.name);
+    // The argument of an option is completed depending on what it is.
+    let arguments = |filter: &dyn Fn(&Opt) -> bool| {
+      command.options.iter().filter(|o| filter(o)).flat_map(names).collect::<Vec<_>>()
+    };
+    let cases = [
+      (arguments(&Opt::takes_dict), format!("COMPREPLY=($(compgen -W \"$({})\" -- \"$cur\"))", LIST_DICTS)),
+      (arguments(&Opt::takes_file), "COMPREPLY=($(compgen -f -- \"$cur\"))".to_string()),
+      (arguments(&|o: &Opt| o.arg.is_some() && !o.takes_dict() && !o.takes_file()), "COMPREPLY=()".to_string()),
+    ];
+    if cases.iter().any(|(names, _)| !names.is_empty()) {
+      let _ = writeln!(s, "      case \"$prev\" in");
+      for (names, reply) in cases.iter().filter(|(names, _)| !names.is_empty()) {
+        let _ = writeln!(s, "        {}) {}; return;;", names.join("|"), reply);
+      }
+      let _ = writeln!(s, "      esac");
+    }
+    let opts = command.options.iter().flat_map(names).collect::<Vec<_>>();
+    let _ = writeln!(s, "      opts=\"{}\";;", opts.join(" "));
+  }
+  let _ = writeln!(s, "  esac");
+  let _ = writeln!(s);
+  let _ = writeln!(s, "  if [[ \"$cur\" == -* ]]; then");
+  let _ = writeln!(s, "    COMPREPLY=($(compgen -W \"$opts\" -- \"$cur\"))");
+  let _ = writeln!(s, "  elif [[ $COMP_CWORD -eq 1 ]]; then");
+  let _ = writeln!(s, "    COMPREPLY=($(compgen -W \"{}\" -- \"$cur\") $(compgen -f -- \"$cur\"))",
+                   command_names.join(" "));
+  let _ = writeln!(s, "  elif [[ \"$cmd\" == {} ]]; then", DEFAULT_COMMAND);
+  let _ = writeln!(s, "    local IFS=$'\\n'");
+  let _ = writeln!(s, "    COMPREPLY=($(_dictcc_cli_terms) $(compgen -f -- \"$cur\"))");
+  let _ = writeln!(s, "  elif [[ \"$cmd\" == help && $COMP_CWORD -eq 2 ]]; then");
+  let _ = writeln!(s, "    COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))", command_names.join(" "));
+  let _ = writeln!(s, "  else");
+  let _ = writeln!(s, "    COMPREPLY=($(compgen -f -- \"$cur\"))");
+  let _ = writeln!(s, "  fi");
+  let _ = writeln!(s, "}}");
+  let _ = writeln!(s, "complete -F _dictcc_cli {}", PROGRAM);
+  s
+}
+
+/// Escape a description for use in an option specification of zsh's
+/// `_arguments`, enclosed in single quotes.
+fn zsh_escape(text: &str) -> String {
+  text
+    .replace('\\', "\\\\")
+    .replace('[', "\\[")
+    .replace(']', "\\]")
+    .replace(':', "\\:")
+    .replace('\'', "'\\''")
+}
+
+/// Create the `_arguments` specification of an option.
+fn zsh_spec(option: &Opt) -> String {
+  let description = zsh_escape(&option.description);
+  let action = match option.arg {
+    Some(ref arg) if option.takes_dict() => format!(":{}:_dictcc_cli_dicts", arg),
+    Some(ref arg) if option.takes_file() => format!(":{}:_files", arg),
+    Some(ref arg) => format!(":{}: ", arg),
+    None => String::new(),
+  };
+  match option.short {
+    Some(short) => format!(
+      "'(-{short} --{long})'{{-{short},--{long}}}'[{description}]{action}'",
+      short = short, long = option.long, description = description, action = action,
+    ),
+    None => format!("'--{}[{}]{}'", option.long, description, action),
+  }
+}
+
+fn zsh(commands: &[Command]) -> String {
+  let mut s = String::new();
+  let _ = writeln!(s, "#compdef {}", PROGRAM);
+  let _ = writeln!(s);
+  let _ = writeln!(s, "_dictcc_cli_dicts() {{");
+  let _ = writeln!(s, "  local -a dicts");
+  let _ = writeln!(s, "  dicts=(${{(f)\"$({})\"}})", LIST_DICTS);
+  let _ = writeln!(s, "  _describe 'dictionary' dicts");
+  let _ = writeln!(s, "}}");
+  let _ = writeln!(s);
+  let _ = writeln!(s, "_dictcc_cli_terms() {{");
+  let _ = writeln!(s, "  local word");
+  let _ = writeln!(s, "  local -a args terms");
+  let _ = writeln!(s, "  for word in $words[2,CURRENT-1]; do");
+  let _ = writeln!(s, "    case $word in");
+  let _ = writeln!(s, "      *.db) args+=($word);;");
+  let _ = writeln!(s, "      -r|--reverse) args+=(-r);;");
+  let _ = writeln!(s, "    esac");
+  let _ = writeln!(s, "  done");
+  let _ = writeln!(s, "  terms=(${{(f)\"$({} $args -- $PREFIX 2>/dev/null)\"}})", COMPLETE_TERM);
+  let _ = writeln!(s, "  compadd -a terms");
+  let _ = writeln!(s, "}}");
+  let _ = writeln!(s);
+  let _ = writeln!(s, "_dictcc_cli_commands() {{");
+  let _ = writeln!(s, "  local -a commands");
+  let _ = writeln!(s, "  commands=(");
+  for command in commands {
+    let _ = writeln!(s, "    '{}:{}'", command.name, zsh_escape(&command.summary));
+  }
+  let _ = writeln!(s, "  )");
+  let _ = writeln!(s, "  _describe 'command' commands");
+  let _ = writeln!(s, "}}");
+  let _ = writeln!(s);
+  let _ = writeln!(s, "_dictcc_cli() {{");
+  let _ = writeln!(s, "  local cmd={}", DEFAULT_COMMAND);
+  let _ = writeln!(s, "  if (( CURRENT > 2 )); then");
+  let _ = writeln!(s, "    case $words[2] in");
+  let names = commands.iter().map(|c| c.name.as_str()).collect::<Vec<_>>();
+  let _ = writeln!(s, "      {})", names.join("|"));
+  let _ = writeln!(s, "        cmd=$words[2]");
+  let _ = writeln!(s, "        shift words");
+  let _ = writeln!(s, "        (( CURRENT-- ));;");
+  let _ = writeln!(s, "    esac");
+  let _ = writeln!(s, "  fi");
+  let _ = writeln!(s);
+  let _ = writeln!(s, "  case $cmd in");
+  for command in commands {
+    let _ = writeln!(s, "    {})", command.name);
+    let _ = writeln!(s, "      _arguments -s \\");
+    for option in &command.options {
+      let _ = writeln!(s, "        {} \\", zsh_spec(option));
+    }
+    // Without a command the first argument may also be one.
+    if command.name == DEFAULT_COMMAND {
+      let _ = writeln!(s, "        '1: :{{_dictcc_cli_commands; _files}}' \\");
+      let _ = writeln!(s, "        '*: :{{_dictcc_cli_terms; _files}}';;");
+    } else {
+      if command.name == "help" {
+        let _ = writeln!(s, "        '1: :_dictcc_cli_commands' \\");
+      }
+      let _ = writeln!(s, "        '*:file:_files';;");
+    }
+  }
+  let _ = writeln!(s, "  esac");
+  let _ = writeln!(s, "}}");
+  let _ = writeln!(s);
+  let _ = writeln!(s, "_dictcc_cli \"$@\"");
+  s
+}
+
+/// Quote a string for fish, using single quotes.
+fn fish_quote(text: &str) -> String {
+  format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn fish(commands: &[Command]) -> String {
+  let names = commands.iter().map(|c| c.name.as_str()).collect::<Vec<_>>();
+  // The options of the default command apply if no other command was
+  // given.
+  let others = names
+    .iter()
+    .filter(|n| **n != DEFAULT_COMMAND)
+    .cloned()
+    .collect::<Vec<_>>();
+
+  let mut s = String::new();
+  let _ = writeln!(s, "# fish completion for {}", PROGRAM);
+  let _ = writeln!(s, "function __dictcc_cli_terms");
+  let _ = writeln!(s, "  set -l args");
+  let _ = writeln!(s, "  for word in (commandline -opc)[2..-1]");
+  let _ = writeln!(s, "    switch $word");
+  let _ = writeln!(s, "      case '*.db'");
+  let _ = writeln!(s, "        set -a args $word");
+  let _ = writeln!(s, "      case -r --reverse");
+  let _ = writeln!(s, "        set -a args -r");
+  let _ = writeln!(s, "    end");
+  let _ = writeln!(s, "  end");
+  let _ = writeln!(s, "  {} $args -- (commandline -ct) 2>/dev/null", COMPLETE_TERM);
+  let _ = writeln!(s, "end");
+  let _ = writeln!(s);
+  let _ = writeln!(s, "set -l commands {}", names.join(" "));
+  for command in commands {
+    let _ = writeln!(
+      s,
+      "complete -c {} -n \"not __fish_seen_subcommand_from $commands\" -a {} -d {}",
+      PROGRAM, command.name, fish_quote(&command.summary),
+    );
+  }
+  let _ = writeln!(
+    s,
+    "complete -c {} -n \"__fish_seen_subcommand_from help\" -f -a \"$commands\"",
+    PROGRAM,
+  );
+  let _ = writeln!(
+    s,
+    "complete -c {} -n \"not __fish_seen_subcommand_from {}\" -a \"(__dictcc_cli_terms)\"",
+    PROGRAM, others.join(" "),
+  );
+
+  for command in commands {
+    let condition = if command.name == DEFAULT_COMMAND {
+      format!("not __fish_seen_subcommand_from {}", others.join(" "))
+    } else {
+      format!("__fish_seen_subcommand_from {}", command.name)
+    };
+    for option in &command.options {
+      let mut line = format!("complete -c {} -n \"{}\"", PROGRAM, condition);
+      if let Some(short) = option.short {
+        let _ = write!(line, " -s {}", short);
+      }
+      let _ = write!(line, " -l {}", option.long);
+      if option.takes_dict() {
+        let _ = write!(line, " -x -a \"({})\"", LIST_DICTS);
+      } else if option.takes_file() {
+        line.push_str(" -r -F");
+      } else if option.arg.is_some() {
+        line.push_str(" -x");
+      }
+      let _ = writeln!(s, "{} -d {}", line, fish_quote(&option.description));
+    }
+  }
+  s
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use getopts;
+
+  fn commands() -> Vec<Command> {
+    let mut opts = getopts::Options::new();
+    opts.optflag("r", "reverse", "Perform reverse lookup, i.e., instead \
+                                  from mapping from lang1 to lang2 map \
+                                  from lang2 to lang1");
+    opts.optflag("", "auto", "Detect the language");
+    opts.optflag("t", "transliterate", "Treat 'ae' as [ä]");
+    opts.optopt("", "dict", "Use the database registered under the \
+                            given name", "NAME");
+    opts.optopt("", "save-results", "Save the results", "FILE");
+    let translate = parse_options(&opts.usage("Usage: dictcc-cli [options] <word>..."));
+
+    let mut opts = getopts::Options::new();
+    opts.optflag("h", "help", "Print the help of the command");
+    let help = parse_options(&opts.usage("Usage: dictcc-cli help [<command>]"));
+
+    vec![
+      Command { name: "help".to_string(), summary: "Print the help".to_string(), options: help },
+      Command { name: "translate".to_string(), summary: "Translate".to_string(), options: translate },
+    ]
+  }
+
+  #[test]
+  fn parse_help() {
+    let commands = commands();
+    let options = &commands[1].options;
+    assert_eq!(options.len(), 5);
+    assert_eq!(options[0], Opt {
+      short: Some('r'),
+      long: "reverse".to_string(),
+      arg: None,
+      description: "Perform reverse lookup, i.e., instead from mapping from \
+                    lang1 to lang2 map from lang2 to lang1".to_string(),
+    });
+    assert_eq!(options[1].short, None);
+    assert_eq!(options[1].long, "auto");
+    // Long option names push the description onto the next line.
+    assert_eq!(options[2].long, "transliterate");
+    assert_eq!(options[2].description, "Treat 'ae' as [ä]");
+    assert_eq!(options[3].arg.as_deref(), Some("NAME"));
+    assert!(options[3].takes_dict());
+    assert!(options[4].takes_file());
+  }
+
+  #[test]
+  fn generate_scripts() {
+    let commands = commands();
+
+    let bash = script(Shell::Bash, &commands);
+    assert!(bash.contains("      help|translate) cmd=\"${COMP_WORDS[1]}\";;\n"));
+    assert!(bash.contains("        --dict) COMPREPLY=($(compgen -W \"$(dictcc-cli list-dicts \
+                           --names 2>/dev/null)\" -- \"$cur\")); return;;\n"));
+    assert!(bash.contains("        --save-results) COMPREPLY=($(compgen -f -- \"$cur\")); return;;\n"));
+    assert!(bash.contains("      opts=\"-r --reverse --auto -t --transliterate \
+                           --dict --save-results\";;\n"));
+
+    assert!(bash.contains("    COMPREPLY=($(_dictcc_cli_terms) $(compgen -f -- \"$cur\"))\n"));
+
+    let zsh = script(Shell::Zsh, &commands);
+    assert!(zsh.contains("        '*: :{_dictcc_cli_terms; _files}';;\n"));
+    assert!(zsh.starts_with("#compdef dictcc-cli\n"));
+    assert!(zsh.contains("'(-t --transliterate)'{-t,--transliterate}'[Treat '\\''ae'\\'' as \\[ä\\]]'"));
+    assert!(zsh.contains("'--dict[Use the database registered under the given name]:NAME:_dictcc_cli_dicts'"));
+
+    let fish = script(Shell::Fish, &commands);
+    assert!(fish.contains("complete -c dictcc-cli -n \"not __fish_seen_subcommand_from help\" \
+                           -s r -l reverse -d "));
+    assert!(fish.contains("-l dict -x -a \"(dictcc-cli list-dicts --names 2>/dev/null)\""));
+    assert!(fish.contains("-l transliterate -d 'Treat \\'ae\\' as [ä]'"));
+    assert!(fish.contains("complete -c dictcc-cli -n \"not __fish_seen_subcommand_from help\" \
+                           -a \"(__dictcc_cli_terms)\"\n"));
+  }
+}
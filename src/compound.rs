@@ -0,0 +1,191 @@
+// compound.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Splitting of (German) compound words into their constituents.
+
+use std::collections::HashSet;
+
+use sqlite;
+
+use term::headword;
+use Error;
+use Result;
+use SEARCH_TBL;
+
+/// The minimum length (in characters) of a constituent.
+const MIN_PART_LEN: usize = 3;
+/// Linking elements ("Fugenelemente") that may appear between the
+/// constituents of a compound, e.g., the 's' in "Liebesbrief".
+const LINKS: [&str; 4] = ["s", "es", "n", "en"];
+
+
+/// A constituent of a compound word.
+#[derive(Debug, PartialEq)]
+pub struct Part {
+  /// The text of the constituent as it appears in the compound,
+  /// including any linking element.
+  pub text: String,
+  /// The dictionary word the constituent corresponds to.
+  pub word: String,
+}
+
+
+/// Load the set of (lower case) single word terms from the given column.
+pub fn load_words(connection: &sqlite::Connection, column: &str) -> Result<HashSet<String>> {
+  let select = format!("SELECT DISTINCT {col} FROM {tbl}", col = column, tbl = SEARCH_TBL);
+  let mut cursor = connection.prepare(select)?.cursor();
+  let mut words = HashSet::new();
+
+  while let Some(row) = cursor.next()? {
+    let term = row[0].as_string().ok_or_else(|| Error::InvalidSchema(format!(
+      "Invalid column in result: {:?}",
+      row
+    )))?;
+    let word = headword(term);
+    if !word.is_empty() && !word.contains(' ') {
+      words.insert(word.to_lowercase());
+    }
+  }
+  Ok(words)
+}
+
+/// Check whether `text` is a known word, possibly followed by a linking
+/// element, and return the word.
+fn lookup(text: &str, words: &HashSet<String>, last: bool) -> Option<String> {
+  if text.chars().count() < MIN_PART_LEN {
+    return None;
+  }
+  if words.contains(text) {
+    return Some(text.to_string());
+  }
+  // The last constituent never carries a linking element.
+  if last {
+    return None;
+  }
+
+  LINKS
+    .iter()
+    .filter_map(|link| text.strip_suffix(link))
+    .find(|word| word.chars().count() >= MIN_PART_LEN && words.contains(*word))
+    .map(|word| word.to_string())
+}
+
+/// Split a compound word into known words.
+///
+/// The split with the fewest constituents is chosen. `None` is returned
+/// if the word cannot be split into at least two known words.
+pub fn split(compound: &str, words: &HashSet<String>) -> Option<Vec<Part>> {
+  let chars = compound.char_indices().map(|(i, _)| i).chain(Some(compound.len())).collect::<Vec<_>>();
+  let lower = compound.to_lowercase();
+  // Lower casing could change the byte length of the string, in which
+  // case our indices would be off. We do not bother with such words.
+  if lower.len() != compound.len() {
+    return None;
+  }
+
+  let n = chars.len() - 1;
+  // best[i] contains the best split of the first i characters, as a
+  // list of (start, end, word) triples.
+  let mut best: Vec<Option<Vec<(usize, usize, String)>>> = vec![None; n + 1];
+  best[0] = Some(Vec::new());
+
+  for end in 1..=n {
+    for start in 0..end {
+      let prefix = match best[start] {
+        Some(ref prefix) => prefix,
+        None => continue,
+      };
+      let text = &lower[chars[start]..chars[end]];
+      if let Some(word) = lookup(text, words, end == n) {
+        let better = match best[end] {
+          Some(ref current) => prefix.len() + 1 < current.len(),
+          None => true,
+        };
+        if better {
+          let mut split = prefix.clone();
+          split.push((chars[start], chars[end], word));
+          best[end] = Some(split);
+        }
+      }
+    }
+  }
+
+  match best[n].take() {
+    Some(ref split) if split.len() >= 2 => {
+      Some(split
+        .iter()
+        .map(|&(start, end, ref word)| Part {
+          text: compound[start..end].to_string(),
+          word: word.clone(),
+        })
+        .collect())
+    },
+    _ => None,
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::path;
+
+  use open;
+  use TERM1_COL;
+
+  fn part(text: &str, word: &str) -> Part {
+    Part {
+      text: text.to_string(),
+      word: word.to_string(),
+    }
+  }
+
+  #[test]
+  fn split_compound() {
+    let words = ["haus", "tür", "schlüssel", "haustür", "liebe", "brief"]
+      .iter()
+      .map(|w| w.to_string())
+      .collect::<HashSet<_>>();
+
+    assert_eq!(
+      split("Haustürschlüssel", &words),
+      Some(vec![part("Haustür", "haustür"), part("schlüssel", "schlüssel")])
+    );
+    assert_eq!(
+      split("Liebesbrief", &words),
+      Some(vec![part("Liebes", "liebe"), part("brief", "brief")])
+    );
+    assert_eq!(split("Haus", &words), None);
+    assert_eq!(split("Hausboot", &words), None);
+  }
+
+  #[test]
+  fn split_with_database() {
+    let connection = open(path::Path::new("./test/test.db")).unwrap();
+    let words = load_words(&connection, TERM1_COL).unwrap();
+    assert!(words.contains("inhalt"));
+    assert!(!words.contains("jdn./etw. knechten"));
+
+    assert_eq!(
+      split("Liebesärger", &words),
+      Some(vec![part("Liebes", "liebe"), part("ärger", "ärger")])
+    );
+  }
+}
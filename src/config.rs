@@ -0,0 +1,1004 @@
+// config.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Support for a configuration file.
+//!
+//! The configuration is stored in `$XDG_CONFIG_HOME/dictcc-cli/config.toml`
+//! and uses a subset of TOML: tables (`[name]`), and `key = value` pairs
+//! with strings, integers, floats, booleans, arrays, and inline tables
+//! as values. Each value has to fit on a single line. Values are
+//! represented using our JSON type.
+//!
+//! The effective configuration is made up of several layers: built-in
+//! defaults, the file, environment variables (`DICTCC_` followed by the
+//! upper case key with '.' and '-' replaced by '_', e.g.,
+//! `DICTCC_TYPE_ORDER`), and command line overrides, with later layers
+//! taking precedence.
+//!
+//! Settings can be overridden for individual databases in a
+//! `[databases.<name>]` table, with the name being the database's file
+//! name without extension. Such overrides rank above the file's
+//! top-level settings, but below environment variables. A `path` in
+//! the table registers the database under the table's name instead,
+//! allowing it to be selected by that name.
+//!
+//! The `[aliases]` table defines sets of options under a name, e.g.,
+//! `de = {dict = "en-de", reverse = true}`, which `@de` on the command
+//! line expands to.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::iter;
+use std::path;
+use std::result;
+use std::str;
+use std::sync::OnceLock;
+
+use json::Value;
+use Error;
+use Result;
+
+/// The name of the directory containing our configuration.
+const CONFIG_DIR: &str = "dictcc-cli";
+/// The name of the configuration file.
+const CONFIG_FILE: &str = "config.toml";
+/// The prefix of environment variables overriding settings.
+const ENV_PREFIX: &str = "DICTCC_";
+/// The key registering a database under the name of its table.
+const PATH_KEY: &str = "path";
+/// The table defining aliases.
+const ALIASES_KEY: &str = "aliases";
+/// The settings we know about, along with their default values, if any.
+const SETTINGS: [(&str, Option<&str>); 16] = [
+  ("reverse", Some("false")),
+  ("languages", None),
+  ("type-order", Some("[]")),
+  ("rank.usage-weight", Some("1.0")),
+  ("rank.length-weight", Some("0.1")),
+  ("user-entries", Some(r#""prefer-user""#)),
+  ("filters.colloquial", Some(r#"["ugs.", "coll.", "fam.", "sl.", "slang"]"#)),
+  ("filters.vulgar", Some(r#"["vulg."]"#)),
+  ("filters.archaic", Some(r#"["veraltet", "veraltend", "archaic", "obs.", "dated"]"#)),
+  ("hooks.on_lookup", None),
+  ("hooks.on_no_result", None),
+  ("cache-results", None),
+  ("sqlite.cache-size", None),
+  ("sqlite.in-memory", None),
+  ("sqlite.mmap-size", None),
+  ("sqlite.temp-store", None),
+];
+
+
+/// Retrieve the path to the configuration file.
+pub fn path() -> Option<path::PathBuf> {
+  let dir = match env::var_os("XDG_CONFIG_HOME") {
+    Some(ref dir) if !dir.is_empty() => path::PathBuf::from(dir),
+    _ => path::PathBuf::from(env::var_os("HOME")?).join(".config"),
+  };
+  Some(dir.join(CONFIG_DIR).join(CONFIG_FILE))
+}
+
+
+/// Where the value of a setting originates from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Origin {
+  /// The built-in default.
+  Default,
+  /// The configuration file.
+  File,
+  /// The overrides for a database in the configuration file.
+  Database,
+  /// An environment variable.
+  Env,
+  /// A command line option.
+  Cli,
+}
+
+impl Origin {
+  /// Retrieve the name of the origin.
+  pub fn name(self) -> &'static str {
+    match self {
+      Origin::Default => "default",
+      Origin::File => "file",
+      Origin::Database => "database",
+      Origin::Env => "env",
+      Origin::Cli => "cli",
+    }
+  }
+}
+
+
+/// The program's configuration.
+#[derive(Debug, PartialEq)]
+pub struct Config {
+  /// The top-level table.
+  root: Value,
+  /// The origins of values not originating from the file, by key.
+  origins: BTreeMap<String, Origin>,
+}
+
+impl Default for Config {
+  fn default() -> Config {
+    Config {
+      root: Value::Object(Vec::new()),
+      origins: BTreeMap::new(),
+    }
+  }
+}
+
+impl Config {
+  /// Load the configuration from its default location, along with the
+  /// overrides from the environment. A missing file results in an
+  /// empty configuration.
+  pub fn load() -> Result<Config> {
+    let mut config = match path() {
+      Some(path) => Config::load_from(&path)?,
+      None => Config::default(),
+    };
+
+    for &(key, _) in &SETTINGS {
+      let var = env_var(key);
+      if let Some(value) = env::var_os(&var) {
+        let value = value
+          .into_string()
+          .map_err(|_| Error::Error(format!("{} is not valid UTF-8", var)))?;
+        config.set(key, parse_value(&value), Origin::Env);
+      }
+    }
+    Ok(config)
+  }
+
+  /// Load the configuration from the given file.
+  pub fn load_from(path: &path::Path) -> Result<Config> {
+    match fs::read_to_string(path) {
+      Ok(content) => parse(&content).map_err(|e| {
+        Error::Error(format!("{}: {}", path.display(), e))
+      }),
+      Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+      Err(e) => Err(Error::Io(format!("Failed to read {}", path.display()), e)),
+    }
+  }
+
+  /// Apply an override in the form `key=value`.
+  pub fn set_from(&mut self, setting: &str, origin: Origin) -> Result<()> {
+    let mut parts = setting.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+      (Some(key), Some(value)) if !key.trim().is_empty() => {
+        self.set(key.trim(), parse_value(value.trim()), origin);
+        Ok(())
+      },
+      _ => Err(Error::Error(format!("Invalid setting: {} (expected KEY=VALUE)", setting))),
+    }
+  }
+
+  /// Apply the overrides configured for the database at the given
+  /// path.
+  pub fn select_database(&mut self, database: &path::Path) {
+    // A database registered under a name is configured by the table of
+    // that name, all others by the one named after the file.
+    let registered = self
+      .databases()
+      .into_iter()
+      .find(|(_, path)| same_file(path, database))
+      .map(|(name, _)| name);
+    let stem = database.file_stem().and_then(|s| s.to_str());
+    let name = match registered.as_deref().or(stem) {
+      Some(name) => name.to_string(),
+      None => return,
+    };
+    // Note that we cannot use `get` here, as the name may contain dots.
+    let overrides = match self.root.get("databases").and_then(|d| d.get(&name)) {
+      Some(overrides) => overrides.clone(),
+      None => return,
+    };
+
+    let mut leaves = Vec::new();
+    flatten(&overrides, String::new(), &mut leaves);
+    for (key, value) in leaves {
+      if key != PATH_KEY && self.origins.get(&key) != Some(&Origin::Env) {
+        self.set(&key, value.clone(), Origin::Database);
+      }
+    }
+  }
+
+  /// Retrieve the databases registered under a name, along with their
+  /// paths, sorted by name.
+  pub fn databases(&self) -> Vec<(String, path::PathBuf)> {
+    let mut databases = match self.root.get("databases") {
+      Some(Value::Object(tables)) => tables
+        .iter()
+        .filter_map(|(name, table)| {
+          let path = table.get(PATH_KEY)?.as_str()?;
+          Some((name.clone(), expand_home(path)))
+        })
+        .collect::<Vec<_>>(),
+      _ => Vec::new(),
+    };
+    databases.sort();
+    databases
+  }
+
+  /// Retrieve the path of the database registered under the given
+  /// name.
+  pub fn database(&self, name: &str) -> Result<path::PathBuf> {
+    let databases = self.databases();
+    match databases.iter().find(|(n, _)| n == name) {
+      Some((_, path)) => Ok(path.clone()),
+      None if databases.is_empty() => Err(Error::Error(format!(
+        "Unknown dictionary: {} (none are configured)", name
+      ))),
+      None => {
+        let names = databases.into_iter().map(|(name, _)| name).collect::<Vec<_>>();
+        Err(Error::Error(format!(
+          "Unknown dictionary: {} (configured are: {})", name, names.join(", ")
+        )))
+      },
+    }
+  }
+
+  /// Expand the alias with the given name into the command line
+  /// options it stands for.
+  ///
+  /// Each key of the alias is the long name of an option. A value of
+  /// `true` enables a flag, while `false` omits it. Other values are
+  /// passed as the option's argument, with arrays specifying the option
+  /// once for each element.
+  pub fn alias(&self, name: &str) -> Result<Vec<String>> {
+    let aliases = match self.root.get(ALIASES_KEY) {
+      Some(Value::Object(aliases)) => aliases.as_slice(),
+      _ => &[],
+    };
+    let members = match aliases.iter().find(|(n, _)| n == name) {
+      Some((_, Value::Object(members))) => members,
+      Some(_) => {
+        return Err(Error::Error(format!("Alias {} must be a table of options", name)))
+      },
+      None if aliases.is_empty() => {
+        return Err(Error::Error(format!("Unknown alias: {} (none are configured)", name)))
+      },
+      None => {
+        let names = aliases.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+        return Err(Error::Error(format!(
+          "Unknown alias: {} (configured are: {})", name, names.join(", ")
+        )))
+      },
+    };
+
+    let argument = |key: &str, value: &Value| match *value {
+      Value::String(ref s) => Ok(s.clone()),
+      Value::Number(n) => Ok(n.to_string()),
+      _ => Err(Error::Error(format!("Invalid value for {} in alias {}", key, name))),
+    };
+
+    let mut args = Vec::new();
+    for (key, value) in members {
+      let option = format!("--{}", key);
+      match *value {
+        Value::Bool(true) => args.push(option),
+        Value::Bool(false) => (),
+        Value::Array(ref values) => {
+          for value in values {
+            args.push(option.clone());
+            args.push(argument(key, value)?);
+          }
+        },
+        ref value => {
+          args.push(option);
+          args.push(argument(key, value)?);
+        },
+      }
+    }
+    Ok(args)
+  }
+
+  /// Set the value with the given (dotted) key, replacing any existing
+  /// one.
+  fn set(&mut self, key: &str, value: Value, origin: Origin) {
+    let keys = key.split('.').map(|k| k.to_string()).collect::<Vec<_>>();
+    let _ = remove(&mut self.root, &keys);
+    // Removal made room for the value, so insertion can only fail if a
+    // key along the path denotes a value other than a table. We make
+    // room for the value in that case as well.
+    if insert(&mut self.root, &keys, value.clone()).is_err() {
+      for i in 1..keys.len() {
+        let _ = remove(&mut self.root, &keys[..i]);
+      }
+      let _ = insert(&mut self.root, &keys, value);
+    }
+    self.origins.insert(key.to_string(), origin);
+  }
+
+  /// Retrieve the value with the given (dotted) key, e.g.,
+  /// "table.key", falling back to its default.
+  pub fn get(&self, key: &str) -> Option<&Value> {
+    key
+      .split('.')
+      .try_fold(&self.root, |value, key| value.get(key))
+      .or_else(|| defaults().get(key))
+  }
+
+  /// Retrieve all settings along with their values and origins, sorted
+  /// by key.
+  pub fn effective(&self) -> Vec<(String, Value, Origin)> {
+    let mut settings = BTreeMap::new();
+    for (key, value) in defaults() {
+      settings.insert(key.clone(), (value.clone(), Origin::Default));
+    }
+
+    let mut leaves = Vec::new();
+    flatten(&self.root, String::new(), &mut leaves);
+    for (key, value) in leaves {
+      let origin = self.origins.get(&key).cloned().unwrap_or(Origin::File);
+      settings.insert(key, (value.clone(), origin));
+    }
+
+    settings
+      .into_iter()
+      .map(|(key, (value, origin))| (key, value, origin))
+      .collect()
+  }
+
+  /// Retrieve the boolean with the given key.
+  pub fn get_bool(&self, key: &str) -> Result<Option<bool>> {
+    match self.get(key) {
+      Some(&Value::Bool(value)) => Ok(Some(value)),
+      Some(_) => Err(Error::Error(format!("Configuration value {} must be a boolean", key))),
+      None => Ok(None),
+    }
+  }
+
+  /// Retrieve the number with the given key.
+  pub fn get_number(&self, key: &str) -> Result<Option<f64>> {
+    match self.get(key) {
+      Some(&Value::Number(value)) => Ok(Some(value)),
+      Some(_) => Err(Error::Error(format!("Configuration value {} must be a number", key))),
+      None => Ok(None),
+    }
+  }
+
+  /// Retrieve the string with the given key.
+  pub fn get_string(&self, key: &str) -> Result<Option<&str>> {
+    match self.get(key) {
+      Some(value) => value.as_str().map(Some).ok_or_else(|| {
+        Error::Error(format!("Configuration value {} must be a string", key))
+      }),
+      None => Ok(None),
+    }
+  }
+
+  /// Retrieve the array of strings with the given key.
+  pub fn get_strings(&self, key: &str) -> Result<Option<Vec<String>>> {
+    let value = match self.get(key) {
+      Some(value) => value,
+      None => return Ok(None),
+    };
+    let strings = value
+      .as_array()
+      .and_then(|a| a.iter().map(|v| v.as_str().map(|s| s.to_string())).collect());
+    match strings {
+      Some(strings) => Ok(Some(strings)),
+      None => Err(Error::Error(format!("Configuration value {} must be an array of strings", key))),
+    }
+  }
+}
+
+
+/// Expand a leading "~/" of a path to the user's home directory.
+fn expand_home(path: &str) -> path::PathBuf {
+  match (path.strip_prefix("~/"), env::var_os("HOME")) {
+    (Some(rest), Some(home)) => path::PathBuf::from(home).join(rest),
+    _ => path::PathBuf::from(path),
+  }
+}
+
+/// Check whether two paths refer to the same file.
+fn same_file(path1: &path::Path, path2: &path::Path) -> bool {
+  match (fs::canonicalize(path1), fs::canonicalize(path2)) {
+    (Ok(path1), Ok(path2)) => path1 == path2,
+    _ => path1 == path2,
+  }
+}
+
+/// Retrieve the name of the environment variable overriding a setting.
+pub fn env_var(key: &str) -> String {
+  let name = key.replace(['.', '-'], "_").to_uppercase();
+  format!("{}{}", ENV_PREFIX, name)
+}
+
+/// Retrieve the default values of all settings.
+fn defaults() -> &'static BTreeMap<String, Value> {
+  static DEFAULTS: OnceLock<BTreeMap<String, Value>> = OnceLock::new();
+  DEFAULTS.get_or_init(|| {
+    SETTINGS
+      .iter()
+      .filter_map(|&(key, default)| default.map(|d| (key.to_string(), parse_value(d))))
+      .collect()
+  })
+}
+
+/// Collect the (dotted) keys of all non-table values.
+fn flatten<'v>(value: &'v Value, prefix: String, leaves: &mut Vec<(String, &'v Value)>) {
+  match *value {
+    Value::Object(ref members) => {
+      for (key, value) in members {
+        let key = if prefix.is_empty() {
+          key.clone()
+        } else {
+          format!("{}.{}", prefix, key)
+        };
+        flatten(value, key, leaves);
+      }
+    },
+    _ => leaves.push((prefix, value)),
+  }
+}
+
+/// Remove the value with the given keys from a table.
+fn remove(table: &mut Value, keys: &[String]) -> Option<Value> {
+  let members = match *table {
+    Value::Object(ref mut members) => members,
+    _ => return None,
+  };
+  let (key, rest) = keys.split_first()?;
+  let i = members.iter().position(|(k, _)| k == key)?;
+  if rest.is_empty() {
+    Some(members.remove(i).1)
+  } else {
+    remove(&mut members[i].1, rest)
+  }
+}
+
+/// Insert a value into a table, creating intermediate tables as
+/// necessary.
+fn insert(table: &mut Value, keys: &[String], value: Value) -> result::Result<(), String> {
+  let members = match *table {
+    Value::Object(ref mut members) => members,
+    _ => return Err(format!("{} is not a table", keys[0])),
+  };
+
+  let (key, rest) = keys.split_first().expect("no key given");
+  match members.iter().position(|(k, _)| k == key) {
+    Some(_) if rest.is_empty() => Err(format!("Duplicate key {}", key)),
+    Some(i) => insert(&mut members[i].1, rest, value),
+    None if rest.is_empty() => {
+      members.push((key.clone(), value));
+      Ok(())
+    },
+    None => {
+      members.push((key.clone(), Value::Object(Vec::new())));
+      let last = members.len() - 1;
+      insert(&mut members[last].1, rest, value)
+    },
+  }
+}
+
+
+struct Parser<'a> {
+  chars: iter::Peekable<str::Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+  fn skip_whitespace(&mut self) {
+    while let Some(&c) = self.chars.peek() {
+      if c == ' ' || c == '\t' {
+        self.chars.next();
+      } else {
+        break;
+      }
+    }
+  }
+
+  fn expect(&mut self, expected: char) -> result::Result<(), String> {
+    self.skip_whitespace();
+    match self.chars.next() {
+      Some(c) if c == expected => Ok(()),
+      Some(c) => Err(format!("Expected '{}' but found '{}'", expected, c)),
+      None => Err(format!("Expected '{}'", expected)),
+    }
+  }
+
+  /// Check that nothing but a comment is left.
+  fn end(&mut self) -> result::Result<(), String> {
+    self.skip_whitespace();
+    match self.chars.peek() {
+      None | Some(&'#') => Ok(()),
+      Some(c) => Err(format!("Unexpected '{}'", c)),
+    }
+  }
+
+  fn key(&mut self) -> result::Result<String, String> {
+    self.skip_whitespace();
+    match self.chars.peek() {
+      Some(&'"') | Some(&'\'') => self.string(),
+      _ => {
+        let mut key = String::new();
+        while let Some(&c) = self.chars.peek() {
+          if c.is_alphanumeric() || c == '_' || c == '-' {
+            key.push(c);
+            self.chars.next();
+          } else {
+            break;
+          }
+        }
+        if key.is_empty() {
+          Err("Expected a key".to_string())
+        } else {
+          Ok(key)
+        }
+      },
+    }
+  }
+
+  /// Parse a dotted key, e.g., `table.key`.
+  fn keys(&mut self) -> result::Result<Vec<String>, String> {
+    let mut keys = vec![self.key()?];
+    loop {
+      self.skip_whitespace();
+      if self.chars.peek() != Some(&'.') {
+        return Ok(keys);
+      }
+      self.chars.next();
+      keys.push(self.key()?);
+    }
+  }
+
+  fn string(&mut self) -> result::Result<String, String> {
+    let quote = self.chars.next();
+    let literal = quote == Some('\'');
+    let mut string = String::new();
+
+    loop {
+      match self.chars.next() {
+        Some(c) if Some(c) == quote => return Ok(string),
+        Some('\\') if !literal => match self.chars.next() {
+          Some('n') => string.push('\n'),
+          Some('t') => string.push('\t'),
+          Some('"') => string.push('"'),
+          Some('\\') => string.push('\\'),
+          Some(c) => return Err(format!("Invalid escape sequence '\\{}'", c)),
+          None => return Err("Unterminated string".to_string()),
+        },
+        Some(c) => string.push(c),
+        None => return Err("Unterminated string".to_string()),
+      }
+    }
+  }
+
+  fn value(&mut self) -> result::Result<Value, String> {
+    self.skip_whitespace();
+    match self.chars.peek() {
+      Some(&'"') | Some(&'\'') => Ok(Value::String(self.string()?)),
+      Some(&'[') => {
+        self.chars.next();
+        let mut values = Vec::new();
+        loop {
+          self.skip_whitespace();
+          if self.chars.peek() == Some(&']') {
+            self.chars.next();
+            return Ok(Value::Array(values));
+          }
+          values.push(self.value()?);
+          self.skip_whitespace();
+          match self.chars.next() {
+            Some(',') => (),
+            Some(']') => return Ok(Value::Array(values)),
+            _ => return Err("Expected ',' or ']' in array".to_string()),
+          }
+        }
+      },
+      Some(&'{') => {
+        self.chars.next();
+        let mut table = Value::Object(Vec::new());
+        loop {
+          self.skip_whitespace();
+          if self.chars.peek() == Some(&'}') {
+            self.chars.next();
+            return Ok(table);
+          }
+          let keys = self.keys()?;
+          self.expect('=')?;
+          let value = self.value()?;
+          insert(&mut table, &keys, value)?;
+          self.skip_whitespace();
+          match self.chars.next() {
+            Some(',') => (),
+            Some('}') => return Ok(table),
+            _ => return Err("Expected ',' or '}' in inline table".to_string()),
+          }
+        }
+      },
+      _ => {
+        let mut word = String::new();
+        while let Some(&c) = self.chars.peek() {
+          if c.is_alphanumeric() || c == '-' || c == '+' || c == '.' || c == '_' {
+            word.push(c);
+            self.chars.next();
+          } else {
+            break;
+          }
+        }
+        match word.as_str() {
+          "true" => Ok(Value::Bool(true)),
+          "false" => Ok(Value::Bool(false)),
+          _ => word
+            .replace('_', "")
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| format!("Invalid value '{}'", word)),
+        }
+      },
+    }
+  }
+}
+
+/// Render a value in TOML syntax.
+pub fn to_toml(value: &Value) -> String {
+  match *value {
+    Value::Null => "\"\"".to_string(),
+    Value::Array(ref values) => {
+      let values = values.iter().map(to_toml).collect::<Vec<_>>();
+      format!("[{}]", values.join(", "))
+    },
+    Value::Object(ref members) => {
+      let members = members
+        .iter()
+        .map(|(k, v)| format!("{} = {}", key_to_toml(k), to_toml(v)))
+        .collect::<Vec<_>>();
+      format!("{{{}}}", members.join(", "))
+    },
+    // The JSON representation of the remaining types is valid TOML.
+    _ => value.to_string(),
+  }
+}
+
+/// Render a key in TOML syntax, quoting it if necessary.
+fn key_to_toml(key: &str) -> String {
+  if !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+    key.to_string()
+  } else {
+    Value::from(key).to_string()
+  }
+}
+
+/// Parse a single value given in TOML syntax. Input not forming a valid
+/// value is treated as a string, to spare users the need for quoting.
+pub fn parse_value(value: &str) -> Value {
+  let mut parser = Parser {
+    chars: value.chars().peekable(),
+  };
+  match parser.value() {
+    Ok(parsed) if parser.chars.peek().is_none() => parsed,
+    _ => Value::from(value),
+  }
+}
+
+/// Set a value in the content of a configuration file, returning the
+/// updated content.
+///
+/// Existing lines are edited in place, preserving comments and the
+/// remaining formatting.
+pub fn set_in(content: &str, key: &str, value: &Value) -> Result<String> {
+  let target = key.split('.').map(|k| k.to_string()).collect::<Vec<_>>();
+  let mut lines = content.lines().map(|l| l.to_string()).collect::<Vec<_>>();
+  // The table currently being parsed and the index of the last line
+  // belonging to each table we encountered.
+  let mut table = Vec::new();
+  let mut ends = vec![(Vec::new(), None)];
+
+  for (i, line) in content.lines().enumerate() {
+    let trimmed = line.trim();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+      continue;
+    }
+
+    let mut parser = Parser {
+      chars: trimmed.chars().peekable(),
+    };
+    if trimmed.starts_with('[') {
+      parser.chars.next();
+      table = parser.keys().map_err(|e| {
+        Error::Error(format!("Invalid configuration in line {}: {}", i + 1, e))
+      })?;
+      ends.push((table.clone(), Some(i)));
+      continue;
+    }
+
+    let keys = parser.keys().map_err(|e| {
+      Error::Error(format!("Invalid configuration in line {}: {}", i + 1, e))
+    })?;
+    if table.iter().chain(&keys).eq(target.iter()) {
+      lines[i] = format!("{} = {}", keys.join("."), to_toml(value));
+      return finish(lines, content.ends_with('\n'));
+    }
+    if let Some(end) = ends.iter_mut().rev().find(|(t, _)| *t == table) {
+      end.1 = Some(i);
+    }
+  }
+
+  // The key is not present yet. Add it to the table that shares the
+  // longest prefix with it, or to a newly created one.
+  let (prefix, end) = ends
+    .iter()
+    .filter(|(t, _)| t.len() < target.len() && target.starts_with(t))
+    .max_by_key(|(t, _)| t.len())
+    .cloned()
+    .expect("root table not present");
+
+  let line = format!("{} = {}", target[prefix.len()..].join("."), to_toml(value));
+  if prefix.is_empty() && target.len() > 1 {
+    lines.push(String::new());
+    lines.push(format!("[{}]", target[..target.len() - 1].join(".")));
+    lines.push(format!("{} = {}", target[target.len() - 1], to_toml(value)));
+  } else {
+    match end {
+      Some(end) => lines.insert(end + 1, line),
+      None => lines.insert(0, line),
+    }
+  }
+  finish(lines, true)
+}
+
+/// Join the lines of a configuration file, making sure the result
+/// remains valid.
+fn finish(lines: Vec<String>, newline: bool) -> Result<String> {
+  let mut content = lines.join("\n");
+  if newline {
+    content.push('\n');
+  }
+  parse(&content)?;
+  Ok(content)
+}
+
+/// Parse a configuration.
+pub fn parse(content: &str) -> Result<Config> {
+  let mut root = Value::Object(Vec::new());
+  let mut table = Vec::new();
+
+  for (i, line) in content.lines().enumerate() {
+    let trimmed = line.trim();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+      continue;
+    }
+
+    let mut parser = Parser {
+      chars: trimmed.chars().peekable(),
+    };
+    let result = if trimmed.starts_with('[') {
+      parser.chars.next();
+      parser.keys().and_then(|keys| {
+        parser.expect(']')?;
+        parser.end()?;
+        // Make sure the table exists even if it stays empty.
+        let exists = keys
+          .iter()
+          .try_fold(&root, |value, key| value.get(key))
+          .is_some();
+        if !exists {
+          insert(&mut root, &keys, Value::Object(Vec::new()))?;
+        }
+        table = keys;
+        Ok(())
+      })
+    } else {
+      parser.keys().and_then(|keys| {
+        parser.expect('=')?;
+        let value = parser.value()?;
+        parser.end()?;
+        let keys = table.iter().cloned().chain(keys).collect::<Vec<_>>();
+        insert(&mut root, &keys, value)
+      })
+    };
+
+    result.map_err(|e| Error::Error(format!("Invalid configuration in line {}: {}", i + 1, e)))?;
+  }
+  Ok(Config {
+    root,
+    origins: BTreeMap::new(),
+  })
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_config() {
+    let config = parse(
+      "# A comment\n\
+       type-order = [\"verb\", \"noun\"] # verbs first\n\
+       \n\
+       [alias]\n\
+       de = { dict = \"en-de\", reverse = true, limit = 20 }\n\
+       name = 'C:\\path'\n",
+    ).unwrap();
+
+    assert_eq!(
+      config.get_strings("type-order").unwrap(),
+      Some(vec!["verb".to_string(), "noun".to_string()])
+    );
+    assert_eq!(config.get("alias.de.dict"), Some(&Value::from("en-de")));
+    assert_eq!(config.get("alias.de.reverse"), Some(&Value::Bool(true)));
+    assert_eq!(config.get("alias.de.limit"), Some(&Value::Number(20.0)));
+    assert_eq!(config.get("alias.name"), Some(&Value::from("C:\\path")));
+    assert_eq!(config.get("alias.en"), None);
+    assert_eq!(config.get_string("alias.name").unwrap(), Some("C:\\path"));
+    assert!(config.get_string("alias.de").is_err());
+    assert_eq!(config.get_strings("missing").unwrap(), None);
+  }
+
+  #[test]
+  fn parse_invalid() {
+    assert!(parse("key").is_err());
+    assert!(parse("key = ").is_err());
+    assert!(parse("key = \"unterminated").is_err());
+    assert!(parse("key = 1\nkey = 2").is_err());
+    assert!(parse("[table").is_err());
+    assert!(parse("key = [1, 2").is_err());
+    assert!(parse("key = 1 2").is_err());
+    assert!(parse("key = 1").unwrap().get_strings("key").is_err());
+  }
+
+  #[test]
+  fn effective_config() {
+    let mut config = parse("type-order = [\"verb\"]\n[hooks]\non_lookup = 'true'").unwrap();
+    config.set_from("filters.vulgar=[\"vulg.\", \"derb\"]", Origin::Cli).unwrap();
+    config.set_from("hooks.on_lookup = logger", Origin::Env).unwrap();
+    assert!(config.set_from("type-order", Origin::Cli).is_err());
+
+    let effective = config
+      .effective()
+      .into_iter()
+      .map(|(key, value, origin)| format!("{} = {} ({})", key, to_toml(&value), origin.name()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      effective,
+      vec![
+        "filters.archaic = [\"veraltet\", \"veraltend\", \"archaic\", \"obs.\", \"dated\"] (default)",
+        "filters.colloquial = [\"ugs.\", \"coll.\", \"fam.\", \"sl.\", \"slang\"] (default)",
+        "filters.vulgar = [\"vulg.\", \"derb\"] (cli)",
+        "hooks.on_lookup = \"logger\" (env)",
+        "rank.length-weight = 0.1 (default)",
+        "rank.usage-weight = 1 (default)",
+        "reverse = false (default)",
+        "type-order = [\"verb\"] (file)",
+        "user-entries = \"prefer-user\" (default)",
+      ]
+    );
+    assert_eq!(config.get_strings("filters.archaic").unwrap().unwrap().len(), 5);
+    assert_eq!(env_var("hooks.on_no_result"), "DICTCC_HOOKS_ON_NO_RESULT");
+  }
+
+  #[test]
+  fn database_overrides() {
+    let mut config = parse(
+      "type-order = [\"verb\"]\n\
+       [rank]\n\
+       usage-weight = 2\n\
+       [databases.dictcc-fr]\n\
+       reverse = true\n\
+       type-order = [\"noun\"]\n\
+       rank.usage-weight = 3\n",
+    ).unwrap();
+    config.set_from("type-order=[\"adj\"]", Origin::Env).unwrap();
+
+    let mut other = parse("").unwrap();
+    other.root = config.root.clone();
+    other.select_database(path::Path::new("/tmp/dictcc-de.db"));
+    assert_eq!(other.get_bool("reverse").unwrap(), Some(false));
+    assert_eq!(other.get_number("rank.usage-weight").unwrap(), Some(2.0));
+
+    config.select_database(path::Path::new("/tmp/dictcc-fr.db"));
+    assert_eq!(config.get_bool("reverse").unwrap(), Some(true));
+    assert_eq!(config.get_number("rank.usage-weight").unwrap(), Some(3.0));
+    assert_eq!(config.get_number("rank.length-weight").unwrap(), Some(0.1));
+    // Environment variables still take precedence.
+    assert_eq!(config.get_strings("type-order").unwrap(), Some(vec!["adj".to_string()]));
+    assert!(config.get_bool("type-order").is_err());
+
+    let origins = config
+      .effective()
+      .into_iter()
+      .filter(|(key, _, _)| key == "reverse" || key == "rank.usage-weight")
+      .map(|(_, _, origin)| origin)
+      .collect::<Vec<_>>();
+    assert_eq!(origins, vec![Origin::Database, Origin::Database]);
+  }
+
+  #[test]
+  fn named_databases() {
+    let mut config = parse(
+      "reverse = false\n\
+       [databases.fr]\n\
+       path = \"/tmp/dictcc-fr.db\"\n\
+       reverse = true\n\
+       [databases.en]\n\
+       path = \"~/dictcc-lp1.db\"\n\
+       [databases.dictcc-es]\n\
+       reverse = true\n",
+    ).unwrap();
+    let home = path::PathBuf::from(env::var_os("HOME").unwrap());
+    assert_eq!(
+      config.databases(),
+      vec![
+        ("en".to_string(), home.join("dictcc-lp1.db")),
+        ("fr".to_string(), path::PathBuf::from("/tmp/dictcc-fr.db")),
+      ]
+    );
+    assert_eq!(config.database("fr").unwrap(), path::PathBuf::from("/tmp/dictcc-fr.db"));
+    let err = config.database("es").unwrap_err().to_string();
+    assert_eq!(err, "Unknown dictionary: es (configured are: en, fr)");
+    assert!(parse("").unwrap().database("fr").is_err());
+
+    config.select_database(&config.database("fr").unwrap());
+    assert_eq!(config.get_bool("reverse").unwrap(), Some(true));
+    assert_eq!(config.get("path"), None);
+  }
+
+  #[test]
+  fn expand_aliases() {
+    let config = parse(
+      "[aliases]
+       de = {dict = \"en-de\", reverse = true, bands = false, subject = [\"med\", \"sport\"]}
+       fast = {jobs = 4}
+       bad = {reverse = [true]}
+       scalar = 1
+",
+    ).unwrap();
+    let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+      config.alias("de").unwrap(),
+      args(&["--dict", "en-de", "--reverse", "--subject", "med", "--subject", "sport"])
+    );
+    assert_eq!(config.alias("fast").unwrap(), args(&["--jobs", "4"]));
+    assert!(config.alias("bad").is_err());
+    assert!(config.alias("scalar").is_err());
+    let err = config.alias("en").unwrap_err().to_string();
+    assert_eq!(err, "Unknown alias: en (configured are: de, fast, bad, scalar)");
+    let err = parse("").unwrap().alias("en").unwrap_err().to_string();
+    assert_eq!(err, "Unknown alias: en (none are configured)");
+  }
+
+  #[test]
+  fn set_in_file() {
+    let content = "# Types first\ntype-order = [\"verb\"] # comment\n\n[hooks]\non_lookup = 'a'\n";
+    let value = parse_value("[\"noun\"]");
+    assert_eq!(
+      set_in(content, "type-order", &value).unwrap(),
+      "# Types first\ntype-order = [\"noun\"]\n\n[hooks]\non_lookup = 'a'\n"
+    );
+    assert_eq!(
+      set_in(content, "hooks.on_no_result", &parse_value("b c")).unwrap(),
+      "# Types first\ntype-order = [\"verb\"] # comment\n\n[hooks]\non_lookup = 'a'\non_no_result = \"b c\"\n"
+    );
+    assert_eq!(
+      set_in("", "filters.vulgar", &parse_value("[]")).unwrap(),
+      "\n[filters]\nvulgar = []\n"
+    );
+    assert_eq!(set_in("[hooks]\n", "x", &parse_value("1")).unwrap(), "x = 1\n[hooks]\n");
+  }
+
+  #[test]
+  fn load_missing() {
+    let config = Config::load_from(path::Path::new("./test/does_not_exist.toml")).unwrap();
+    assert_eq!(config, Config::default());
+  }
+}
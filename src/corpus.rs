@@ -0,0 +1,306 @@
+// corpus.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Support for a bilingual corpus of aligned sentence pairs.
+//!
+//! The corpus is stored in a separate table inside the dictionary
+//! database. Each row contains a sentence in language 1 and its
+//! translation in language 2.
+
+use std::cmp;
+use std::collections::HashMap;
+
+use sqlite;
+
+use pattern;
+use term::find_word;
+use transaction;
+use Direction;
+use Error;
+use Result;
+
+/// The name of the table containing the corpus.
+pub const CORPUS_TBL: &str = "corpus";
+const SENTENCE1_COL: &str = "sentence1";
+const SENTENCE2_COL: &str = "sentence2";
+
+
+/// A pair of aligned sentences.
+#[derive(Debug, PartialEq)]
+pub struct Pair {
+  /// The sentence in the source language.
+  pub source: String,
+  /// The sentence in the target language.
+  pub target: String,
+}
+
+
+/// Retrieve the (source, target) sentence column names for a direction.
+fn columns(direction: &Direction) -> (&'static str, &'static str) {
+  match *direction {
+    Direction::Lang1ToLang2 => (SENTENCE1_COL, SENTENCE2_COL),
+    Direction::Lang2ToLang1 => (SENTENCE2_COL, SENTENCE1_COL),
+  }
+}
+
+/// Check whether the database contains a corpus.
+pub fn exists(connection: &sqlite::Connection) -> Result<bool> {
+  let mut statement = connection.prepare(
+    "SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND name=?"
+  )?;
+  statement.bind(1, CORPUS_TBL)?;
+  statement.next()?;
+  Ok(statement.read::<i64>(0)? > 0)
+}
+
+/// Import sentence pairs into the corpus.
+///
+/// The content is expected to contain one pair per line, with the two
+/// sentences separated by a tab. Empty lines are ignored. The function
+/// returns the number of imported pairs.
+pub fn import(connection: &sqlite::Connection, content: &str) -> Result<usize> {
+  connection.execute(format!(
+    "CREATE TABLE IF NOT EXISTS {tbl} (\
+       id INTEGER PRIMARY KEY NOT NULL, \
+       {s1} VARCHAR NOT NULL, \
+       {s2} VARCHAR NOT NULL\
+     )",
+    tbl = CORPUS_TBL, s1 = SENTENCE1_COL, s2 = SENTENCE2_COL,
+  ))?;
+  transaction(connection, || {
+    let mut statement = connection.prepare(format!(
+      "INSERT INTO {tbl} ({s1}, {s2}) VALUES (?, ?)",
+      tbl = CORPUS_TBL, s1 = SENTENCE1_COL, s2 = SENTENCE2_COL,
+    ))?;
+    let mut count = 0;
+
+    for (i, line) in content.lines().enumerate() {
+      if line.trim().is_empty() {
+        continue;
+      }
+
+      let mut fields = line.split('\t');
+      match (fields.next(), fields.next()) {
+        (Some(sentence1), Some(sentence2)) => {
+          statement.reset()?;
+          statement.bind(1, sentence1.trim())?;
+          statement.bind(2, sentence2.trim())?;
+          statement.next()?;
+          count += 1;
+        },
+        _ => return Err(Error::Error(format!("Invalid sentence pair in line {}: {}", i + 1, line))),
+      }
+    }
+    Ok(count)
+  })
+}
+
+/// Find all sentence pairs whose source sentence contains the given
+/// word.
+pub fn concord(connection: &sqlite::Connection,
+               word: &str,
+               direction: &Direction) -> Result<Vec<Pair>> {
+  if !exists(connection)? {
+    return Err(Error::InvalidSchema("The database does not contain a corpus".to_string()));
+  }
+
+  let (src_col, dst_col) = columns(direction);
+  let select = format!(
+    "SELECT {src}, {dst} FROM {tbl} WHERE {src} LIKE ? ESCAPE '{esc}' ORDER BY id",
+    src = src_col, dst = dst_col, tbl = CORPUS_TBL, esc = pattern::ESCAPE,
+  );
+  let mut cursor = connection.prepare(select)?.cursor();
+  cursor.bind(&[sqlite::Value::String(pattern::containing(word))])?;
+
+  let word = word.to_lowercase();
+  let mut pairs = Vec::new();
+  while let Some(row) = cursor.next()? {
+    let (source, target) = match (row[0].as_string(), row[1].as_string()) {
+      (Some(source), Some(target)) => (source, target),
+      _ => return Err(Error::InvalidSchema(format!("Invalid sentence pair: {:?}", row))),
+    };
+
+    // LIKE matches substrings but we are only interested in the word
+    // itself and not, say, in compounds containing it.
+    if !find_word(&source.to_lowercase(), &word).is_empty() {
+      pairs.push(Pair {
+        source: source.to_string(),
+        target: target.to_string(),
+      })
+    }
+  }
+  Ok(pairs)
+}
+
+
+/// A word frequently appearing in the vicinity of another one.
+#[derive(Debug, PartialEq)]
+pub struct Collocation {
+  /// The (lower case) collocate.
+  pub word: String,
+  /// The number of times the collocate appeared next to the word.
+  pub count: usize,
+  /// The pointwise mutual information of the word and the collocate.
+  pub pmi: f64,
+}
+
+/// Split a sentence into its (lower case) words.
+fn tokenize(sentence: &str) -> Vec<String> {
+  sentence
+    .split(|c: char| !c.is_alphanumeric())
+    .filter(|w| !w.is_empty())
+    .map(|w| w.to_lowercase())
+    .collect()
+}
+
+/// Compute the collocations of a word in the corpus.
+///
+/// All words at most `window` positions away from an occurrence of
+/// `word` in a source sentence are considered collocates. Collocates
+/// seen fewer than `min_count` times are ignored. The result is ranked
+/// by pointwise mutual information, i.e., by how much more often the
+/// two words appear together than would be expected by chance.
+pub fn collocations(connection: &sqlite::Connection,
+                    word: &str,
+                    direction: &Direction,
+                    window: usize,
+                    min_count: usize) -> Result<Vec<Collocation>> {
+  if !exists(connection)? {
+    return Err(Error::InvalidSchema("The database does not contain a corpus".to_string()));
+  }
+
+  let (src_col, _) = columns(direction);
+  let select = format!("SELECT {src} FROM {tbl}", src = src_col, tbl = CORPUS_TBL);
+  let mut cursor = connection.prepare(select)?.cursor();
+
+  let word = word.to_lowercase();
+  let mut total = 0usize;
+  let mut frequencies = HashMap::<String, usize>::new();
+  let mut cooccurrences = HashMap::<String, usize>::new();
+
+  while let Some(row) = cursor.next()? {
+    let sentence = row[0].as_string().ok_or_else(|| Error::InvalidSchema(format!(
+      "Invalid sentence: {:?}",
+      row
+    )))?;
+    let tokens = tokenize(sentence);
+    total += tokens.len();
+
+    for (i, token) in tokens.iter().enumerate() {
+      *frequencies.entry(token.clone()).or_insert(0) += 1;
+
+      if *token == word {
+        let start = i.saturating_sub(window);
+        let end = cmp::min(i + window + 1, tokens.len());
+        for (j, neighbor) in tokens[start..end].iter().enumerate() {
+          if start + j != i && *neighbor != word {
+            *cooccurrences.entry(neighbor.clone()).or_insert(0) += 1;
+          }
+        }
+      }
+    }
+  }
+
+  let word_count = frequencies.get(&word).cloned().unwrap_or(0);
+  let mut collocations = cooccurrences
+    .into_iter()
+    .filter(|&(_, count)| count >= min_count)
+    .map(|(collocate, count)| {
+      let collocate_count = frequencies[&collocate];
+      let pmi = ((count * total) as f64 / (word_count * collocate_count) as f64).log2();
+      Collocation {
+        word: collocate,
+        count,
+        pmi,
+      }
+    })
+    .collect::<Vec<_>>();
+
+  collocations.sort_by(|x, y| {
+    y.pmi.partial_cmp(&x.pmi).unwrap_or(cmp::Ordering::Equal)
+      .then_with(|| y.count.cmp(&x.count))
+      .then_with(|| x.word.cmp(&y.word))
+  });
+  Ok(collocations)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn import_and_concord() {
+    let connection = sqlite::open(":memory:").unwrap();
+    assert!(!exists(&connection).unwrap());
+    assert!(concord(&connection, "Liebe", &Direction::Lang1ToLang2).is_err());
+
+    let content = "Die Liebe ist blind.\tLove is blind.\n\
+                   \n\
+                   Er liebt sie.\tHe loves her.\n\
+                   Liebeskummer tut weh.\tHeartbreak hurts.\n";
+    assert_eq!(import(&connection, content).unwrap(), 3);
+    assert!(exists(&connection).unwrap());
+
+    let pairs = concord(&connection, "liebe", &Direction::Lang1ToLang2).unwrap();
+    assert_eq!(
+      pairs,
+      vec![
+        Pair {
+          source: "Die Liebe ist blind.".to_string(),
+          target: "Love is blind.".to_string(),
+        },
+      ]
+    );
+
+    let pairs = concord(&connection, "hurts", &Direction::Lang2ToLang1).unwrap();
+    assert_eq!(pairs.len(), 1);
+    assert_eq!(pairs[0].target, "Liebeskummer tut weh.");
+  }
+
+  #[test]
+  fn collocations_pmi() {
+    let connection = sqlite::open(":memory:").unwrap();
+    let content = "Er trinkt starken Kaffee.\tHe drinks strong coffee.\n\
+                   Sie trinkt starken Kaffee.\tShe drinks strong coffee.\n\
+                   Der Kaffee ist kalt.\tThe coffee is cold.\n\
+                   Der Tee ist kalt.\tThe tea is cold.\n\
+                   Der Wind ist stark.\tThe wind is strong.\n";
+    import(&connection, content).unwrap();
+
+    let found = collocations(&connection, "Kaffee", &Direction::Lang1ToLang2, 2, 2).unwrap();
+    let words = found.iter().map(|c| c.word.as_str()).collect::<Vec<_>>();
+    assert_eq!(words, vec!["starken", "trinkt"]);
+    assert_eq!(found[0].count, 2);
+
+    let found = collocations(&connection, "Kaffee", &Direction::Lang1ToLang2, 2, 1).unwrap();
+    assert!(found.iter().any(|c| c.word == "der"));
+  }
+
+  #[test]
+  fn tokenize_sentence() {
+    assert_eq!(tokenize("Der Ärger, der bleibt!"), vec!["der", "ärger", "der", "bleibt"]);
+  }
+
+  #[test]
+  fn import_invalid() {
+    let connection = sqlite::open(":memory:").unwrap();
+    assert!(import(&connection, "Ein Satz ohne Übersetzung\n").is_err());
+  }
+}
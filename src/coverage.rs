@@ -0,0 +1,143 @@
+// coverage.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Checking how well the dictionary covers a list of words.
+//!
+//! A word is covered if the dictionary contains an entry for it as
+//! such, i.e., an exact match ignoring annotations and case. Phrases
+//! merely containing the word do not count.
+
+use std::path;
+
+use rank::classify;
+use rank::Match;
+use translate;
+use Direction;
+use Result;
+
+
+/// The coverage of a word list by the dictionary.
+#[derive(Debug, PartialEq)]
+pub struct Coverage {
+  /// The number of words checked.
+  pub total: usize,
+  /// The number of words covered.
+  pub covered: usize,
+  /// The number of covered words per entry type, most common first. A
+  /// word with entries of multiple types counts towards each of them.
+  pub types: Vec<(String, usize)>,
+  /// The words not covered, in the order of the list.
+  pub missing: Vec<String>,
+}
+
+impl Coverage {
+  /// Retrieve the fraction of words covered, in percent.
+  pub fn percentage(&self) -> f64 {
+    if self.total == 0 {
+      100.0
+    } else {
+      self.covered as f64 * 100.0 / self.total as f64
+    }
+  }
+}
+
+
+/// Parse a word list, containing one word (or term) per line.
+///
+/// Empty lines and lines starting with `#` are ignored, as are
+/// duplicate words.
+pub fn parse(content: &str) -> Vec<String> {
+  let mut words = Vec::<String>::new();
+  for line in content.lines() {
+    let word = line.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !word.is_empty() && !word.starts_with('#') && !words.contains(&word) {
+      words.push(word);
+    }
+  }
+  words
+}
+
+/// Check which of the given words the dictionary covers.
+pub fn check(db: &path::Path, words: &[String], direction: &Direction) -> Result<Coverage> {
+  let mut types = Vec::<(String, usize)>::new();
+  let mut missing = Vec::new();
+
+  for word in words {
+    let mut found = Vec::<String>::new();
+    translate(db, &[word], direction, |entry| {
+      let type_ = entry.type_name();
+      if classify(&entry.source.text, word) == Match::Exact && !found.contains(&type_) {
+        found.push(type_);
+      }
+      Ok(())
+    })?;
+
+    if found.is_empty() {
+      missing.push(word.clone());
+    }
+    for type_ in found {
+      match types.iter_mut().find(|(t, _)| *t == type_) {
+        Some((_, count)) => *count += 1,
+        None => types.push((type_, 1)),
+      }
+    }
+  }
+
+  types.sort_by(|(t1, c1), (t2, c2)| c2.cmp(c1).then_with(|| t1.cmp(t2)));
+  Ok(Coverage {
+    total: words.len(),
+    covered: words.len() - missing.len(),
+    types,
+    missing,
+  })
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_word_list() {
+    let words = parse("# Lesson 1\nZorn\n\n  to  subjugate \nZorn\n");
+    assert_eq!(words, vec!["Zorn".to_string(), "to subjugate".to_string()]);
+  }
+
+  #[test]
+  fn check_coverage() {
+    let db = path::Path::new("./test/test.db");
+    let words = parse("Zorn\nStatistik\nStat\nBlitz\n");
+    let coverage = check(db, &words, &Direction::Lang1ToLang2).unwrap();
+    assert_eq!(
+      coverage,
+      Coverage {
+        total: 4,
+        covered: 2,
+        types: vec![("noun".to_string(), 2)],
+        missing: vec!["Stat".to_string(), "Blitz".to_string()],
+      }
+    );
+    assert_eq!(coverage.percentage(), 50.0);
+
+    let words = parse("subjugate\nnauseating\n");
+    let coverage = check(db, &words, &Direction::Lang2ToLang1).unwrap();
+    assert_eq!(coverage.types, vec![("adj".to_string(), 1), ("verb".to_string(), 1)]);
+    assert!(coverage.missing.is_empty());
+  }
+}
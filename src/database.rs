@@ -0,0 +1,525 @@
+// database.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+
+//! A handle to an open dictionary database.
+//!
+//! Functions like [`translate`](::translate) open the database anew for
+//! every lookup and prepare the (rather large) statement performing it
+//! from scratch. A [`Database`] instead holds on to the connection and
+//! caches prepared statements by their SQL, so that repeated lookups
+//! only have to bind new parameters. Lookups beyond plain translations
+//! are performed by means of [`Query::run_on`].
+
+use std::cell;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::mem;
+use std::path;
+use std::rc;
+use std::sync;
+use std::time;
+
+use authorizer;
+use backend::Backend;
+use cancel::interruptible;
+use cancel::CancelToken;
+use explain;
+use fulltext::FullText;
+use open_read_only;
+use optimize;
+use overlay;
+use read_row;
+use row_id;
+use sqlite;
+use sqlite3_sys as ffi;
+use term_select;
+use tuning;
+use Direction;
+use Entry;
+use Error;
+use Options;
+use Query;
+use Result;
+use Tuning;
+
+/// The maximum number of prepared statements kept around. Statements
+/// embedding literal values, e.g., when ranking, may not be reused
+/// much, so we do not want to accumulate them indefinitely.
+const MAX_STATEMENTS: usize = 64;
+
+
+/// An open dictionary database.
+pub struct Database {
+  /// The prepared statements, by their SQL.
+  ///
+  /// The statements borrow the connection, which we cannot express as
+  /// part of the same struct. They are declared first so that they are
+  /// dropped (finalized) before the connection is.
+  statements: cell::RefCell<HashMap<String, sqlite::Cursor<'static>>>,
+  /// The connection to the database.
+  connection: rc::Rc<sqlite::Connection>,
+  /// The kind of full-text index the database has to narrow down the
+  /// entries to check for a term with, if any.
+  fulltext: Option<FullText>,
+  /// Whether the database has a headwords table (see `optimize`) to
+  /// narrow down the entries to check for an exact match with.
+  headwords: bool,
+  /// Whether the user's entries (see `overlay`) are looked up as well.
+  overlay: bool,
+}
+
+impl Database {
+  /// Open a database for reading, tuned as set by `tuning::set`.
+  pub fn open(db: &path::Path) -> Result<Database> {
+    Self::open_with(db, &tuning::current())
+  }
+
+  /// Open a database for reading, applying the given tuning.
+  pub fn open_with(db: &path::Path, tuning: &Tuning) -> Result<Database> {
+    let connection = open_read_only(db, tuning)?;
+    info!("Opened database {}", db.display());
+    let fulltext = FullText::detect(&connection)?;
+    if fulltext.is_none() {
+      info!("Database lacks a full-text index, matching all entries");
+    }
+    let headwords = optimize::exists(&connection)?;
+    if headwords && !optimize::has_statistics(&connection)? {
+      warn!("Database {} lacks statistics, which may slow down lookups; run the analyze command \
+             on it", db.display());
+    }
+    let overlay = overlay::attached(&connection)?;
+    authorizer::read_only(&connection)?;
+    Ok(Database {
+      statements: cell::RefCell::new(HashMap::new()),
+      connection: rc::Rc::new(connection),
+      fulltext,
+      headwords,
+      overlay,
+    })
+  }
+
+  /// Retrieve the underlying connection.
+  pub fn connection(&self) -> &rc::Rc<sqlite::Connection> {
+    &self.connection
+  }
+
+  /// Check whether lookups can make use of the database's full-text
+  /// index.
+  pub fn fulltext(&self) -> bool {
+    self.fulltext.is_some()
+  }
+
+  /// Retrieve the kind of full-text index lookups can make use of.
+  pub fn fulltext_index(&self) -> Option<FullText> {
+    self.fulltext
+  }
+
+  /// Check whether lookups of exact matches can make use of the
+  /// database's headwords table.
+  pub fn headwords(&self) -> bool {
+    self.headwords
+  }
+
+  /// Check whether lookups include the user's entries.
+  pub fn overlay(&self) -> bool {
+    self.overlay
+  }
+
+  /// Translate the given terms, reporting each entry found to
+  /// `callback`.
+  pub fn translate<F, S>(&self, terms: &[S], direction: &Direction, callback: F) -> Result<()>
+  where
+    F: FnMut(&Entry) -> Result<()>,
+    S: AsRef<str>,
+  {
+    Query::with_terms(terms).direction(*direction).run_on(self, callback)
+  }
+
+  /// Execute a query with the given parameters, reporting each row of
+  /// the result to `callback`.
+  pub fn execute<F>(&self, query: &str, binds: &[sqlite::Value],
+                    options: &Options, mut callback: F) -> Result<()>
+  where
+    F: FnMut(&[sqlite::Value]) -> Result<()>,
+  {
+    self.execute_while(query, binds, options, |row| callback(row).map(|_| true))
+  }
+
+  /// Execute a query with the given parameters, reporting each row of
+  /// the result to `callback` for as long as it asks for more.
+  pub fn execute_while<F>(&self, query: &str, binds: &[sqlite::Value],
+                          options: &Options, mut callback: F) -> Result<()>
+  where
+    F: FnMut(&[sqlite::Value]) -> Result<bool>,
+  {
+    let mut cursor = self.statement(query, binds, options)?;
+    let start = time::Instant::now();
+    let mut rows = 0;
+    while let Some(row) = cursor.next()? {
+      rows += 1;
+      if !callback(row)? {
+        // The statement has not run to completion. Resetting it ends
+        // its read transaction, which allows for caching it.
+        unsafe { ffi::sqlite3_reset(cursor.as_raw()) };
+        break
+      }
+    }
+    let elapsed = start.elapsed();
+    info!("Query returned {} rows in {:.1?}", rows, elapsed);
+    self.release(query, cursor);
+    Ok(())
+  }
+
+  /// Execute a query with the given parameters, if any, yielding the
+  /// distinct entries of the result one by one until `token` is
+  /// cancelled, up to `limit` entries (and `Options::max_results`).
+  pub fn stream(&self, sql: Option<(String, Vec<sqlite::Value>)>, limit: Option<usize>,
+                options: &Options, token: CancelToken) -> Result<Entries<'_>> {
+    let (query, cursor) = match sql {
+      Some((query, binds)) => {
+        let cursor = self.statement(&query, &binds, options)?;
+        (query, Some(cursor))
+      },
+      None => (String::new(), None),
+    };
+    Ok(Entries {
+      database: self,
+      query,
+      cursor,
+      token,
+      seen: HashSet::new(),
+      remaining: options.cap(limit),
+      rows: 0,
+      start: time::Instant::now(),
+    })
+  }
+
+  /// Retrieve the statement for a query with the given parameters bound
+  /// to it, ready for execution.
+  ///
+  /// The statement is taken out of the cache while it is in use, so
+  /// that lookups performed while it is can neither observe nor disturb
+  /// it.
+  fn statement(&self, query: &str, binds: &[sqlite::Value],
+               options: &Options) -> Result<sqlite::Cursor<'static>> {
+    debug!("Querying with {} bind parameters", binds.len());
+    if options.explain {
+      eprint!("{}", explain::explain(&self.connection, query, binds)?);
+    }
+
+    let cached = self.statements.borrow_mut().remove(query);
+    let mut cursor = match cached {
+      Some(cursor) => {
+        debug!("Reusing prepared statement");
+        cursor
+      },
+      None => {
+        let cursor = self.connection.prepare(query)?.cursor();
+        // SAFETY: The connection is kept alive by `self` for as long
+        //         as the statement is, as the statement is either
+        //         dropped while borrowing `self` or, if cached, before
+        //         the connection (see `statements`).
+        unsafe { mem::transmute::<sqlite::Cursor<'_>, sqlite::Cursor<'static>>(cursor) }
+      },
+    };
+    cursor.bind(binds)?;
+    Ok(cursor)
+  }
+
+  /// Return a statement run to completion to the cache.
+  ///
+  /// Statements not run to completion must not be cached, as they
+  /// would keep their read transaction open.
+  fn release(&self, query: &str, cursor: sqlite::Cursor<'static>) {
+    let mut statements = self.statements.borrow_mut();
+    if statements.len() >= MAX_STATEMENTS {
+      statements.clear();
+    }
+    statements.insert(query.to_string(), cursor);
+  }
+}
+
+thread_local! {
+  /// The connections each thread opened on behalf of shared databases,
+  /// along with a reference telling whether the database is still in
+  /// use.
+  static POOL: cell::RefCell<Vec<(sync::Weak<path::PathBuf>, rc::Rc<Database>)>> =
+    const { cell::RefCell::new(Vec::new()) };
+}
+
+/// A database that can be shared between threads.
+///
+/// A `Database` and the statements it prepared cannot be used from
+/// threads other than the one that opened it. A `SharedDatabase`
+/// instead opens a connection on each thread using it, on first use,
+/// and keeps it around for as long as the database is. Clones refer to
+/// the same logical database.
+#[derive(Clone, Debug)]
+pub struct SharedDatabase {
+  path: sync::Arc<path::PathBuf>,
+}
+
+impl SharedDatabase {
+  /// Open a database for reading, from any number of threads.
+  pub fn open(db: &path::Path) -> Result<SharedDatabase> {
+    let shared = SharedDatabase {
+      path: sync::Arc::new(db.to_path_buf()),
+    };
+    // We open the database right away, so that errors surface here.
+    let _ = shared.database()?;
+    Ok(shared)
+  }
+
+  /// Retrieve the database connection of the current thread, opening
+  /// it if necessary.
+  fn database(&self) -> Result<rc::Rc<Database>> {
+    POOL.with(|pool| {
+      let mut pool = pool.borrow_mut();
+      // Connections to databases no longer in use are closed.
+      pool.retain(|(path, _)| path.strong_count() > 0);
+
+      let key = sync::Arc::downgrade(&self.path);
+      if let Some((_, database)) = pool.iter().find(|(path, _)| path.ptr_eq(&key)) {
+        return Ok(database.clone());
+      }
+      let database = rc::Rc::new(Database::open(&self.path)?);
+      pool.push((key, database.clone()));
+      Ok(database)
+    })
+  }
+
+  /// Run a function with the current thread's connection to the
+  /// database.
+  pub fn with<F, T>(&self, f: F) -> Result<T>
+  where
+    F: FnOnce(&Database) -> Result<T>,
+  {
+    f(&*self.database()?)
+  }
+
+  /// Translate the given terms, reporting each entry found to
+  /// `callback`.
+  pub fn translate<F, S>(&self, terms: &[S], direction: &Direction, callback: F) -> Result<()>
+  where
+    F: FnMut(&Entry) -> Result<()>,
+    S: AsRef<str>,
+  {
+    self.with(|database| database.translate(terms, direction, callback))
+  }
+}
+
+impl Backend for SharedDatabase {
+  fn lookup(&self, terms: &[String], direction: &Direction, options: &Options,
+            limit: Option<usize>, callback: &mut dyn FnMut(&Entry) -> Result<()>) -> Result<()> {
+    self.with(|database| database.lookup(terms, direction, options, limit, callback))
+  }
+}
+
+
+/// An iterator over the entries found by a lookup, as created by
+/// [`Query::stream`].
+///
+/// Once the lookup is cancelled, an `Error::Cancelled` is yielded and
+/// iteration ends.
+pub struct Entries<'d> {
+  database: &'d Database,
+  query: String,
+  /// The statement producing the entries, until iteration ends.
+  cursor: Option<sqlite::Cursor<'static>>,
+  token: CancelToken,
+  /// The ids of the entries yielded so far.
+  seen: HashSet<i64>,
+  /// The number of entries still to yield, if limited.
+  remaining: Option<usize>,
+  /// The number of rows the statement returned so far.
+  rows: usize,
+  /// The time the statement started executing at.
+  start: time::Instant,
+}
+
+impl<'d> Entries<'d> {
+  /// End iteration, returning the statement to the cache.
+  fn finish(&mut self) {
+    if let Some(cursor) = self.cursor.take() {
+      let elapsed = self.start.elapsed();
+      let duplicates = self.rows - self.seen.len();
+      info!("Query returned {} rows ({} duplicates) in {:.1?}", self.rows, duplicates, elapsed);
+      // Once the limit is reached the statement has not run to
+      // completion. Resetting it ends its read transaction, which
+      // allows for caching it.
+      unsafe { ffi::sqlite3_reset(cursor.as_raw()) };
+      self.database.release(&self.query, cursor);
+    }
+  }
+}
+
+impl<'d> Iterator for Entries<'d> {
+  type Item = Result<Entry>;
+
+  fn next(&mut self) -> Option<Result<Entry>> {
+    loop {
+      if self.token.is_cancelled() {
+        return self.cursor.take().map(|_| Err(Error::Cancelled));
+      }
+      if self.remaining == Some(0) {
+        self.finish();
+        return None;
+      }
+
+      let result = {
+        let cursor = self.cursor.as_mut()?;
+        interruptible(&self.database.connection, &self.token, || {
+          cursor.next().map(|row| row.map(|row| (row_id(row), read_row(row))))
+        })
+      };
+      match result {
+        Ok(Some((id, entry))) => {
+          self.rows += 1;
+          // An entry matching in more than one way is selected more
+          // than once, but yielded only the first time.
+          if id.is_none_or(|id| self.seen.insert(id)) {
+            self.remaining = self.remaining.map(|remaining| remaining - 1);
+            return Some(entry);
+          }
+        },
+        Ok(None) => {
+          self.finish();
+          return None;
+        },
+        Err(_) if self.token.is_cancelled() => {
+          return self.cursor.take().map(|_| Err(Error::Cancelled))
+        },
+        Err(err) => return self.cursor.take().map(|_| Err(err.into())),
+      }
+    }
+  }
+}
+
+impl Backend for Database {
+  fn lookup(&self, terms: &[String], direction: &Direction, options: &Options,
+            limit: Option<usize>, callback: &mut dyn FnMut(&Entry) -> Result<()>) -> Result<()> {
+    let sql = term_select(terms, direction, options, self.fulltext, self.headwords, self.overlay);
+    for entry in self.stream(sql, limit, options, CancelToken::new())? {
+      callback(&entry?)?;
+    }
+    Ok(())
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::thread;
+
+  #[test]
+  fn reuse_statements() {
+    let db = Database::open(path::Path::new("./test/test.db")).unwrap();
+    let mut found = Vec::new();
+    for term in &["anger", "love", "anger"] {
+      db.translate(&[term], &Direction::Lang2ToLang1, |entry| {
+        found.push(entry.target.headword.clone());
+        Ok(())
+      })
+      .unwrap();
+    }
+    assert_eq!(found.len(), 5 + 2 + 5);
+    assert_eq!(found[..5], found[7..]);
+    assert_eq!(db.statements.borrow().len(), 1);
+  }
+
+  #[test]
+  fn nested_lookups() {
+    let db = Database::open(path::Path::new("./test/test.db")).unwrap();
+    let mut found = Vec::new();
+    db.translate(&["worse"], &Direction::Lang2ToLang1, |entry| {
+      // Translating back needs the very statement the outer lookup is
+      // still using.
+      db.translate(&[&entry.target.headword], &Direction::Lang1ToLang2, |entry| {
+        found.push(entry.target.text.clone());
+        Ok(())
+      })
+    })
+    .unwrap();
+    assert_eq!(found, vec!["worse", "worse", "worse", "anger"]);
+  }
+
+  #[test]
+  fn stream_entries() {
+    let db = Database::open(path::Path::new("./test/test.db")).unwrap();
+    let query = Query::new("anger").direction(Direction::Lang2ToLang1);
+    let token = CancelToken::new();
+
+    let found = query.stream(&db, token.clone()).unwrap().collect::<Result<Vec<_>>>().unwrap();
+    assert_eq!(found.len(), 5);
+    // The statement is cached once the stream is exhausted.
+    assert_eq!(db.statements.borrow().len(), 1);
+
+    let mut entries = query.stream(&db, token.clone()).unwrap();
+    assert_eq!(entries.next().unwrap().unwrap(), found[0]);
+    token.cancel();
+    match entries.next() {
+      Some(Err(Error::Cancelled)) => (),
+      _ => panic!("lookup was not cancelled"),
+    }
+    assert!(entries.next().is_none());
+
+    assert_eq!(Query::with_terms::<&str>(&[]).stream(&db, CancelToken::new()).unwrap().count(), 0);
+  }
+
+  #[test]
+  fn share_between_threads() {
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+    let db = SharedDatabase::open(path::Path::new("./test/test.db")).unwrap();
+    assert_send_sync(&db);
+
+    let lookup = |db: &SharedDatabase| {
+      let mut found = Vec::new();
+      db.translate(&["anger"], &Direction::Lang2ToLang1, |entry| {
+        found.push(entry.clone());
+        Ok(())
+      })
+      .unwrap();
+      found
+    };
+    let expected = lookup(&db);
+    assert_eq!(expected.len(), 5);
+
+    let threads = (0..4)
+      .map(|_| {
+        let db = db.clone();
+        thread::spawn(move || {
+          let found = lookup(&db);
+          // Each thread reuses its connection.
+          let connection = db.with(|d| Ok(d.connection().clone())).unwrap();
+          assert!(rc::Rc::ptr_eq(&connection, &db.with(|d| Ok(d.connection().clone())).unwrap()));
+          found
+        })
+      })
+      .collect::<Vec<_>>();
+    for thread in threads {
+      assert_eq!(thread.join().unwrap(), expected);
+    }
+
+    assert!(SharedDatabase::open(path::Path::new("./test/does_not_exist.db")).is_err());
+  }
+}
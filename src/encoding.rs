@@ -0,0 +1,136 @@
+// encoding.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Encoding of output for consumers not supporting UTF-8.
+//!
+//! Latin-1 covers umlauts and 'ß', but not all the characters found in
+//! the dictionary or used in our output. Such characters are replaced
+//! by an ASCII approximation, e.g., "->" for '→', falling back to the
+//! base letter of accented characters and to '?' for everything else.
+
+use std::io;
+use std::io::Write;
+
+use like::unaccent;
+use Error;
+use Result;
+
+/// ASCII approximations of characters not covered by Latin-1.
+const APPROXIMATIONS: [(char, &str); 16] = [
+  ('→', "->"),
+  ('←', "<-"),
+  ('●', "*"),
+  ('○', "o"),
+  ('…', "..."),
+  ('–', "-"),
+  ('—', "-"),
+  ('‚', "'"),
+  ('‘', "'"),
+  ('’', "'"),
+  ('„', "\""),
+  ('“', "\""),
+  ('”', "\""),
+  ('œ', "oe"),
+  ('Œ', "OE"),
+  ('€', "EUR"),
+];
+
+
+/// An encoding to write output in.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Encoding {
+  /// UTF-8, which all our text is in natively.
+  #[default]
+  Utf8,
+  /// ISO 8859-1, with characters outside of it being approximated.
+  Latin1,
+}
+
+impl Encoding {
+  /// Parse an encoding from its name.
+  pub fn from_name(name: &str) -> Result<Encoding> {
+    match name.to_lowercase().as_str() {
+      "utf8" | "utf-8" => Ok(Encoding::Utf8),
+      "latin1" | "latin-1" | "iso-8859-1" => Ok(Encoding::Latin1),
+      _ => Err(Error::Error(format!("Unsupported encoding: {} (expected latin1 or utf8)", name))),
+    }
+  }
+
+  /// Encode a text.
+  pub fn encode(self, text: &str) -> Vec<u8> {
+    match self {
+      Encoding::Utf8 => text.as_bytes().to_vec(),
+      Encoding::Latin1 => {
+        let mut bytes = Vec::with_capacity(text.len());
+        for c in text.chars() {
+          if (c as u32) < 0x100 {
+            bytes.push(c as u8);
+          } else {
+            bytes.extend(approximate(c).bytes());
+          }
+        }
+        bytes
+      },
+    }
+  }
+
+  /// Write a text to stdout in this encoding.
+  pub fn print(self, text: &str) {
+    // Akin to print!, but without panicking if stdout got closed.
+    let _ = io::stdout().write_all(&self.encode(text));
+  }
+}
+
+
+/// Approximate a character not covered by Latin-1 using ASCII.
+fn approximate(c: char) -> String {
+  if let Some(&(_, approximation)) = APPROXIMATIONS.iter().find(|&&(d, _)| d == c) {
+    return approximation.to_string();
+  }
+
+  let base = unaccent(&c.to_string());
+  if base.is_ascii() {
+    base
+  } else {
+    "?".to_string()
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn encode_latin1() {
+    let text = "Ärger {m} → anger [ugs.]";
+    assert_eq!(Encoding::Utf8.encode(text), text.as_bytes());
+    assert_eq!(Encoding::Latin1.encode(text), b"\xc4rger {m} -> anger [ugs.]");
+    assert_eq!(Encoding::Latin1.encode("Straße"), b"Stra\xdfe");
+    assert_eq!(Encoding::Latin1.encode("●●○ Łódź"), b"**o L\xf3dz");
+    assert_eq!(Encoding::Latin1.encode("日本"), b"??");
+  }
+
+  #[test]
+  fn encoding_names() {
+    assert_eq!(Encoding::from_name("UTF-8").unwrap(), Encoding::Utf8);
+    assert_eq!(Encoding::from_name("latin1").unwrap(), Encoding::Latin1);
+    assert!(Encoding::from_name("ascii").is_err());
+  }
+}
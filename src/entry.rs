@@ -0,0 +1,310 @@
+// entry.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Structured representation of dictionary entries.
+//!
+//! The database stores each side of an entry as a single string, with
+//! annotations embedded in it: gender and number tags (`{...}`),
+//! comments (`[...]`), and abbreviations (`<...>`). An [`Entry`] makes
+//! these available as fields of their own, along with the entry's type
+//! and usage.
+
+use std::fmt;
+
+pub use term::headword;
+
+
+/// The type (word class) of an entry.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+  /// An adjective ("adj").
+  Adjective,
+  /// An adverb ("adv").
+  Adverb,
+  /// A conjunction ("conj").
+  Conjunction,
+  /// A noun ("noun").
+  Noun,
+  /// A past participle ("past-p").
+  PastParticiple,
+  /// A prefix ("prefix").
+  Prefix,
+  /// A preposition ("prep").
+  Preposition,
+  /// A present participle ("pres-p").
+  PresentParticiple,
+  /// A pronoun ("pron").
+  Pronoun,
+  /// A suffix ("suffix").
+  Suffix,
+  /// A verb ("verb").
+  Verb,
+  /// An entry without type ("unknown").
+  Unknown,
+  /// Any other type, by its name.
+  Other(String),
+}
+
+impl Type {
+  /// Parse a type from its name as used by dict.cc.
+  pub fn from_name(name: &str) -> Type {
+    match name {
+      "adj" => Type::Adjective,
+      "adv" => Type::Adverb,
+      "conj" => Type::Conjunction,
+      "noun" => Type::Noun,
+      "past-p" => Type::PastParticiple,
+      "prefix" => Type::Prefix,
+      "prep" => Type::Preposition,
+      "pres-p" => Type::PresentParticiple,
+      "pron" => Type::Pronoun,
+      "suffix" => Type::Suffix,
+      "verb" => Type::Verb,
+      "" | "unknown" => Type::Unknown,
+      _ => Type::Other(name.to_string()),
+    }
+  }
+
+  /// Retrieve the name of the type as used by dict.cc.
+  pub fn name(&self) -> &str {
+    match *self {
+      Type::Adjective => "adj",
+      Type::Adverb => "adv",
+      Type::Conjunction => "conj",
+      Type::Noun => "noun",
+      Type::PastParticiple => "past-p",
+      Type::Prefix => "prefix",
+      Type::Preposition => "prep",
+      Type::PresentParticiple => "pres-p",
+      Type::Pronoun => "pron",
+      Type::Suffix => "suffix",
+      Type::Verb => "verb",
+      Type::Unknown => "unknown",
+      Type::Other(ref name) => name,
+    }
+  }
+}
+
+
+/// One side of an entry, i.e., a term along with its annotations.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Term {
+  /// The term as stored in the database, e.g., "Inhalt {m} <Inh.>".
+  pub text: String,
+  /// The term without any annotations, e.g., "Inhalt".
+  pub headword: String,
+  /// The gender and number tags, e.g., "m" or "pl".
+  pub genders: Vec<String>,
+  /// The comments, e.g., "ugs." or "beim Tennis".
+  pub comments: Vec<String>,
+  /// The abbreviations, e.g., "Inh.".
+  pub abbreviations: Vec<String>,
+}
+
+impl Term {
+  /// Parse a term as stored in the database.
+  pub fn parse(text: &str) -> Term {
+    Term {
+      text: text.to_string(),
+      headword: headword(text),
+      genders: genders(text),
+      comments: comments(text),
+      abbreviations: abbreviations(text),
+    }
+  }
+}
+
+impl fmt::Display for Term {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(&self.text)
+  }
+}
+
+
+/// A dictionary entry.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+  /// The term in the source language.
+  pub source: Term,
+  /// The term in the destination language.
+  pub target: Term,
+  /// The types of the entry. Some entries have multiple, e.g., those
+  /// being both an adjective and an adverb.
+  pub types: Vec<Type>,
+  /// How commonly the entry is used, relative to all others.
+  pub usage: i64,
+  /// Whether the entry is one of the user's own, as opposed to being
+  /// part of the dictionary.
+  pub user: bool,
+  /// The id of the entry in the database it was found in, if known.
+  pub id: Option<i64>,
+}
+
+impl Entry {
+  /// Create an entry from the source and destination terms, the type
+  /// (as a space separated list of names), and the usage as stored in
+  /// the database.
+  pub fn new(source: &str, target: &str, type_: &str, usage: i64) -> Entry {
+    let mut types = type_.split_whitespace().map(Type::from_name).collect::<Vec<_>>();
+    if types.is_empty() {
+      types.push(Type::Unknown);
+    }
+
+    Entry {
+      source: Term::parse(source),
+      target: Term::parse(target),
+      types,
+      usage,
+      user: false,
+      id: None,
+    }
+  }
+
+  /// Retrieve the name of the entry's type, as a space separated list
+  /// of the names of its types, e.g., "adj adv".
+  pub fn type_name(&self) -> String {
+    self.types.iter().map(Type::name).collect::<Vec<_>>().join(" ")
+  }
+}
+
+
+/// Retrieve the gender and number tags (`{...}`) of a term, e.g., "m"
+/// for "Zorn {m}" or "pl" for "Arme {pl}".
+pub fn genders(text: &str) -> Vec<String> {
+  enclosed(text, '{', '}')
+}
+
+/// Retrieve the comments (`[...]`) of a term, e.g., "ugs." for
+/// "bekloppt [ugs.]".
+///
+/// Comments may contain further annotations, which are retained, e.g.,
+/// "Chem. <S>" for "Schwefel {m} [Chem. <S>]".
+pub fn comments(text: &str) -> Vec<String> {
+  enclosed(text, '[', ']')
+}
+
+/// Retrieve the abbreviations (`<...>`) of a term, e.g., "Inh." for
+/// "Inhalt {m} <Inh.>".
+pub fn abbreviations(text: &str) -> Vec<String> {
+  enclosed(text, '<', '>')
+}
+
+/// Retrieve the contents of all top-level `open`...`close` brackets
+/// in a text. Brackets left open are ignored.
+fn enclosed(text: &str, open: char, close: char) -> Vec<String> {
+  let mut found = Vec::new();
+  let mut start = None;
+  let mut depth = 0usize;
+
+  for (i, c) in text.char_indices() {
+    match c {
+      '[' | '{' | '<' => {
+        if depth == 0 && c == open {
+          start = Some(i + c.len_utf8());
+        }
+        depth += 1;
+      },
+      ']' | '}' | '>' if depth > 0 => {
+        depth -= 1;
+        if depth == 0 && c == close {
+          if let Some(start) = start.take() {
+            found.push(text[start..i].trim().to_string());
+          }
+        }
+      },
+      _ => (),
+    }
+  }
+  found
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_terms() {
+    let term = Term::parse("Inhalt {m} <Inh.>");
+    assert_eq!(term.headword, "Inhalt");
+    assert_eq!(term.genders, vec!["m"]);
+    assert!(term.comments.is_empty());
+    assert_eq!(term.abbreviations, vec!["Inh."]);
+
+    let term = Term::parse("durchgeknallt [ugs.] [verrückt]");
+    assert_eq!(term.headword, "durchgeknallt");
+    assert_eq!(term.comments, vec!["ugs.", "verrückt"]);
+
+    let term = Term::parse("Schwefel {m} <S> [Chem. <S>]");
+    assert_eq!(term.genders, vec!["m"]);
+    assert_eq!(term.comments, vec!["Chem. <S>"]);
+    assert_eq!(term.abbreviations, vec!["S"]);
+    assert_eq!(term.to_string(), "Schwefel {m} <S> [Chem. <S>]");
+  }
+
+  #[test]
+  fn entry_types() {
+    let entry = Entry::new("ärger", "worse", "adj adv", 12);
+    assert_eq!(entry.types, vec![Type::Adjective, Type::Adverb]);
+    assert_eq!(entry.type_name(), "adj adv");
+    assert_eq!(entry.usage, 12);
+
+    assert_eq!(Entry::new("a", "b", "", 0).types, vec![Type::Unknown]);
+    assert_eq!(Entry::new("a", "b", "idiom", 0).types, vec![Type::Other("idiom".to_string())]);
+    assert_eq!(Entry::new("a", "b", "idiom", 0).type_name(), "idiom");
+  }
+
+  #[test]
+  fn parse_annotations() {
+    let text = "Statistik {f} [science that collects data] [treated as sg.] <stats>";
+    assert_eq!(headword(text), "Statistik");
+    assert_eq!(genders(text), vec!["f"]);
+    assert_eq!(comments(text), vec!["science that collects data", "treated as sg."]);
+    assert_eq!(abbreviations(text), vec!["stats"]);
+
+    // Annotations may appear in between words.
+    let text = "to give [sth.] up";
+    assert_eq!(headword(text), "to give up");
+    assert_eq!(comments(text), vec!["sth."]);
+
+    // Several tags and white space inside of brackets.
+    let text = "Ärzte {pl} { f }";
+    assert_eq!(genders(text), vec!["pl", "f"]);
+
+    // Nested annotations belong to the enclosing one.
+    let text = "Schwefel {m} [Chem. <S> {m}]";
+    assert_eq!(genders(text), vec!["m"]);
+    assert_eq!(comments(text), vec!["Chem. <S> {m}"]);
+    assert!(abbreviations(text).is_empty());
+    assert_eq!(headword(text), "Schwefel");
+
+    // Unbalanced brackets do not trip up parsing.
+    let text = "Liebe {f} [ugs.";
+    assert_eq!(genders(text), vec!["f"]);
+    assert!(comments(text).is_empty());
+    assert_eq!(comments("Liebe ugs.]"), Vec::<String>::new());
+
+    let text = "Liebe";
+    assert_eq!(headword(text), "Liebe");
+    assert!(genders(text).is_empty());
+    assert!(comments(text).is_empty());
+    assert!(abbreviations(text).is_empty());
+    assert_eq!(headword(""), "");
+  }
+}
@@ -0,0 +1,131 @@
+// explain.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Explanation of the queries performed for a lookup.
+//!
+//! To diagnose slow or surprising lookups, `--explain` reports the SQL
+//! text of each query, the values bound to its parameters, and the plan
+//! SQLite devised for executing it.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use sqlite;
+
+use optimize;
+use Result;
+
+
+/// Format a bound value the way it would be written in SQL.
+fn format_value(value: &sqlite::Value) -> String {
+  match *value {
+    sqlite::Value::Binary(ref data) => {
+      let hex = data.iter().map(|b| format!("{:02X}", b)).collect::<String>();
+      format!("X'{}'", hex)
+    },
+    sqlite::Value::Float(f) => f.to_string(),
+    sqlite::Value::Integer(i) => i.to_string(),
+    sqlite::Value::String(ref s) => format!("'{}'", s.replace('\'', "''")),
+    sqlite::Value::Null => "NULL".to_string(),
+  }
+}
+
+/// Retrieve the query plan of a query as lines indented according to
+/// their nesting.
+fn plan(connection: &sqlite::Connection, query: &str,
+        binds: &[sqlite::Value]) -> Result<Vec<String>> {
+  let mut cursor = connection.prepare(format!("EXPLAIN QUERY PLAN {}", query))?.cursor();
+  cursor.bind(binds)?;
+
+  let mut depths = HashMap::new();
+  let mut lines = Vec::new();
+  while let Some(row) = cursor.next()? {
+    let id = row[0].as_integer().unwrap_or(0);
+    let parent = row[1].as_integer().unwrap_or(0);
+    let detail = row[3].as_string().unwrap_or("");
+    let depth = depths.get(&parent).map_or(0, |depth| depth + 1);
+    let _ = depths.insert(id, depth);
+    lines.push(format!("{}{}", "  ".repeat(depth), detail));
+  }
+  Ok(lines)
+}
+
+/// Explain a query with the given parameters bound to it.
+pub fn explain(connection: &sqlite::Connection, query: &str,
+               binds: &[sqlite::Value]) -> Result<String> {
+  let mut explanation = String::new();
+  let _ = writeln!(explanation, "Query:\n  {}", query);
+  if !binds.is_empty() {
+    let _ = writeln!(explanation, "Parameters:");
+    for (i, bind) in binds.iter().enumerate() {
+      let _ = writeln!(explanation, "  ?{} = {}", i + 1, format_value(bind));
+    }
+  }
+  let _ = writeln!(explanation, "Plan:");
+  let plan = plan(connection, query, binds)?;
+  for line in &plan {
+    let _ = writeln!(explanation, "  {}", line);
+  }
+  // Without statistics the planner has to guess which of the indexes
+  // to use, if there is a choice at all.
+  if plan.iter().any(|line| line.contains(" USING ")) && !optimize::has_statistics(connection)? {
+    let _ = writeln!(
+      explanation,
+      "Note: The database lacks statistics, so the plan may be poor (see the analyze command)",
+    );
+  }
+  Ok(explanation)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::path;
+
+  use open;
+
+  #[test]
+  fn format_values() {
+    assert_eq!(format_value(&sqlite::Value::String("don't".to_string())), "'don''t'");
+    assert_eq!(format_value(&sqlite::Value::Integer(42)), "42");
+    assert_eq!(format_value(&sqlite::Value::Binary(vec![0xde, 0xad])), "X'DEAD'");
+    assert_eq!(format_value(&sqlite::Value::Null), "NULL");
+  }
+
+  #[test]
+  fn explain_query() {
+    let connection = open(path::Path::new("./test/test.db")).unwrap();
+    let query = "SELECT term1 FROM main_ft WHERE term1 = ? AND term2 = ?";
+    let binds = [
+      sqlite::Value::String("Zorn".to_string()),
+      sqlite::Value::String("anger".to_string()),
+    ];
+    let explanation = explain(&connection, query, &binds).unwrap();
+    let mut lines = explanation.lines();
+    assert_eq!(lines.next(), Some("Query:"));
+    assert_eq!(lines.next(), Some(&*format!("  {}", query)));
+    assert_eq!(lines.next(), Some("Parameters:"));
+    assert_eq!(lines.next(), Some("  ?1 = 'Zorn'"));
+    assert_eq!(lines.next(), Some("  ?2 = 'anger'"));
+    assert_eq!(lines.next(), Some("Plan:"));
+    assert!(lines.next().unwrap().starts_with("  SCAN"), "{}", explanation);
+  }
+}
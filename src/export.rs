@@ -0,0 +1,654 @@
+// export.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Export of the dictionary into formats of other dictionary programs.
+//!
+//! Entries are read in a streaming fashion, optionally restricted by
+//! subjects and types and filtered by register, so that exports of the
+//! complete dictionary need not hold all of it in memory.
+//!
+//! For the formats of dictionary programs, entries are formatted as
+//! lines in the form "Zorn {m} (noun): anger", looked up by the
+//! headword of their source term.
+//!
+//! StarDict dictionaries, as read by GoldenDict and others, consist of
+//! an `.ifo` file describing the dictionary, an `.idx` file mapping the
+//! (sorted) headwords to the location of their article, and the
+//! articles themselves in a `.dict.dz` file. The latter is compressed
+//! in the dictzip format, a gzip file compressed in chunks that can be
+//! decompressed independently, allowing for random access. Articles
+//! group all entries sharing a headword, the ones used most first.
+//!
+//! dictd dictionaries consist of a `.dict` file with the definitions
+//! and an `.index` file mapping headwords to them. The format allows for
+//! multiple definitions per headword, so each entry is one definition
+//! and only the index has to be kept in memory.
+
+use std::cmp;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Write;
+use std::str::FromStr;
+
+use miniz_oxide::deflate::core::compress;
+use miniz_oxide::deflate::core::create_comp_flags_from_zip_params;
+use miniz_oxide::deflate::core::CompressorOxide;
+use miniz_oxide::deflate::core::TDEFLFlush;
+use miniz_oxide::deflate::core::TDEFLStatus;
+use sqlite;
+
+use filter::Filter;
+use for_each_entry;
+use json::Value;
+use Direction;
+use Entry;
+use Error;
+use Options;
+use Result;
+
+/// The amount of uncompressed data per chunk of a dictzip file, as
+/// used by the dictzip program.
+const CHUNK_LEN: usize = 58315;
+/// The compression level to use.
+const LEVEL: i32 = 9;
+/// The table for computing CRC-32 checksums of bytes.
+const CRC_TABLE: [u32; 256] = crc_table();
+/// The digits of the base64 encoding used by dictd indexes.
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+/// The header line of CSV exports.
+pub const CSV_HEADER: &str = "source,target,type,usage\n";
+
+
+/// A format to export into.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+  /// The format of StarDict.
+  StarDict,
+  /// The format of dictd, the server of the DICT protocol.
+  Dictd,
+  /// Comma separated values, one entry per line.
+  Csv,
+  /// JSON objects, one entry per line.
+  Jsonl,
+}
+
+impl FromStr for Format {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Format> {
+    match s {
+      "stardict" => Ok(Format::StarDict),
+      "dictd" => Ok(Format::Dictd),
+      "csv" => Ok(Format::Csv),
+      "jsonl" => Ok(Format::Jsonl),
+      _ => Err(Error::Error(format!("Invalid export format: {}", s))),
+    }
+  }
+}
+
+
+/// The entries sharing a headword.
+#[derive(Debug, PartialEq)]
+pub struct Article {
+  /// The headword.
+  pub headword: String,
+  /// The entries, one per line.
+  pub lines: Vec<String>,
+}
+
+
+/// The files of a StarDict dictionary.
+#[derive(Debug)]
+pub struct StarDict {
+  /// The content of the `.ifo` file.
+  pub ifo: String,
+  /// The content of the `.idx` file.
+  pub idx: Vec<u8>,
+  /// The content of the `.dict.dz` file.
+  pub dict: Vec<u8>,
+}
+
+
+/// A writer of dictd dictionaries.
+///
+/// Definitions are written to the `.dict` file as they are added,
+/// while the index is built up in memory, as it has to be sorted.
+#[derive(Debug)]
+pub struct Dictd<W> {
+  /// The `.dict` file.
+  dict: W,
+  /// The offset of the next definition.
+  offset: u64,
+  /// The headwords along with the offset and length of their
+  /// definitions.
+  index: Vec<(String, u64, u64)>,
+}
+
+impl<W> Dictd<W>
+where
+  W: Write,
+{
+  /// Create a writer of a dictionary with the given name, writing
+  /// definitions to `dict`.
+  pub fn new(dict: W, name: &str) -> io::Result<Dictd<W>> {
+    let mut dictd = Dictd {
+      dict,
+      offset: 0,
+      index: Vec::new(),
+    };
+    // The entries describing the dictionary, as written by dictfmt.
+    dictd.define("00-database-utf8", "")?;
+    dictd.define("00-database-allchars", "")?;
+    dictd.define("00-database-short", &format!("     {}\n", name.replace('\n', " ")))?;
+    dictd.define("00-database-info", "     Exported by dictcc-cli\n")?;
+    Ok(dictd)
+  }
+
+  /// Write a definition of a headword.
+  fn define(&mut self, headword: &str, text: &str) -> io::Result<()> {
+    let definition = format!("{}\n{}", headword, text);
+    self.dict.write_all(definition.as_bytes())?;
+    self.index.push((headword.to_string(), self.offset, definition.len() as u64));
+    self.offset += definition.len() as u64;
+    Ok(())
+  }
+
+  /// Add an entry as a definition of its source term's headword.
+  /// Entries without a headword cannot be looked up and are skipped.
+  pub fn add(&mut self, entry: &Entry) -> io::Result<()> {
+    if entry.source.headword.is_empty() {
+      return Ok(())
+    }
+    let headword = entry.source.headword.replace(['\t', '\n'], " ");
+    self.define(&headword, &format!("   {}\n\n", line(entry)))
+  }
+
+  /// Finish writing the definitions, retrieving the content of the
+  /// `.index` file.
+  pub fn finish(mut self) -> io::Result<String> {
+    self.dict.flush()?;
+    self.index.sort_by(|(word1, ..), (word2, ..)| {
+      word1.to_lowercase().cmp(&word2.to_lowercase()).then_with(|| word1.cmp(word2))
+    });
+
+    let mut index = String::new();
+    for (headword, offset, len) in &self.index {
+      index += &format!("{}\t{}\t{}\n", headword, base64(*offset), base64(*len));
+    }
+    Ok(index)
+  }
+}
+
+
+/// Format an entry as a line of an article.
+fn line(entry: &Entry) -> String {
+  format!("{} ({}): {}", entry.source, entry.type_name(), entry.target)
+}
+
+/// Quote a field of a CSV record, if necessary.
+fn csv_field(field: &str) -> String {
+  if field.contains([',', '"', '\n', '\r']) {
+    format!("\"{}\"", field.replace('"', "\"\""))
+  } else {
+    field.to_string()
+  }
+}
+
+/// Format an entry as a CSV record, following `CSV_HEADER`.
+pub fn csv_record(entry: &Entry) -> String {
+  format!(
+    "{},{},{},{}\n",
+    csv_field(&entry.source.text),
+    csv_field(&entry.target.text),
+    csv_field(&entry.type_name()),
+    entry.usage,
+  )
+}
+
+/// Format an entry as a line of JSON, with the same members the
+/// results of lookups have.
+pub fn jsonl_record(entry: &Entry) -> String {
+  let object = Value::Object(vec![
+    ("source".to_string(), Value::from(entry.source.text.as_str())),
+    ("target".to_string(), Value::from(entry.target.text.as_str())),
+    ("type".to_string(), Value::from(entry.type_name())),
+    ("usage".to_string(), Value::Number(entry.usage as f64)),
+  ]);
+  format!("{}\n", object)
+}
+
+/// Encode a number the way dictd indexes do: in base64, without
+/// padding and leading zeros.
+fn base64(mut number: u64) -> String {
+  let mut digits = Vec::new();
+  loop {
+    digits.push(BASE64[(number % 64) as usize]);
+    number /= 64;
+    if number == 0 {
+      break
+    }
+  }
+  digits.iter().rev().map(|&digit| digit as char).collect()
+}
+
+/// Invoke a callback for each entry of the dictionary in the given
+/// direction that matches the subjects and types in `options` and
+/// passes `filter`, returning the number of entries.
+pub fn entries<F>(connection: &sqlite::Connection,
+                  direction: &Direction,
+                  options: &Options,
+                  filter: &Filter,
+                  mut callback: F) -> Result<usize>
+where
+  F: FnMut(Entry) -> Result<()>,
+{
+  let mut count = 0;
+  for_each_entry(connection, direction, options, |entry| {
+    if filter.admits(&entry.source.text, &entry.target.text) {
+      callback(entry)?;
+      count += 1;
+    }
+    Ok(())
+  })?;
+  Ok(count)
+}
+
+/// Collect the articles of the dictionary in the given direction,
+/// ordered by their headword.
+pub fn articles(connection: &sqlite::Connection,
+                direction: &Direction,
+                options: &Options,
+                filter: &Filter) -> Result<Vec<Article>> {
+  let mut entries = BTreeMap::<String, Vec<Entry>>::new();
+  self::entries(connection, direction, options, filter, |entry| {
+    if !entry.source.headword.is_empty() {
+      entries.entry(entry.source.headword.clone()).or_default().push(entry);
+    }
+    Ok(())
+  })?;
+
+  let articles = entries
+    .into_iter()
+    .map(|(headword, mut entries)| {
+      entries.sort_by(|e1, e2| {
+        e2.usage.cmp(&e1.usage).then_with(|| e1.source.text.cmp(&e2.source.text))
+      });
+      Article {
+        headword,
+        lines: entries.iter().map(line).collect(),
+      }
+    })
+    .collect();
+  Ok(articles)
+}
+
+/// Build the table for computing CRC-32 checksums.
+const fn crc_table() -> [u32; 256] {
+  let mut table = [0; 256];
+  let mut i = 0;
+  while i < 256 {
+    let mut crc = i as u32;
+    let mut j = 0;
+    while j < 8 {
+      crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+      j += 1;
+    }
+    table[i] = crc;
+    i += 1;
+  }
+  table
+}
+
+/// Compute the CRC-32 checksum of some data, as used by gzip.
+pub fn crc32(data: &[u8]) -> u32 {
+  !data.iter().fold(!0, |crc, &byte| CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+/// Compress a chunk of data, appending the result to `output`.
+fn deflate(compressor: &mut CompressorOxide,
+           mut input: &[u8],
+           flush: TDEFLFlush,
+           output: &mut Vec<u8>) -> Result<()> {
+  let mut buffer = vec![0; CHUNK_LEN * 2];
+  loop {
+    let (status, consumed, produced) = compress(compressor, input, &mut buffer, flush);
+    output.extend_from_slice(&buffer[..produced]);
+    input = &input[consumed..];
+    match status {
+      TDEFLStatus::Done => return Ok(()),
+      TDEFLStatus::Okay if input.is_empty() && produced < buffer.len() => return Ok(()),
+      TDEFLStatus::Okay => (),
+      _ => return Err(Error::Error(format!("Failed to compress data: {:?}", status))),
+    }
+  }
+}
+
+/// Compress data in the dictzip format.
+pub fn dictzip(data: &[u8]) -> Result<Vec<u8>> {
+  // Raw deflate data, i.e., without zlib header, as gzip requires.
+  let flags = create_comp_flags_from_zip_params(LEVEL, -15, 0);
+  let mut compressor = CompressorOxide::new(flags);
+  let mut compressed = Vec::new();
+  let mut sizes = Vec::new();
+
+  let chunks = data.chunks(CHUNK_LEN).collect::<Vec<_>>();
+  for (i, chunk) in chunks.iter().enumerate() {
+    // Each chunk but the last ends with a full flush, so that it can be
+    // decompressed on its own.
+    let flush = if i + 1 == chunks.len() { TDEFLFlush::Finish } else { TDEFLFlush::Full };
+    let start = compressed.len();
+    deflate(&mut compressor, chunk, flush, &mut compressed)?;
+    sizes.push(compressed.len() - start);
+  }
+  if chunks.is_empty() {
+    deflate(&mut compressor, &[], TDEFLFlush::Finish, &mut compressed)?;
+    sizes.push(compressed.len());
+  }
+
+  // The "RA" (random access) field lists the compressed size of each
+  // chunk. It has to fit into the gzip header's extra field.
+  let ra_len = 6 + 2 * sizes.len();
+  if ra_len + 4 > u16::MAX as usize || sizes.iter().any(|&s| s > u16::MAX as usize) {
+    return Err(Error::Error("The data are too large for the dictzip format".to_string()))
+  }
+
+  let mut gzip = Vec::with_capacity(compressed.len() + ra_len + 30);
+  // Magic number, deflate method, FEXTRA flag, no modification time,
+  // no extra flags, Unix.
+  gzip.extend_from_slice(&[0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 3]);
+  gzip.extend_from_slice(&((ra_len + 4) as u16).to_le_bytes());
+  gzip.extend_from_slice(b"RA");
+  gzip.extend_from_slice(&(ra_len as u16).to_le_bytes());
+  gzip.extend_from_slice(&1u16.to_le_bytes());
+  gzip.extend_from_slice(&(CHUNK_LEN as u16).to_le_bytes());
+  gzip.extend_from_slice(&(sizes.len() as u16).to_le_bytes());
+  for size in sizes {
+    gzip.extend_from_slice(&(size as u16).to_le_bytes());
+  }
+  gzip.extend_from_slice(&compressed);
+  gzip.extend_from_slice(&crc32(data).to_le_bytes());
+  gzip.extend_from_slice(&(data.len() as u32).to_le_bytes());
+  Ok(gzip)
+}
+
+/// Compare headwords the way StarDict expects the index to be sorted:
+/// ignoring the case of ASCII letters, with ties broken bytewise.
+fn stardict_cmp(word1: &str, word2: &str) -> cmp::Ordering {
+  let fold = |word: &str| word.bytes().map(|b| b.to_ascii_lowercase()).collect::<Vec<_>>();
+  fold(word1).cmp(&fold(word2)).then_with(|| word1.cmp(word2))
+}
+
+/// Export articles as a StarDict dictionary with the given name.
+pub fn stardict(articles: &[Article], name: &str) -> Result<StarDict> {
+  let mut sorted = articles.iter().collect::<Vec<_>>();
+  sorted.sort_by(|a1, a2| stardict_cmp(&a1.headword, &a2.headword));
+
+  let mut idx = Vec::new();
+  let mut dict = Vec::new();
+  let mut words = 0;
+  for article in sorted {
+    // Headwords are limited to 256 bytes, including the terminator.
+    if article.headword.len() >= 256 || article.headword.contains('\0') {
+      continue
+    }
+    let text = article.lines.join("\n");
+    if dict.len() + text.len() > u32::MAX as usize {
+      return Err(Error::Error("The dictionary is too large for the StarDict format".to_string()))
+    }
+
+    idx.extend_from_slice(article.headword.as_bytes());
+    idx.push(0);
+    idx.extend_from_slice(&(dict.len() as u32).to_be_bytes());
+    idx.extend_from_slice(&(text.len() as u32).to_be_bytes());
+    dict.extend_from_slice(text.as_bytes());
+    words += 1;
+  }
+
+  let ifo = format!(
+    "StarDict's dict ifo file\n\
+     version=2.4.2\n\
+     bookname={name}\n\
+     wordcount={words}\n\
+     idxfilesize={size}\n\
+     sametypesequence=m\n",
+    name = name.replace('\n', " "), words = words, size = idx.len(),
+  );
+
+  Ok(StarDict {
+    ifo,
+    idx,
+    dict: dictzip(&dict)?,
+  })
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::path;
+
+  use miniz_oxide::inflate::core::decompress;
+  use miniz_oxide::inflate::core::inflate_flags::TINFL_FLAG_HAS_MORE_INPUT;
+  use miniz_oxide::inflate::core::inflate_flags::TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF;
+  use miniz_oxide::inflate::core::DecompressorOxide;
+  use miniz_oxide::inflate::decompress_to_vec;
+  use miniz_oxide::inflate::TINFLStatus;
+
+  use config::Config;
+  use entry::Type;
+  use filter::Register;
+  use open;
+
+  /// Extract the compressed chunks from dictzip data.
+  fn chunks(data: &[u8]) -> Vec<&[u8]> {
+    let count = u16::from_le_bytes([data[20], data[21]]) as usize;
+    let sizes = (0..count).map(|i| u16::from_le_bytes([data[22 + 2 * i], data[23 + 2 * i]]));
+    let mut offset = 22 + 2 * count;
+    sizes
+      .map(|size| {
+        let chunk = &data[offset..offset + size as usize];
+        offset += size as usize;
+        chunk
+      })
+      .collect()
+  }
+
+  /// Decompress a single chunk of dictzip data, the way readers do
+  /// for random access.
+  fn inflate(chunk: &[u8]) -> Vec<u8> {
+    let mut decompressor = DecompressorOxide::new();
+    // Leave room beyond a chunk's size, so that a full output buffer
+    // does not stop decompression before the end of the chunk.
+    let mut output = vec![0; CHUNK_LEN + 1];
+    let flags = TINFL_FLAG_HAS_MORE_INPUT | TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF;
+    let (status, consumed, produced) = decompress(&mut decompressor, chunk, &mut output, 0, flags);
+    assert!(status == TINFLStatus::Done || status == TINFLStatus::NeedsMoreInput, "{:?}", status);
+    assert_eq!(consumed, chunk.len());
+    output.truncate(produced);
+    output
+  }
+
+  #[test]
+  fn crc32_check_value() {
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+  }
+
+  #[test]
+  fn dictzip_round_trip() {
+    let data = (0..3 * CHUNK_LEN)
+      .map(|i| format!("{} ", i * 7 % 1013))
+      .collect::<String>()
+      .into_bytes();
+    let compressed = dictzip(&data).unwrap();
+    assert_eq!(&compressed[..4], &[0x1f, 0x8b, 8, 4]);
+    assert_eq!(&compressed[12..14], b"RA");
+    assert_eq!(u16::from_le_bytes([compressed[18], compressed[19]]) as usize, CHUNK_LEN);
+
+    let chunks = chunks(&compressed);
+    assert_eq!(chunks.len(), data.len().div_ceil(CHUNK_LEN));
+
+    // The chunks decompress independently of each other.
+    let mut inflated = Vec::new();
+    for chunk in &chunks {
+      let mut chunk = inflate(chunk);
+      assert!(chunk.len() <= CHUNK_LEN);
+      inflated.append(&mut chunk);
+    }
+    assert_eq!(inflated, data);
+
+    let payload = &compressed[22 + 2 * chunks.len()..compressed.len() - 8];
+    assert_eq!(decompress_to_vec(payload).unwrap(), data);
+
+    let trailer = &compressed[compressed.len() - 8..];
+    assert_eq!(&trailer[..4], &crc32(&data).to_le_bytes());
+    assert_eq!(&trailer[4..], &(data.len() as u32).to_le_bytes());
+  }
+
+  #[test]
+  fn collect_articles() {
+    let connection = open(path::Path::new("./test/test.db")).unwrap();
+    let options = Options::default();
+    let filter = Filter::default();
+    let articles = articles(&connection, &Direction::Lang2ToLang1, &options, &filter).unwrap();
+    let anger = articles.iter().find(|a| a.headword == "anger").unwrap();
+    assert_eq!(anger.lines, vec![
+      "anger (noun): Zorn {m}",
+      "anger (noun): Ärger {m}",
+      "anger (noun): Groll {m} [geh.]",
+      "anger (noun): Ressentiment {n} [geh.]",
+      "anger (noun): Wut {f}",
+    ]);
+
+    let articles = self::articles(&connection, &Direction::Lang1ToLang2, &options, &filter);
+    let worse = articles.unwrap().into_iter().find(|a| a.headword == "schlechter").unwrap();
+    assert_eq!(worse.lines, vec!["schlechter (adj adv): worse"]);
+  }
+
+  #[test]
+  fn filter_entries() {
+    let connection = open(path::Path::new("./test/test.db")).unwrap();
+    let direction = Direction::Lang1ToLang2;
+    let options = Options::default();
+    let filter = Filter::default();
+    let count = entries(&connection, &direction, &options, &filter, |_| Ok(())).unwrap();
+    assert_eq!(count, 29);
+
+    let options = Options {
+      types: vec![Type::Adjective],
+      ..Default::default()
+    };
+    let filter = Filter::new(&[Register::Colloquial], &Config::default()).unwrap();
+    let mut terms = Vec::new();
+    let count = entries(&connection, &direction, &options, &filter, |entry| {
+      terms.push(entry.source.text);
+      Ok(())
+    });
+    assert_eq!(count.unwrap(), 5);
+    terms.sort();
+    assert_eq!(terms, vec!["ekelerregend", "schlechter", "schlimmer", "widerlich", "ärger"]);
+
+    let options = Options {
+      subjects: vec![89],
+      ..Default::default()
+    };
+    let count = entries(&connection, &direction, &options, &Filter::default(), |entry| {
+      assert!(entry.source.text.starts_with("Statistik"));
+      Ok(())
+    });
+    assert_eq!(count.unwrap(), 2);
+  }
+
+  #[test]
+  fn format_records() {
+    let entry = Entry::new("Zorn {m}", "anger", "noun", 42);
+    assert_eq!(csv_record(&entry), "Zorn {m},anger,noun,42\n");
+    assert_eq!(jsonl_record(&entry),
+               "{\"source\":\"Zorn {m}\",\"target\":\"anger\",\"type\":\"noun\",\"usage\":42}\n");
+
+    let entry = Entry::new("jdn./etw. \"knechten\"", "to subjugate sb., sth.", "verb adj", 0);
+    assert_eq!(csv_record(&entry),
+               "\"jdn./etw. \"\"knechten\"\"\",\"to subjugate sb., sth.\",verb adj,0\n");
+  }
+
+  #[test]
+  fn encode_base64() {
+    assert_eq!(base64(0), "A");
+    assert_eq!(base64(63), "/");
+    assert_eq!(base64(64), "BA");
+    assert_eq!(base64(12345), "DA5");
+  }
+
+  #[test]
+  fn export_dictd() {
+    let mut dict = Vec::new();
+    let mut dictd = Dictd::new(&mut dict, "Test").unwrap();
+    dictd.add(&Entry::new("Zorn {m}", "anger", "noun", 42)).unwrap();
+    dictd.add(&Entry::new("abgeben", "to hand in", "verb", 31)).unwrap();
+    dictd.add(&Entry::new("[abgeben]", "to hand in", "verb", 31)).unwrap();
+    let index = dictd.finish().unwrap();
+    let dict = String::from_utf8(dict).unwrap();
+
+    let lines = index.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 6);
+    assert!(lines[0].starts_with("00-database-allchars\t"));
+    assert!(lines[4].starts_with("abgeben\t"));
+    assert!(lines[5].starts_with("Zorn\t"));
+
+    let decode = |digits: &str| {
+      digits.bytes().fold(0, |n, d| n * 64 + BASE64.iter().position(|&b| b == d).unwrap())
+    };
+    let fields = lines[5].split('\t').collect::<Vec<_>>();
+    let (offset, len) = (decode(fields[1]), decode(fields[2]));
+    assert_eq!(&dict[offset..offset + len], "Zorn\n   Zorn {m} (noun): anger\n\n");
+
+    let fields = lines[2].split('\t').collect::<Vec<_>>();
+    let (offset, len) = (decode(fields[1]), decode(fields[2]));
+    assert_eq!(&dict[offset..offset + len], "00-database-short\n     Test\n");
+  }
+
+  #[test]
+  fn export_stardict() {
+    let articles = vec![
+      Article {
+        headword: "b".to_string(),
+        lines: vec!["b (noun): 2".to_string()],
+      },
+      Article {
+        headword: "A".to_string(),
+        lines: vec!["A (noun): 1".to_string(), "A (verb): 3".to_string()],
+      },
+    ];
+    let dict = stardict(&articles, "Test").unwrap();
+    assert_eq!(dict.ifo, "StarDict's dict ifo file\n\
+                          version=2.4.2\n\
+                          bookname=Test\n\
+                          wordcount=2\n\
+                          idxfilesize=20\n\
+                          sametypesequence=m\n");
+
+    assert_eq!(dict.idx.len(), 20);
+    assert_eq!(&dict.idx[..10], b"A\0\0\0\0\0\0\0\0\x17");
+    assert_eq!(&dict.idx[10..], b"b\0\0\0\0\x17\0\0\0\x0b");
+
+    let text = inflate(chunks(&dict.dict)[0]);
+    assert_eq!(text, b"A (noun): 1\nA (verb): 3b (noun): 2".to_vec());
+  }
+}
@@ -0,0 +1,482 @@
+// fetch.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Download and installation of databases.
+//!
+//! dict.cc offers the vocabulary of a language pair for download via a
+//! personal link, as a zip archive containing a text file. We download
+//! it using `curl`, verify its SHA-256 checksum if one was given, unpack
+//! it, and install it into the directory databases are searched in by
+//! default. Text files are imported into a new database first, while
+//! archives already containing a database are installed as they are.
+
+use std::fs;
+use std::path;
+use std::process;
+
+use miniz_oxide::inflate::decompress_to_vec_with_limit;
+
+use sha2::Digest;
+use sha2::Sha256;
+
+use export::crc32;
+use import;
+use Error;
+use Result;
+
+/// The first bytes of every SQLite database file.
+const SQLITE_MAGIC: &[u8] = b"SQLite format 3\0";
+/// The first bytes of gzip compressed data.
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+/// The signature of a local file header of a zip archive.
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+/// The signature of the end of central directory record of a zip
+/// archive.
+const ZIP_END: &[u8] = b"PK\x05\x06";
+/// The signature of a central directory file header of a zip archive.
+const ZIP_ENTRY: &[u8] = b"PK\x01\x02";
+/// The maximum size of a file we are willing to unpack. Even the
+/// largest vocabulary exports of dict.cc are well below it, but it
+/// protects against archives that expand to exhaust memory.
+const MAX_UNPACKED: usize = 1 << 30;
+
+
+/// The contents of a downloaded file, once unpacked.
+#[derive(Debug, PartialEq)]
+pub enum Content {
+  /// An SQLite database.
+  Database(Vec<u8>),
+  /// A vocabulary export of dict.cc, to be imported.
+  Export(String),
+}
+
+impl Content {
+  /// Classify unpacked data.
+  pub fn new(data: Vec<u8>) -> Result<Content> {
+    if data.starts_with(SQLITE_MAGIC) {
+      Ok(Content::Database(data))
+    } else {
+      String::from_utf8(data)
+        .map(Content::Export)
+        .map_err(|_| Error::Error("Downloaded file is neither a database nor a text file".to_string()))
+    }
+  }
+}
+
+
+/// Format the SHA-256 digest of some data in hexadecimal.
+pub fn checksum(data: &[u8]) -> String {
+  Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Verify that data have the given SHA-256 checksum, in hexadecimal
+/// and optionally prefixed with "sha256:".
+pub fn verify(data: &[u8], expected: &str) -> Result<()> {
+  let expected = expected.trim();
+  let expected = expected.strip_prefix("sha256:").unwrap_or(expected);
+  let actual = checksum(data);
+  if !actual.eq_ignore_ascii_case(expected) {
+    return Err(Error::Error(format!(
+      "Checksum mismatch: expected {}, got {}", expected, actual
+    )))
+  }
+  Ok(())
+}
+
+/// Read a little endian 16 bit integer at the given offset.
+fn u16_at(data: &[u8], offset: usize) -> Result<usize> {
+  data
+    .get(offset..offset + 2)
+    .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+    .ok_or_else(|| Error::Error("Archive is truncated".to_string()))
+}
+
+/// Read a little endian 32 bit integer at the given offset.
+fn u32_at(data: &[u8], offset: usize) -> Result<usize> {
+  data
+    .get(offset..offset + 4)
+    .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+    .ok_or_else(|| Error::Error("Archive is truncated".to_string()))
+}
+
+/// Check the size of a file as declared by an archive against the
+/// maximum we unpack.
+fn check_size(size: usize) -> Result<usize> {
+  if size > MAX_UNPACKED {
+    return Err(Error::Error(format!(
+      "Archive contains a file of {} bytes, exceeding the maximum of {}",
+      size, MAX_UNPACKED
+    )))
+  }
+  Ok(size)
+}
+
+/// Inflate raw deflate data, which are expected to expand to exactly
+/// the given number of bytes.
+fn inflate(data: &[u8], size: usize) -> Result<Vec<u8>> {
+  let content = decompress_to_vec_with_limit(data, size)
+    .map_err(|e| Error::Error(format!("Failed to decompress archive: {:?}", e.status)))?;
+  if content.len() != size {
+    return Err(Error::Error("Archive is corrupted: size mismatch".to_string()))
+  }
+  Ok(content)
+}
+
+/// Decompress gzip compressed data.
+fn gunzip(data: &[u8]) -> Result<Vec<u8>> {
+  const FHCRC: u8 = 0x02;
+  const FEXTRA: u8 = 0x04;
+  const FNAME: u8 = 0x08;
+  const FCOMMENT: u8 = 0x10;
+
+  let truncated = || Error::Error("Archive is truncated".to_string());
+  if data.len() < 18 || data[2] != 8 {
+    return Err(Error::Error("Unsupported gzip compression".to_string()))
+  }
+  let flags = data[3];
+  let mut offset = 10;
+  if flags & FEXTRA != 0 {
+    offset += 2 + u16_at(data, offset)?;
+  }
+  for flag in &[FNAME, FCOMMENT] {
+    if flags & flag != 0 {
+      let end = data.get(offset..).and_then(|d| d.iter().position(|&b| b == 0));
+      offset += end.ok_or_else(truncated)? + 1;
+    }
+  }
+  if flags & FHCRC != 0 {
+    offset += 2;
+  }
+
+  // The trailer stores the size of the uncompressed data modulo 2^32,
+  // which is exact for anything we are willing to unpack.
+  let trailer = data.len() - 8;
+  let payload = data.get(offset..trailer).ok_or_else(truncated)?;
+  let size = check_size(u32_at(data, trailer + 4)?)?;
+  let content = inflate(payload, size)?;
+  if u32_at(data, trailer)? as u32 != crc32(&content) {
+    return Err(Error::Error("Archive is corrupted: checksum mismatch".to_string()))
+  }
+  Ok(content)
+}
+
+/// Extract the file from a zip archive, returning its name along with
+/// its content. If the archive contains more than one file, the first
+/// text file or database is extracted.
+fn unzip(data: &[u8]) -> Result<(String, Vec<u8>)> {
+  // The end of central directory record is at least 22 bytes in size
+  // and may be followed by a comment.
+  let end = data
+    .windows(ZIP_END.len())
+    .rposition(|window| window == ZIP_END)
+    .ok_or_else(|| Error::Error("Archive lacks a central directory".to_string()))?;
+  let count = u16_at(data, end + 10)?;
+  let mut offset = u32_at(data, end + 16)?;
+
+  let mut files = Vec::new();
+  for _ in 0..count {
+    if data.get(offset..offset + 4) != Some(ZIP_ENTRY) {
+      return Err(Error::Error("Archive has an invalid central directory".to_string()))
+    }
+    let method = u16_at(data, offset + 10)?;
+    let crc = u32_at(data, offset + 16)? as u32;
+    let size = u32_at(data, offset + 20)?;
+    let unpacked = u32_at(data, offset + 24)?;
+    let name_len = u16_at(data, offset + 28)?;
+    let extra_len = u16_at(data, offset + 30)?;
+    let comment_len = u16_at(data, offset + 32)?;
+    let header = u32_at(data, offset + 42)?;
+    let name = data
+      .get(offset + 46..offset + 46 + name_len)
+      .map(|name| String::from_utf8_lossy(name).into_owned())
+      .ok_or_else(|| Error::Error("Archive is truncated".to_string()))?;
+    offset += 46 + name_len + extra_len + comment_len;
+
+    if !name.ends_with('/') {
+      files.push((name, method, crc, size, unpacked, header));
+    }
+  }
+
+  let wanted = |name: &str| name.ends_with(".txt") || name.ends_with(".db");
+  let index = files.iter().position(|(name, ..)| wanted(name)).unwrap_or(0);
+  let (name, method, crc, size, unpacked, header) = files
+    .into_iter()
+    .nth(index)
+    .ok_or_else(|| Error::Error("Archive is empty".to_string()))?;
+
+  // The sizes of the name and extra field may differ between the local
+  // header and the central directory.
+  let start = header + 30 + u16_at(data, header + 26)? + u16_at(data, header + 28)?;
+  let compressed = data
+    .get(start..start + size)
+    .ok_or_else(|| Error::Error("Archive is truncated".to_string()))?;
+  let unpacked = check_size(unpacked)?;
+  let content = match method {
+    0 if size == unpacked => compressed.to_vec(),
+    0 => return Err(Error::Error("Archive is corrupted: size mismatch".to_string())),
+    8 => inflate(compressed, unpacked)?,
+    _ => return Err(Error::Error(format!("Unsupported zip compression method {}", method))),
+  };
+  if crc32(&content) != crc {
+    return Err(Error::Error("Archive is corrupted: checksum mismatch".to_string()))
+  }
+  Ok((name, content))
+}
+
+/// Unpack downloaded data, if compressed, returning the name of the
+/// contained file, if known, along with the content.
+pub fn unpack(data: Vec<u8>) -> Result<(Option<String>, Content)> {
+  let (name, data) = if data.starts_with(ZIP_MAGIC) {
+    let (name, content) = unzip(&data)?;
+    (Some(name), content)
+  } else if data.starts_with(GZIP_MAGIC) {
+    (None, gunzip(&data)?)
+  } else {
+    (None, data)
+  };
+  Ok((name, Content::new(data)?))
+}
+
+/// Derive the name of the database to install from the name of the
+/// downloaded file, e.g., "de-en.db" for "de-en.txt.zip".
+pub fn database_name(file: &str) -> String {
+  let file = file.rsplit('/').next().unwrap_or(file);
+  let file = file.split(['?', '#']).next().unwrap_or(file);
+  let stem = file.split('.').next().unwrap_or(file);
+  if stem.is_empty() {
+    "dictcc.db".to_string()
+  } else {
+    format!("{}.db", stem)
+  }
+}
+
+/// Download a file using `curl`.
+pub fn download(url: &str) -> Result<Vec<u8>> {
+  let output = process::Command::new("curl")
+    .arg("--silent")
+    .arg("--show-error")
+    .arg("--fail")
+    .arg("--location")
+    .arg("--")
+    .arg(url)
+    .stdin(process::Stdio::null())
+    .stderr(process::Stdio::inherit())
+    .output()
+    .map_err(|e| Error::Io("Failed to run curl".to_string(), e))?;
+  if !output.status.success() {
+    return Err(Error::Error(format!("Download of {} failed", url)))
+  }
+  Ok(output.stdout)
+}
+
+/// Install unpacked content as the given database, importing it first
+/// if necessary. Returns the number of entries imported, if any.
+///
+/// The database is created next to its final location and only moved
+/// there once complete.
+pub fn install(content: &Content, db: &path::Path, force: bool) -> Result<Option<usize>> {
+  if db.exists() && !force {
+    return Err(Error::Error(format!("Database {} already exists", db.display())))
+  }
+  if let Some(dir) = db.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+    fs::create_dir_all(dir)
+      .map_err(|e| Error::Io(format!("Failed to create {}", dir.display()), e))?;
+  }
+
+  let partial = db.with_extension("db.partial");
+  let _ = fs::remove_file(&partial);
+  let count = match *content {
+    Content::Database(ref data) => {
+      fs::write(&partial, data)
+        .map_err(|e| Error::Io(format!("Failed to write {}", partial.display()), e))?;
+      None
+    },
+    Content::Export(ref text) => Some(import::create(&partial, text)?),
+  };
+  fs::rename(&partial, db)
+    .map_err(|e| Error::Io(format!("Failed to install {}", db.display()), e))?;
+  Ok(count)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::env;
+
+  use miniz_oxide::deflate::compress_to_vec;
+
+  use export::dictzip;
+
+  const EXPORT: &str = "\
+# de-en vocabulary database\tcompiled by dict.cc
+Zorn {m}\tanger\tnoun\t
+Wut {f}\tanger\tnoun\t[psych.]
+";
+
+
+  /// Create a zip archive containing the given files, compressed.
+  fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut archive = Vec::new();
+    let mut directory = Vec::new();
+    for (name, content) in files {
+      let compressed = compress_to_vec(content, 6);
+      let mut header = Vec::new();
+      header.extend_from_slice(&8u16.to_le_bytes());
+      header.extend_from_slice(&[0; 4]);
+      header.extend_from_slice(&crc32(content).to_le_bytes());
+      header.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+      header.extend_from_slice(&(content.len() as u32).to_le_bytes());
+      header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+      header.extend_from_slice(&[0; 2]);
+
+      directory.extend_from_slice(ZIP_ENTRY);
+      directory.extend_from_slice(&[20, 0, 20, 0, 0, 0]);
+      directory.extend_from_slice(&header);
+      directory.extend_from_slice(&[0; 10]);
+      directory.extend_from_slice(&(archive.len() as u32).to_le_bytes());
+      directory.extend_from_slice(name.as_bytes());
+
+      archive.extend_from_slice(ZIP_MAGIC);
+      archive.extend_from_slice(&[20, 0, 0, 0]);
+      archive.extend_from_slice(&header);
+      archive.extend_from_slice(name.as_bytes());
+      archive.extend_from_slice(&compressed);
+    }
+
+    let offset = archive.len();
+    archive.extend_from_slice(&directory);
+    archive.extend_from_slice(ZIP_END);
+    archive.extend_from_slice(&[0; 4]);
+    archive.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    archive.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    archive.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+    archive.extend_from_slice(&(offset as u32).to_le_bytes());
+    archive.extend_from_slice(&[0; 2]);
+    archive
+  }
+
+  #[test]
+  fn sha256_digests() {
+    assert_eq!(
+      checksum(b""),
+      "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert_eq!(
+      checksum(b"abc"),
+      "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    // Two blocks worth of padded data.
+    assert_eq!(
+      checksum(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+      "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+    );
+  }
+
+  #[test]
+  fn verify_checksums() {
+    let sum = "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD";
+    assert!(verify(b"abc", sum).is_ok());
+    assert!(verify(b"abc", &format!("sha256:{}\n", sum.to_lowercase())).is_ok());
+
+    let err = verify(b"abd", sum).unwrap_err().to_string();
+    assert!(err.starts_with("Checksum mismatch"), "{}", err);
+  }
+
+  #[test]
+  fn unpack_archives() {
+    let (name, content) = unpack(EXPORT.as_bytes().to_vec()).unwrap();
+    assert_eq!(name, None);
+    assert_eq!(content, Content::Export(EXPORT.to_string()));
+
+    let (name, content) = unpack(dictzip(EXPORT.as_bytes()).unwrap()).unwrap();
+    assert_eq!(name, None);
+    assert_eq!(content, Content::Export(EXPORT.to_string()));
+
+    let archive = zip(&[("README", b"Personal use only"), ("de-en.txt", EXPORT.as_bytes())]);
+    let (name, content) = unpack(archive).unwrap();
+    assert_eq!(name.as_deref(), Some("de-en.txt"));
+    assert_eq!(content, Content::Export(EXPORT.to_string()));
+
+    let db = fs::read("./test/test.db").unwrap();
+    let (name, content) = unpack(zip(&[("dictcc-lp1.db", &db)])).unwrap();
+    assert_eq!(name.as_deref(), Some("dictcc-lp1.db"));
+    assert_eq!(content, Content::Database(db));
+
+    let mut corrupted = dictzip(EXPORT.as_bytes()).unwrap();
+    let len = corrupted.len();
+    corrupted[len - 8] ^= 0xff;
+    assert!(unpack(corrupted).is_err());
+    assert!(unpack(vec![0xff, 0xfe, 0xfd]).is_err());
+  }
+
+  /// Check that archives declaring sizes other than those of their
+  /// content are rejected instead of being unpacked without bounds.
+  #[test]
+  fn unpack_size_mismatches() {
+    let archive = zip(&[("de-en.txt", EXPORT.as_bytes())]);
+    let entry = archive
+      .windows(ZIP_ENTRY.len())
+      .position(|window| window == ZIP_ENTRY)
+      .unwrap();
+    for size in &[EXPORT.len() as u32 - 1, EXPORT.len() as u32 + 1] {
+      let mut archive = archive.clone();
+      archive[entry + 24..entry + 28].copy_from_slice(&size.to_le_bytes());
+      let err = unpack(archive).unwrap_err().to_string();
+      assert!(err.contains("decompress") || err.contains("size mismatch"), "{}", err);
+    }
+
+    let mut bomb = archive.clone();
+    bomb[entry + 24..entry + 28].copy_from_slice(&u32::MAX.to_le_bytes());
+    let err = unpack(bomb).unwrap_err().to_string();
+    assert!(err.contains("exceeding the maximum"), "{}", err);
+
+    let mut corrupted = dictzip(EXPORT.as_bytes()).unwrap();
+    let len = corrupted.len();
+    corrupted[len - 4..].copy_from_slice(&(EXPORT.len() as u32 - 1).to_le_bytes());
+    assert!(unpack(corrupted).is_err());
+  }
+
+  #[test]
+  fn derive_database_names() {
+    assert_eq!(database_name("de-en.txt"), "de-en.db");
+    assert_eq!(database_name("https://example.com/dl/de-en.txt.zip?id=42"), "de-en.db");
+    assert_eq!(database_name("dictcc-lp1.db"), "dictcc-lp1.db");
+    assert_eq!(database_name("https://example.com/"), "dictcc.db");
+  }
+
+  #[test]
+  fn install_databases() {
+    let dir = env::temp_dir().join(format!("dictcc-cli-fetch-{}", process::id()));
+    let db = dir.join("de-en.db");
+    let content = Content::Export(EXPORT.to_string());
+    assert_eq!(install(&content, &db, false).unwrap(), Some(2));
+    assert!(!db.with_extension("db.partial").exists());
+
+    let err = install(&content, &db, false).unwrap_err().to_string();
+    assert!(err.contains("already exists"), "{}", err);
+
+    let data = fs::read("./test/test.db").unwrap();
+    let content = Content::Database(data.clone());
+    assert_eq!(install(&content, &db, true).unwrap(), None);
+    assert_eq!(fs::read(&db).unwrap(), data);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
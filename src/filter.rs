@@ -0,0 +1,137 @@
+// filter.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Filtering of results based on the annotations of their terms.
+//!
+//! Terms carry annotations in square brackets, e.g., "bekloppt [ugs.]",
+//! some of which denote the register a term belongs to. Entries with
+//! either term carrying one of the tags of an excluded register are
+//! dropped. The tags of each register are configured in the `filters`
+//! table of the configuration, e.g., `colloquial = ["ugs.", "coll."]`.
+
+use config::Config;
+use Result;
+
+
+/// A register entries can be excluded by.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Register {
+  Colloquial,
+  Vulgar,
+  Archaic,
+}
+
+impl Register {
+  /// All registers we know of.
+  pub const ALL: [Register; 3] = [Register::Colloquial, Register::Vulgar, Register::Archaic];
+
+  /// Retrieve the name of the register, as used in options and in the
+  /// configuration.
+  pub fn name(self) -> &'static str {
+    match self {
+      Register::Colloquial => "colloquial",
+      Register::Vulgar => "vulgar",
+      Register::Archaic => "archaic",
+    }
+  }
+}
+
+
+/// A filter dropping entries annotated with any of a set of tags.
+#[derive(Debug, Default)]
+pub struct Filter {
+  /// The (lower case) tags to exclude, without brackets.
+  tags: Vec<String>,
+}
+
+impl Filter {
+  /// Create a filter excluding the given registers, using the tags as
+  /// configured.
+  pub fn new(registers: &[Register], config: &Config) -> Result<Filter> {
+    let mut tags = Vec::new();
+    for register in registers {
+      let key = format!("filters.{}", register.name());
+      tags.extend(config.get_strings(&key)?.unwrap_or_default());
+    }
+
+    Ok(Filter {
+      tags: tags
+        .iter()
+        .map(|t| t.trim_matches(|c| c == '[' || c == ']').to_lowercase())
+        .collect(),
+    })
+  }
+
+  /// Check whether the filter drops nothing at all.
+  pub fn is_empty(&self) -> bool {
+    self.tags.is_empty()
+  }
+
+  /// Check whether an entry passes the filter.
+  pub fn admits(&self, src_term: &str, dst_term: &str) -> bool {
+    !annotations(src_term)
+      .chain(annotations(dst_term))
+      .any(|a| self.tags.contains(&a.trim().to_lowercase()))
+  }
+}
+
+
+/// Retrieve the square bracket annotations of a term, without the
+/// brackets.
+fn annotations(term: &str) -> impl Iterator<Item = &str> {
+  term
+    .split('[')
+    .skip(1)
+    .filter_map(|part| part.find(']').map(|end| &part[..end]))
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use config;
+
+  #[test]
+  fn find_annotations() {
+    let found = annotations("deppert [österr.] [südd.]").collect::<Vec<_>>();
+    assert_eq!(found, vec!["österr.", "südd."]);
+    assert_eq!(annotations("dorky").count(), 0);
+  }
+
+  #[test]
+  fn filter_registers() {
+    let filter = Filter::new(&[Register::Colloquial], &Config::default()).unwrap();
+    assert!(!filter.admits("bekloppt [ugs.]", "dorky [coll.]"));
+    assert!(!filter.admits("idiotisch", "dorky [Coll.]"));
+    assert!(filter.admits("Groll {m} [geh.]", "anger"));
+
+    let filter = Filter::default();
+    assert!(filter.is_empty());
+    assert!(filter.admits("bekloppt [ugs.]", "dorky [coll.]"));
+  }
+
+  #[test]
+  fn configured_tags() {
+    let config = config::parse("[filters]\narchaic = [\"[geh.]\"]").unwrap();
+    let filter = Filter::new(&[Register::Archaic], &config).unwrap();
+    assert!(!filter.admits("Groll {m} [geh.]", "anger"));
+    assert!(filter.admits("Zorn {m}", "anger [obs.]"));
+  }
+}
@@ -0,0 +1,232 @@
+// fulltext.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! The full-text index of the search table.
+//!
+//! The databases of dict.cc come with an FTS3 index, the tokenizer of
+//! which folds the case of ASCII letters only. Hence, it is of no help
+//! for narrowing down lookups of terms containing umlauts and the like.
+//! The `migrate` command converts the search table into an FTS5 table
+//! using the unicode61 tokenizer, which folds the case of all letters
+//! and, optionally, removes diacritics, without requiring SQLite to be
+//! built with ICU support.
+
+use sqlite;
+
+use check::table_sql;
+use schema;
+use subjects::table_columns;
+use transaction;
+use Error;
+use Result;
+use SEARCH_TBL;
+use TERM1_COL;
+use TERM2_COL;
+
+/// The name of the table the search table is migrated into, before
+/// taking its place.
+const MIGRATED_TBL: &str = "main_ft_migrated";
+/// The value of the unicode61 tokenizer's remove_diacritics option if
+/// not specified.
+const DEFAULT_REMOVE_DIACRITICS: u8 = 1;
+
+
+/// The kind of full-text index of a database.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FullText {
+  /// An index folding the case of ASCII letters only, as those of FTS3
+  /// and FTS4 tables do by default.
+  Ascii,
+  /// An index folding the case of all letters, as the unicode61
+  /// tokenizer does, removing diacritics as per its option.
+  Unicode {
+    /// The value of the remove_diacritics option.
+    remove_diacritics: u8,
+  },
+}
+
+impl FullText {
+  /// Detect the kind of full-text index of the search table, if it has
+  /// one usable for lookups.
+  ///
+  /// Some copies of the database lack the index, e.g., because it was
+  /// dropped to save space, in which case matching with it fails. Nor
+  /// can the index be used if the search table got mapped onto a view.
+  pub fn detect(connection: &sqlite::Connection) -> Result<Option<FullText>> {
+    if schema::is_adapted(connection)? {
+      return Ok(None)
+    }
+    let sql = match table_sql(connection, SEARCH_TBL)? {
+      Some(sql) => sql.to_lowercase(),
+      None => return Ok(None),
+    };
+
+    // FTS3 and FTS4 tables keep their index in the segdir table, FTS5
+    // ones in the data table.
+    let index = if sql.contains("using fts5") { "data" } else { "segdir" };
+    if table_sql(connection, &format!("{}_{}", SEARCH_TBL, index))?.is_none() {
+      return Ok(None)
+    }
+
+    if !sql.contains("unicode61") {
+      return Ok(Some(FullText::Ascii))
+    }
+    let remove_diacritics = sql
+      .find("remove_diacritics")
+      .and_then(|i| {
+        sql[i + "remove_diacritics".len()..]
+          .trim_start_matches(|c: char| c.is_whitespace() || c == '=')
+          .chars()
+          .next()
+      })
+      .and_then(|c| c.to_digit(10))
+      .map(|digit| digit as u8)
+      .unwrap_or(DEFAULT_REMOVE_DIACRITICS);
+    Ok(Some(FullText::Unicode { remove_diacritics }))
+  }
+}
+
+
+/// Convert the search table into an FTS5 table using the unicode61
+/// tokenizer with the given remove_diacritics option, returning the
+/// number of entries migrated.
+///
+/// Only the terms get indexed. Entries keep their row ids, so that
+/// tables referencing them (e.g., the headwords table) stay valid.
+pub fn migrate(connection: &sqlite::Connection, remove_diacritics: u8) -> Result<usize> {
+  if remove_diacritics > 2 {
+    return Err(Error::Error(format!("Invalid remove_diacritics value: {}", remove_diacritics)))
+  }
+  let columns = table_columns(connection, SEARCH_TBL)?;
+  if !columns.iter().any(|c| c == TERM1_COL) || !columns.iter().any(|c| c == TERM2_COL) {
+    return Err(Error::InvalidSchema(format!(
+      "The search table {} is missing or lacks the term columns",
+      SEARCH_TBL,
+    )))
+  }
+
+  let definitions = columns
+    .iter()
+    .map(|c| if c == TERM1_COL || c == TERM2_COL {
+      c.clone()
+    } else {
+      format!("{} UNINDEXED", c)
+    })
+    .collect::<Vec<_>>();
+
+  transaction(connection, || {
+    connection.execute(format!(
+      "CREATE VIRTUAL TABLE {new} USING \
+         fts5({defs}, tokenize = 'unicode61 remove_diacritics {remove}');\
+       INSERT INTO {new} (rowid, {cols}) SELECT rowid, {cols} FROM {tbl};\
+       DROP TABLE {tbl};\
+       ALTER TABLE {new} RENAME TO {tbl};",
+      new = MIGRATED_TBL, defs = definitions.join(", "), remove = remove_diacritics,
+      cols = columns.join(", "), tbl = SEARCH_TBL,
+    ))?;
+
+    let mut statement = connection.prepare(format!("SELECT COUNT(*) FROM {}", SEARCH_TBL))?;
+    statement.next()?;
+    Ok(statement.read::<i64>(0)? as usize)
+  })
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::env;
+  use std::fs;
+  use std::path;
+  use std::process;
+
+  use open_writable;
+  use optimize;
+  use term_select;
+  use translate_with;
+  use Database;
+  use Direction;
+  use Options;
+
+
+  #[test]
+  fn detect_fulltext() {
+    let connection = open_writable(path::Path::new("./test/test.db")).unwrap();
+    assert_eq!(FullText::detect(&connection).unwrap(), Some(FullText::Ascii));
+
+    let connection = sqlite::open(":memory:").unwrap();
+    assert_eq!(FullText::detect(&connection).unwrap(), None);
+    connection.execute(
+      "CREATE VIRTUAL TABLE main_ft USING fts4(term1, term2, tokenize=unicode61 \"remove_diacritics=0\")",
+    ).unwrap();
+    assert_eq!(
+      FullText::detect(&connection).unwrap(),
+      Some(FullText::Unicode { remove_diacritics: 0 })
+    );
+  }
+
+  #[test]
+  fn migrate_to_fts5() {
+    let db = env::temp_dir().join(format!("dictcc-cli-migrate-{}.db", process::id()));
+    fs::copy("./test/test.db", &db).unwrap();
+    let connection = open_writable(&db).unwrap();
+    optimize::build(&connection).unwrap();
+    assert!(migrate(&connection, 3).is_err());
+    assert_eq!(migrate(&connection, 2).unwrap(), 29);
+    assert_eq!(
+      FullText::detect(&connection).unwrap(),
+      Some(FullText::Unicode { remove_diacritics: 2 })
+    );
+    drop(connection);
+
+    let database = Database::open(&db).unwrap();
+    let index = database.fulltext_index();
+    let options = Options::default();
+    let (query, _) = term_select(&["ärger"], &Direction::Lang1ToLang2, &options, index, false, false).unwrap();
+    assert!(query.contains("MATCH"));
+
+    let lookup = |db: &path::Path, term: &str, options: &Options| {
+      let mut found = Vec::new();
+      translate_with(db, &[term], &Direction::Lang1ToLang2, options, |e| {
+        found.push(e.clone());
+        Ok(())
+      }).unwrap();
+      found
+    };
+    let exact = Options {
+      exact: true,
+      ..Default::default()
+    };
+    let ignore_accents = Options {
+      ignore_accents: true,
+      ..Default::default()
+    };
+    for term in &["Zorn", "ärger", "ÄRGER", "Statistik", "Arger"] {
+      for options in &[Options::default(), exact.clone(), ignore_accents.clone()] {
+        let migrated = lookup(&db, term, options);
+        let original = lookup(path::Path::new("./test/test.db"), term, options);
+        assert_eq!(migrated, original, "{}", term);
+      }
+    }
+    assert!(!lookup(&db, "ärger", &Options::default()).is_empty());
+    assert!(!lookup(&db, "Arger", &ignore_accents).is_empty());
+    fs::remove_file(&db).unwrap();
+  }
+}
@@ -0,0 +1,207 @@
+// glossary.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Terminology consistency checking of translated documents.
+//!
+//! A glossary maps source terms to the target terms a translator is
+//! supposed to use. Using the dictionary we determine which other
+//! translations exist for each source term and flag all places in a
+//! document where one of those is used instead of the prescribed one.
+
+use std::path;
+
+use term::find_word;
+use translate_with;
+use Direction;
+use Error;
+use Options;
+use Result;
+
+
+/// An entry of a glossary.
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+  /// The term in the source language.
+  pub source: String,
+  /// The term in the target language that should be used.
+  pub target: String,
+}
+
+/// A use of a translation deviating from the glossary.
+#[derive(Debug, PartialEq)]
+pub struct Deviation {
+  /// The (1-based) line number the deviation was found in.
+  pub line: usize,
+  /// The term that was used.
+  pub found: String,
+  /// The glossary entry that was violated.
+  pub source: String,
+  /// The term that should have been used.
+  pub expected: String,
+}
+
+
+/// Parse a glossary in the form of tab separated values.
+///
+/// Each line contains a source term and a target term, separated by a
+/// tab. Empty lines and lines starting with `#` are ignored.
+pub fn parse(content: &str) -> Result<Vec<Entry>> {
+  let mut entries = Vec::new();
+
+  for (i, line) in content.lines().enumerate() {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+
+    let mut fields = line.split('\t');
+    match (fields.next(), fields.next()) {
+      (Some(source), Some(target)) if !source.trim().is_empty() && !target.trim().is_empty() => {
+        entries.push(Entry {
+          source: source.trim().to_string(),
+          target: target.trim().to_string(),
+        })
+      },
+      _ => return Err(Error::Error(format!("Invalid glossary entry in line {}: {}", i + 1, line))),
+    }
+  }
+  Ok(entries)
+}
+
+/// Check a document for deviations from the given glossary.
+pub fn check(db: &path::Path,
+             glossary: &[Entry],
+             document: &str,
+             direction: &Direction) -> Result<Vec<Deviation>> {
+  let mut deviations = Vec::new();
+  // Only translations of the source term itself are alternatives to the
+  // prescribed one, not those of phrases merely containing it.
+  let options = Options {
+    exact: true,
+    ..Default::default()
+  };
+
+  for entry in glossary {
+    let expected = entry.target.to_lowercase();
+    let mut alternatives = Vec::<String>::new();
+    translate_with(db, &[&entry.source], direction, &options, |found| {
+      let alternative = found.target.headword.to_lowercase();
+      // Alternatives that contain the prescribed term (or vice versa)
+      // cannot be told apart reliably and so we skip them.
+      if !alternative.is_empty() &&
+         !alternative.contains(&expected) &&
+         !expected.contains(&alternative) &&
+         !alternatives.contains(&alternative) {
+        alternatives.push(alternative);
+      }
+      Ok(())
+    })?;
+
+    for (i, line) in document.lines().enumerate() {
+      let line = line.to_lowercase();
+      for alternative in &alternatives {
+        if !find_word(&line, alternative).is_empty() {
+          deviations.push(Deviation {
+            line: i + 1,
+            found: alternative.clone(),
+            source: entry.source.clone(),
+            expected: entry.target.clone(),
+          })
+        }
+      }
+    }
+  }
+
+  deviations.sort_by_key(|d| d.line);
+  Ok(deviations)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_glossary() {
+    let glossary = parse("# comment\nnauseating\tekelerregend\n\nlove\tLiebe\n").unwrap();
+    assert_eq!(
+      glossary,
+      vec![
+        Entry {
+          source: "nauseating".to_string(),
+          target: "ekelerregend".to_string(),
+        },
+        Entry {
+          source: "love".to_string(),
+          target: "Liebe".to_string(),
+        },
+      ]
+    );
+    assert!(parse("nauseating").is_err());
+  }
+
+  #[test]
+  fn check_document() {
+    let db = path::Path::new("./test/test.db");
+    let glossary = parse("nauseating\tekelerregend\ndorky\tidiotisch\n").unwrap();
+    let document = "Das Essen war ekelerregend.\n\
+                    Der Geruch war widerlich und\n\
+                    der Kellner ziemlich bekloppt.\n";
+    let deviations = check(db, &glossary, document, &Direction::Lang2ToLang1).unwrap();
+    assert_eq!(
+      deviations,
+      vec![
+        Deviation {
+          line: 2,
+          found: "widerlich".to_string(),
+          source: "nauseating".to_string(),
+          expected: "ekelerregend".to_string(),
+        },
+        Deviation {
+          line: 3,
+          found: "bekloppt".to_string(),
+          source: "dorky".to_string(),
+          expected: "idiotisch".to_string(),
+        },
+      ]
+    );
+  }
+
+  /// Check that translations of phrases merely containing a source term
+  /// are not taken for alternatives to the prescribed one.
+  #[test]
+  fn check_document_with_phrases() {
+    let db = path::Path::new("./test/test.db");
+    let glossary = parse("poor\tbedürftig\nto subjugate\tunterwerfen\n").unwrap();
+    let document = "Die Arme war bedürftig.\n\
+                    Sie wollten das Volk bezwingen.\n";
+    let deviations = check(db, &glossary, document, &Direction::Lang2ToLang1).unwrap();
+    assert_eq!(
+      deviations,
+      vec![
+        Deviation {
+          line: 2,
+          found: "bezwingen".to_string(),
+          source: "to subjugate".to_string(),
+          expected: "unterwerfen".to_string(),
+        },
+      ]
+    );
+  }
+}
@@ -0,0 +1,152 @@
+// hooks.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Support for running external commands on certain events.
+//!
+//! Hooks are configured in the `hooks` table of the configuration file,
+//! e.g., `on_lookup = "logger -t dictcc"`. Commands are run by the shell
+//! with the results (in the JSON format used for saving them) passed on
+//! standard input. The event, the query, and the number of results are
+//! additionally provided in the `DICTCC_EVENT`, `DICTCC_QUERY`, and
+//! `DICTCC_COUNT` environment variables.
+
+use std::io::Write;
+use std::process;
+
+use config::Config;
+use results::Results;
+use Error;
+use Result;
+
+
+/// An event hooks can be run on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+  /// A lookup was performed.
+  Lookup,
+  /// A lookup did not yield any results.
+  NoResult,
+}
+
+impl Event {
+  /// Retrieve the name of the event.
+  pub fn name(self) -> &'static str {
+    match self {
+      Event::Lookup => "lookup",
+      Event::NoResult => "no_result",
+    }
+  }
+}
+
+
+/// The commands to run on events.
+#[derive(Debug, Default)]
+pub struct Hooks {
+  on_lookup: Option<String>,
+  on_no_result: Option<String>,
+}
+
+impl Hooks {
+  /// Retrieve the hooks from the configuration.
+  pub fn new(config: &Config) -> Result<Hooks> {
+    let hook = |event: Event| -> Result<Option<String>> {
+      let key = format!("hooks.on_{}", event.name());
+      Ok(config.get_string(&key)?.map(|c| c.to_string()))
+    };
+
+    Ok(Hooks {
+      on_lookup: hook(Event::Lookup)?,
+      on_no_result: hook(Event::NoResult)?,
+    })
+  }
+
+  /// Run the hook for the given event, if one is configured.
+  pub fn run(&self, event: Event, results: &Results) -> Result<()> {
+    let command = match event {
+      Event::Lookup => &self.on_lookup,
+      Event::NoResult => &self.on_no_result,
+    };
+    let command = match *command {
+      Some(ref command) => command,
+      None => return Ok(()),
+    };
+    let failed = |e: &dyn ToString| {
+      Error::Error(format!("Hook on_{} failed: {}", event.name(), e.to_string()))
+    };
+
+    let mut child = process::Command::new("sh")
+      .arg("-c")
+      .arg(command)
+      .env("DICTCC_EVENT", event.name())
+      .env("DICTCC_QUERY", &results.query)
+      .env("DICTCC_COUNT", results.entries.len().to_string())
+      .stdin(process::Stdio::piped())
+      .spawn()
+      .map_err(|e| failed(&e))?;
+
+    // The command may not be interested in the results at all and exit
+    // without reading them, so we ignore errors writing them.
+    let _ = child
+      .stdin
+      .take()
+      .expect("stdin not captured")
+      .write_all(results.to_json().to_string().as_bytes());
+
+    let status = child.wait().map_err(|e| failed(&e))?;
+    if status.success() {
+      Ok(())
+    } else {
+      Err(failed(&status))
+    }
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::env;
+  use std::fs;
+
+  use config;
+
+  #[test]
+  fn run_hooks() {
+    let path = env::temp_dir().join(format!("dictcc-cli-hook-{}", process::id()));
+    let config = config::parse(&format!(
+      "[hooks]\non_lookup = 'echo $DICTCC_EVENT $DICTCC_QUERY $DICTCC_COUNT > {path}; cat >> {path}'\n\
+       on_no_result = 'exit 1'",
+      path = path.display(),
+    )).unwrap();
+    let hooks = Hooks::new(&config).unwrap();
+    let results = Results {
+      query: "Liebe".to_string(),
+      entries: Vec::new(),
+    };
+
+    hooks.run(Event::Lookup, &results).unwrap();
+    let output = fs::read_to_string(&path).unwrap();
+    let _ = fs::remove_file(&path);
+    assert_eq!(output, "lookup Liebe 0\n{\"query\":\"Liebe\",\"entries\":[]}");
+
+    assert!(hooks.run(Event::NoResult, &results).is_err());
+    assert!(Hooks::default().run(Event::NoResult, &results).is_ok());
+  }
+}
@@ -0,0 +1,110 @@
+// hunspell.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Export of the words of one side of the dictionary as a Hunspell
+//! dictionary.
+//!
+//! A Hunspell dictionary consists of a `.dic` file, listing the words
+//! (preceded by their count), and an `.aff` file describing affix rules.
+//! We do not derive any affix rules and so the latter merely declares
+//! the encoding.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use sqlite;
+
+use term::headword;
+use Error;
+use Result;
+use SEARCH_TBL;
+
+/// The content of the `.aff` file.
+pub const AFF: &str = "SET UTF-8\n";
+
+
+/// Collect the distinct words used in the terms of the given column.
+///
+/// Annotations are ignored. Case is preserved, as it is significant in
+/// some languages (e.g., German nouns are capitalized).
+pub fn words(connection: &sqlite::Connection, column: &str) -> Result<BTreeSet<String>> {
+  let select = format!("SELECT DISTINCT {col} FROM {tbl}", col = column, tbl = SEARCH_TBL);
+  let mut cursor = connection.prepare(select)?.cursor();
+  let mut words = BTreeSet::new();
+
+  while let Some(row) = cursor.next()? {
+    let term = row[0].as_string().ok_or_else(|| Error::InvalidSchema(format!(
+      "Invalid column in result: {:?}",
+      row
+    )))?;
+    let term = headword(term);
+    let found = term
+      .split(|c: char| !(c.is_alphanumeric() || c == '-' || c == '\''))
+      .map(|w| w.trim_matches(|c| c == '-' || c == '\''))
+      .filter(|w| w.chars().any(|c| c.is_alphabetic()));
+
+    for word in found {
+      words.insert(word.to_string());
+    }
+  }
+  Ok(words)
+}
+
+/// Render words as the content of a `.dic` file.
+pub fn dic(words: &BTreeSet<String>) -> String {
+  let mut dic = String::new();
+  let _ = writeln!(dic, "{}", words.len());
+  for word in words {
+    let _ = writeln!(dic, "{}", word);
+  }
+  dic
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::path;
+
+  use open;
+  use TERM1_COL;
+  use TERM2_COL;
+
+  #[test]
+  fn export_words() {
+    let connection = open(path::Path::new("./test/test.db")).unwrap();
+    let words1 = words(&connection, TERM1_COL).unwrap();
+    assert!(words1.contains("Liebe"));
+    assert!(words1.contains("knechten"));
+    assert!(words1.contains("jdn"));
+    assert!(!words1.contains("geh"));
+    assert!(!words1.contains("f"));
+
+    let words2 = words(&connection, TERM2_COL).unwrap();
+    assert!(words2.contains("subjugate"));
+    assert!(!words2.contains("Liebe"));
+  }
+
+  #[test]
+  fn render_dic() {
+    let words = ["Liebe", "Zorn"].iter().map(|w| w.to_string()).collect();
+    assert_eq!(dic(&words), "2\nLiebe\nZorn\n");
+  }
+}
@@ -0,0 +1,564 @@
+// import.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Creation of databases from the vocabulary exports of dict.cc.
+//!
+//! dict.cc offers its vocabulary for download as a text file, with one
+//! entry per line and the fields separated by tabs: the term in
+//! language 1, the term in language 2, the word classes (e.g., "noun"
+//! or "adj adv"), and the subjects the entry belongs to (e.g.,
+//! "[med.] [zool.]"). Lines starting with '#' contain comments.
+//!
+//! The export lacks a few pieces of information present in the
+//! databases distributed otherwise. Most importantly, there is no
+//! record of how commonly an entry is used. We approximate it by the
+//! number of translations the entry's headwords have, the reasoning
+//! being that common words are the ones with the most meanings.
+//!
+//! An existing database can be brought up to date with a newer export.
+//! Only entries that changed are touched, so the usage recorded for the
+//! others as well as additional tables (e.g., the corpus) are kept.
+//!
+//! Both record the header of the export and the date in the metadata
+//! of the database.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::fs;
+use std::path;
+
+use sqlite;
+
+#[cfg(feature = "sqlcipher")]
+use cipher;
+use metadata;
+use optimize;
+use optimize::fold;
+use subjects;
+use subjects::parse_ids;
+use term::headword;
+use transaction;
+use Error;
+use Result;
+use SEARCH_TBL;
+
+/// The name of the table containing the subjects.
+const SUBJECTS_TBL: &str = "subjects";
+/// The usage assigned to entries whose headwords have no other
+/// translation.
+const MIN_USAGE: i64 = 20;
+/// The maximum usage assigned to an entry.
+const MAX_USAGE: i64 = 45;
+
+
+/// An entry as read from an export.
+#[derive(Debug, PartialEq)]
+struct Record {
+  term1: String,
+  term2: String,
+  entry_type: String,
+  subjects: Vec<String>,
+}
+
+
+/// An entry as stored in the database.
+#[derive(Debug)]
+struct Row {
+  id: i64,
+  entry_type: String,
+  subj_ids: Vec<i64>,
+}
+
+
+/// The changes made by an update of a database.
+#[derive(Debug, Default, PartialEq)]
+pub struct Changes {
+  /// The number of entries added.
+  pub inserted: usize,
+  /// The number of entries whose type or subjects changed.
+  pub updated: usize,
+  /// The number of entries removed.
+  pub deleted: usize,
+}
+
+
+/// The ids of subjects by name.
+struct SubjectIds {
+  ids: HashMap<String, i64>,
+  /// Subjects not yet stored in the database.
+  added: Vec<(i64, String)>,
+  next: i64,
+}
+
+impl SubjectIds {
+  /// Load the subjects known to a database.
+  fn load(connection: &sqlite::Connection) -> Result<Self> {
+    let subjects = subjects::subjects(connection)?;
+    let next = subjects.iter().map(|s| s.id).max().unwrap_or(0) + 1;
+    let ids = subjects
+      .into_iter()
+      .filter_map(|s| Some((s.name?, s.id)))
+      .collect();
+
+    Ok(SubjectIds {
+      ids,
+      added: Vec::new(),
+      next,
+    })
+  }
+
+  /// Retrieve the list of ids (e.g., ",6,89,") of the given subjects,
+  /// assigning new ids to those not known yet.
+  fn resolve(&mut self, names: &[String]) -> String {
+    if names.is_empty() {
+      return String::new()
+    }
+
+    let mut list = ",".to_string();
+    for name in names {
+      let id = match self.ids.get(name) {
+        Some(&id) => id,
+        None => {
+          let id = self.next;
+          self.next += 1;
+          self.ids.insert(name.clone(), id);
+          self.added.push((id, name.clone()));
+          id
+        },
+      };
+      list += &format!("{},", id);
+    }
+    list
+  }
+
+  /// Store the subjects that got assigned a new id.
+  fn store(&self, connection: &sqlite::Connection) -> Result<()> {
+    connection.execute(format!(
+      "CREATE TABLE IF NOT EXISTS {} (id INTEGER PRIMARY KEY NOT NULL, name VARCHAR NOT NULL)",
+      SUBJECTS_TBL,
+    ))?;
+
+    let mut insert = connection.prepare(format!(
+      "INSERT INTO {} (id, name) VALUES (?, ?)",
+      SUBJECTS_TBL,
+    ))?;
+    for (id, name) in &self.added {
+      insert.reset()?;
+      insert.bind(1, *id)?;
+      insert.bind(2, name.as_str())?;
+      insert.next()?;
+    }
+    Ok(())
+  }
+}
+
+
+/// Normalize the white space of a field, trimming it and collapsing
+/// runs of white spaces into a single space.
+fn normalize(field: &str) -> String {
+  field.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Parse the subjects field, e.g., "[med.] [zool.]", into the names of
+/// the subjects.
+fn parse_subjects(field: &str) -> Vec<String> {
+  field
+    .split(|c: char| c == '[' || c == ']' || c.is_whitespace())
+    .filter(|s| !s.is_empty())
+    .map(|s| s.to_string())
+    .collect()
+}
+
+/// Parse the lines of an export into records.
+fn parse(content: &str) -> Result<Vec<Record>> {
+  let mut records = Vec::new();
+  for (i, line) in content.lines().enumerate() {
+    if line.trim().is_empty() || line.starts_with('#') {
+      continue;
+    }
+
+    let mut fields = line.split('\t').map(str::trim);
+    let term1 = normalize(fields.next().unwrap_or(""));
+    let term2 = normalize(fields.next().unwrap_or(""));
+    if term1.is_empty() || term2.is_empty() {
+      return Err(Error::Error(format!("Invalid entry in line {}: {}", i + 1, line)))
+    }
+
+    records.push(Record {
+      term1,
+      term2,
+      entry_type: normalize(fields.next().unwrap_or("")),
+      subjects: parse_subjects(fields.next().unwrap_or("")),
+    });
+  }
+  Ok(records)
+}
+
+/// Retrieve the value of the sort columns for an entry of the given
+/// type, mirroring the values found in the databases of dict.cc.
+fn sort_key(entry_type: &str) -> i64 {
+  match entry_type {
+    "noun" => 16,
+    "verb" => 14,
+    _ => 12,
+  }
+}
+
+/// Approximate the usage of every record by the number of translations
+/// of its headwords.
+fn usages(records: &[Record]) -> Vec<i64> {
+  let keys = records
+    .iter()
+    .map(|r| (fold(&headword(&r.term1)), fold(&headword(&r.term2))))
+    .collect::<Vec<_>>();
+  let mut counts1 = HashMap::<&str, i64>::new();
+  let mut counts2 = HashMap::<&str, i64>::new();
+  for (key1, key2) in &keys {
+    *counts1.entry(key1).or_insert(0) += 1;
+    *counts2.entry(key2).or_insert(0) += 1;
+  }
+
+  keys
+    .iter()
+    .map(|(key1, key2)| {
+      let others = counts1[key1.as_str()] + counts2[key2.as_str()] - 2;
+      cmp::min(MIN_USAGE + others, MAX_USAGE)
+    })
+    .collect()
+}
+
+
+/// Insert a record into the search table.
+fn insert(statement: &mut sqlite::Statement<'_>,
+          id: i64,
+          record: &Record,
+          subj_ids: &str,
+          usage: i64) -> Result<()> {
+  let sort = sort_key(&record.entry_type);
+  statement.reset()?;
+  statement.bind(1, id)?;
+  statement.bind(2, id)?;
+  statement.bind(3, record.term1.as_str())?;
+  statement.bind(4, record.term2.as_str())?;
+  statement.bind(5, sort)?;
+  statement.bind(6, sort)?;
+  statement.bind(7, subj_ids)?;
+  statement.bind(8, record.entry_type.as_str())?;
+  statement.bind(9, usage)?;
+  statement.next()?;
+  Ok(())
+}
+
+/// Prepare the statement inserting records for use with `insert`.
+fn prepare_insert(connection: &sqlite::Connection) -> Result<sqlite::Statement<'_>> {
+  let statement = connection.prepare(format!(
+    "INSERT INTO {tbl} (rowid, id, term1, term2, sort1, sort2, subj_ids, entry_type, vt_usage) \
+     VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    tbl = SEARCH_TBL,
+  ))?;
+  Ok(statement)
+}
+
+/// Create the search table and fill it with the given records,
+/// returning the number of entries.
+fn fill(connection: &sqlite::Connection, records: &[Record]) -> Result<usize> {
+  connection.execute(format!(
+    "CREATE VIRTUAL TABLE {tbl} USING \
+       fts3(id INTEGER PRIMARY KEY NOT NULL, term1 VARCHAR, term2 VARCHAR, \
+            sort1 INTEGER, sort2 INTEGER, subj_ids VARCHAR, entry_type VARCHAR, \
+            vt_usage INTEGER)",
+    tbl = SEARCH_TBL,
+  ))?;
+
+  let mut statement = prepare_insert(connection)?;
+  let mut subjects = SubjectIds::load(connection)?;
+  for (i, (record, usage)) in records.iter().zip(usages(records)).enumerate() {
+    let subj_ids = subjects.resolve(&record.subjects);
+    insert(&mut statement, i as i64 + 1, record, &subj_ids, usage)?;
+  }
+  subjects.store(connection)?;
+  Ok(records.len())
+}
+
+/// Import the entries of an export into the given (empty) database,
+/// returning the number of entries imported.
+pub fn import(connection: &sqlite::Connection, content: &str) -> Result<usize> {
+  let records = parse(content)?;
+  transaction(connection, || {
+    let count = fill(connection, &records)?;
+    metadata::record(connection, &metadata::header(content), metadata::IMPORTED)?;
+    Ok(count)
+  })
+}
+
+/// Load the entries of the search table, keyed by their (normalized)
+/// terms.
+fn load_rows(connection: &sqlite::Connection) -> Result<HashMap<(String, String), Vec<Row>>> {
+  let select = format!(
+    "SELECT rowid, term1, term2, entry_type, subj_ids FROM {}",
+    SEARCH_TBL,
+  );
+  let mut cursor = connection.prepare(select)?.cursor();
+  let mut rows = HashMap::<_, Vec<_>>::new();
+  while let Some(row) = cursor.next()? {
+    let invalid = || Error::InvalidSchema(format!("Invalid entry in database: {:?}", row));
+    let id = row[0].as_integer().ok_or_else(invalid)?;
+    let term1 = row[1].as_string().ok_or_else(invalid)?;
+    let term2 = row[2].as_string().ok_or_else(invalid)?;
+
+    rows
+      .entry((normalize(term1), normalize(term2)))
+      .or_default()
+      .push(Row {
+        id,
+        entry_type: row[3].as_string().unwrap_or("").to_string(),
+        subj_ids: row[4].as_string().map(parse_ids).unwrap_or_default(),
+      });
+  }
+  Ok(rows)
+}
+
+/// Apply the difference between the entries of the search table and
+/// the given records.
+fn apply(connection: &sqlite::Connection, records: &[Record]) -> Result<Changes> {
+  let mut rows = load_rows(connection)?;
+  let mut subjects = SubjectIds::load(connection)?;
+  let mut next = rows.values().flatten().map(|r| r.id).max().unwrap_or(0) + 1;
+  let mut changes = Changes::default();
+
+  let mut statement = prepare_insert(connection)?;
+  let mut update = connection.prepare(format!(
+    "UPDATE {} SET entry_type = ?, subj_ids = ?, sort1 = ?, sort2 = ? WHERE rowid = ?",
+    SEARCH_TBL,
+  ))?;
+
+  for (record, usage) in records.iter().zip(usages(records)) {
+    let subj_ids = subjects.resolve(&record.subjects);
+    // An entry's terms identify it. Some terms have multiple entries
+    // differing in type only, so we prefer the one of the same type.
+    let row = rows
+      .get_mut(&(record.term1.clone(), record.term2.clone()))
+      .and_then(|rows| {
+        let index = rows
+          .iter()
+          .position(|r| r.entry_type == record.entry_type)
+          .or(if rows.is_empty() { None } else { Some(0) })?;
+        Some(rows.swap_remove(index))
+      });
+
+    match row {
+      Some(row) => {
+        if row.entry_type != record.entry_type || row.subj_ids != parse_ids(&subj_ids) {
+          let sort = sort_key(&record.entry_type);
+          update.reset()?;
+          update.bind(1, record.entry_type.as_str())?;
+          update.bind(2, subj_ids.as_str())?;
+          update.bind(3, sort)?;
+          update.bind(4, sort)?;
+          update.bind(5, row.id)?;
+          update.next()?;
+          changes.updated += 1;
+        }
+      },
+      None => {
+        insert(&mut statement, next, record, &subj_ids, usage)?;
+        next += 1;
+        changes.inserted += 1;
+      },
+    }
+  }
+
+  let mut delete = connection.prepare(format!("DELETE FROM {} WHERE rowid = ?", SEARCH_TBL))?;
+  for row in rows.values().flatten() {
+    delete.reset()?;
+    delete.bind(1, row.id)?;
+    delete.next()?;
+    changes.deleted += 1;
+  }
+
+  subjects.store(connection)?;
+  // The headwords table is derived from the search table and would
+  // become stale otherwise.
+  if optimize::exists(connection)? {
+    optimize::fill(connection)?;
+  }
+  Ok(changes)
+}
+
+/// Update a database with the entries of a newer export, inserting,
+/// updating, and deleting entries as necessary.
+pub fn update(connection: &sqlite::Connection, content: &str) -> Result<Changes> {
+  let records = parse(content)?;
+  transaction(connection, || {
+    let changes = apply(connection, &records)?;
+    metadata::record(connection, &metadata::header(content), metadata::UPDATED)?;
+    Ok(changes)
+  })
+}
+
+/// Create a new database from an export, returning the number of
+/// entries imported.
+pub fn create(db: &path::Path, content: &str) -> Result<usize> {
+  if db.exists() {
+    return Err(Error::Error(format!("Database {} already exists", db.display())))
+  }
+
+  let result = sqlite::open(db).map_err(Error::from).and_then(|connection| {
+    // A new database gets encrypted with the key, if one is set.
+    #[cfg(feature = "sqlcipher")]
+    cipher::apply(&connection)?;
+    import(&connection, content)
+  });
+  if result.is_err() {
+    // Do not leave behind a partially created database.
+    let _ = fs::remove_file(db);
+  }
+  result
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::env;
+  use std::process;
+
+  use corpus;
+  use subjects::subjects;
+  use translate_with;
+  use Direction;
+  use Options;
+
+  const EXPORT: &str = "\
+# de-en vocabulary database\tcompiled by dict.cc
+# License\tPersonal use only
+
+Bank {f}\tbank\tnoun\t[fin.]
+Bank {f}\tbench\tnoun\t
+Ufer {n}\tbank  [of a river]\tnoun\t[geogr.] [fin.]
+ bezwingen \tto subjugate\tverb\t
+";
+
+
+  /// Retrieve a path to a database (not yet existing) for a test.
+  fn temp_db(name: &str) -> path::PathBuf {
+    env::temp_dir().join(format!("dictcc-cli-import-{}-{}.db", name, process::id()))
+  }
+
+  #[test]
+  fn parse_export() {
+    let records = parse(EXPORT).unwrap();
+    assert_eq!(records.len(), 4);
+    assert_eq!(records[2], Record {
+      term1: "Ufer {n}".to_string(),
+      term2: "bank [of a river]".to_string(),
+      entry_type: "noun".to_string(),
+      subjects: vec!["geogr.".to_string(), "fin.".to_string()],
+    });
+    assert_eq!(records[3].term1, "bezwingen");
+    assert!(records[3].subjects.is_empty());
+
+    let err = parse("Bank {f}\tbank\nUfer {n}\n").unwrap_err();
+    assert_eq!(err.to_string(), "Invalid entry in line 2: Ufer {n}");
+  }
+
+  #[test]
+  fn usage_by_translations() {
+    let records = parse(EXPORT).unwrap();
+    assert_eq!(usages(&records), vec![22, 21, 21, 20]);
+  }
+
+  #[test]
+  fn create_database() {
+    let db = temp_db("create");
+    assert_eq!(create(&db, EXPORT).unwrap(), 4);
+
+    let mut found = Vec::new();
+    translate_with(&db, &["bank"], &Direction::Lang2ToLang1, &Options::default(), |e| {
+      found.push((e.source.to_string(), e.target.to_string(), e.usage));
+      Ok(())
+    }).unwrap();
+    assert_eq!(found, vec![
+      ("bank".to_string(), "Bank {f}".to_string(), 22),
+      ("bank [of a river]".to_string(), "Ufer {n}".to_string(), 21),
+    ]);
+
+    let connection = sqlite::open(&db).unwrap();
+    let subjects = subjects(&connection).unwrap();
+    let names = subjects
+      .iter()
+      .map(|s| (s.name.clone().unwrap(), s.count))
+      .collect::<Vec<_>>();
+    assert_eq!(names, vec![("fin.".to_string(), 2), ("geogr.".to_string(), 1)]);
+
+    let metadata = metadata::load(&connection).unwrap();
+    let source = "de-en vocabulary database compiled by dict.cc";
+    assert_eq!(metadata[0], (metadata::SOURCE.to_string(), source.to_string()));
+    assert_eq!(metadata[1].0, metadata::IMPORTED);
+
+    // We never overwrite an existing database.
+    assert!(create(&db, EXPORT).is_err());
+    fs::remove_file(&db).unwrap();
+
+    assert!(create(&db, "Bank {f}\n").is_err());
+    assert!(!db.exists());
+  }
+
+  #[test]
+  fn update_database() {
+    let db = temp_db("update");
+    create(&db, EXPORT).unwrap();
+    let connection = sqlite::open(&db).unwrap();
+    corpus::import(&connection, "Das Ufer.\tThe bank.\n").unwrap();
+    optimize::build(&connection).unwrap();
+
+    let export = "\
+Bank {f}\tbank\tnoun\t[fin.]
+Bank {f}\tbench\tnoun\t[furn.]
+bezwingen\tto subjugate\tverb\t
+Haus {n}\thouse\tnoun\t
+";
+    let changes = update(&connection, export).unwrap();
+    assert_eq!(changes, Changes {
+      inserted: 1,
+      updated: 1,
+      deleted: 1,
+    });
+    // Applying the same export again changes nothing.
+    assert_eq!(update(&connection, export).unwrap(), Changes::default());
+
+    let subjects = subjects(&connection).unwrap();
+    let names = subjects
+      .iter()
+      .map(|s| (s.id, s.name.clone().unwrap(), s.count))
+      .collect::<Vec<_>>();
+    assert_eq!(names, vec![
+      (1, "fin.".to_string(), 1),
+      (2, "geogr.".to_string(), 0),
+      (3, "furn.".to_string(), 1),
+    ]);
+
+    // The corpus is kept and the headwords reflect the new entries.
+    assert!(corpus::exists(&connection).unwrap());
+    let headwords = optimize::complete(&connection, "term1", "", 10).unwrap();
+    assert_eq!(headwords, vec!["Bank", "Haus", "bezwingen"]);
+    fs::remove_file(&db).unwrap();
+  }
+}
@@ -0,0 +1,203 @@
+// inflect.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+
+//! Inflection tables of dict.cc app databases.
+//!
+//! Databases of the dict.cc app may ship the conjugations of verbs and
+//! the declensions of nouns in auxiliary tables, listing the inflected
+//! forms of each headword in order, e.g., "ich gebe", "gab", and
+//! "gegeben" for "geben". As with the table containing the entries (see
+//! `schema`), the layout varies between versions of the app, so we
+//! probe the tables of a database for those listing forms by headword.
+
+use sqlite;
+
+use subjects::table_columns;
+use Result;
+
+/// Candidates for the tables containing inflected forms, in order of
+/// preference.
+const TABLES: [&str; 5] = ["inflections", "flexions", "conjugations", "declensions", "forms"];
+/// Candidates for the column containing the headword a form belongs to.
+const LEMMA_COLS: [&str; 4] = ["lemma", "headword", "term", "word"];
+/// Candidates for the column containing the inflected form.
+const FORM_COLS: [&str; 4] = ["form", "inflection", "inflected", "text"];
+/// Candidates for the column marking principal forms.
+const PRINCIPAL_COLS: [&str; 2] = ["principal", "is_principal"];
+/// Candidates for the column ordering the forms of a headword.
+const ORDER_COLS: [&str; 4] = ["position", "idx", "seq", "ord"];
+/// The number of forms considered principal if the table does not mark
+/// them explicitly.
+const PRINCIPAL_FORMS: usize = 3;
+
+
+/// The layout of a table containing inflected forms.
+#[derive(Debug, PartialEq)]
+struct Layout {
+  /// The name of the table.
+  table: &'static str,
+  /// The column containing the headword.
+  lemma: &'static str,
+  /// The column containing the form.
+  form: &'static str,
+  /// The column marking principal forms, if any.
+  principal: Option<&'static str>,
+  /// The column ordering the forms, if any.
+  order: Option<&'static str>,
+}
+
+impl Layout {
+  /// Build the select of the principal forms of a headword.
+  fn select(&self) -> String {
+    let principal = self
+      .principal
+      .map(|col| format!(" AND {} <> 0", col))
+      .unwrap_or_default();
+    let limit = if self.principal.is_some() {
+      String::new()
+    } else {
+      format!(" LIMIT {}", PRINCIPAL_FORMS)
+    };
+    format!(
+      "SELECT {form} FROM {tbl} WHERE {lemma} = ?{principal} ORDER BY {order}{limit}",
+      form = self.form, tbl = self.table, lemma = self.lemma, principal = principal,
+      order = self.order.unwrap_or("rowid"), limit = limit,
+    )
+  }
+}
+
+
+/// The inflection tables of a database.
+#[derive(Debug, PartialEq)]
+pub struct Inflections {
+  /// The layouts of the tables, in order of preference.
+  tables: Vec<Layout>,
+}
+
+impl Inflections {
+  /// Probe the tables of a database for those containing inflected
+  /// forms, if any.
+  pub fn detect(connection: &sqlite::Connection) -> Result<Option<Inflections>> {
+    let mut tables = Vec::new();
+    for table in &TABLES {
+      let columns = table_columns(connection, table)?;
+      let find = |candidates: &[&'static str]| {
+        candidates.iter().find(|c| columns.iter().any(|col| col == *c)).cloned()
+      };
+
+      if let (Some(lemma), Some(form)) = (find(&LEMMA_COLS), find(&FORM_COLS)) {
+        tables.push(Layout {
+          table,
+          lemma,
+          form,
+          principal: find(&PRINCIPAL_COLS),
+          order: find(&ORDER_COLS),
+        })
+      }
+    }
+    if tables.is_empty() {
+      Ok(None)
+    } else {
+      Ok(Some(Inflections { tables }))
+    }
+  }
+
+  /// Retrieve the principal forms of a headword, in order, from the
+  /// first table listing any.
+  ///
+  /// Unless a table marks principal forms explicitly, the first few
+  /// forms of a headword are considered principal.
+  pub fn principal_forms(&self, connection: &sqlite::Connection,
+                         headword: &str) -> Result<Vec<String>> {
+    for layout in &self.tables {
+      let mut cursor = connection.prepare(layout.select())?.cursor();
+      cursor.bind(&[sqlite::Value::String(headword.to_string())])?;
+      let mut forms = Vec::new();
+      while let Some(row) = cursor.next()? {
+        if let Some(form) = row[0].as_string() {
+          forms.push(form.to_string());
+        }
+      }
+      if !forms.is_empty() {
+        return Ok(forms)
+      }
+    }
+    Ok(Vec::new())
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+
+  #[test]
+  fn detect_tables() {
+    let connection = sqlite::open(":memory:").unwrap();
+    assert_eq!(Inflections::detect(&connection).unwrap(), None);
+
+    connection
+      .execute(
+        "CREATE TABLE conjugations(lemma TEXT, form TEXT, position INTEGER);\
+         CREATE TABLE declensions(Headword TEXT, Inflection TEXT, principal INTEGER);\
+         CREATE TABLE forms(id INTEGER, value TEXT)",
+      )
+      .unwrap();
+    let inflections = Inflections::detect(&connection).unwrap().unwrap();
+    assert_eq!(inflections.tables, vec![
+      Layout {
+        table: "conjugations",
+        lemma: "lemma",
+        form: "form",
+        principal: None,
+        order: Some("position"),
+      },
+      Layout {
+        table: "declensions",
+        lemma: "headword",
+        form: "inflection",
+        principal: Some("principal"),
+        order: None,
+      },
+    ]);
+  }
+
+  #[test]
+  fn retrieve_principal_forms() {
+    let connection = sqlite::open(":memory:").unwrap();
+    connection
+      .execute(
+        "CREATE TABLE conjugations(lemma TEXT, form TEXT, position INTEGER);\
+         INSERT INTO conjugations VALUES\
+           ('geben', 'gab', 2), ('geben', 'gegeben', 3), ('geben', 'ich gebe', 1),\
+           ('geben', 'gib!', 4);\
+         CREATE TABLE declensions(headword TEXT, inflection TEXT, principal INTEGER);\
+         INSERT INTO declensions VALUES\
+           ('Zorn', 'Zorn', 1), ('Zorn', 'Zorne', 0), ('Zorn', 'Zorns', 1);",
+      )
+      .unwrap();
+    let inflections = Inflections::detect(&connection).unwrap().unwrap();
+    let forms = |headword| inflections.principal_forms(&connection, headword).unwrap();
+    assert_eq!(forms("geben"), vec!["ich gebe", "gab", "gegeben"]);
+    assert_eq!(forms("Zorn"), vec!["Zorn", "Zorns"]);
+    assert_eq!(forms("anger"), Vec::<String>::new());
+  }
+}
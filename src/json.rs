@@ -0,0 +1,395 @@
+// json.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! A minimal JSON representation along with a parser and serializer.
+//!
+//! We only need JSON for a couple of well defined interchange formats
+//! and so we do not bother pulling in a full blown serialization
+//! framework.
+
+use std::char;
+use std::fmt;
+use std::iter;
+use std::str;
+
+use Error;
+use Result;
+
+/// The maximum nesting depth of arrays and objects, beyond which we
+/// refuse to parse a document rather than to overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+
+/// A JSON value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+  /// The `null` value.
+  Null,
+  /// A boolean.
+  Bool(bool),
+  /// A number. JSON does not distinguish between integers and floats.
+  Number(f64),
+  /// A string.
+  String(String),
+  /// An array of values.
+  Array(Vec<Value>),
+  /// An object. We retain the order of members to produce
+  /// deterministic output.
+  Object(Vec<(String, Value)>),
+}
+
+impl Value {
+  /// Retrieve the member with the given name, if this is an object.
+  pub fn get(&self, key: &str) -> Option<&Value> {
+    match *self {
+      Value::Object(ref members) => {
+        members.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+      },
+      _ => None,
+    }
+  }
+
+  /// Retrieve the string contained in this value, if any.
+  pub fn as_str(&self) -> Option<&str> {
+    match *self {
+      Value::String(ref s) => Some(s),
+      _ => None,
+    }
+  }
+
+  /// Retrieve the elements of this value, if it is an array.
+  pub fn as_array(&self) -> Option<&[Value]> {
+    match *self {
+      Value::Array(ref a) => Some(a),
+      _ => None,
+    }
+  }
+}
+
+impl<'a> From<&'a str> for Value {
+  fn from(s: &'a str) -> Value {
+    Value::String(s.to_string())
+  }
+}
+
+impl From<String> for Value {
+  fn from(s: String) -> Value {
+    Value::String(s)
+  }
+}
+
+/// Write a string in its escaped and quoted JSON form.
+fn write_str(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+  write!(f, "\"")?;
+  for c in s.chars() {
+    match c {
+      '"' => write!(f, "\\\"")?,
+      '\\' => write!(f, "\\\\")?,
+      '\n' => write!(f, "\\n")?,
+      '\r' => write!(f, "\\r")?,
+      '\t' => write!(f, "\\t")?,
+      c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+      c => write!(f, "{}", c)?,
+    }
+  }
+  write!(f, "\"")
+}
+
+impl fmt::Display for Value {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Value::Null => write!(f, "null"),
+      Value::Bool(b) => write!(f, "{}", b),
+      Value::Number(n) => write!(f, "{}", n),
+      Value::String(ref s) => write_str(f, s),
+      Value::Array(ref a) => {
+        write!(f, "[")?;
+        for (i, v) in a.iter().enumerate() {
+          if i > 0 {
+            write!(f, ",")?;
+          }
+          write!(f, "{}", v)?;
+        }
+        write!(f, "]")
+      },
+      Value::Object(ref o) => {
+        write!(f, "{{")?;
+        for (i, (k, v)) in o.iter().enumerate() {
+          if i > 0 {
+            write!(f, ",")?;
+          }
+          write_str(f, k)?;
+          write!(f, ":{}", v)?;
+        }
+        write!(f, "}}")
+      },
+    }
+  }
+}
+
+
+struct Parser<'a> {
+  chars: iter::Peekable<str::Chars<'a>>,
+  /// The number of arrays and objects currently being parsed.
+  depth: usize,
+}
+
+impl<'a> Parser<'a> {
+  fn error<T>(&self, what: &str) -> Result<T> {
+    Err(Error::Error(format!("Invalid JSON: {}", what)))
+  }
+
+  /// Enter an array or object, checking that the nesting depth stays
+  /// within bounds.
+  fn descend(&mut self) -> Result<()> {
+    if self.depth >= MAX_DEPTH {
+      return self.error("nested too deeply");
+    }
+    self.depth += 1;
+    Ok(())
+  }
+
+  fn skip_whitespace(&mut self) {
+    while let Some(&c) = self.chars.peek() {
+      if !c.is_whitespace() {
+        break;
+      }
+      self.chars.next();
+    }
+  }
+
+  fn expect(&mut self, expected: &str) -> Result<()> {
+    for c in expected.chars() {
+      if self.chars.next() != Some(c) {
+        return self.error(&format!("expected '{}'", expected));
+      }
+    }
+    Ok(())
+  }
+
+  fn parse_value(&mut self) -> Result<Value> {
+    self.skip_whitespace();
+    let value = match self.chars.peek().cloned() {
+      Some('n') => self.expect("null").map(|_| Value::Null)?,
+      Some('t') => self.expect("true").map(|_| Value::Bool(true))?,
+      Some('f') => self.expect("false").map(|_| Value::Bool(false))?,
+      Some('"') => Value::String(self.parse_string()?),
+      Some('[') => {
+        self.descend()?;
+        let array = self.parse_array()?;
+        self.depth -= 1;
+        array
+      },
+      Some('{') => {
+        self.descend()?;
+        let object = self.parse_object()?;
+        self.depth -= 1;
+        object
+      },
+      Some(c) if c == '-' || c.is_ascii_digit() => self.parse_number()?,
+      Some(c) => return self.error(&format!("unexpected character '{}'", c)),
+      None => return self.error("unexpected end of input"),
+    };
+    self.skip_whitespace();
+    Ok(value)
+  }
+
+  fn parse_number(&mut self) -> Result<Value> {
+    let mut s = String::new();
+    while let Some(&c) = self.chars.peek() {
+      match c {
+        '0'..='9' | '-' | '+' | '.' | 'e' | 'E' => s.push(c),
+        _ => break,
+      }
+      self.chars.next();
+    }
+    match s.parse() {
+      Ok(n) => Ok(Value::Number(n)),
+      Err(_) => self.error(&format!("invalid number '{}'", s)),
+    }
+  }
+
+  fn parse_hex4(&mut self) -> Result<u32> {
+    let mut value = 0;
+    for _ in 0..4 {
+      match self.chars.next().and_then(|c| c.to_digit(16)) {
+        Some(d) => value = value * 16 + d,
+        None => return self.error("invalid unicode escape"),
+      }
+    }
+    Ok(value)
+  }
+
+  fn parse_string(&mut self) -> Result<String> {
+    self.expect("\"")?;
+    let mut s = String::new();
+    loop {
+      match self.chars.next() {
+        Some('"') => break,
+        Some('\\') => {
+          let c = match self.chars.next() {
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('/') => '/',
+            Some('b') => '\u{8}',
+            Some('f') => '\u{c}',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('u') => {
+              let mut code = self.parse_hex4()?;
+              // Characters outside of the basic multilingual plane are
+              // encoded as a surrogate pair.
+              if (0xd800..0xdc00).contains(&code) {
+                self.expect("\\u")?;
+                let low = self.parse_hex4()?;
+                if !(0xdc00..0xe000).contains(&low) {
+                  return self.error("invalid surrogate pair");
+                }
+                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+              }
+              match char::from_u32(code) {
+                Some(c) => c,
+                None => return self.error("invalid unicode escape"),
+              }
+            },
+            _ => return self.error("invalid escape sequence"),
+          };
+          s.push(c)
+        },
+        Some(c) => s.push(c),
+        None => return self.error("unterminated string"),
+      }
+    }
+    Ok(s)
+  }
+
+  fn parse_array(&mut self) -> Result<Value> {
+    self.expect("[")?;
+    let mut array = Vec::new();
+    self.skip_whitespace();
+    if self.chars.peek() == Some(&']') {
+      self.chars.next();
+      return Ok(Value::Array(array));
+    }
+    loop {
+      array.push(self.parse_value()?);
+      match self.chars.next() {
+        Some(',') => (),
+        Some(']') => break,
+        _ => return self.error("expected ',' or ']'"),
+      }
+    }
+    Ok(Value::Array(array))
+  }
+
+  fn parse_object(&mut self) -> Result<Value> {
+    self.expect("{")?;
+    let mut object = Vec::new();
+    self.skip_whitespace();
+    if self.chars.peek() == Some(&'}') {
+      self.chars.next();
+      return Ok(Value::Object(object));
+    }
+    loop {
+      self.skip_whitespace();
+      let key = self.parse_string()?;
+      self.skip_whitespace();
+      self.expect(":")?;
+      let value = self.parse_value()?;
+      object.push((key, value));
+      match self.chars.next() {
+        Some(',') => (),
+        Some('}') => break,
+        _ => return self.error("expected ',' or '}'"),
+      }
+    }
+    Ok(Value::Object(object))
+  }
+}
+
+/// Parse a JSON document.
+pub fn parse(s: &str) -> Result<Value> {
+  let mut parser = Parser {
+    chars: s.chars().peekable(),
+    depth: 0,
+  };
+  let value = parser.parse_value()?;
+  if parser.chars.next().is_some() {
+    return parser.error("trailing characters");
+  }
+  Ok(value)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_translation_response() {
+    let value = parse(r#"{"translations": [{"detected_source_language": "DE", "text": "Hello"}]}"#)
+      .unwrap();
+    let text = value
+      .get("translations")
+      .and_then(|t| t.as_array())
+      .and_then(|t| t.first())
+      .and_then(|t| t.get("text"))
+      .and_then(|t| t.as_str());
+    assert_eq!(text, Some("Hello"));
+  }
+
+  #[test]
+  fn parse_escapes() {
+    let value = parse(r#""Ärger \"quoted\"\n😀""#).unwrap();
+    assert_eq!(value, Value::String("Ärger \"quoted\"\n\u{1f600}".to_string()));
+    assert_eq!(parse(r#""\ud83d\ude00""#).unwrap(), Value::String("\u{1f600}".to_string()));
+    assert!(parse(r#""\ud800\u0041""#).is_err());
+    assert!(parse(r#""\ud800\ud800""#).is_err());
+    assert!(parse(r#""\udc00""#).is_err());
+  }
+
+  #[test]
+  fn parse_invalid() {
+    assert!(parse("{\"a\": }").is_err());
+    assert!(parse("[1, 2").is_err());
+    assert!(parse("true false").is_err());
+  }
+
+  #[test]
+  fn parse_nested() {
+    let nested = |depth| "[".repeat(depth) + &"]".repeat(depth);
+    assert!(parse(&nested(MAX_DEPTH)).is_ok());
+    assert!(parse(&nested(MAX_DEPTH + 1)).is_err());
+    assert!(parse(&nested(100_000)).is_err());
+    assert!(parse(&"{\"a\":".repeat(100_000)).is_err());
+  }
+
+  #[test]
+  fn serialize_roundtrip() {
+    let value = Value::Object(vec![
+      ("term".to_string(), "Liebe {f}".into()),
+      ("list".to_string(), Value::Array(vec![Value::Null, Value::Bool(true), Value::Number(42.0)])),
+      ("quote".to_string(), "\"\\\t".into()),
+    ]);
+    let string = value.to_string();
+    assert_eq!(string, r#"{"term":"Liebe {f}","list":[null,true,42],"quote":"\"\\\t"}"#);
+    assert_eq!(parse(&string).unwrap(), value);
+  }
+}
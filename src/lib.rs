@@ -0,0 +1,2400 @@
+// lib.rs
+
+// *************************************************************************
+// * Copyright (C) 2017-2018 Daniel Mueller (deso@posteo.net)              *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+
+#![deny(missing_docs)]
+
+//! A library for translating between languages by means of the offline
+//! data from dict.cc.
+//!
+//! Lookups are performed against a dict.cc SQLite database, as
+//! downloadable from <https://www1.dict.cc/translation_file_request.php>:
+//! ```no_run
+//! # #[cfg(feature = "sqlite")] {
+//! use std::path::Path;
+//!
+//! use dictcc::translate;
+//! use dictcc::Direction;
+//!
+//! let db = Path::new("dictcc-lp1.db");
+//! translate(db, &["Zorn"], &Direction::Lang1ToLang2, |entry| {
+//!   println!("{} ({}): {}", entry.source, entry.type_name(), entry.target);
+//!   Ok(())
+//! }).unwrap();
+//! # }
+//! ```
+//!
+//! Lookups with more control over how terms are matched, e.g., only
+//! reporting exact matches of certain types, are built by means of a
+//! [`Query`]. They are performed by a [`Backend`], which the database
+//! is one of. [`Query::stream`] yields entries one by one instead and
+//! can be aborted by means of a [`CancelToken`].
+//!
+//! Programs performing many lookups should keep a [`Database`] open,
+//! which reuses prepared statements, or a [`SharedDatabase`] when
+//! performing lookups from several threads. With the `async` feature
+//! enabled, the `asynchronous` module provides the same for
+//! asynchronous applications.
+//!
+//! Each [`Entry`] found carries its terms along with their parsed
+//! annotations. Boolean queries are parsed by the [`query`] module and
+//! results can be rendered in various formats by means of the
+//! [`results`] module.
+
+extern crate libc;
+#[cfg_attr(feature = "sqlite", macro_use)]
+extern crate log;
+extern crate rust_stemmers;
+#[cfg(feature = "sqlite")]
+extern crate sqlite;
+#[cfg(feature = "sqlite")]
+extern crate sqlite3_sys;
+
+// SQLCipher is a drop-in replacement of SQLite, but the two cannot be
+// linked at the same time.
+#[cfg(all(feature = "sqlcipher", feature = "linkage"))]
+compile_error!("the sqlcipher feature requires disabling the linkage feature");
+
+#[cfg(feature = "async")]
+pub mod asynchronous;
+#[cfg(feature = "sqlite")]
+mod authorizer;
+pub mod backend;
+pub mod band;
+#[cfg(feature = "sqlite")]
+pub mod cache;
+pub mod cancel;
+#[cfg(feature = "sqlite")]
+pub mod check;
+#[cfg(feature = "sqlcipher")]
+pub mod cipher;
+pub mod collate;
+#[cfg(feature = "sqlite")]
+pub mod compound;
+#[cfg(feature = "sqlite")]
+pub mod database;
+pub mod entry;
+#[cfg(feature = "sqlite")]
+mod explain;
+#[cfg(feature = "sqlite")]
+pub mod fulltext;
+pub mod json;
+pub mod like;
+pub mod lookup;
+#[cfg(feature = "memory")]
+pub mod memory;
+#[cfg(feature = "sqlite")]
+pub mod optimize;
+#[cfg(feature = "sqlite")]
+pub mod overlay;
+#[cfg(any(feature = "sqlite", feature = "memory"))]
+pub mod pattern;
+pub mod query;
+pub mod rank;
+pub mod results;
+#[cfg(feature = "sqlite")]
+mod schema;
+#[cfg(feature = "sqlite")]
+pub mod spell;
+pub mod stem;
+#[cfg(feature = "sqlite")]
+pub mod subjects;
+#[cfg(feature = "sqlite")]
+pub mod suggest;
+pub mod term;
+pub mod translit;
+#[cfg(feature = "sqlite")]
+pub mod tuning;
+
+use std::borrow;
+use std::cmp;
+#[cfg(feature = "sqlite")]
+use std::cell;
+#[cfg(feature = "sqlite")]
+use std::collections::HashMap;
+#[cfg(feature = "sqlite")]
+use std::collections::HashSet;
+use std::error;
+#[cfg(feature = "sqlite")]
+use std::ffi;
+use std::fmt;
+use std::io;
+#[cfg(feature = "sqlite")]
+use std::mem;
+use std::path;
+#[cfg(feature = "sqlite")]
+use std::os::raw::c_char;
+#[cfg(feature = "sqlite")]
+use std::os::raw::c_int;
+#[cfg(feature = "sqlite")]
+use std::rc;
+use std::result;
+#[cfg(feature = "sqlite")]
+use std::sync;
+#[cfg(feature = "sqlite")]
+use std::time;
+
+#[cfg(feature = "sqlite")]
+use fulltext::FullText;
+
+pub use backend::Backend;
+pub use cancel::CancelToken;
+#[cfg(feature = "sqlite")]
+pub use database::Database;
+#[cfg(feature = "sqlite")]
+pub use database::Entries;
+#[cfg(feature = "sqlite")]
+pub use database::SharedDatabase;
+pub use entry::Entry;
+pub use lookup::Query;
+#[cfg(feature = "sqlite")]
+pub use tuning::Tuning;
+
+/// An error comprising the various different error types.
+///
+/// More specific variants may be added in the future, so matching on
+/// errors has to account for others.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+  /// The database at the given path does not exist.
+  DatabaseNotFound(path::PathBuf),
+  /// The database does not have the structure of a dict.cc database,
+  /// e.g., because a column holds values of an unexpected type.
+  InvalidSchema(String),
+  /// A boolean query could not be parsed.
+  QuerySyntax(String),
+  /// The lookup was cancelled.
+  Cancelled,
+  /// An Sqlite error reported by the sqlite crate.
+  #[cfg(feature = "sqlite")]
+  Sql(sqlite::Error),
+  /// An I/O error, along with a description of the failed operation.
+  Io(String, io::Error),
+  /// A custom error in the form of a string.
+  Error(String),
+}
+
+#[cfg(feature = "sqlite")]
+impl From<sqlite::Error> for Error {
+  fn from(e: sqlite::Error) -> Error {
+    Error::Sql(e)
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Error::DatabaseNotFound(ref path) => write!(f, "Database {} not found", path.display()),
+      Error::InvalidSchema(ref e) => write!(f, "{}", e),
+      Error::QuerySyntax(ref e) => write!(f, "{}", e),
+      Error::Cancelled => write!(f, "The lookup was cancelled"),
+      #[cfg(feature = "sqlite")]
+      Error::Sql(ref e) => write!(f, "SQL error: {}", e),
+      Error::Io(ref what, ref e) => write!(f, "{}: {}", what, e),
+      Error::Error(ref e) => write!(f, "{}", e),
+    }
+  }
+}
+
+impl error::Error for Error {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    match *self {
+      #[cfg(feature = "sqlite")]
+      Error::Sql(ref e) => Some(e),
+      Error::Io(_, ref e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+/// The result type used throughout the crate.
+pub type Result<T> = result::Result<T, Error>;
+
+/// A direction to translate in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+  /// Map from term1 (in language 1) to term2 (in language 2).
+  Lang1ToLang2,
+  /// Map from term2 (in language 2) to term1 (in language 1).
+  Lang2ToLang1,
+}
+
+// CREATE VIRTUAL TABLE "main_ft" using
+//   fts3("id" INTEGER PRIMARY KEY NOT NULL,
+//        "term1" VARCHAR,
+//        "term2" VARCHAR,
+//        "sort1" INTEGER,
+//        "sort2" INTEGER,
+//        "subj_ids" VARCHAR,
+//        "entry_type" VARCHAR,
+//        "vt_usage" INTEGER);
+/// The name of the table holding the dictionary's entries.
+pub const SEARCH_TBL: &str = "main_ft";
+/// The name of the column holding the terms of the first language.
+pub const TERM1_COL: &str = "term1";
+/// The name of the column holding the terms of the second language.
+pub const TERM2_COL: &str = "term2";
+/// The name of the column holding the types of entries.
+#[cfg(feature = "sqlite")]
+pub const TYPE_COL: &str = "entry_type";
+/// The name of the column holding the usage counts of entries.
+#[cfg(feature = "sqlite")]
+pub const USAGE_COL: &str = "vt_usage";
+#[cfg(feature = "sqlite")]
+const SUBJ_COL: &str = "subj_ids";
+
+
+/// Normalize the white space of a term as stored in the database,
+/// collapsing runs of spaces into a single one.
+///
+/// Some terms in the database contain superfluous spaces, e.g.,
+/// "dorky  [coll.]". Terms reported in entries are normalized already.
+pub fn normalize(string: &str) -> borrow::Cow<'_, str> {
+  if string.contains("  ") {
+    let mut s = string.to_string();
+    loop {
+      s = s.replace("  ", " ");
+      if !s.contains("  ") {
+        break;
+      }
+    }
+    s.into()
+  } else {
+    string.into()
+  }
+}
+
+/// Retrieve the entry represented by a result row, comprising the
+/// source and destination terms, the type, and the usage of an entry.
+#[cfg(feature = "sqlite")]
+fn read_row(row: &[sqlite::Value]) -> Result<Entry> {
+  let src_term = row[0].as_string().ok_or_else(|| Error::InvalidSchema(format!(
+    "Invalid first column in result: {:?}",
+    row
+  )))?;
+  let dst_term = row[1].as_string().ok_or_else(|| Error::InvalidSchema(format!(
+    "Invalid second column in result: {:?}",
+    row
+  )))?;
+  let type_ = row[2].as_string().ok_or_else(|| Error::InvalidSchema(format!(
+    "Invalid third column in result: {:?}",
+    row
+  )))?;
+  // The usage is merely used for ordering and classification, so we
+  // do not insist on it being present.
+  // Columns of FTS tables store the usage as text.
+  let usage = row[3]
+    .as_integer()
+    .or_else(|| row[3].as_string().and_then(|usage| usage.parse().ok()))
+    .unwrap_or(0);
+  let mut entry = Entry::new(&normalize(src_term), &normalize(dst_term), type_, usage);
+  entry.id = row_id(row);
+  entry.user = entry.id.is_some_and(|id| id < 0);
+  Ok(entry)
+}
+
+/// Retrieve the id of the entry represented by a result row, if the
+/// row contains it.
+#[cfg(feature = "sqlite")]
+fn row_id(row: &[sqlite::Value]) -> Option<i64> {
+  row.get(4).and_then(|id| id.as_integer())
+}
+
+#[cfg(feature = "sqlite")]
+thread_local! {
+  /// The read-only databases shared between commands, if sharing is
+  /// enabled (as it is while running a script).
+  static CONNECTIONS: cell::RefCell<Option<HashMap<path::PathBuf, rc::Rc<Database>>>> =
+    const { cell::RefCell::new(None) };
+}
+
+#[cfg(feature = "sqlite")]
+thread_local! {
+  /// The writable connections taking part in the transaction in
+  /// progress, if any (as while running a script).
+  static TRANSACTION: cell::RefCell<Option<HashMap<path::PathBuf, rc::Rc<sqlite::Connection>>>> =
+    const { cell::RefCell::new(None) };
+}
+
+/// Run a function sharing connections between all lookups it performs.
+#[cfg(feature = "sqlite")]
+pub fn sharing_connections<F, T>(f: F) -> T
+where
+  F: FnOnce() -> T,
+{
+  // Sharing may already be enabled by an enclosing batch of lookups,
+  // in which case it is left to that one to end it.
+  let enabled = CONNECTIONS.with(|c| c.borrow().is_some());
+  if !enabled {
+    CONNECTIONS.with(|c| *c.borrow_mut() = Some(HashMap::new()));
+  }
+  let result = f();
+  if !enabled {
+    CONNECTIONS.with(|c| *c.borrow_mut() = None);
+  }
+  result
+}
+
+/// Open a database for reading.
+///
+/// If connections are shared, an already open database is reused,
+/// along with the statements it has prepared.
+#[cfg(feature = "sqlite")]
+pub fn database(db: &path::Path) -> Result<rc::Rc<Database>> {
+  let shared = CONNECTIONS.with(|c| c.borrow().as_ref().and_then(|c| c.get(db).cloned()));
+  if let Some(database) = shared {
+    debug!("Reusing connection to database {}", db.display());
+    return Ok(database);
+  }
+
+  // A connection that is not shared serves a single lookup, so that
+  // the database can be assumed not to change while it is open.
+  let sharing = CONNECTIONS.with(|c| c.borrow().is_some());
+  let tuning = Tuning {
+    immutable: !sharing,
+    ..tuning::current()
+  };
+  let database = rc::Rc::new(Database::open_with(db, &tuning)?);
+  CONNECTIONS.with(|c| {
+    if let Some(ref mut connections) = *c.borrow_mut() {
+      connections.insert(db.to_path_buf(), database.clone());
+    }
+  });
+  Ok(database)
+}
+
+/// Open a database for reading, retrieving the connection to it.
+///
+/// If connections are shared, an already open connection to the
+/// database is reused.
+#[cfg(feature = "sqlite")]
+pub fn open(db: &path::Path) -> Result<rc::Rc<sqlite::Connection>> {
+  Ok(database(db)?.connection().clone())
+}
+
+/// Open a database for reading and writing.
+///
+/// Within `atomically` the connection takes part in its transaction.
+#[cfg(feature = "sqlite")]
+pub fn open_writable(db: &path::Path) -> Result<rc::Rc<sqlite::Connection>> {
+  // Note that sqlite::open by default creates the database if it does
+  // not exist. That is not a desired behavior. So we catch cases where
+  // the database does not exist in advance.
+  if !db.exists() {
+    return Err(Error::DatabaseNotFound(db.to_path_buf()))
+  }
+
+  transactional(db, || {
+    let connection = sqlite::open(db)?;
+    #[cfg(feature = "sqlcipher")]
+    cipher::apply(&connection)?;
+    like::register(&connection)?;
+    collate::register(&connection)?;
+    Ok(connection)
+  })
+}
+
+/// Open a connection for writing to a database using `open`, or, if a
+/// transaction is in progress, retrieve the one taking part in it.
+#[cfg(feature = "sqlite")]
+fn transactional<F>(db: &path::Path, open: F) -> Result<rc::Rc<sqlite::Connection>>
+where
+  F: FnOnce() -> Result<sqlite::Connection>,
+{
+  let shared = TRANSACTION.with(|t| t.borrow().as_ref().and_then(|t| t.get(db).cloned()));
+  if let Some(connection) = shared {
+    return Ok(connection)
+  }
+
+  let connection = rc::Rc::new(open()?);
+  if TRANSACTION.with(|t| t.borrow().is_some()) {
+    connection.execute("BEGIN")?;
+    TRANSACTION.with(|t| {
+      if let Some(ref mut connections) = *t.borrow_mut() {
+        connections.insert(db.to_path_buf(), connection.clone());
+      }
+    });
+  }
+  Ok(connection)
+}
+
+/// Run a function making all changes to databases it performs in a
+/// single transaction, which is committed only if the function
+/// succeeds.
+#[cfg(feature = "sqlite")]
+pub fn atomically<F, T>(f: F) -> Result<T>
+where
+  F: FnOnce() -> Result<T>,
+{
+  if TRANSACTION.with(|t| t.borrow().is_some()) {
+    return f()
+  }
+
+  TRANSACTION.with(|t| *t.borrow_mut() = Some(HashMap::new()));
+  let result = f();
+  let connections = TRANSACTION.with(|t| t.borrow_mut().take()).unwrap_or_default();
+  match result {
+    Ok(value) => {
+      for connection in connections.values() {
+        connection.execute("COMMIT")?;
+      }
+      Ok(value)
+    },
+    Err(err) => {
+      for connection in connections.values() {
+        let _ = connection.execute("ROLLBACK");
+      }
+      Err(err)
+    },
+  }
+}
+
+/// Commit the changes made so far within `atomically`, for them to be
+/// visible to lookups. Later changes are made in a transaction of
+/// their own.
+///
+/// Shared connections opened before the commit may not reflect the
+/// changes, so they are closed as well.
+#[cfg(feature = "sqlite")]
+pub fn commit() -> Result<()> {
+  let connections = TRANSACTION.with(|t| t.borrow_mut().as_mut().map(mem::take).unwrap_or_default());
+  if connections.is_empty() {
+    return Ok(())
+  }
+
+  for connection in connections.values() {
+    connection.execute("COMMIT")?;
+  }
+  CONNECTIONS.with(|c| {
+    if let Some(ref mut connections) = *c.borrow_mut() {
+      connections.clear();
+    }
+  });
+  Ok(())
+}
+
+/// Check whether changes to databases are currently made as part of
+/// `atomically`, i.e., they may not have been committed yet.
+#[cfg(feature = "sqlite")]
+pub fn in_transaction() -> bool {
+  TRANSACTION.with(|t| t.borrow().is_some())
+}
+
+/// Run a function inside of a transaction, committing its changes if
+/// it succeeds and rolling them back otherwise.
+///
+/// The transaction is a savepoint, so that it may be part of an
+/// enclosing one, e.g., that of `atomically`, in which case its changes
+/// are committed only along with it.
+#[cfg(feature = "sqlite")]
+pub fn transaction<F, T>(connection: &sqlite::Connection, f: F) -> Result<T>
+where
+  F: FnOnce() -> Result<T>,
+{
+  connection.execute("SAVEPOINT dictcc")?;
+  match f() {
+    Ok(value) => {
+      connection.execute("RELEASE dictcc")?;
+      Ok(value)
+    },
+    Err(err) => {
+      let _ = connection.execute("ROLLBACK TO dictcc; RELEASE dictcc");
+      Err(err)
+    },
+  }
+}
+
+/// Check whether SQLite interprets the file names it is passed as URIs.
+///
+/// URI support can only be enabled before SQLite is first used. If
+/// that is too late and the library was not built with it enabled,
+/// file names are taken literally.
+#[cfg(feature = "sqlite")]
+fn uri_support() -> bool {
+  static SUPPORT: sync::OnceLock<bool> = sync::OnceLock::new();
+
+  *SUPPORT.get_or_init(|| unsafe {
+    sqlite3_sys::sqlite3_config(sqlite3_sys::SQLITE_CONFIG_URI, 1 as c_int) == sqlite3_sys::SQLITE_OK
+      || sqlite3_sys::sqlite3_compileoption_used(b"USE_URI\0".as_ptr() as *const c_char) != 0
+  })
+}
+
+/// Build the URI for opening the database at the given path read-only
+/// and, optionally, immutable, if the path can be expressed as one.
+#[cfg(feature = "sqlite")]
+fn read_only_uri(db: &path::Path, immutable: bool) -> Option<String> {
+  let path = db.to_str()?;
+  // An absolute path needs an (empty) authority in front of it, or a
+  // path starting with two slashes would be taken as one.
+  let mut uri = if db.is_absolute() {
+    "file://".to_string()
+  } else {
+    "file:".to_string()
+  };
+  for c in path.chars() {
+    match c {
+      '%' => uri += "%25",
+      '?' => uri += "%3f",
+      '#' => uri += "%23",
+      c => uri.push(c),
+    }
+  }
+  uri += "?mode=ro";
+  if immutable {
+    uri += "&immutable=1";
+  }
+  Some(uri)
+}
+
+/// Copy the contents of a database into a new in-memory database.
+#[cfg(feature = "sqlite")]
+fn load_into_memory(connection: &sqlite::Connection) -> Result<sqlite::Connection> {
+  let memory = sqlite::open(":memory:")?;
+  let main = b"main\0".as_ptr() as *const c_char;
+  let result = unsafe {
+    let backup = sqlite3_sys::sqlite3_backup_init(memory.as_raw(), main, connection.as_raw(), main);
+    if backup.is_null() {
+      sqlite3_sys::sqlite3_errcode(memory.as_raw())
+    } else {
+      // Copy all pages in one step; nobody else writes to the source.
+      let result = sqlite3_sys::sqlite3_backup_step(backup, -1);
+      let finish = sqlite3_sys::sqlite3_backup_finish(backup);
+      if result == sqlite3_sys::SQLITE_DONE {
+        finish
+      } else {
+        result
+      }
+    }
+  };
+  if result != sqlite3_sys::SQLITE_OK {
+    let message = unsafe {
+      ffi::CStr::from_ptr(sqlite3_sys::sqlite3_errstr(result)).to_string_lossy().into_owned()
+    };
+    return Err(Error::Sql(sqlite::Error {
+      code: Some(result as isize),
+      message: Some(message),
+    }))
+  }
+  Ok(memory)
+}
+
+/// Open a database for reading only.
+///
+/// With `Tuning::immutable` set, the database is opened as immutable,
+/// which also allows for reading it from read-only media. Without URI
+/// support, we fall back to merely refusing writes. With
+/// `Tuning::in_memory` set, the database gets copied into memory in its
+/// entirety right away, so that it is always opened as immutable. The user database,
+/// if any, gets attached (see `overlay`).
+#[cfg(feature = "sqlite")]
+fn open_read_only(db: &path::Path, tuning: &Tuning) -> Result<sqlite::Connection> {
+  if !db.exists() {
+    return Err(Error::DatabaseNotFound(db.to_path_buf()));
+  }
+
+  let connection = match read_only_uri(db, tuning.immutable || tuning.in_memory) {
+    Some(ref uri) if uri_support() => sqlite::open(uri)?,
+    _ => {
+      info!("Opening database {} without URI support", db.display());
+      sqlite::open(db)?
+    },
+  };
+  #[cfg(feature = "sqlcipher")]
+  cipher::apply(&connection)?;
+  let connection = if tuning.in_memory {
+    let start = time::Instant::now();
+    let memory = load_into_memory(&connection)?;
+    info!("Loaded database {} into memory in {:.1?}", db.display(), start.elapsed());
+    memory
+  } else {
+    connection
+  };
+  tuning.apply(&connection)?;
+  // Mapping the entries onto the expected layout may require creating
+  // a (temporary) view. That has to happen before refusing writes, but
+  // after setting where temporary data are stored, as that discards
+  // them.
+  schema::adapt(&connection)?;
+  if let Some(db) = overlay::current() {
+    overlay::attach(&connection, &db)?;
+  }
+  connection.execute("PRAGMA query_only = ON")?;
+  like::register(&connection)?;
+  collate::register(&connection)?;
+  Ok(connection)
+}
+
+/// Retrieve the (source, destination) column names for a direction.
+pub fn columns(direction: &Direction) -> (&'static str, &'static str) {
+  match *direction {
+    Direction::Lang1ToLang2 => (TERM1_COL, TERM2_COL),
+    Direction::Lang2ToLang1 => (TERM2_COL, TERM1_COL),
+  }
+}
+
+/// How entries of the user duplicating ones of the dictionary are
+/// reported. Entries are considered duplicates if their terms are the
+/// same, disregarding annotations.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Merge {
+  /// Report only the user's entry.
+  #[default]
+  PreferUser,
+  /// Report only the dictionary's entry.
+  PreferMain,
+  /// Report the dictionary's entry, with the annotations of the
+  /// user's added to its terms.
+  Combine,
+}
+
+impl Merge {
+  /// Parse a merge policy from its name as used in the configuration.
+  pub fn from_name(name: &str) -> Result<Merge> {
+    match name {
+      "prefer-user" => Ok(Merge::PreferUser),
+      "prefer-main" => Ok(Merge::PreferMain),
+      "combine" => Ok(Merge::Combine),
+      _ => Err(Error::Error(format!(
+        "Unsupported merge policy: {} (expected prefer-user, prefer-main, or combine)", name
+      ))),
+    }
+  }
+}
+
+/// Options influencing how terms are matched.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+  /// Whether to ignore diacritics, e.g., to match "Arger" with "Ärger".
+  pub ignore_accents: bool,
+  /// Whether to match terms as an exact phrase, i.e., to find all
+  /// entries containing the words in the given order.
+  pub phrase: bool,
+  /// Whether to match entries containing all the words of a term, in
+  /// any order.
+  pub all_words: bool,
+  /// The entry types to list first, in that order. Types not listed
+  /// follow in alphabetical order.
+  pub type_order: Vec<String>,
+  /// The ids of the subjects to restrict the search to. Entries
+  /// belonging to any of them are reported. If empty, no restriction
+  /// applies.
+  pub subjects: Vec<i64>,
+  /// Whether to distinguish between upper and lower case characters.
+  pub case_sensitive: bool,
+  /// Whether to also match terms inside of comments (`[...]`) and
+  /// abbreviations (`<...>`).
+  pub include_comments: bool,
+  /// The query to rank results by their relevance to, if any. Ranked
+  /// results are not grouped by type.
+  pub rank: Option<String>,
+  /// The weights to use when ranking results.
+  pub weights: rank::Weights,
+  /// Whether to explain each query on stderr before performing it.
+  pub explain: bool,
+  /// The collation to order terms by.
+  pub collation: collate::Collation,
+  /// Whether to only report entries matching a term itself, possibly
+  /// with annotations, omitting phrases merely containing it.
+  pub exact: bool,
+  /// The types to restrict the search to. Entries having any of them
+  /// are reported. If empty, no restriction applies.
+  pub types: Vec<entry::Type>,
+  /// The maximum number of entries to report, as a safety cap for
+  /// broad lookups. Unlike the limit of a [`Query`], it also applies to
+  /// words looked up individually and to boolean queries.
+  pub max_results: Option<usize>,
+  /// How to report entries of the user duplicating ones of the
+  /// dictionary.
+  pub merge: Merge,
+}
+
+impl Options {
+  /// Combine a limit on the number of entries with `max_results`.
+  pub fn cap(&self, limit: Option<usize>) -> Option<usize> {
+    match (limit, self.max_results) {
+      (Some(limit), Some(max)) => Some(cmp::min(limit, max)),
+      (limit, max) => limit.or(max),
+    }
+  }
+
+  /// Retrieve the LIMIT clause enforcing `max_results`, if any.
+  #[cfg(feature = "sqlite")]
+  fn limit_clause(&self) -> String {
+    match self.max_results {
+      Some(max) => format!(" LIMIT {}", max),
+      None => String::new(),
+    }
+  }
+}
+
+/// Translate the given terms, reporting each entry found to
+/// `callback`.
+///
+/// All terms are matched in a single query, i.e., the results for the
+/// individual terms are merged and ordered as a whole.
+#[cfg(feature = "sqlite")]
+pub fn translate<F, S>(db: &path::Path, terms: &[S],
+                       direction: &Direction, callback: F) -> Result<()>
+where
+  F: FnMut(&Entry) -> Result<()>,
+  S: AsRef<str>,
+{
+  translate_with(db, terms, direction, &Options::default(), callback)
+}
+
+/// Build the select clause for retrieving (source, destination, type,
+/// usage, id) rows in the given direction.
+#[cfg(feature = "sqlite")]
+fn select_clause(direction: &Direction) -> String {
+  select_from(direction, SEARCH_TBL, "rowid")
+}
+
+/// Build the select of the user's entries satisfying a condition (see
+/// `overlay`), in the form of `select_clause`.
+#[cfg(feature = "sqlite")]
+fn user_select(direction: &Direction, cond: &str) -> String {
+  // User entries are told apart by their negated ids.
+  format!("{} WHERE {}", select_from(direction, overlay::TABLE, "-rowid"), cond)
+}
+
+/// Build the select of the rows retrieved by `rows`, a select in the
+/// form of `select_clause`, narrowed down to those of entries not hidden
+/// by the user (see `overlay`) and with the user's entries duplicating
+/// ones of the dictionary merged as `merge` dictates, if lookups include
+/// the user's entries.
+#[cfg(feature = "sqlite")]
+fn visible_select(rows: &str, direction: &Direction, overlay: bool, merge: Merge) -> String {
+  if !overlay {
+    return rows.to_string();
+  }
+
+  // Duplicates are told apart by their headwords.
+  let key = |table: &str| {
+    format!(
+      "headword({tbl}{term1}) || char(9) || headword({tbl}{term2})",
+      tbl = table, term1 = TERM1_COL, term2 = TERM2_COL,
+    )
+  };
+  let visible = format!(
+    "WITH __all__ AS ({rows}), \
+       __rows__ AS (SELECT * FROM __all__ \
+         WHERE __id__ NOT IN (SELECT id FROM {tbl} WHERE id IS NOT NULL) \
+           AND NOT EXISTS (SELECT 1 FROM {tbl} \
+             WHERE term IN ({term1}, {term2}, headword({term1}), headword({term2}))))",
+    rows = rows, tbl = overlay::HIDDEN_TABLE, term1 = TERM1_COL, term2 = TERM2_COL,
+  );
+  // Note that the sub-queries checking for duplicates are not
+  // correlated, so that each is evaluated only once.
+  match merge {
+    Merge::PreferUser => format!(
+      "{visible} SELECT * FROM __rows__ \
+         WHERE __id__ < 0 OR {key} NOT IN (SELECT {key} FROM __rows__ WHERE __id__ < 0)",
+      visible = visible, key = key(""),
+    ),
+    Merge::PreferMain => format!(
+      "{visible} SELECT * FROM __rows__ \
+         WHERE __id__ > 0 OR {key} NOT IN (SELECT {key} FROM __rows__ WHERE __id__ > 0)",
+      visible = visible, key = key(""),
+    ),
+    Merge::Combine => {
+      let (src_col, dst_col) = columns(direction);
+      format!(
+        "{visible}, \
+           __user__ AS (SELECT {key} AS __key__, MIN({src}) AS {src}, MIN({dst}) AS {dst}, \
+             MIN(__type__) AS __type__, MAX({use}) AS {use} \
+             FROM __rows__ WHERE __id__ < 0 GROUP BY __key__) \
+         SELECT combine_annotations(r.{src}, u.{src}) AS {src}, \
+           combine_annotations(r.{dst}, u.{dst}) AS {dst}, \
+           CASE r.__type__ WHEN 'unknown' THEN IFNULL(u.__type__, r.__type__) \
+             ELSE r.__type__ \
+           END AS __type__, \
+           MAX(r.{use}, IFNULL(u.{use}, r.{use})) AS {use}, \
+           r.__id__ AS __id__ \
+         FROM __rows__ AS r LEFT JOIN __user__ AS u ON r.__id__ > 0 AND u.__key__ = {row_key} \
+         WHERE r.__id__ > 0 OR {row_key} NOT IN (SELECT {key} FROM __rows__ WHERE __id__ > 0)",
+        visible = visible, key = key(""), row_key = key("r."),
+        src = src_col, dst = dst_col, use = USAGE_COL,
+      )
+    },
+  }
+}
+
+/// Build the select clause for retrieving (source, destination, type,
+/// usage, id) rows from the given table.
+#[cfg(feature = "sqlite")]
+fn select_from(direction: &Direction, table: &str, id: &str) -> String {
+  let (src_col, dst_col) = columns(direction);
+  // Note that for some reason some terms in the database do not have a
+  // proper type associated with them. We make this fact a little more
+  // explicit by replacing the empty string. Note that it is important
+  // to properly handle this problem at the level of SQL. We sort by the
+  // type column and if we perform the replacement afterwards we mess up
+  // the order because the empty string '' is sorted before all other
+  // strings.
+  format!(
+    "SELECT {src},{dst}, \
+       CASE {typ} WHEN '' \
+         THEN 'unknown' \
+         ELSE entry_type \
+       END AS __type__, \
+       {use}, \
+       {id} AS __id__ \
+     FROM {tbl}",
+    src = src_col, dst = dst_col,
+    typ = TYPE_COL, tbl = table, use = USAGE_COL, id = id,
+  )
+}
+
+/// Quote a string for use as an SQL string literal.
+#[cfg(feature = "sqlite")]
+fn quote(string: &str) -> String {
+  format!("'{}'", string.replace('\'', "''"))
+}
+
+/// Build the order clause for rows retrieved by `select_clause`.
+#[cfg(feature = "sqlite")]
+fn order_clause(direction: &Direction, options: &Options) -> String {
+  let query = options.rank.as_deref().map(quote).unwrap_or_default();
+  format!("ORDER BY {}", order_terms(direction, options, &query))
+}
+
+/// Build the terms of the order clause for rows retrieved by
+/// `select_clause`. When ranking, relevance is determined with respect
+/// to the SQL expression `query`.
+#[cfg(feature = "sqlite")]
+fn order_terms(direction: &Direction, options: &Options, query: &str) -> String {
+  let (src_col, _) = columns(direction);
+  if options.rank.is_some() {
+    return format!(
+      "relevance({src}, {query}, {use}, {usage:?}, {length:?}) DESC, \
+       {use} DESC, \
+       {src} COLLATE {collation} ASC",
+      src = src_col, query = query, use = USAGE_COL,
+      usage = options.weights.usage, length = options.weights.length,
+      collation = options.collation.sql_name(),
+    );
+  }
+
+  // Types the user wants to see first are ranked by their position in
+  // the list, all others come afterwards.
+  let types = if options.type_order.is_empty() {
+    String::new()
+  } else {
+    let whens = options
+      .type_order
+      .iter()
+      .enumerate()
+      .map(|(i, type_)| format!("WHEN {} THEN {}", quote(type_), i))
+      .collect::<Vec<_>>();
+    format!(
+      "CASE __type__ {whens} ELSE {n} END ASC, ",
+      whens = whens.join(" "), n = whens.len(),
+    )
+  };
+  // We order by type first and then by the number of uses. The reason
+  // is that we first want to print all the translations for a
+  // particular type sorted by the number of uses before moving on to
+  // the next type.
+  format!(
+    "{types}__type__ ASC, \
+     {use} DESC, \
+     {src} COLLATE {collation} ASC",
+    types = types, src = src_col, use = USAGE_COL, collation = options.collation.sql_name(),
+  )
+}
+
+/// Retrieve the source column expression to match against and the
+/// placeholder to use for parameters.
+#[cfg(feature = "sqlite")]
+fn match_operands(direction: &Direction, options: &Options) -> (String, &'static str) {
+  let (src_col, _) = columns(direction);
+  // For accent-insensitive matching we strip diacritics from both the
+  // column and the pattern.
+  if options.ignore_accents {
+    (format!("unaccent({})", src_col), "unaccent(?)")
+  } else {
+    (src_col.to_string(), "?")
+  }
+}
+
+/// Retrieve the operator to match LIKE patterns with along with the
+/// expression for the pattern, given the placeholder for it.
+///
+/// The patterns are expected to be escaped with `pattern::ESCAPE`.
+#[cfg(feature = "sqlite")]
+fn match_operator(arg: &str, options: &Options) -> (&'static str, String) {
+  // SQLite's GLOB operator is case sensitive. The patterns we bind are
+  // LIKE patterns, though, and need to be converted.
+  if options.case_sensitive {
+    ("GLOB", format!("like_to_glob({}, '{}')", arg, pattern::ESCAPE))
+  } else {
+    ("LIKE", format!("{} ESCAPE '{}'", arg, pattern::ESCAPE))
+  }
+}
+
+/// Build the two sets of conditions a single term is matched with.
+///
+/// The first set matches the term itself, possibly with annotations (see
+/// `pattern::matches`). The second one matches entries containing the
+/// term along with other words, outside of annotations unless comments
+/// are to be included.
+#[cfg(feature = "sqlite")]
+fn term_conditions(col: &str, arg: &str, options: &Options) -> (String, String) {
+  let (op, pattern) = match_operator(arg, options);
+
+  // Verbs are additionally matched with a preceding "to" (with and
+  // without trailing annotations).
+  let verb = format!("({src} {op} {pat} AND __type__='verb')", src = col, op = op, pat = pattern);
+  let cond1 = format!(
+    "matches_term({src}, {arg}, {case}) OR {verb} OR {verb}",
+    src = col, arg = arg, case = options.case_sensitive as u8, verb = verb,
+  );
+  let mut cond2 = format!(
+    "headword({src}) {op} {pat} OR \
+     headword({src}) {op} {pat} OR \
+     headword({src}) {op} {pat}",
+    src = col, op = op, pat = pattern,
+  );
+  if options.include_comments {
+    cond2 += &format!(" OR comments_contain({src}, {arg})", src = col, arg = arg);
+  }
+  (cond1, cond2)
+}
+
+/// Build the parameters for the conditions returned by
+/// `term_conditions`, in the very same order.
+///
+/// The parameters of the second set of conditions are only needed if
+/// not only exact matches are of interest, so they are omitted with
+/// `exact` set.
+#[cfg(feature = "sqlite")]
+fn term_binds(to_translate: &str, include_comments: bool,
+              exact: bool) -> (Vec<sqlite::Value>, Vec<sqlite::Value>) {
+  let mut binds1 = vec![sqlite::Value::String(to_translate.to_string())];
+  binds1.extend(pattern::verb(to_translate).into_iter().map(sqlite::Value::String));
+  if exact {
+    return (binds1, Vec::new())
+  }
+
+  let mut binds2 = pattern::in_phrase(to_translate)
+    .into_iter()
+    .map(sqlite::Value::String)
+    .collect::<Vec<_>>();
+  if include_comments {
+    binds2.push(sqlite::Value::String(to_translate.to_string()));
+  }
+  (binds1, binds2)
+}
+
+/// Restrict a condition to the entries admitted by the filters in
+/// `options`.
+#[cfg(feature = "sqlite")]
+fn restrict(cond: &str, options: &Options) -> String {
+  let mut cond = cond.to_string();
+  if !options.subjects.is_empty() {
+    // The ids are integers, so we can safely embed them directly.
+    let subjects = options
+      .subjects
+      .iter()
+      .map(|id| format!("{col} LIKE '%,{id},%'", col = SUBJ_COL, id = id))
+      .collect::<Vec<_>>();
+    cond = format!("({}) AND ({})", cond, subjects.join(" OR "));
+  }
+  if !options.types.is_empty() {
+    // An entry may have several types, separated by spaces.
+    let types = options
+      .types
+      .iter()
+      .map(|type_| {
+        let type_ = quote(&format!(" {} ", type_.name()));
+        format!("instr(' ' || __type__ || ' ', {}) > 0", type_)
+      })
+      .collect::<Vec<_>>();
+    cond = format!("({}) AND ({})", cond, types.join(" OR "));
+  }
+  cond
+}
+
+/// Build the conditions for matching the given terms along with the
+/// parameters to bind to them.
+///
+/// The result is a set of conditions, each to be used in a select of
+/// its own, with the individual selects being combined.
+#[cfg(feature = "sqlite")]
+pub fn term_query<S>(terms: &[S], direction: &Direction,
+                     options: &Options) -> (Vec<String>, Vec<sqlite::Value>)
+where
+  S: AsRef<str>,
+{
+  let (conds, binds): (Vec<_>, Vec<_>) = term_conds(terms, direction, options).into_iter().unzip();
+  (conds, binds.concat())
+}
+
+/// Build the conditions for matching the given terms, each along with
+/// the parameters to bind to it.
+#[cfg(feature = "sqlite")]
+fn term_conds<S>(terms: &[S], direction: &Direction,
+                 options: &Options) -> Vec<(String, Vec<sqlite::Value>)>
+where
+  S: AsRef<str>,
+{
+  let (col, arg) = match_operands(direction, options);
+
+  if options.phrase {
+    // The words of a phrase may be interspersed with annotations in
+    // the database, e.g., "to give [sth.] up". So we cannot just use
+    // LIKE here.
+    let case = options.case_sensitive as u8;
+    let cond = if options.include_comments {
+      format!(
+        "contains_phrase({src}, {arg}, {case}) OR comments_contain({src}, {arg})",
+        src = col, arg = arg, case = case,
+      )
+    } else {
+      format!("contains_phrase({src}, {arg}, {case})", src = col, arg = arg, case = case)
+    };
+    let conds = vec![format!("({})", cond); terms.len()];
+    let per_term = if options.include_comments { 2 } else { 1 };
+    let binds = terms
+      .iter()
+      .flat_map(|t| vec![sqlite::Value::String(t.as_ref().to_string()); per_term])
+      .collect();
+    return vec![(restrict(&conds.join(" OR "), options), binds)];
+  }
+
+  if options.all_words {
+    // Each word has to appear somewhere in the term, not necessarily
+    // as a word of its own.
+    let (op, pattern) = match_operator(arg, options);
+    let src = if options.include_comments {
+      col
+    } else {
+      format!("headword({})", col)
+    };
+    let mut conds = Vec::new();
+    let mut binds = Vec::new();
+    for term in terms {
+      let words = term
+        .as_ref()
+        .split_whitespace()
+        .map(|word| {
+          binds.push(sqlite::Value::String(pattern::containing(word)));
+          format!("{} {} {}", src, op, pattern)
+        })
+        .collect::<Vec<_>>();
+      if !words.is_empty() {
+        conds.push(format!("({})", words.join(" AND ")));
+      }
+    }
+    if conds.is_empty() {
+      return Vec::new();
+    }
+    return vec![(restrict(&conds.join(" OR "), options), binds)];
+  }
+
+  let mut conds1 = Vec::new();
+  let mut conds2 = Vec::new();
+  let mut binds1 = Vec::new();
+  let mut binds2 = Vec::new();
+  for to_translate in terms {
+    let (c1, c2) = term_conditions(&col, arg, options);
+    let (b1, b2) = term_binds(to_translate.as_ref(), options.include_comments, options.exact);
+    conds1.push(format!("({})", c1));
+    conds2.push(format!("({})", c2));
+    binds1.push(b1);
+    binds2.push(b2);
+  }
+  // Exact matches are covered by the first set of conditions alone.
+  if options.exact {
+    return vec![(restrict(&conds1.join(" OR "), options), binds1.concat())];
+  }
+  vec![
+    (restrict(&conds1.join(" OR "), options), binds1.concat()),
+    (restrict(&conds2.join(" OR "), options), binds2.concat()),
+  ]
+}
+
+/// Build the full-text query narrowing down the entries that may match
+/// a term, if the full-text index can be used for it.
+///
+/// The index merely narrows down the candidates, which are then checked
+/// with the regular conditions, so the query must not be any more
+/// selective than those. SQLite's default tokenizer splits text at
+/// ASCII characters other than letters and digits and folds the case of
+/// ASCII letters only. Hence, we require only those words of the term
+/// to be present that consist of nothing but ASCII letters and digits.
+/// The unicode61 tokenizer folds the case of all letters, so that words
+/// consisting of any letters and digits can be required, and, if it
+/// removes all diacritics, even when ignoring accents. Matching inside
+/// of other words or at boundaries not recognized by the tokenizer, as
+/// phrases and comments are, cannot be narrowed down.
+#[cfg(feature = "sqlite")]
+fn fulltext_query(term: &str, direction: &Direction, options: &Options,
+                  index: FullText) -> Option<String> {
+  if options.phrase || options.all_words || options.include_comments {
+    return None;
+  }
+
+  let usable = |word: &str| match index {
+    FullText::Ascii => word.is_ascii(),
+    FullText::Unicode { .. } => word.chars().all(char::is_alphanumeric),
+  };
+  match index {
+    FullText::Unicode { remove_diacritics: 2 } => (),
+    _ if options.ignore_accents => return None,
+    _ => (),
+  }
+
+  let (src_col, _) = columns(direction);
+  let mut words = term
+    .split(|c: char| c.is_ascii() && !c.is_ascii_alphanumeric())
+    .filter(|word| !word.is_empty() && usable(word))
+    .map(|word| format!("{}:{}", src_col, word.to_lowercase()))
+    .collect::<Vec<_>>();
+  words.sort();
+  words.dedup();
+  if words.is_empty() {
+    None
+  } else {
+    Some(words.join(" "))
+  }
+}
+
+/// Build the condition narrowing down the entries that may match a
+/// term exactly by means of the headwords table (see `optimize`), along
+/// with the parameters to bind to it, if it can be used.
+///
+/// An exact match has the term as its headword, ignoring case, or, as a
+/// verb, has a headword of "to" followed by the term and possibly more
+/// words.
+#[cfg(feature = "sqlite")]
+fn headword_cond(term: &str, direction: &Direction,
+                 options: &Options) -> Option<(String, Vec<sqlite::Value>)> {
+  if !options.exact || options.ignore_accents || options.phrase || options.all_words {
+    return None;
+  }
+
+  let (src_col, _) = columns(direction);
+  let key = optimize::fold(&term::headword(term));
+  if key.is_empty() {
+    return None;
+  }
+  let (lower, upper) = optimize::prefix_range(&format!("to {} ", key));
+  let cond = format!(
+    "rowid IN (SELECT id FROM {tbl} WHERE col = {col} AND \
+     (key = ? OR key = ? OR key BETWEEN ? AND ?))",
+    tbl = optimize::HEADWORDS_TBL, col = optimize::column_id(src_col),
+  );
+  let binds = vec![
+    sqlite::Value::String(format!("to {}", key)),
+    sqlite::Value::String(key),
+    sqlite::Value::String(lower),
+    sqlite::Value::String(upper),
+  ];
+  Some((cond, binds))
+}
+
+/// Build the statement selecting the entries matching the given terms
+/// along with the parameters to bind to it, if any can match.
+///
+/// If `fulltext` is set, the database's full-text index of the given
+/// kind is used to narrow down the entries to check for terms that
+/// allow for it. If
+/// `headwords` is set, the headwords table is used to the same end,
+/// which is preferred where possible, as it narrows down further. If
+/// `overlay` is set, the user's entries are selected as well, while
+/// those hidden by the user are not.
+///
+/// An entry matching in more than one way is selected once for each.
+/// Rather than having SQLite deduplicate the (possibly large) result,
+/// it is up to the caller to skip rows with an id seen already (see
+/// `row_id`) and, hence, to enforce a limit on the number of entries.
+#[cfg(feature = "sqlite")]
+fn term_select<S>(terms: &[S], direction: &Direction, options: &Options,
+                  fulltext: Option<FullText>, headwords: bool,
+                  overlay: bool) -> Option<(String, Vec<sqlite::Value>)>
+where
+  S: AsRef<str>,
+{
+  if terms.is_empty() {
+    return None;
+  }
+
+  let start = time::Instant::now();
+  let select = select_clause(direction);
+  let mut selects = Vec::new();
+  let mut binds = Vec::new();
+  let mut rest = Vec::new();
+  for term in terms {
+    let term = term.as_ref();
+    if let Some((narrow, narrow_binds)) = headword_cond(term, direction, options).filter(|_| headwords) {
+      for (cond, cond_binds) in term_conds(&[term], direction, options) {
+        selects.push(format!(
+          "{select} WHERE {narrow} AND ({cond})",
+          select = select, narrow = narrow, cond = cond,
+        ));
+        binds.extend(narrow_binds.iter().cloned());
+        binds.extend(cond_binds);
+      }
+      continue
+    }
+
+    match fulltext.and_then(|index| fulltext_query(term, direction, options, index)) {
+      // A MATCH cannot be part of a disjunction, so each term narrowed
+      // down by the index is looked up by selects of its own.
+      Some(query) => {
+        for (cond, cond_binds) in term_conds(&[term], direction, options) {
+          selects.push(format!(
+            "{select} WHERE {tbl} MATCH ? AND ({cond})",
+            select = select, tbl = SEARCH_TBL, cond = cond,
+          ));
+          binds.push(sqlite::Value::String(query.clone()));
+          binds.extend(cond_binds);
+        }
+      },
+      None => rest.push(term),
+    }
+  }
+
+  if !rest.is_empty() {
+    for (cond, cond_binds) in term_conds(&rest, direction, options) {
+      selects.push(format!("{select} WHERE {cond}", select = select, cond = cond));
+      binds.extend(cond_binds);
+    }
+  }
+  // There are few user entries, so they are not worth narrowing down.
+  if overlay {
+    for (cond, cond_binds) in term_conds(terms, direction, options) {
+      selects.push(user_select(direction, &cond));
+      binds.extend(cond_binds);
+    }
+  }
+  if selects.is_empty() {
+    return None;
+  }
+
+  // Note that the terms of the ORDER BY clause of a compound select
+  // have to refer to result columns and cannot be arbitrary
+  // expressions. Hence, we order the result of a sub-query.
+  let query = format!(
+    "SELECT * FROM ({rows}) {order}",
+    rows = visible_select(&selects.join(" UNION ALL "), direction, overlay, options.merge),
+    order = order_clause(direction, options),
+  );
+  info!("Built query with {} parameters in {:.1?}", binds.len(), start.elapsed());
+  Some((query, binds))
+}
+
+/// Translate the given terms, matching them as dictated by `options`.
+///
+/// This is a shorthand for running a [`Query`] for the terms.
+#[cfg(feature = "sqlite")]
+pub fn translate_with<F, S>(db: &path::Path, terms: &[S], direction: &Direction,
+                            options: &Options, callback: F) -> Result<()>
+where
+  F: FnMut(&Entry) -> Result<()>,
+  S: AsRef<str>,
+{
+  Query::with_terms(terms)
+    .direction(*direction)
+    .options(options.clone())
+    .run(db, callback)
+}
+
+/// Translate several independent words in a single query.
+///
+/// Each word is expanded into the terms to search for by means of
+/// `expand` and matched on its own. Results are reported along with the
+/// index of the word they were found for, in the order of the words.
+#[cfg(feature = "sqlite")]
+pub fn translate_words<F, S, E>(db: &path::Path, words: &[S], direction: &Direction,
+                                options: &Options, mut expand: E, mut callback: F) -> Result<()>
+where
+  F: FnMut(usize, &Entry) -> Result<()>,
+  S: AsRef<str>,
+  E: FnMut(&str) -> Vec<String>,
+{
+  let database = database(db)?;
+  let select = select_clause(direction);
+  let mut selects = Vec::new();
+  let mut binds = Vec::new();
+  for (i, word) in words.iter().enumerate() {
+    let terms = expand(word.as_ref());
+    if terms.is_empty() {
+      continue;
+    }
+
+    let (conds, mut word_binds) = term_query(&terms, direction, options);
+    let mut word_selects = conds
+      .iter()
+      .map(|cond| format!("{select} WHERE {cond}", select = select, cond = cond))
+      .collect::<Vec<_>>();
+    if database.overlay() {
+      word_selects.extend(conds.iter().map(|cond| user_select(direction, cond)));
+      word_binds.extend(word_binds.clone());
+    }
+    // We tag each row with the word it belongs to, which is also what
+    // relevance is determined against when ranking. Duplicates are
+    // merged for each word on its own.
+    let rows = visible_select(
+      &word_selects.join(" UNION ALL "), direction, database.overlay(), options.merge,
+    );
+    selects.push(format!(
+      "SELECT *, {i} AS __word__, {word} AS __query__ FROM ({rows})",
+      i = i, word = quote(word.as_ref()), rows = rows,
+    ));
+    binds.extend(word_binds);
+  }
+  if selects.is_empty() {
+    return Ok(());
+  }
+
+  // Rows include duplicates, so a limit cannot be imposed on them.
+  // Rather, we stop once the maximum number of entries was reported.
+  let query = format!(
+    "SELECT * FROM ({selects}) ORDER BY __word__ ASC, {order}",
+    selects = selects.join(" UNION ALL "), order = order_terms(direction, options, "__query__"),
+  );
+  // An entry matching a word in more than one way is reported once.
+  let mut seen = HashSet::new();
+  let mut remaining = options.max_results;
+  database.execute_while(&query, &binds, options, |row| {
+    if remaining == Some(0) {
+      return Ok(false)
+    }
+    let word = row[5].as_integer().ok_or_else(|| Error::InvalidSchema(format!(
+      "Invalid word column in result: {:?}",
+      row
+    )))?;
+    if row_id(row).is_none_or(|id| seen.insert((word, id))) {
+      remaining = remaining.map(|remaining| remaining - 1);
+      callback(word as usize, &read_row(row)?)?;
+    }
+    Ok(remaining != Some(0))
+  })
+}
+
+/// Build the condition for matching a boolean query along with the
+/// parameters to bind to it.
+///
+/// Each term in the query is expanded into the terms to search for by
+/// means of `expand`, e.g., to cover transliterations.
+#[cfg(feature = "sqlite")]
+pub fn boolean_query<E>(query: &query::Expr, direction: &Direction,
+                        options: &Options, mut expand: E) -> (String, Vec<sqlite::Value>)
+where
+  E: FnMut(&str) -> Vec<String>,
+{
+  // Filters apply to the query as a whole and not to its individual
+  // terms, or negated terms would admit entries not passing them.
+  let phrase_options = Options {
+    phrase: true,
+    subjects: Vec::new(),
+    types: Vec::new(),
+    ..options.clone()
+  };
+  let term_options = Options {
+    phrase: false,
+    subjects: Vec::new(),
+    types: Vec::new(),
+    ..options.clone()
+  };
+
+  let (cond, binds) = query.compile(&mut |leaf| {
+    let (conds, binds) = match *leaf {
+      query::Expr::Term(ref term) => term_query(&expand(term), direction, &term_options),
+      query::Expr::Phrase(ref phrase) => term_query(&[phrase], direction, &phrase_options),
+      _ => unreachable!(),
+    };
+    let conds = conds.iter().map(|c| format!("({})", c)).collect::<Vec<_>>();
+    (conds.join(" OR "), binds)
+  });
+  (restrict(&cond, options), binds)
+}
+
+/// Translate the entries matching a boolean query.
+#[cfg(feature = "sqlite")]
+pub fn translate_query<F, E>(db: &path::Path, query: &query::Expr, direction: &Direction,
+                             options: &Options, expand: E, mut callback: F) -> Result<()>
+where
+  F: FnMut(&Entry) -> Result<()>,
+  E: FnMut(&str) -> Vec<String>,
+{
+  let database = database(db)?;
+  let (cond, mut binds) = boolean_query(query, direction, options, expand);
+  let select = if database.overlay() {
+    binds.extend(binds.clone());
+    let rows = format!(
+      "{select} WHERE {cond} UNION ALL {user}",
+      select = select_clause(direction), cond = cond, user = user_select(direction, &cond),
+    );
+    format!(
+      "SELECT * FROM ({rows}) {order}{limit}",
+      rows = visible_select(&rows, direction, true, options.merge),
+      order = order_clause(direction, options), limit = options.limit_clause(),
+    )
+  } else {
+    format!(
+      "{select} WHERE {cond} {order}{limit}",
+      select = select_clause(direction), cond = cond, order = order_clause(direction, options),
+      limit = options.limit_clause(),
+    )
+  };
+  database.execute(&select, &binds, options, |row| callback(&read_row(row)?))
+}
+
+/// Build the select of the entries satisfying any of the given
+/// conditions, including the user's but not those hidden by the user,
+/// along with the parameters to bind.
+///
+/// Duplicates among them are reported once, and how they are merged
+/// does not matter for counting or checking for their existence.
+#[cfg(feature = "sqlite")]
+fn any_select(database: &Database, direction: &Direction, conds: &[String],
+              binds: &[sqlite::Value]) -> (String, Vec<sqlite::Value>) {
+  let conds = conds.iter().map(|c| format!("({})", c)).collect::<Vec<_>>();
+  let cond = conds.join(" OR ");
+  let select = format!("{} WHERE {}", select_clause(direction), cond);
+  if database.overlay() {
+    let rows = format!("{} UNION ALL {}", select, user_select(direction, &cond));
+    let select = visible_select(&rows, direction, true, Merge::default());
+    (select, [binds, binds].concat())
+  } else {
+    (select, binds.to_vec())
+  }
+}
+
+/// Count the entries satisfying any of the given conditions.
+#[cfg(feature = "sqlite")]
+fn count(db: &path::Path, direction: &Direction,
+         conds: &[String], binds: &[sqlite::Value]) -> Result<usize> {
+  if conds.is_empty() {
+    return Ok(0);
+  }
+
+  let database = database(db)?;
+  let (select, binds) = any_select(&database, direction, conds, binds);
+  let select = format!("SELECT COUNT(*) FROM ({})", select);
+  let mut statement = database.connection().prepare(select)?;
+  for (i, bind) in binds.iter().enumerate() {
+    statement.bind(i + 1, bind)?;
+  }
+  statement.next()?;
+  Ok(statement.read::<i64>(0)? as usize)
+}
+
+/// Heuristically determine which language a term is in and, hence, the
+/// direction to translate it in.
+///
+/// Terms containing umlauts or 'ß' are assumed to be German. Otherwise
+/// we pick the direction in which the term matches more entries, as
+/// determined by `conditions`.
+#[cfg(feature = "sqlite")]
+pub fn detect_direction<C>(db: &path::Path, term: &str, conditions: C) -> Result<Direction>
+where
+  C: Fn(&Direction) -> Result<(Vec<String>, Vec<sqlite::Value>)>,
+{
+  if term.chars().any(|c| "äöüÄÖÜß".contains(c)) {
+    return match spell::german_column(&*open(db)?)? {
+      TERM1_COL => Ok(Direction::Lang1ToLang2),
+      _ => Ok(Direction::Lang2ToLang1),
+    };
+  }
+
+  let mut counts = Vec::with_capacity(2);
+  for direction in &[Direction::Lang1ToLang2, Direction::Lang2ToLang1] {
+    let (conds, binds) = conditions(direction)?;
+    counts.push(count(db, direction, &conds, &binds)?);
+  }
+  if counts[1] > counts[0] {
+    Ok(Direction::Lang2ToLang1)
+  } else {
+    Ok(Direction::Lang1ToLang2)
+  }
+}
+
+/// Check whether any entry satisfies any of the given conditions.
+///
+/// In contrast to a translation we stop at the first match.
+#[cfg(feature = "sqlite")]
+pub fn exists(db: &path::Path, direction: &Direction,
+              conds: &[String], binds: &[sqlite::Value]) -> Result<bool> {
+  if conds.is_empty() {
+    return Ok(false);
+  }
+
+  let database = database(db)?;
+  let (select, binds) = any_select(&database, direction, conds, binds);
+  let select = format!("{} LIMIT 1", select);
+  let mut cursor = database.connection().prepare(select)?.cursor();
+  cursor.bind(&binds)?;
+  Ok(cursor.next()?.is_some())
+}
+
+/// Invoke a callback for each entry of the dictionary in the given
+/// direction that matches the subjects and types in `options`.
+#[cfg(feature = "sqlite")]
+pub fn for_each_entry<F>(connection: &sqlite::Connection, direction: &Direction,
+                         options: &Options, mut callback: F) -> Result<()>
+where
+  F: FnMut(Entry) -> Result<()>,
+{
+  let query = format!("{} WHERE {}", select_clause(direction), restrict("1", options));
+  let mut cursor = connection.prepare(query)?.cursor();
+  while let Some(row) = cursor.next()? {
+    callback(read_row(row)?)?;
+  }
+  Ok(())
+}
+
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+  use super::*;
+
+  #[test]
+  #[allow(clippy::assertions_on_constants)]
+  fn fail_db_not_found() {
+    let db = path::Path::new("./test/does_not_exist.db");
+    let callback = |_: &Entry| {
+      assert!(false);
+      Err(Error::Error("unreachable".to_string()))
+    };
+
+    let err = translate(db, &[""], &Direction::Lang2ToLang1, callback).unwrap_err();
+    match err {
+      Error::DatabaseNotFound(ref x) => assert_eq!(x, db),
+      _ => panic!("Unexpected error: {}", err),
+    }
+    assert_eq!(err.to_string(), "Database ./test/does_not_exist.db not found");
+  }
+
+  #[test]
+  fn read_only_uris() {
+    assert_eq!(
+      read_only_uri(path::Path::new("test/test.db"), true).unwrap(),
+      "file:test/test.db?mode=ro&immutable=1"
+    );
+    assert_eq!(
+      read_only_uri(path::Path::new("//tmp/50%?#.db"), false).unwrap(),
+      "file:////tmp/50%25%3f%23.db?mode=ro"
+    );
+  }
+
+  #[test]
+  fn open_read_only_database() {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    // A name that has to be escaped in a URI.
+    let dir = env::temp_dir().join(format!("dictcc-cli-read-only-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let db = dir.join("dict?cc#1%.db");
+    fs::copy("./test/test.db", &db).unwrap();
+
+    let count = |connection: &sqlite::Connection| {
+      let mut statement = connection.prepare(format!("SELECT COUNT(*) FROM {}", SEARCH_TBL)).unwrap();
+      statement.next().unwrap();
+      statement.read::<i64>(0).unwrap()
+    };
+    let connection = open_read_only(&db, &Tuning::default()).unwrap();
+    assert_eq!(count(&connection), 29);
+    assert!(connection.execute(format!("DELETE FROM {}", SEARCH_TBL)).is_err());
+
+    // Changes made while the connection is open are seen by it.
+    let writable = open_writable(&db).unwrap();
+    writable.execute(format!("DELETE FROM {} WHERE rowid = (SELECT MIN(rowid) FROM {0})", SEARCH_TBL)).unwrap();
+    drop(writable);
+    assert_eq!(count(&connection), 28);
+    drop(connection);
+
+    let tuning = Tuning {
+      immutable: true,
+      ..Default::default()
+    };
+    let connection = open_read_only(&db, &tuning).unwrap();
+    assert_eq!(count(&connection), 28);
+    drop(connection);
+
+    // No other files got created along the way.
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn normalize_terms() {
+    assert_eq!(normalize("dorky  [coll.]"), "dorky [coll.]");
+    assert_eq!(normalize("a   b    c"), "a b c");
+    assert!(matches!(normalize("dorky [coll.]"), borrow::Cow::Borrowed("dorky [coll.]")));
+    assert_eq!(normalize(""), "");
+  }
+
+  #[test]
+  fn error_sources() {
+    use std::error::Error as StdError;
+
+    let err = Error::Io("Failed to read x".to_string(), io::Error::from(io::ErrorKind::NotFound));
+    assert!(err.to_string().starts_with("Failed to read x: "));
+    assert!(err.source().is_some());
+
+    match query::parse("(Zorn OR Wut") {
+      Err(ref err @ Error::QuerySyntax(_)) => assert!(err.source().is_none()),
+      result => panic!("Unexpected result: {:?}", result.map(|_| ())),
+    }
+  }
+
+  #[test]
+  #[allow(clippy::assertions_on_constants)]
+  fn no_results() {
+    let db = path::Path::new("./test/test.db");
+    let callback = |_: &Entry| {
+      assert!(false);
+      Err(Error::Error("unreachable".to_string()))
+    };
+
+    // We attempt translation of a word that has no translations. We
+    // expect no errors.
+    translate(db, &["awordthatdoesnotexist"], &Direction::Lang2ToLang1, callback).unwrap();
+  }
+
+  /// Check that the length of a phrase is not limited by the depth of
+  /// the expressions SQLite supports.
+  #[test]
+  fn long_phrase() {
+    let db = path::Path::new("./test/test.db");
+    let phrase = (0..40).map(|i| format!("w{}", i)).collect::<Vec<_>>().join(" ");
+    let mut found = 0;
+    translate(db, &[phrase.as_str()], &Direction::Lang2ToLang1, |_| {
+      found += 1;
+      Ok(())
+    }).unwrap();
+    assert_eq!(found, 0);
+  }
+
+  fn collect_translations_dir<S>(to_translate: S,
+                                 direction: &Direction)
+                                 -> Vec<(String, String, String)>
+  where
+    S: Into<String>,
+  {
+    let mut found = Vec::new();
+    let db = path::Path::new("./test/test.db");
+    {
+      let callback = |entry: &Entry| {
+        found.push((entry.source.text.clone(), entry.type_name(), entry.target.text.clone()));
+        Ok(())
+      };
+
+      translate(db, &[to_translate.into()], direction, callback).unwrap();
+    }
+    found
+  }
+
+  fn collect_translations<S>(to_translate: S) -> Vec<(String, String, String)>
+  where
+    S: Into<String>,
+  {
+    collect_translations_dir(to_translate, &Direction::Lang2ToLang1)
+  }
+
+  #[test]
+  fn max_results() {
+    let db = path::Path::new("./test/test.db");
+    let options = Options {
+      max_results: Some(2),
+      ..Default::default()
+    };
+    assert_eq!(options.cap(None), Some(2));
+    assert_eq!(options.cap(Some(1)), Some(1));
+    assert_eq!(options.cap(Some(3)), Some(2));
+    assert_eq!(Options::default().cap(Some(3)), Some(3));
+
+    let mut found = 0;
+    translate_with(db, &["anger"], &Direction::Lang2ToLang1, &options, |_| {
+      found += 1;
+      Ok(())
+    }).unwrap();
+    assert_eq!(found, 2);
+
+    let expr = query::parse("anger OR worse").unwrap();
+    let mut found = 0;
+    translate_query(db, &expr, &Direction::Lang2ToLang1, &options, |t| vec![t.to_string()], |_| {
+      found += 1;
+      Ok(())
+    }).unwrap();
+    assert_eq!(found, 2);
+
+    let mut found = 0;
+    let expand = |w: &str| vec![w.to_string()];
+    translate_words(db, &["anger", "worse"], &Direction::Lang2ToLang1, &options, expand, |_, _| {
+      found += 1;
+      Ok(())
+    }).unwrap();
+    assert_eq!(found, 2);
+
+    // Entries matching in more than one way, here "Arme {pl} [arme
+    // Leute als Klasse]" by term and by comment, do not count towards
+    // the maximum more than once.
+    let options = Options {
+      include_comments: true,
+      ..options
+    };
+    let mut found = Vec::new();
+    let expand = |_: &str| vec!["Arme".to_string(), "Inhalt".to_string()];
+    translate_words(db, &["arme"], &Direction::Lang1ToLang2, &options, expand, |_, entry| {
+      found.push(entry.source.headword.clone());
+      Ok(())
+    }).unwrap();
+    assert_eq!(found, vec!["Arme", "Inhalt"]);
+  }
+
+  #[test]
+  fn exact_binds() {
+    let (binds1, binds2) = term_binds("Zorn", true, false);
+    assert_eq!(binds1.len(), 1 + 2);
+    assert_eq!(binds2.len(), 3 + 1);
+
+    let (binds1, binds2) = term_binds("Zorn", true, true);
+    assert_eq!(binds1.len(), 1 + 2);
+    assert!(binds2.is_empty());
+
+    let exact = Options {
+      exact: true,
+      ..Default::default()
+    };
+    let conds = term_conds(&["Zorn"], &Direction::Lang1ToLang2, &exact);
+    assert_eq!(conds.len(), 1);
+    assert_eq!(conds[0].1.len(), 1 + 2);
+  }
+
+  #[test]
+  fn fulltext_queries() {
+    let options = Options::default();
+    let query = |term| fulltext_query(term, &Direction::Lang2ToLang1, &options, FullText::Ascii);
+    assert_eq!(query("give [sth.] up").unwrap(), "term2:give term2:sth term2:up");
+    assert_eq!(query("Ärger machen").unwrap(), "term2:machen");
+    assert_eq!(query("AND OR AND").unwrap(), "term2:and term2:or");
+    assert_eq!(query("Ärger"), None);
+    assert_eq!(query("%_"), None);
+
+    let options = Options {
+      ignore_accents: true,
+      ..Default::default()
+    };
+    assert_eq!(fulltext_query("anger", &Direction::Lang2ToLang1, &options, FullText::Ascii), None);
+
+    let query = |term, options: &Options, remove_diacritics| {
+      let index = FullText::Unicode { remove_diacritics };
+      fulltext_query(term, &Direction::Lang1ToLang2, options, index)
+    };
+    assert_eq!(query("Ärger machen", &Options::default(), 0).unwrap(), "term1:machen term1:ärger");
+    assert_eq!(query("„Ärger“", &Options::default(), 0), None);
+    assert_eq!(query("Arger", &options, 1), None);
+    assert_eq!(query("Arger", &options, 2).unwrap(), "term1:arger");
+  }
+
+  /// Check that narrowing down the entries to check by means of the
+  /// full-text index does not change the entries found.
+  #[test]
+  fn translate_fulltext() {
+    let db = database(path::Path::new("./test/test.db")).unwrap();
+    assert!(db.fulltext());
+
+    let lookup = |terms: &[&str], direction: &Direction, options: &Options, fulltext: bool| {
+      let index = Some(FullText::Ascii).filter(|_| fulltext);
+      let (query, binds) = term_select(terms, direction, options, index, false, false).unwrap();
+      assert_eq!(query.contains("MATCH"), fulltext);
+      let mut seen = HashSet::new();
+      let mut found = Vec::new();
+      db.execute(&query, &binds, options, |row| {
+        if seen.insert(row_id(row).unwrap()) {
+          found.push(read_row(row)?);
+        }
+        Ok(())
+      })
+      .unwrap();
+      found
+    };
+
+    let exact = Options {
+      exact: true,
+      ..Default::default()
+    };
+    let case_sensitive = Options {
+      case_sensitive: true,
+      ..Default::default()
+    };
+    let terms: &[&[&str]] = &[
+      &["anger"],
+      &["ANGER", "love"],
+      &["dorky"],
+      &["subjugate"],
+      &["the poor"],
+      &["sulfur"],
+      &["contents"],
+    ];
+    for terms in terms {
+      for options in &[Options::default(), exact.clone(), case_sensitive.clone()] {
+        for direction in &[Direction::Lang1ToLang2, Direction::Lang2ToLang1] {
+          let found = lookup(terms, direction, options, true);
+          assert_eq!(found, lookup(terms, direction, options, false), "{:?}", terms);
+        }
+      }
+    }
+    assert_eq!(lookup(&["anger"], &Direction::Lang2ToLang1, &exact, true).len(), 5);
+  }
+
+  #[test]
+  fn inject_malicious_sql() {
+    // By injecting a condition that is always true we would effectively
+    // dump the entire table's contents, if the code were prone to SQL
+    // injection.
+    let code = format!("' OR 1=1 OR {src}='", src = TERM2_COL);
+    let found = collect_translations(code);
+    assert_eq!(found, vec![]);
+  }
+
+  #[test]
+  fn translate_nauseating() {
+    let found = collect_translations("nauseating");
+    assert_eq!(
+      found,
+      vec![
+        ("nauseating".to_string(), "adj".to_string(), "ekelerregend".to_string()),
+        ("nauseating".to_string(), "adj".to_string(), "widerlich".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn translate_surefire() {
+    let found = collect_translations("surefire");
+    assert_eq!(
+      found,
+      vec![
+        ("surefire [coll.]".to_string(), "adj".to_string(), "todsicher [ugs.]".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn translate_dorky() {
+    let found = collect_translations("dorky");
+    assert_eq!(
+      found,
+      vec![
+        ("dorky [coll.]".to_string(), "adj".to_string(), "bekloppt [ugs.]".to_string()),
+        ("dorky [coll.]".to_string(), "adj".to_string(), "idiotisch".to_string()),
+        ("dorky [coll.]".to_string(), "adj".to_string(), "deppert [österr.] [südd.]".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn translate_subjugate() {
+    let found = collect_translations("subjugate");
+    assert_eq!(
+      found,
+      vec![
+        ("to subjugate".to_string(), "verb".to_string(), "unterwerfen".to_string()),
+        ("to subjugate".to_string(), "verb".to_string(), "bezwingen".to_string()),
+        ("to subjugate".to_string(), "verb".to_string(), "unterjochen".to_string()),
+        (
+          "to subjugate sb./sth.".to_string(),
+          "verb".to_string(),
+          "jdn./etw. knechten [geh.] [pej.] [unterwerfen]".to_string()
+        ),
+      ]
+    );
+  }
+
+  #[test]
+  fn translate_love() {
+    let found = collect_translations("love");
+    assert_eq!(
+      found,
+      vec![
+        ("love".to_string(), "noun".to_string(), "Liebe {f}".to_string()),
+        ("love".to_string(), "unknown".to_string(), "null [beim Tennis]".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn translate_christmas() {
+    let found = collect_translations("christmas");
+    assert_eq!(
+      found,
+      vec![
+        ("Christmas".to_string(), "noun".to_string(), "Weihnachten {n}".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn translate_wherewithals() {
+    let found = collect_translations("wherewithals");
+    assert_eq!(
+      found,
+      vec![
+        ("wherewithals {pl}".to_string(), "noun".to_string(), "Nötiges {n}".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn translate_statistics() {
+    let found = collect_translations("statistics");
+    assert_eq!(
+      found,
+      vec![
+        (
+          "statistics {pl} [science that collects and interprets numerical data] [treated as sg.] \
+           <stats>"
+          .to_string(),
+          "noun".to_string(),
+          "Statistik {f}".to_string()
+        ),
+        ("statistics".to_string(), "noun".to_string(), "Statistiken {pl}".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn translate_contents() {
+    let found = collect_translations("contents");
+    assert_eq!(
+      found,
+      vec![
+        ("contents {pl} <cont.>".to_string(), "noun".to_string(), "Inhalt {m} <Inh.>".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn translate_sulfur() {
+    let found = collect_translations("sulfur");
+    assert_eq!(
+      found,
+      vec![
+        ("sulfur <S> [Am.]".to_string(), "noun".to_string(), "Schwefel {m} <S>".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn translate_poor() {
+    let found = collect_translations("poor");
+    assert_eq!(
+      found,
+      vec![
+        (
+          "the poor {pl}".to_string(),
+          "noun".to_string(),
+          "Arme {pl} [arme Leute als Klasse]".to_string()
+        ),
+      ]
+    );
+  }
+
+  #[test]
+  fn translate_stemmed() {
+    let mut found = Vec::new();
+    let db = path::Path::new("./test/test.db");
+    {
+      let callback = |entry: &Entry| {
+        found.push((entry.source.text.clone(), entry.type_name(), entry.target.text.clone()));
+        Ok(())
+      };
+
+      let terms = stem::candidates("loves");
+      translate(db, &terms, &Direction::Lang2ToLang1, callback).unwrap();
+    }
+    assert_eq!(
+      found,
+      vec![
+        ("love".to_string(), "noun".to_string(), "Liebe {f}".to_string()),
+        ("love".to_string(), "unknown".to_string(), "null [beim Tennis]".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn translate_transliterated() {
+    let mut found = Vec::new();
+    let db = path::Path::new("./test/test.db");
+    {
+      let callback = |entry: &Entry| {
+        found.push((entry.source.text.clone(), entry.type_name(), entry.target.text.clone()));
+        Ok(())
+      };
+
+      let terms = translit::variants("aerger");
+      translate(db, &terms, &Direction::Lang1ToLang2, callback).unwrap();
+    }
+    assert_eq!(
+      found,
+      vec![
+        ("ärger".to_string(), "adj adv".to_string(), "worse".to_string()),
+        ("Ärger {m}".to_string(), "noun".to_string(), "anger".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn translate_ignore_accents() {
+    let mut found = Vec::new();
+    let db = path::Path::new("./test/test.db");
+    {
+      let callback = |entry: &Entry| {
+        found.push((entry.source.text.clone(), entry.type_name(), entry.target.text.clone()));
+        Ok(())
+      };
+
+      let options = Options {
+        ignore_accents: true,
+        ..Default::default()
+      };
+      translate_with(db, &["arger"], &Direction::Lang1ToLang2, &options, callback).unwrap();
+    }
+    assert_eq!(
+      found,
+      vec![
+        ("ärger".to_string(), "adj adv".to_string(), "worse".to_string()),
+        ("Ärger {m}".to_string(), "noun".to_string(), "anger".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn translate_exact_phrase() {
+    let db = path::Path::new("./test/test.db");
+    let options = Options {
+      phrase: true,
+      ..Default::default()
+    };
+    let collect = |phrase: &str| {
+      let mut found = Vec::new();
+      let callback = |entry: &Entry| {
+        found.push((entry.source.text.clone(), entry.target.text.clone()));
+        Ok(())
+      };
+      translate_with(db, &[phrase], &Direction::Lang2ToLang1, &options, callback).unwrap();
+      found
+    };
+
+    let found = collect("subjugate sb");
+    assert_eq!(
+      found,
+      vec![(
+        "to subjugate sb./sth.".to_string(),
+        "jdn./etw. knechten [geh.] [pej.] [unterwerfen]".to_string()
+      )]
+    );
+    assert_eq!(collect("to subjugate").len(), 4);
+    assert!(collect("subjugate s").is_empty());
+    assert!(collect("poor the").is_empty());
+  }
+
+  #[test]
+  fn translate_boolean_query() {
+    let db = path::Path::new("./test/test.db");
+    let collect = |query: &str| {
+      let mut found = Vec::new();
+      let callback = |entry: &Entry| {
+        found.push((entry.source.text.clone(), entry.target.text.clone()));
+        Ok(())
+      };
+      let expr = query::parse(query).unwrap();
+      let expand = |term: &str| vec![term.to_string()];
+      translate_query(db, &expr, &Direction::Lang2ToLang1, &Options::default(), expand, callback)
+        .unwrap();
+      found
+    };
+
+    assert_eq!(collect("subjugate").len(), 4);
+    let found = collect("subjugate NOT \"subjugate sb\"");
+    assert_eq!(found.len(), 3);
+    assert!(found.iter().all(|(src, _)| src == "to subjugate"));
+    assert_eq!(
+      collect("love OR speciation AND NOT love"),
+      collect("love OR speciation")
+    );
+    assert!(collect("love AND anger").is_empty());
+  }
+
+  #[test]
+  fn check_exists() {
+    let db = path::Path::new("./test/test.db");
+    let check = |term: &str, options: &Options| {
+      let (conds, binds) = term_query(&[term], &Direction::Lang1ToLang2, options);
+      exists(db, &Direction::Lang1ToLang2, &conds, &binds).unwrap()
+    };
+
+    assert!(check("Liebe", &Options::default()));
+    assert!(check("jdn./etw. knechten", &Options::default()));
+    assert!(!check("Hass", &Options::default()));
+    assert!(!check("Arger", &Options::default()));
+
+    let options = Options {
+      ignore_accents: true,
+      ..Default::default()
+    };
+    assert!(check("Arger", &options));
+  }
+
+  #[test]
+  fn detect_directions() {
+    let db = path::Path::new("./test/test.db");
+    let detect = |term: &str| {
+      detect_direction(db, term, |direction| {
+        Ok(term_query(&[term], direction, &Options::default()))
+      }).unwrap()
+    };
+
+    assert!(matches!(detect("Liebe"), Direction::Lang1ToLang2));
+    assert!(matches!(detect("anger"), Direction::Lang2ToLang1));
+    assert!(matches!(detect("Ärgr"), Direction::Lang1ToLang2));
+  }
+
+  #[test]
+  fn translate_type_order() {
+    let mut found = Vec::new();
+    let db = path::Path::new("./test/test.db");
+    {
+      let callback = |entry: &Entry| {
+        found.push((entry.source.text.clone(), entry.type_name(), entry.target.text.clone()));
+        Ok(())
+      };
+
+      let options = Options {
+        type_order: vec!["noun".to_string()],
+        ..Default::default()
+      };
+      translate_with(db, &["Ärger"], &Direction::Lang1ToLang2, &options, callback).unwrap();
+    }
+    assert_eq!(
+      found,
+      vec![
+        ("Ärger {m}".to_string(), "noun".to_string(), "anger".to_string()),
+        ("ärger".to_string(), "adj adv".to_string(), "worse".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn translate_case_sensitive() {
+    let translate_aerger = |term: &str| {
+      let mut found = Vec::new();
+      let options = Options {
+        case_sensitive: true,
+        ..Default::default()
+      };
+      let db = path::Path::new("./test/test.db");
+      translate_with(db, &[term], &Direction::Lang1ToLang2, &options, |entry| {
+        found.push(entry.source.text.clone());
+        Ok(())
+      }).unwrap();
+      found
+    };
+
+    assert_eq!(translate_aerger("Ärger"), vec!["Ärger {m}".to_string()]);
+    assert_eq!(translate_aerger("ärger"), vec!["ärger".to_string()]);
+    assert_eq!(translate_aerger("ÄRGER"), Vec::<String>::new());
+  }
+
+  #[test]
+  fn translate_comments() {
+    let translate_that = |include_comments| {
+      let mut found = Vec::new();
+      let options = Options {
+        include_comments,
+        ..Default::default()
+      };
+      let db = path::Path::new("./test/test.db");
+      translate_with(db, &["that"], &Direction::Lang2ToLang1, &options, |entry| {
+        found.push(entry.target.text.clone());
+        Ok(())
+      }).unwrap();
+      found
+    };
+
+    assert_eq!(translate_that(false), Vec::<String>::new());
+    assert_eq!(translate_that(true), vec!["Statistik {f}".to_string()]);
+  }
+
+  #[test]
+  fn translate_ranked() {
+    let mut found = Vec::new();
+    let db = path::Path::new("./test/test.db");
+    {
+      let callback = |entry: &Entry| {
+        found.push(entry.source.text.clone());
+        Ok(())
+      };
+
+      let options = Options {
+        rank: Some("Ärger".to_string()),
+        ..Default::default()
+      };
+      translate_with(db, &["Ärger"], &Direction::Lang1ToLang2, &options, callback).unwrap();
+    }
+    assert_eq!(found, vec!["Ärger {m}".to_string(), "ärger".to_string()]);
+  }
+
+  #[test]
+  fn translate_any_words() {
+    let mut found = Vec::new();
+    let db = path::Path::new("./test/test.db");
+    {
+      let callback = |word: usize, entry: &Entry| {
+        found.push((word, entry.source.text.clone()));
+        Ok(())
+      };
+
+      let options = Options {
+        rank: Some(String::new()),
+        ..Default::default()
+      };
+      let expand = |word: &str| vec![word.to_string()];
+      let words = ["Statistik", "Ärger", "Xylophon"];
+      translate_words(db, &words, &Direction::Lang1ToLang2, &options, expand, callback).unwrap();
+    }
+    assert_eq!(
+      found,
+      vec![
+        (0, "Statistik {f}".to_string()),
+        (1, "Ärger {m}".to_string()),
+        (1, "ärger".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn translate_all_words() {
+    let mut found = Vec::new();
+    let db = path::Path::new("./test/test.db");
+    {
+      let mut callback = |entry: &Entry| {
+        found.push(entry.source.text.clone());
+        Ok(())
+      };
+
+      let options = Options {
+        all_words: true,
+        ..Default::default()
+      };
+      let (dir1, dir2) = (Direction::Lang1ToLang2, Direction::Lang2ToLang1);
+      translate_with(db, &["sb. subjugate"], &dir2, &options, &mut callback).unwrap();
+      translate_with(db, &["Stat ken"], &dir1, &options, &mut callback).unwrap();
+      // Annotations are not matched unless comments are included.
+      translate_with(db, &["null Tennis"], &dir1, &options, &mut callback).unwrap();
+    }
+    assert_eq!(
+      found,
+      vec!["to subjugate sb./sth.".to_string(), "Statistiken {pl}".to_string()]
+    );
+  }
+
+  #[test]
+  fn translate_wildcards() {
+    let db = path::Path::new("./test/test.db");
+    let dir = Direction::Lang1ToLang2;
+    for case_sensitive in &[false, true] {
+      let options = Options {
+        case_sensitive: *case_sensitive,
+        ..Default::default()
+      };
+      let mut found = Vec::new();
+      {
+        let mut callback = |entry: &Entry| {
+          found.push(entry.source.text.clone());
+          Ok(())
+        };
+        // Wildcards in the term are matched literally.
+        for term in &["Zor%", "Zor_", "%", "_"] {
+          translate_with(db, &[term], &dir, &options, &mut callback).unwrap();
+        }
+        translate_with(db, &["Zorn"], &dir, &options, &mut callback).unwrap();
+      }
+      assert_eq!(found, vec!["Zorn {m}".to_string()]);
+    }
+  }
+
+  #[test]
+  fn translate_subject() {
+    let mut found = Vec::new();
+    let db = path::Path::new("./test/test.db");
+    {
+      let mut callback = |entry: &Entry| {
+        found.push((entry.source.text.clone(), entry.target.text.clone()));
+        Ok(())
+      };
+
+      let options = Options {
+        subjects: vec![22],
+        ..Default::default()
+      };
+      translate_with(db, &["love"], &Direction::Lang2ToLang1, &options, &mut callback).unwrap();
+
+      let options = Options {
+        subjects: vec![6],
+        ..Default::default()
+      };
+      let expr = query::parse("Artenbildung OR Schwefel OR Liebe").unwrap();
+      let expand = |term: &str| vec![term.to_string()];
+      translate_query(db, &expr, &Direction::Lang1ToLang2, &options, expand, &mut callback).unwrap();
+    }
+    assert_eq!(
+      found,
+      vec![
+        ("love".to_string(), "null [beim Tennis]".to_string()),
+        ("Schwefel {m} <S>".to_string(), "sulfur <S> [Am.]".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn translate_inhalt() {
+    let found = collect_translations_dir("inhalt", &Direction::Lang1ToLang2);
+    assert_eq!(
+      found,
+      vec![
+        ("Inhalt {m} <Inh.>".to_string(), "noun".to_string(), "contents {pl} <cont.>".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn translate_aerger() {
+    // Note that a sqlite library without ICU support does not treat
+    // Unicode characters in a case-insensitive manner. This test case
+    // verifies that our own LIKE implementation takes care of that.
+    let found = collect_translations_dir("Ärger", &Direction::Lang1ToLang2);
+    assert_eq!(
+      found,
+      vec![
+        ("ärger".to_string(), "adj adv".to_string(), "worse".to_string()),
+        ("Ärger {m}".to_string(), "noun".to_string(), "anger".to_string()),
+      ]
+    );
+  }
+}
@@ -0,0 +1,554 @@
+// like.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Unicode aware SQL functions.
+//!
+//! SQLite's built-in LIKE operator only folds the case of ASCII
+//! characters, unless the library was built with ICU support. In the
+//! absence of ICU, "Ärger" would not match "ärger", for example. We
+//! register our own implementation of the `like` function (which is
+//! what the LIKE operator is backed by) that handles Unicode properly.
+//! We also provide an `unaccent` function removing diacritics from a
+//! string, for accent-insensitive matching, a `like_to_glob` function
+//! converting LIKE patterns for case sensitive matching, a `headword`
+//! function stripping the annotations of a term, a
+//! `combine_annotations` function adding those of another term to one,
+//! a `matches_term`
+//! function checking whether a term is a given one, possibly annotated,
+//! a `contains_phrase` function checking whether a term contains a
+//! phrase as a whole (and a `comments_contain` one checking the same for
+//! its comments), and a `relevance` function scoring a term with
+//! respect to a query (optionally using custom weights for usage and
+//! length).
+
+#[cfg(feature = "sqlite")]
+use std::ffi::CStr;
+#[cfg(feature = "sqlite")]
+use std::ffi::CString;
+#[cfg(feature = "sqlite")]
+use std::os::raw::c_char;
+#[cfg(feature = "sqlite")]
+use std::os::raw::c_int;
+#[cfg(feature = "sqlite")]
+use std::os::raw::c_void;
+#[cfg(feature = "sqlite")]
+use std::ptr;
+#[cfg(feature = "sqlite")]
+use std::slice;
+#[cfg(feature = "sqlite")]
+use std::str;
+
+#[cfg(feature = "sqlite")]
+use sqlite;
+#[cfg(feature = "sqlite")]
+use sqlite3_sys as ffi;
+
+#[cfg(feature = "sqlite")]
+use pattern;
+#[cfg(feature = "sqlite")]
+use rank;
+#[cfg(feature = "sqlite")]
+use term;
+#[cfg(feature = "sqlite")]
+use Error;
+#[cfg(feature = "sqlite")]
+use Result;
+
+/// Mapping of characters carrying diacritics to their base character.
+/// Only lower case characters are listed.
+const ACCENTS: [(char, char); 61] = [
+  ('à', 'a'), ('á', 'a'), ('â', 'a'), ('ã', 'a'), ('ä', 'a'), ('å', 'a'),
+  ('ā', 'a'), ('ă', 'a'), ('ą', 'a'),
+  ('ç', 'c'), ('ć', 'c'), ('č', 'c'),
+  ('ď', 'd'), ('đ', 'd'),
+  ('è', 'e'), ('é', 'e'), ('ê', 'e'), ('ë', 'e'), ('ē', 'e'), ('ė', 'e'),
+  ('ę', 'e'), ('ě', 'e'),
+  ('ğ', 'g'),
+  ('ì', 'i'), ('í', 'i'), ('î', 'i'), ('ï', 'i'), ('ī', 'i'), ('į', 'i'),
+  ('ı', 'i'),
+  ('ł', 'l'), ('ľ', 'l'),
+  ('ñ', 'n'), ('ń', 'n'), ('ň', 'n'),
+  ('ò', 'o'), ('ó', 'o'), ('ô', 'o'), ('õ', 'o'), ('ö', 'o'), ('ø', 'o'),
+  ('ő', 'o'),
+  ('ř', 'r'),
+  ('ś', 's'), ('š', 's'), ('ş', 's'),
+  ('ť', 't'), ('ţ', 't'),
+  ('ù', 'u'), ('ú', 'u'), ('û', 'u'), ('ü', 'u'), ('ū', 'u'), ('ů', 'u'),
+  ('ű', 'u'), ('ų', 'u'),
+  ('ý', 'y'), ('ÿ', 'y'),
+  ('ź', 'z'), ('ż', 'z'), ('ž', 'z'),
+];
+
+
+/// Remove diacritics from a string, preserving the case of characters.
+pub fn unaccent(string: &str) -> String {
+  string
+    .chars()
+    .map(|c| {
+      let lower = c.to_lowercase().next().unwrap_or(c);
+      match ACCENTS.iter().find(|&&(accented, _)| accented == lower) {
+        Some(&(_, base)) if c != lower => base.to_uppercase().next().unwrap_or(base),
+        Some(&(_, base)) => base,
+        None => c,
+      }
+    })
+    .collect()
+}
+
+/// Fold the case of a string, character by character.
+fn fold(string: &str) -> Vec<char> {
+  string.chars().flat_map(|c| c.to_lowercase()).collect()
+}
+
+/// Check whether `text` matches the LIKE `pattern`, ignoring case.
+///
+/// A '%' in the pattern matches any sequence of zero or more
+/// characters, a '_' matches exactly one character. The escape
+/// character, if any, causes the character following it to be matched
+/// literally.
+pub fn like(pattern: &str, text: &str, escape: Option<char>) -> bool {
+  like_cased(pattern, text, escape, false)
+}
+
+/// Check whether `text` matches the LIKE `pattern`, as `like` does,
+/// but optionally distinguishing between upper and lower case.
+pub fn like_cased(pattern: &str, text: &str, escape: Option<char>, case_sensitive: bool) -> bool {
+  let chars = |string: &str| if case_sensitive {
+    string.chars().collect()
+  } else {
+    fold(string)
+  };
+
+  #[derive(Clone, Copy, PartialEq)]
+  enum Token {
+    Any,
+    One,
+    Char(char),
+  }
+
+  let mut tokens = Vec::new();
+  let mut pattern = chars(pattern).into_iter();
+  while let Some(c) = pattern.next() {
+    let token = match c {
+      c if Some(c) == escape => match pattern.next() {
+        Some(c) => Token::Char(c),
+        None => Token::Char(c),
+      },
+      '%' => Token::Any,
+      '_' => Token::One,
+      c => Token::Char(c),
+    };
+    tokens.push(token);
+  }
+
+  let text = chars(text);
+  let (mut p, mut t) = (0, 0);
+  // The position of the last '%' in the pattern and the position in
+  // the text it is currently assumed to match up to.
+  let mut backtrack = None;
+
+  while t < text.len() {
+    match tokens.get(p) {
+      Some(&Token::Any) => {
+        backtrack = Some((p, t));
+        p += 1;
+        continue;
+      },
+      Some(&Token::One) => {
+        p += 1;
+        t += 1;
+        continue;
+      },
+      Some(&Token::Char(c)) if c == text[t] => {
+        p += 1;
+        t += 1;
+        continue;
+      },
+      _ => (),
+    }
+
+    match backtrack {
+      Some((bp, bt)) => {
+        backtrack = Some((bp, bt + 1));
+        p = bp + 1;
+        t = bt + 1;
+      },
+      None => return false,
+    }
+  }
+  tokens[p..].iter().all(|&t| t == Token::Any)
+}
+
+
+/// Convert a LIKE pattern into an equivalent GLOB pattern. The escape
+/// character, if any, causes the character following it to be matched
+/// literally.
+pub fn like_to_glob(pattern: &str, escape: Option<char>) -> String {
+  let mut glob = String::with_capacity(pattern.len());
+  let mut chars = pattern.chars();
+  while let Some(c) = chars.next() {
+    let c = match c {
+      c if Some(c) == escape => chars.next().unwrap_or(c),
+      '%' => {
+        glob.push('*');
+        continue
+      },
+      '_' => {
+        glob.push('?');
+        continue
+      },
+      c => c,
+    };
+    match c {
+      '*' | '?' | '[' => {
+        glob.push('[');
+        glob.push(c);
+        glob.push(']');
+      },
+      c => glob.push(c),
+    }
+  }
+  glob
+}
+
+
+/// Retrieve the text of an SQL function argument.
+#[cfg(feature = "sqlite")]
+unsafe fn text<'a>(value: *mut ffi::sqlite3_value) -> Option<&'a str> {
+  if ffi::sqlite3_value_type(value) == ffi::SQLITE_NULL {
+    return None;
+  }
+  let text = ffi::sqlite3_value_text(value);
+  if text.is_null() {
+    return None;
+  }
+  let len = ffi::sqlite3_value_bytes(value) as usize;
+  str::from_utf8(slice::from_raw_parts(text, len)).ok()
+}
+
+#[cfg(feature = "sqlite")]
+extern "C" fn like_fn(context: *mut ffi::sqlite3_context,
+                      argc: c_int,
+                      argv: *mut *mut ffi::sqlite3_value) {
+  unsafe {
+    let args = slice::from_raw_parts(argv, argc as usize);
+    let escape = if args.len() > 2 {
+      match text(args[2]).map(|e| e.chars().collect::<Vec<_>>()) {
+        Some(ref e) if e.len() == 1 => Some(e[0]),
+        _ => {
+          let error = b"ESCAPE expression must be a single character";
+          ffi::sqlite3_result_error(context, error.as_ptr() as *const c_char, error.len() as c_int);
+          return;
+        },
+      }
+    } else {
+      None
+    };
+
+    match (text(args[0]), text(args[1])) {
+      (Some(pattern), Some(string)) => {
+        ffi::sqlite3_result_int(context, like(pattern, string, escape) as c_int)
+      },
+      _ => ffi::sqlite3_result_null(context),
+    }
+  }
+}
+
+#[cfg(feature = "sqlite")]
+extern "C" fn matches_term_fn(context: *mut ffi::sqlite3_context,
+                              argc: c_int,
+                              argv: *mut *mut ffi::sqlite3_value) {
+  unsafe {
+    let args = slice::from_raw_parts(argv, argc as usize);
+    // An optional third argument enables case sensitive matching.
+    let case_sensitive = args.len() > 2 && ffi::sqlite3_value_int(args[2]) != 0;
+    match (text(args[0]), text(args[1])) {
+      (Some(string), Some(term)) => {
+        let found = pattern::matches(string, term, case_sensitive);
+        ffi::sqlite3_result_int(context, found as c_int)
+      },
+      _ => ffi::sqlite3_result_null(context),
+    }
+  }
+}
+
+#[cfg(feature = "sqlite")]
+extern "C" fn contains_phrase_fn(context: *mut ffi::sqlite3_context,
+                                 argc: c_int,
+                                 argv: *mut *mut ffi::sqlite3_value) {
+  unsafe {
+    let args = slice::from_raw_parts(argv, argc as usize);
+    // An optional third argument enables case sensitive matching.
+    let case_sensitive = args.len() > 2 && ffi::sqlite3_value_int(args[2]) != 0;
+    match (text(args[0]), text(args[1])) {
+      (Some(string), Some(phrase)) => {
+        let found = term::contains_phrase(string, phrase, case_sensitive);
+        ffi::sqlite3_result_int(context, found as c_int)
+      },
+      _ => ffi::sqlite3_result_null(context),
+    }
+  }
+}
+
+#[cfg(feature = "sqlite")]
+extern "C" fn comments_contain_fn(context: *mut ffi::sqlite3_context,
+                                  argc: c_int,
+                                  argv: *mut *mut ffi::sqlite3_value) {
+  unsafe {
+    let args = slice::from_raw_parts(argv, argc as usize);
+    match (text(args[0]), text(args[1])) {
+      (Some(string), Some(phrase)) => {
+        ffi::sqlite3_result_int(context, term::comments_contain(string, phrase) as c_int)
+      },
+      _ => ffi::sqlite3_result_null(context),
+    }
+  }
+}
+
+#[cfg(feature = "sqlite")]
+extern "C" fn relevance_fn(context: *mut ffi::sqlite3_context,
+                           argc: c_int,
+                           argv: *mut *mut ffi::sqlite3_value) {
+  unsafe {
+    let args = slice::from_raw_parts(argv, argc as usize);
+    match (text(args[0]), text(args[1])) {
+      (Some(term), Some(query)) => {
+        let usage = ffi::sqlite3_value_int64(args[2]);
+        let weights = if args.len() > 4 {
+          rank::Weights {
+            usage: ffi::sqlite3_value_double(args[3]),
+            length: ffi::sqlite3_value_double(args[4]),
+          }
+        } else {
+          rank::Weights::default()
+        };
+        ffi::sqlite3_result_double(context, rank::score(term, query, usage, &weights))
+      },
+      _ => ffi::sqlite3_result_null(context),
+    }
+  }
+}
+
+#[cfg(feature = "sqlite")]
+extern "C" fn free(ptr: *mut c_void) {
+  unsafe { ffi::sqlite3_free(ptr) }
+}
+
+/// Set the result of an SQL function to the given text.
+#[cfg(feature = "sqlite")]
+unsafe fn result_text(context: *mut ffi::sqlite3_context, result: &str) {
+  let buffer = ffi::sqlite3_malloc(result.len() as c_int + 1) as *mut u8;
+  if buffer.is_null() {
+    ffi::sqlite3_result_error_nomem(context);
+    return;
+  }
+  ptr::copy_nonoverlapping(result.as_ptr(), buffer, result.len());
+  ffi::sqlite3_result_text(context,
+                           buffer as *const c_char,
+                           result.len() as c_int,
+                           Some(free));
+}
+
+#[cfg(feature = "sqlite")]
+extern "C" fn unaccent_fn(context: *mut ffi::sqlite3_context,
+                          argc: c_int,
+                          argv: *mut *mut ffi::sqlite3_value) {
+  unsafe {
+    let args = slice::from_raw_parts(argv, argc as usize);
+    match text(args[0]) {
+      Some(string) => result_text(context, &unaccent(string)),
+      None => ffi::sqlite3_result_null(context),
+    }
+  }
+}
+
+#[cfg(feature = "sqlite")]
+extern "C" fn like_to_glob_fn(context: *mut ffi::sqlite3_context,
+                              argc: c_int,
+                              argv: *mut *mut ffi::sqlite3_value) {
+  unsafe {
+    let args = slice::from_raw_parts(argv, argc as usize);
+    let escape = if args.len() > 1 {
+      text(args[1]).and_then(|e| e.chars().next())
+    } else {
+      None
+    };
+    match text(args[0]) {
+      Some(string) => result_text(context, &like_to_glob(string, escape)),
+      None => ffi::sqlite3_result_null(context),
+    }
+  }
+}
+
+#[cfg(feature = "sqlite")]
+extern "C" fn headword_fn(context: *mut ffi::sqlite3_context,
+                          argc: c_int,
+                          argv: *mut *mut ffi::sqlite3_value) {
+  unsafe {
+    let args = slice::from_raw_parts(argv, argc as usize);
+    match text(args[0]) {
+      Some(string) => result_text(context, &term::headword(string)),
+      None => ffi::sqlite3_result_null(context),
+    }
+  }
+}
+
+#[cfg(feature = "sqlite")]
+extern "C" fn combine_annotations_fn(context: *mut ffi::sqlite3_context,
+                                     argc: c_int,
+                                     argv: *mut *mut ffi::sqlite3_value) {
+  unsafe {
+    let args = slice::from_raw_parts(argv, argc as usize);
+    match (text(args[0]), text(args[1])) {
+      (Some(term), Some(other)) => result_text(context, &term::combine_annotations(term, other)),
+      (Some(term), None) => result_text(context, term),
+      (None, _) => ffi::sqlite3_result_null(context),
+    }
+  }
+}
+
+/// Register a scalar SQL function with the given connection.
+#[cfg(feature = "sqlite")]
+fn create_function(connection: &sqlite::Connection,
+                   name: &str,
+                   args: c_int,
+                   function: ffi::sqlite3_create_function_callback1) -> Result<()> {
+  let name = CString::new(name).expect("function name contains NUL byte");
+  let result = unsafe {
+    ffi::sqlite3_create_function_v2(connection.as_raw(),
+                                    name.as_ptr(),
+                                    args,
+                                    ffi::SQLITE_UTF8 | ffi::SQLITE_DETERMINISTIC,
+                                    ptr::null_mut(),
+                                    Some(function),
+                                    None,
+                                    None,
+                                    None)
+  };
+
+  if result != ffi::SQLITE_OK {
+    let message = unsafe {
+      CStr::from_ptr(ffi::sqlite3_errmsg(connection.as_raw())).to_string_lossy().into_owned()
+    };
+    return Err(Error::Error(format!("Failed to register SQL function {:?}: {}", name, message)));
+  }
+  Ok(())
+}
+
+/// Register our Unicode aware SQL functions with a connection.
+#[cfg(feature = "sqlite")]
+pub fn register(connection: &sqlite::Connection) -> Result<()> {
+  create_function(connection, "like", 2, like_fn)?;
+  create_function(connection, "like", 3, like_fn)?;
+  create_function(connection, "unaccent", 1, unaccent_fn)?;
+  create_function(connection, "headword", 1, headword_fn)?;
+  create_function(connection, "combine_annotations", 2, combine_annotations_fn)?;
+  create_function(connection, "like_to_glob", 1, like_to_glob_fn)?;
+  create_function(connection, "like_to_glob", 2, like_to_glob_fn)?;
+  create_function(connection, "matches_term", 2, matches_term_fn)?;
+  create_function(connection, "matches_term", 3, matches_term_fn)?;
+  create_function(connection, "contains_phrase", 2, contains_phrase_fn)?;
+  create_function(connection, "contains_phrase", 3, contains_phrase_fn)?;
+  create_function(connection, "comments_contain", 2, comments_contain_fn)?;
+  create_function(connection, "relevance", 3, relevance_fn)?;
+  create_function(connection, "relevance", 5, relevance_fn)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn like_patterns() {
+    assert!(like("love", "love", None));
+    assert!(like("LOVE", "love", None));
+    assert!(like("ärger", "Ärger", None));
+    assert!(like("Ärger {%}", "ärger {m}", None));
+    assert!(like("% up", "to give up", None));
+    assert!(like("%", "", None));
+    assert!(like("l_ve", "love", None));
+    assert!(like("%i%i%", "statistics", None));
+    assert!(!like("love", "loves", None));
+    assert!(!like("l_ve", "lve", None));
+    assert!(!like("%x%", "love", None));
+
+    assert!(like_cased("Ärger {%}", "Ärger {m}", None, true));
+    assert!(!like_cased("ärger {%}", "Ärger {m}", None, true));
+  }
+
+  #[test]
+  fn like_escape() {
+    assert!(like("100\\%", "100%", Some('\\')));
+    assert!(!like("100\\%", "1000", Some('\\')));
+    assert!(like("a\\_b", "a_b", Some('\\')));
+    assert!(!like("a\\_b", "axb", Some('\\')));
+  }
+
+  #[test]
+  fn glob_patterns() {
+    assert_eq!(like_to_glob("Ärger {%}", None), "Ärger {*}");
+    assert_eq!(like_to_glob("l_ve", None), "l?ve");
+    assert_eq!(like_to_glob("a*b?[c]", None), "a[*]b[?][[]c]");
+    assert_eq!(like_to_glob("100\\% \\_%", Some('\\')), "100% _*");
+    assert_eq!(like_to_glob("\\\\[%", Some('\\')), "\\[[]*");
+  }
+
+  #[test]
+  fn remove_accents() {
+    assert_eq!(unaccent("Ärger"), "Arger");
+    assert_eq!(unaccent("crème brûlée"), "creme brulee");
+    assert_eq!(unaccent("Straße"), "Straße");
+    assert_eq!(unaccent("love"), "love");
+  }
+
+  #[test]
+  #[cfg(feature = "sqlite")]
+  fn sql_functions() {
+    let connection = sqlite::open(":memory:").unwrap();
+    register(&connection).unwrap();
+
+    let mut statement = connection
+      .prepare("SELECT 'ärger' LIKE 'ÄRGER', unaccent('Ärger'), 'a%' LIKE 'a!%' ESCAPE '!', \
+                       contains_phrase('to give [sth.] up', 'give up'), \
+                       relevance('Ärger {m}', 'ärger', 0) > relevance('Ärger machen', 'ärger', 9), \
+                       headword('Inhalt {m} <Inh.>'), comments_contain('sulfur <S>', 's'), \
+                       'Ärger {m}' GLOB like_to_glob('Ärger {%}'), \
+                       'ärger' GLOB like_to_glob('Ärger'), contains_phrase('Ärger', 'ärger', 1), \
+                       relevance('Ärger', 'ärger', 9, 1.0, 0.1) = relevance('Ärger', 'ärger', 9), \
+                       relevance('Ärger', 'ärger', 9, 0.0, 0.1) < relevance('Ärger', 'ärger', 9), \
+                       combine_annotations('Zorn {m}', 'Zorn [fig.]'), combine_annotations('Zorn', NULL)")
+      .unwrap();
+    statement.next().unwrap();
+    assert_eq!(statement.read::<i64>(0).unwrap(), 1);
+    assert_eq!(statement.read::<String>(1).unwrap(), "Arger");
+    assert_eq!(statement.read::<i64>(2).unwrap(), 1);
+    assert_eq!(statement.read::<i64>(3).unwrap(), 1);
+    assert_eq!(statement.read::<i64>(4).unwrap(), 1);
+    assert_eq!(statement.read::<String>(5).unwrap(), "Inhalt");
+    assert_eq!(statement.read::<i64>(6).unwrap(), 1);
+    assert_eq!(statement.read::<i64>(7).unwrap(), 1);
+    assert_eq!(statement.read::<i64>(8).unwrap(), 0);
+    assert_eq!(statement.read::<i64>(9).unwrap(), 0);
+    assert_eq!(statement.read::<i64>(10).unwrap(), 1);
+    assert_eq!(statement.read::<i64>(11).unwrap(), 1);
+    assert_eq!(statement.read::<String>(12).unwrap(), "Zorn {m} [fig.]");
+    assert_eq!(statement.read::<String>(13).unwrap(), "Zorn");
+  }
+}
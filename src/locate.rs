@@ -0,0 +1,136 @@
+// locate.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Discovery of the database to use if none was given.
+//!
+//! The database is taken from the `DICTCC_DB` environment variable if
+//! it is set. Otherwise we look for a single `*.db` file in
+//! `$XDG_DATA_HOME/dictcc/` (defaulting to `~/.local/share/dictcc/`).
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path;
+
+use Error;
+use Result;
+
+/// The environment variable naming the database to use.
+pub const ENV_VAR: &str = "DICTCC_DB";
+/// The name of the directory databases are searched in.
+const DATA_DIR: &str = "dictcc";
+/// The extension of database files.
+const EXTENSION: &str = "db";
+
+
+/// Retrieve the path to the directory searched for databases.
+pub fn data_dir() -> Option<path::PathBuf> {
+  let dir = match env::var_os("XDG_DATA_HOME") {
+    Some(ref dir) if !dir.is_empty() => path::PathBuf::from(dir),
+    _ => path::PathBuf::from(env::var_os("HOME")?).join(".local").join("share"),
+  };
+  Some(dir.join(DATA_DIR))
+}
+
+/// Check whether an argument names a database, as opposed to being a
+/// word to look up.
+pub fn is_database(arg: &str) -> bool {
+  let path = path::Path::new(arg);
+  path.is_file() || path.extension().is_some_and(|e| e == EXTENSION)
+}
+
+/// Find the database given the value of the environment variable and
+/// the directory to search.
+fn find(var: Option<OsString>, dir: Option<path::PathBuf>) -> Result<path::PathBuf> {
+  match var {
+    Some(ref db) if !db.is_empty() => return Ok(path::PathBuf::from(db)),
+    _ => (),
+  }
+
+  let mut searched = vec![format!("${} (not set)", ENV_VAR)];
+  if let Some(dir) = dir {
+    let mut found = fs::read_dir(&dir)
+      .map(|entries| {
+        entries
+          .filter_map(|entry| entry.ok().map(|e| e.path()))
+          .filter(|path| path.is_file() && path.extension().is_some_and(|e| e == EXTENSION))
+          .collect::<Vec<_>>()
+      })
+      .unwrap_or_default();
+    found.sort();
+
+    match found.len() {
+      0 => searched.push(format!("{}/*.{} (none found)", dir.display(), EXTENSION)),
+      1 => return Ok(found.remove(0)),
+      _ => {
+        let found = found.iter().map(|p| p.display().to_string()).collect::<Vec<_>>();
+        return Err(Error::Error(format!(
+          "No database given and multiple found: {}\nSelect one using ${}",
+          found.join(", "), ENV_VAR,
+        )))
+      },
+    }
+  }
+
+  Err(Error::Error(format!(
+    "No database given and none found. Searched:\n  {}",
+    searched.join("\n  "),
+  )))
+}
+
+/// Find the database to use by default.
+pub fn database() -> Result<path::PathBuf> {
+  find(env::var_os(ENV_VAR), data_dir())
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::process;
+
+  #[test]
+  fn recognize_databases() {
+    assert!(is_database("./test/test.db"));
+    assert!(is_database("dictcc-fr.db"));
+    assert!(!is_database("Zorn"));
+  }
+
+  #[test]
+  fn find_database() {
+    let dir = env::temp_dir().join(format!("dictcc-cli-locate-{}", process::id()));
+    let db = find(Some(OsString::from("/tmp/x.db")), Some(dir.clone())).unwrap();
+    assert_eq!(db, path::PathBuf::from("/tmp/x.db"));
+
+    let err = find(Some(OsString::new()), Some(dir.clone())).unwrap_err().to_string();
+    assert!(err.contains("$DICTCC_DB (not set)"), "{}", err);
+    assert!(err.contains(&format!("{}/*.db (none found)", dir.display())), "{}", err);
+
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("notes.txt"), "").unwrap();
+    fs::write(dir.join("dictcc-lp1.db"), "").unwrap();
+    assert_eq!(find(None, Some(dir.clone())).unwrap(), dir.join("dictcc-lp1.db"));
+
+    fs::write(dir.join("dictcc-fr.db"), "").unwrap();
+    let err = find(None, Some(dir.clone())).unwrap_err().to_string();
+    assert!(err.contains("multiple found"), "{}", err);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,81 @@
+// logger.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Reporting of diagnostics on standard error.
+//!
+//! Diagnostics are emitted using the `log` facade, e.g., which database
+//! was opened or how long a query took, and printed only if requested
+//! by means of the `-v`/`--verbose` option.
+
+use log;
+
+
+/// A logger printing messages to standard error.
+struct Logger;
+
+impl log::Log for Logger {
+  fn enabled(&self, metadata: &log::Metadata) -> bool {
+    metadata.level() <= log::max_level()
+  }
+
+  fn log(&self, record: &log::Record) {
+    if self.enabled(record.metadata()) {
+      eprintln!("{}: {}", record.level().as_str().to_lowercase(), record.args());
+    }
+  }
+
+  fn flush(&self) {}
+}
+
+static LOGGER: Logger = Logger;
+
+
+/// Map the number of times verbose output was requested to the level of
+/// messages to print.
+fn level(verbosity: usize) -> log::LevelFilter {
+  match verbosity {
+    0 => log::LevelFilter::Warn,
+    1 => log::LevelFilter::Info,
+    _ => log::LevelFilter::Debug,
+  }
+}
+
+/// Set up printing of diagnostics at the given verbosity.
+///
+/// The function may be called repeatedly, e.g., for each command of a
+/// script, with the last verbosity taking effect.
+pub fn init(verbosity: usize) {
+  // Setting the logger fails if it was set already, which is fine.
+  let _ = log::set_logger(&LOGGER);
+  log::set_max_level(level(verbosity));
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn verbosity_levels() {
+    assert_eq!(level(0), log::LevelFilter::Warn);
+    assert_eq!(level(1), log::LevelFilter::Info);
+    assert_eq!(level(2), log::LevelFilter::Debug);
+    assert_eq!(level(5), log::LevelFilter::Debug);
+  }
+}
@@ -0,0 +1,327 @@
+// lookup.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+
+//! A builder for dictionary lookups.
+//!
+//! A [`Query`] collects the terms to look up along with everything
+//! influencing how they are matched, and assembles the SQL statement
+//! and its parameters from that:
+//! ```no_run
+//! # #[cfg(feature = "sqlite")] {
+//! use std::path::Path;
+//!
+//! use dictcc::entry::Type;
+//! use dictcc::Direction;
+//! use dictcc::Query;
+//!
+//! let db = Path::new("dictcc-lp1.db");
+//! Query::new("love")
+//!   .direction(Direction::Lang2ToLang1)
+//!   .exact(true)
+//!   .types([Type::Noun])
+//!   .limit(20)
+//!   .run(db, |entry| {
+//!     println!("{}: {}", entry.source, entry.target);
+//!     Ok(())
+//!   })
+//!   .unwrap();
+//! # }
+//! ```
+
+#[cfg(feature = "sqlite")]
+use std::path;
+
+use backend::Backend;
+#[cfg(feature = "sqlite")]
+use cancel::CancelToken;
+use collate::Collation;
+#[cfg(feature = "sqlite")]
+use database;
+use entry::Type;
+#[cfg(feature = "sqlite")]
+use sqlite;
+#[cfg(feature = "sqlite")]
+use term_select;
+#[cfg(feature = "sqlite")]
+use Database;
+use Direction;
+#[cfg(feature = "sqlite")]
+use Entries;
+use Entry;
+use Options;
+use Result;
+
+
+/// A lookup of one or more terms.
+///
+/// All terms are matched in a single query, i.e., the results for the
+/// individual terms are merged and ordered as a whole.
+#[derive(Clone, Debug)]
+pub struct Query {
+  /// The terms to look up.
+  terms: Vec<String>,
+  /// The direction to translate in.
+  direction: Direction,
+  /// The options influencing how terms are matched.
+  options: Options,
+  /// The maximum number of entries to report, if any.
+  limit: Option<usize>,
+}
+
+impl Query {
+  /// Create a query for a single term, translating from language 1 to
+  /// language 2 with default options.
+  pub fn new(term: &str) -> Query {
+    Query::with_terms(&[term])
+  }
+
+  /// Create a query for several terms.
+  pub fn with_terms<S>(terms: &[S]) -> Query
+  where
+    S: AsRef<str>,
+  {
+    Query {
+      terms: terms.iter().map(|t| t.as_ref().to_string()).collect(),
+      direction: Direction::Lang1ToLang2,
+      options: Options::default(),
+      limit: None,
+    }
+  }
+
+  /// Set the direction to translate in.
+  pub fn direction(mut self, direction: Direction) -> Query {
+    self.direction = direction;
+    self
+  }
+
+  /// Replace all options influencing how terms are matched.
+  pub fn options(mut self, options: Options) -> Query {
+    self.options = options;
+    self
+  }
+
+  /// Only report entries matching a term itself, possibly with
+  /// annotations, and not phrases merely containing it.
+  pub fn exact(mut self, exact: bool) -> Query {
+    self.options.exact = exact;
+    self
+  }
+
+  /// Restrict the lookup to entries having any of the given types.
+  pub fn types<I>(mut self, types: I) -> Query
+  where
+    I: IntoIterator<Item = Type>,
+  {
+    self.options.types = types.into_iter().collect();
+    self
+  }
+
+  /// Restrict the lookup to entries belonging to any of the subjects
+  /// with the given ids.
+  pub fn subjects<I>(mut self, subjects: I) -> Query
+  where
+    I: IntoIterator<Item = i64>,
+  {
+    self.options.subjects = subjects.into_iter().collect();
+    self
+  }
+
+  /// Ignore diacritics, e.g., to match "Arger" with "Ärger".
+  pub fn ignore_accents(mut self, ignore: bool) -> Query {
+    self.options.ignore_accents = ignore;
+    self
+  }
+
+  /// Distinguish between upper and lower case characters.
+  pub fn case_sensitive(mut self, sensitive: bool) -> Query {
+    self.options.case_sensitive = sensitive;
+    self
+  }
+
+  /// Match terms as an exact phrase, i.e., find all entries
+  /// containing the words in the given order.
+  pub fn phrase(mut self, phrase: bool) -> Query {
+    self.options.phrase = phrase;
+    self
+  }
+
+  /// Match entries containing all the words of a term, in any order.
+  pub fn all_words(mut self, all_words: bool) -> Query {
+    self.options.all_words = all_words;
+    self
+  }
+
+  /// Also match terms inside of comments (`[...]`) and abbreviations
+  /// (`<...>`).
+  pub fn include_comments(mut self, include: bool) -> Query {
+    self.options.include_comments = include;
+    self
+  }
+
+  /// List entries of the given types first, in that order.
+  pub fn type_order<I>(mut self, types: I) -> Query
+  where
+    I: IntoIterator<Item = Type>,
+  {
+    self.options.type_order = types.into_iter().map(|t| t.name().to_string()).collect();
+    self
+  }
+
+  /// Order entries by their relevance to the given query instead of
+  /// grouping them by type.
+  pub fn rank(mut self, query: &str) -> Query {
+    self.options.rank = Some(query.to_string());
+    self
+  }
+
+  /// Set the collation to order terms by.
+  pub fn collation(mut self, collation: Collation) -> Query {
+    self.options.collation = collation;
+    self
+  }
+
+  /// Report at most the given number of entries.
+  pub fn limit(mut self, limit: usize) -> Query {
+    self.limit = Some(limit);
+    self
+  }
+
+  /// Assemble the SQL statement performing the lookup along with the
+  /// parameters to bind to it.
+  ///
+  /// The statement does not rely on the database's full-text index or
+  /// its headwords table, as lookups in a [`Database`](::Database)
+  /// having them do. An entry may
+  /// be selected more than once, with the id of the entry being the
+  /// fifth column of each row. The limit is not part of the statement,
+  /// as it applies to distinct entries.
+  ///
+  /// `None` is returned if the query cannot match anything, e.g.,
+  /// because it contains no terms.
+  #[cfg(feature = "sqlite")]
+  pub fn to_sql(&self) -> Option<(String, Vec<sqlite::Value>)> {
+    term_select(&self.terms, &self.direction, &self.options, None, false, false)
+  }
+
+  /// Perform the lookup in the database at the given path, reporting
+  /// each entry found to `callback`.
+  #[cfg(feature = "sqlite")]
+  pub fn run<F>(&self, db: &path::Path, callback: F) -> Result<()>
+  where
+    F: FnMut(&Entry) -> Result<()>,
+  {
+    self.run_on(&*database(db)?, callback)
+  }
+
+  /// Perform the lookup in an open database, yielding the entries found
+  /// one by one, until `token` is cancelled.
+  #[cfg(feature = "sqlite")]
+  pub fn stream<'d>(&self, database: &'d Database, token: CancelToken) -> Result<Entries<'d>> {
+    let sql = term_select(
+      &self.terms, &self.direction, &self.options, database.fulltext_index(), database.headwords(),
+      database.overlay(),
+    );
+    database.stream(sql, self.limit, &self.options, token)
+  }
+
+  /// Perform the lookup by means of the given backend, e.g., an open
+  /// [`Database`](::Database), reporting each entry found to `callback`.
+  pub fn run_on<B, F>(&self, backend: &B, mut callback: F) -> Result<()>
+  where
+    B: Backend + ?Sized,
+    F: FnMut(&Entry) -> Result<()>,
+  {
+    if self.terms.is_empty() {
+      return Ok(());
+    }
+    backend.lookup(&self.terms, &self.direction, &self.options, self.limit, &mut callback)
+  }
+}
+
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+  use super::*;
+
+  fn lookup(query: &Query) -> Vec<(String, String, String)> {
+    let db = path::Path::new("./test/test.db");
+    let mut found = Vec::new();
+    query
+      .run(db, |entry| {
+        found.push((entry.source.text.clone(), entry.type_name(), entry.target.text.clone()));
+        Ok(())
+      })
+      .unwrap();
+    found
+  }
+
+  #[test]
+  fn filter_types() {
+    let query = Query::new("love").direction(Direction::Lang2ToLang1);
+    assert_eq!(lookup(&query).len(), 2);
+
+    let found = lookup(&query.clone().types([Type::Noun]));
+    assert_eq!(found, vec![("love".to_string(), "noun".to_string(), "Liebe {f}".to_string())]);
+
+    let found = lookup(&query.types([Type::Adverb, Type::Unknown]));
+    let null = ("love".to_string(), "unknown".to_string(), "null [beim Tennis]".to_string());
+    assert_eq!(found, vec![null]);
+
+    let query = Query::new("worse").direction(Direction::Lang2ToLang1).types([Type::Adverb]);
+    assert_eq!(lookup(&query).len(), 3);
+  }
+
+  #[test]
+  fn exact_matches() {
+    let query = Query::new("poor").direction(Direction::Lang2ToLang1);
+    let found = lookup(&query);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].0, "the poor {pl}");
+    assert_eq!(lookup(&query.exact(true)), vec![]);
+
+    let query = Query::new("statistics").direction(Direction::Lang2ToLang1).exact(true);
+    assert_eq!(lookup(&query).len(), 2);
+  }
+
+  #[test]
+  fn limit_entries() {
+    let query = Query::new("anger").direction(Direction::Lang2ToLang1);
+    assert_eq!(lookup(&query).len(), 5);
+    assert_eq!(lookup(&query.clone().limit(2)).len(), 2);
+    assert_eq!(lookup(&query.limit(0)), vec![]);
+  }
+
+  #[test]
+  fn distinct_entries() {
+    let query = Query::new("anger").direction(Direction::Lang2ToLang1);
+    let found = lookup(&query);
+
+    // Each entry is selected for both terms, but reported only once.
+    let query = Query::with_terms(&["anger", "ANGER"]).direction(Direction::Lang2ToLang1);
+    assert_eq!(lookup(&query), found);
+    assert_eq!(lookup(&query.limit(3)), found[..3].to_vec());
+  }
+
+  #[test]
+  fn empty_query() {
+    assert!(Query::with_terms::<&str>(&[]).to_sql().is_none());
+    assert!(Query::new("anger").to_sql().is_some());
+  }
+}
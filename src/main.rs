@@ -22,294 +22,2004 @@
 //! dictcc-cli is a command line interface to translating between
 //! languages by means of the offline data from dict.cc.
 
+extern crate dictcc;
 extern crate getopts;
+extern crate libc;
+#[macro_use]
+extern crate log;
+extern crate miniz_oxide;
+extern crate sha2;
 extern crate sqlite;
 
-use std::borrow;
+mod annotate;
+mod commands;
+mod completion;
+mod config;
+mod corpus;
+mod coverage;
+mod encoding;
+mod export;
+mod fetch;
+mod filter;
+mod glossary;
+mod hooks;
+mod hunspell;
+mod import;
+mod inflect;
+mod locate;
+mod logger;
+mod metadata;
+#[cfg(feature = "online")]
+mod mt;
+mod orient;
+mod script;
+mod serve;
+mod stats;
+mod types;
+mod version;
+
+use std::cmp;
+use std::collections::HashMap;
 use std::env;
+use std::error;
 use std::fmt;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::io::BufRead;
 use std::path;
 use std::process;
 use std::result;
+use std::slice;
+use std::sync;
+use std::sync::atomic;
+use std::thread;
+use std::time;
+
+use dictcc::band;
+use dictcc::cache;
+use dictcc::check;
+#[cfg(feature = "sqlcipher")]
+use dictcc::cipher;
+use dictcc::collate;
+use dictcc::compound;
+use dictcc::entry;
+use dictcc::fulltext;
+use dictcc::json;
+use dictcc::like;
+use dictcc::optimize;
+use dictcc::overlay;
+use dictcc::query;
+use dictcc::rank;
+use dictcc::results;
+use dictcc::spell;
+use dictcc::stem;
+use dictcc::subjects;
+use dictcc::suggest;
+use dictcc::term;
+use dictcc::translit;
+use dictcc::tuning;
+use dictcc::for_each_entry;
+use dictcc::pattern;
+use dictcc::transaction;
+use dictcc::Direction;
+use dictcc::Entry;
+use dictcc::Error;
+use dictcc::Merge;
+use dictcc::Options;
+use dictcc::Result;
+use dictcc::SEARCH_TBL;
+use dictcc::TERM1_COL;
+use dictcc::TERM2_COL;
+use dictcc::TYPE_COL;
+use dictcc::USAGE_COL;
+use dictcc::atomically;
+use dictcc::commit;
+use dictcc::boolean_query;
+use dictcc::columns;
+use dictcc::detect_direction;
+use dictcc::exists;
+use dictcc::in_transaction;
+use dictcc::normalize;
+use dictcc::open;
+use dictcc::open_writable;
+use dictcc::sharing_connections;
+use dictcc::term_query;
+use dictcc::translate;
+use dictcc::translate_query;
+use dictcc::translate_with;
+use dictcc::translate_words;
 
+
+/// An error of a command, which may be one reported by the library or
+/// one concerning the command line.
 #[derive(Debug)]
-/// Internally used error comprising the various different error types.
-pub enum Error {
+enum CommandError {
+  /// An error reported by the library.
+  Dictcc(Error),
   /// `getopts` reported an argument-parsing related error.
   GetoptsFail(getopts::Fail),
-  /// An Sqlite error reported by the sqlite crate.
-  SqlError(sqlite::Error),
-  /// A custom error in the form of a string.
-  Error(String),
+  /// The help was requested. Not an error as such, but it aborts the
+  /// command all the same.
+  Help(String),
 }
 
-impl From<getopts::Fail> for Error {
-  fn from(e: getopts::Fail) -> Error {
-    Error::GetoptsFail(e)
+impl From<Error> for CommandError {
+  fn from(e: Error) -> CommandError {
+    CommandError::Dictcc(e)
   }
 }
 
-impl From<sqlite::Error> for Error {
-  fn from(e: sqlite::Error) -> Error {
-    Error::SqlError(e)
+impl From<sqlite::Error> for CommandError {
+  fn from(e: sqlite::Error) -> CommandError {
+    CommandError::Dictcc(Error::from(e))
   }
 }
 
-impl fmt::Display for Error {
+impl From<getopts::Fail> for CommandError {
+  fn from(e: getopts::Fail) -> CommandError {
+    CommandError::GetoptsFail(e)
+  }
+}
+
+impl fmt::Display for CommandError {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match *self {
-      Error::GetoptsFail(ref e) => return write!(f, "Argument error: {}", e),
-      Error::SqlError(ref e) => return write!(f, "SQL error: {}", e),
-      Error::Error(ref e) => return write!(f, "{}", e),
+      CommandError::Dictcc(ref e) => write!(f, "{}", e),
+      CommandError::GetoptsFail(ref e) => write!(f, "Argument error: {}", e),
+      CommandError::Help(ref e) => write!(f, "{}", e),
+    }
+  }
+}
+
+impl error::Error for CommandError {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    match *self {
+      CommandError::Dictcc(ref e) => e.source(),
+      CommandError::GetoptsFail(ref e) => Some(e),
+      CommandError::Help(_) => None,
     }
   }
 }
 
-type Result<T> = result::Result<T, Error>;
+/// The result type of commands.
+type CommandResult<T> = result::Result<T, CommandError>;
 
-enum Direction {
-  // Map from term1 (in language 1) to term2 (in language 2).
-  Lang1ToLang2,
-  // Map from term2 (in language 2) to term1 (in language 1).
-  Lang2ToLang1,
+
+fn usage(opts: &getopts::Options, synopses: &[&str]) -> String {
+  let program = env::args().next().unwrap_or_else(|| "dictcc-cli".to_string());
+  let synopses = synopses
+    .iter()
+    .map(|synopsis| format!("{} {}", program, synopsis))
+    .collect::<Vec<_>>()
+    .join("\n       ");
+  let usage = format!("Usage: {}", synopses);
+  opts.usage(&usage)
 }
 
-// CREATE VIRTUAL TABLE "main_ft" using
-//   fts3("id" INTEGER PRIMARY KEY NOT NULL,
-//        "term1" VARCHAR,
-//        "term2" VARCHAR,
-//        "sort1" INTEGER,
-//        "sort2" INTEGER,
-//        "subj_ids" VARCHAR,
-//        "entry_type" VARCHAR,
-//        "vt_usage" INTEGER);
-const SEARCH_TBL: &str = "main_ft";
-const TERM1_COL: &str = "term1";
-const TERM2_COL: &str = "term2";
-const TYPE_COL: &str = "entry_type";
-const USAGE_COL: &str = "vt_usage";
+/// Insert the default database in front of the free arguments of a
+/// command that omitted it.
+fn default_database(free: &mut Vec<String>) -> Result<()> {
+  let db = locate::database()?;
+  free.insert(0, db.to_string_lossy().into_owned());
+  Ok(())
+}
 
+/// Check whether the free arguments of a command taking a database
+/// followed by words lack the database.
+fn lacks_database(free: &[String]) -> bool {
+  match free.first() {
+    Some(first) => free.len() == 1 || !locate::is_database(first),
+    None => false,
+  }
+}
 
-fn normalize(string: &str) -> borrow::Cow<str> {
-  if string.contains("  ") {
-    let mut s = string.to_string();
-    loop {
-      s = s.replace("  ", " ");
-      if !s.contains("  ") {
-        break;
-      }
+/// Parse the arguments of a command, printing its help (described by
+/// `synopses`) if requested.
+fn parse_options(opts: &mut getopts::Options, args: &[String],
+                 synopses: &[&str]) -> CommandResult<getopts::Matches> {
+  opts.optflag("h", "help", "Print the help of the command");
+  let matches = opts.parse(args)?;
+  if matches.opt_present("h") {
+    return Err(CommandError::Help(usage(opts, synopses)));
+  }
+  Ok(matches)
+}
+
+/// The arguments the program was invoked with.
+struct Args {
+  database: String,
+  /// The term as given by the user.
+  input: String,
+  term: String,
+  /// The individual words the term consists of.
+  words: Vec<String>,
+  direction: Direction,
+  /// The names of lang1 and lang2, if configured.
+  languages: Option<(String, String)>,
+  stem: bool,
+  transliterate: bool,
+  both: bool,
+  auto: bool,
+  any: bool,
+  exists: bool,
+  show_normalization: bool,
+  bands: bool,
+  /// Whether to print the principal forms of verbs and nouns.
+  inflections: bool,
+  subjects: Vec<String>,
+  filter: filter::Filter,
+  save_results: Option<String>,
+  /// The age in days beyond which to warn about the dictionary being
+  /// outdated.
+  warn_stale: Option<u64>,
+  /// Whether to answer the lookup from the cache of query results.
+  cache: bool,
+  format: results::Format,
+  hooks: hooks::Hooks,
+  options: Options,
+  #[cfg(feature = "online")]
+  mt: Option<mt::Config>,
+}
+
+/// The synopses of the translate command.
+const TRANSLATE_SYNOPSES: &[&str] = &[
+  "[translate] [options] [<database>] <word>...",
+  "[translate] --all-dicts [options] <word>...",
+  "[translate] --stdin [options] [<database>]",
+  "[translate] --from-file <file> [options] [<database>]",
+  "[translate] --each [options] [<database>] <word>...",
+  "<command> [options] [<argument>...]",
+];
+
+/// Create the options of the translate command.
+fn translate_options() -> getopts::Options {
+  let mut opts = getopts::Options::new();
+  opts.optflag("r", "reverse", "Perform reverse lookup, i.e., instead \
+                                from mapping from lang1 to lang2 map \
+                                from lang2 to lang1");
+  opts.optflag("b", "both", "Search in both directions, labeling each \
+                             result with the direction it was found in");
+  opts.optflag("", "auto", "Detect the language of the term and \
+                           translate in the corresponding direction");
+  opts.optflag("s", "stem", "Also search for the stems of the words \
+                             to translate, e.g., to find \"to run\" \
+                             when searching for \"running\"");
+  opts.optflag("t", "transliterate", "Treat ASCII transliterations of \
+                                      umlauts and 'ß' (e.g., 'ae' and \
+                                      'ss') as such");
+  opts.optflag("a", "ignore-accents", "Ignore diacritics when matching \
+                                       terms, e.g., match \"Ärger\" \
+                                       when searching for \"Arger\"");
+  if cfg!(feature = "online") {
+    opts.optopt("", "mt", "Pass queries looking like sentences to a machine \
+                           translation service (deepl or libretranslate); \
+                           the API key is read from the DICTCC_MT_KEY \
+                           environment variable", "SERVICE");
+    opts.optopt("", "mt-url", "Use a custom endpoint for the machine \
+                               translation service", "URL");
+    opts.optopt("", "mt-languages", "The language codes of lang1 and lang2 \
+                                     to use for machine translation", "L1:L2");
+  }
+  opts.optflag("", "any", "Look up each of the words on its own instead \
+                          of as a single term, labeling each result \
+                          with the word it belongs to");
+  opts.optflag("", "all-words", "Find entries containing all of the \
+                                words, in any order");
+  opts.optflag("", "exact", "Only report entries matching the term \
+                            itself, possibly annotated, omitting \
+                            phrases containing it");
+  opts.optmulti("", "subject", "Only report entries belonging to the \
+                                given subject area (e.g., \"med\"), \
+                                including its sub-areas; may be \
+                                given multiple times", "SUBJECT");
+  for register in &filter::Register::ALL {
+    opts.optflag("", &format!("no-{}", register.name()), &format!(
+      "Omit {} entries, as identified by their annotations",
+      register.name()
+    ));
+  }
+  opts.optflag("", "compact", "Print each result as a single line pair \
+                              of terms, without the type");
+  opts.optflag("", "one-line", "Print only the single best translation, \
+                               without any decoration (implies --rank)");
+  opts.optflag("", "first", "Like --one-line, but print only the \
+                            single best translation overall, even for \
+                            multiple words; the exit code is 1 if \
+                            there is none");
+  opts.optopt("", "format", "The format to print the results in: \
+                            plain, compact, one-line, first, tsv, or \
+                            json (default: plain)", "FORMAT");
+  opts.optopt("", "collate", "The collation to sort terms by: de (treating \
+                              umlauts like their base letters and 'ß' \
+                              like \"ss\"), en (ignoring case and \
+                              diacritics), or binary (default: de)", "COLLATION");
+  opts.optopt("", "output-encoding", "The encoding to print results in: \
+                                      utf8 or latin1, approximating \
+                                      characters not covered by the \
+                                      latter (default: utf8)", "ENCODING");
+  opts.optopt("", "save-results", "Save the results to a file, for \
+                                   rendering them again later on using \
+                                   the replay command", "FILE");
+  opts.optflag("", "case-sensitive", "Distinguish between upper and \
+                                     lower case characters, e.g., \
+                                     \"Essen\" and \"essen\"");
+  opts.optflag("", "include-comments", "Also match the term inside of \
+                                       comments ([...]) and \
+                                       abbreviations (<...>)");
+  opts.optflag("", "bands", "Mark each result with how commonly it is \
+                            used compared to all others: very common \
+                            (●●●), common (●●○), or rare (●○○)");
+  opts.optflag("", "inflections", "Print the principal forms of verbs and \
+                                  nouns found, if the database has \
+                                  inflection tables");
+  opts.optflag("", "rank", "Order results by their relevance to the term \
+                           (exact matches first, followed by matches \
+                           of whole words and partial ones) instead \
+                           of by type");
+  opts.optflag("", "raw-query", "Look up the term as is, instead of \
+                                stripping punctuation, quotes, and \
+                                brackets surrounding it");
+  opts.optflag("", "show-normalization", "Print how the term was \
+                                         transformed before matching \
+                                         (to stderr)");
+  opts.optmulti("", "set", "Override a configuration setting, e.g., \
+                            type-order=[\"verb\"]", "KEY=VALUE");
+  opts.optflagmulti("v", "verbose", "Report which database is used, how \
+                                    the term is matched, and how long \
+                                    queries take (to stderr); may be \
+                                    given twice for more details");
+  opts.optflag("", "version", "Print the version along with information \
+                               about the SQLite library in use");
+  opts.optflag("", "explain", "Print the SQL text, the bound parameters, \
+                               and the query plan of each query \
+                               performed (to stderr)");
+  opts.optflag("", "profile", "Print how long assembling and running \
+                               each query took, along with its number \
+                               of parameters and rows (to stderr); \
+                               implies --verbose");
+  opts.optopt("", "cache-size", "The size of the database page cache, \
+                                in KiB", "KIB");
+  opts.optopt("", "mmap-size", "The maximum number of bytes of the \
+                               database to memory map (0 disables \
+                               memory mapping)", "BYTES");
+  opts.optopt("", "max-results", "Stop after finding the given number \
+                                 of entries, as a safety cap for broad \
+                                 lookups", "N");
+  opts.optflag("", "no-cache", "Do not answer the lookup from the cache \
+                               of recent results");
+  opts.optopt("", "warn-stale", "Warn if the dictionary is older than \
+                                the given number of days", "DAYS");
+  opts.optflag("", "in-memory", "Load the entire database into memory \
+                                first, speeding up repeated lookups at \
+                                the expense of memory");
+  #[cfg(feature = "sqlcipher")]
+  opts.optopt("", "key-file", "Read the key of an encrypted database \
+                              from a file (instead of $DICTCC_DB_KEY)", "FILE");
+  opts.optopt("", "dict", "Use the database registered under the given \
+                          name in the configuration", "NAME");
+  opts.optflag("", "stdin", "Read the terms to look up from standard \
+                            input, one per line (also enabled by an \
+                            argument of \"-\")");
+  opts.optopt("", "from-file", "Look up each line of a file as a term, \
+                               summarizing which terms have no \
+                               translation; the exit code is 1 if any", "FILE");
+  opts.optflag("", "each", "Look up each of the words as a term of its \
+                           own, printing the results of each \
+                           separately");
+  opts.optopt("", "jobs", "Look up the terms read from standard input \
+                          or a file, or given with --each, using the \
+                          given number of jobs in parallel", "N");
+  opts.optflag("", "all-dicts", "Look up the term in all databases \
+                                registered in the configuration, \
+                                grouping the results by database");
+  opts.optflag("e", "exists", "Only check whether the term is in the \
+                               dictionary, without printing anything; \
+                               the exit code is 0 if it is and 1 \
+                               otherwise");
+  opts
+}
+
+/// Parse the program's arguments.
+fn parse_arguments(args: &[String]) -> CommandResult<Args> {
+  let mut opts = translate_options();
+
+  // The help of the translate command doubles as the program's help,
+  // as translating is what it does by default.
+  let help = |opts: &getopts::Options| format!("{}\n{}", usage(opts, TRANSLATE_SYNOPSES), commands());
+  let mut matches = match parse_options(&mut opts, args, TRANSLATE_SYNOPSES) {
+    Err(CommandError::Help(_)) => return Err(CommandError::Help(help(&opts))),
+    result => result?,
+  };
+  if matches.opt_present("version") {
+    return Err(CommandError::Help(version::Version::detect()?.to_string()))
+  }
+  // Timings are reported at the level of verbose output.
+  logger::init(cmp::max(matches.opt_count("v"), matches.opt_present("profile") as usize));
+  let mut config = config::Config::load()?;
+  if let Some(name) = matches.opt_str("dict") {
+    // With the database selected by name all arguments are words.
+    let db = config.database(&name)?;
+    matches.free.insert(0, db.to_string_lossy().into_owned());
+    info!("Using database {} registered as {}", matches.free[0], name);
+  } else if lacks_database(&matches.free) {
+    default_database(&mut matches.free)?;
+    info!("Using database {} found by default", matches.free[0]);
+  } else {
+    info!("Using database {}", matches.free[0]);
+  }
+  if matches.free.len() < 2 {
+    return Err(Error::Error(help(&opts)).into());
+  }
+  for exclusive in &[&["reverse", "both", "auto"][..], &["any", "all-words"]] {
+    let present = exclusive
+      .iter()
+      .filter(|o| matches.opt_present(o))
+      .collect::<Vec<_>>();
+    if present.len() > 1 {
+      return Err(Error::Error(format!("--{} and --{} are mutually exclusive", present[0], present[1])).into());
     }
-    s.into()
+  }
+  if matches.opt_present("jobs") {
+    return Err(Error::Error("--jobs requires --stdin, --from-file, or --each".to_string()).into());
+  }
+  config.select_database(path::Path::new(&matches.free[0]));
+  for setting in matches.opt_strs("set") {
+    config.set_from(&setting, config::Origin::Cli)?;
+  }
+  tuning::set(connection_tuning(&matches, &config)?);
+  #[cfg(feature = "sqlcipher")]
+  if let Some(path) = matches.opt_str("key-file") {
+    cipher::set(Some(cipher::read_key_file(path::Path::new(&path))?));
+  }
+  overlay::set(overlay::default_path());
+
+  let direction = match default_direction(path::Path::new(&matches.free[0]), &config)? {
+    Direction::Lang1ToLang2 if matches.opt_present("r") => Direction::Lang2ToLang1,
+    Direction::Lang2ToLang1 if matches.opt_present("r") => Direction::Lang1ToLang2,
+    direction => direction,
+  };
+  info!(
+    "Translating {}",
+    match direction {
+      Direction::Lang1ToLang2 => "from lang1 to lang2",
+      Direction::Lang2ToLang1 => "from lang2 to lang1",
+    },
+  );
+  let languages = languages(&config)?;
+  #[cfg(feature = "online")]
+  let mt = match matches.opt_str("mt") {
+    Some(service) => {
+      let service = mt::Service::from_name(&service)?;
+      let languages = matches.opt_str("mt-languages").ok_or_else(|| Error::Error(
+        "Machine translation requires --mt-languages".to_string()
+      ))?;
+      let (lang1, lang2) = mt::parse_languages(&languages)?;
+      let (source, target) = match direction {
+        Direction::Lang1ToLang2 => (lang1, lang2),
+        Direction::Lang2ToLang1 => (lang2, lang1),
+      };
+      Some(mt::Config {
+        service,
+        url: matches.opt_str("mt-url"),
+        source,
+        target,
+      })
+    },
+    None => None,
+  };
+
+  // We treat all arguments past the database path itself as words to
+  // search for (in that order, with a single space in between them,
+  // ignoring any surrounding white space and, unless a raw query is
+  // requested, punctuation as copied from running text along with
+  // them).
+  // If the words are enclosed in double quotes they are treated as an
+  // exact phrase, unless they are to be looked up individually or in
+  // any order.
+  let any = matches.opt_present("any");
+  let all_words = matches.opt_present("all-words");
+  let raw_query = matches.opt_present("raw-query");
+  let strip = |term: &str| match term::strip_punctuation(term) {
+    stripped if !raw_query && !stripped.is_empty() => stripped.to_string(),
+    _ => term.to_string(),
+  };
+  let input = matches.free[1..].join(" ");
+  let term = strip(&input.split_whitespace().collect::<Vec<_>>().join(" "));
+  let (term, phrase) = match term::unquote(&term) {
+    Some(phrase) if !any && !all_words => (strip(phrase), true),
+    _ => (term, false),
+  };
+
+  let max_results = match matches.opt_str("max-results") {
+    Some(max) => Some(
+      max
+        .parse::<usize>()
+        .ok()
+        .filter(|max| *max > 0)
+        .ok_or_else(|| Error::Error(format!("Invalid maximum number of results: {}", max)))?,
+    ),
+    None => None,
+  };
+  let warn_stale = match matches.opt_str("warn-stale") {
+    Some(days) => Some(
+      days
+        .parse::<u64>()
+        .map_err(|_| Error::Error(format!("Invalid number of days: {}", days)))?,
+    ),
+    None => None,
+  };
+  let collation = match matches.opt_str("collate") {
+    Some(name) => collate::Collation::from_name(&name)?,
+    None => collate::Collation::default(),
+  };
+  let format = match matches.opt_str("format") {
+    Some(name) => results::Format::from_name(&name)?,
+    None => results::Format::Plain,
+  };
+  // The first translation is a variant of one-line output.
+  let first = matches.opt_present("first") || format == results::Format::First;
+  let one_line = first || matches.opt_present("one-line") || format == results::Format::OneLine;
+  let rank = if matches.opt_present("rank") || one_line {
+    Some(term.clone())
+  } else {
+    None
+  };
+  let registers = filter::Register::ALL
+    .iter()
+    .filter(|r| matches.opt_present(&format!("no-{}", r.name())))
+    .cloned()
+    .collect::<Vec<_>>();
+
+  Ok(Args {
+    database: matches.free[0].clone(),
+    input,
+    term,
+    words: matches.free[1..].iter().map(|w| strip(w)).collect(),
+    direction,
+    languages,
+    stem: matches.opt_present("s"),
+    transliterate: matches.opt_present("t"),
+    both: matches.opt_present("b"),
+    auto: matches.opt_present("auto"),
+    any,
+    exists: matches.opt_present("e"),
+    show_normalization: matches.opt_present("show-normalization"),
+    bands: matches.opt_present("bands"),
+    inflections: matches.opt_present("inflections"),
+    subjects: matches.opt_strs("subject"),
+    filter: filter::Filter::new(&registers, &config)?,
+    hooks: hooks::Hooks::new(&config)?,
+    save_results: matches.opt_str("save-results"),
+    warn_stale,
+    // Lookups whose timing is to be shown are always performed.
+    cache: !matches.opt_present("no-cache")
+      && !matches.opt_present("profile")
+      && config.get_bool("cache-results")?.unwrap_or(true),
+    format: if first {
+      results::Format::First
+    } else if one_line {
+      results::Format::OneLine
+    } else if matches.opt_present("compact") {
+      results::Format::Compact
+    } else {
+      format
+    },
+    options: Options {
+      ignore_accents: matches.opt_present("a"),
+      phrase,
+      all_words,
+      exact: matches.opt_present("exact"),
+      max_results,
+      type_order: config.get_strings("type-order")?.unwrap_or_default(),
+      // Subjects are resolved once we have access to the database.
+      subjects: Vec::new(),
+      case_sensitive: matches.opt_present("case-sensitive"),
+      include_comments: matches.opt_present("include-comments"),
+      rank,
+      weights: rank_weights(&config)?,
+      merge: merge_policy(&config)?,
+      explain: matches.opt_present("explain"),
+      collation,
+      ..Default::default()
+    },
+    #[cfg(feature = "online")]
+    mt,
+  })
+}
+
+/// Determine the direction to translate in by default.
+///
+/// By default we translate from German, irrespective of the column the
+/// database stores it in. The configuration may reverse the default
+/// direction, in which case --reverse reverts it again.
+fn default_direction(db: &path::Path, config: &config::Config) -> Result<Direction> {
+  let german = orient::german_column(db)?;
+  info!("German terms are in column {}", german);
+  let reverse = config.get_bool("reverse")?.unwrap_or(false) != (german == TERM2_COL);
+  if reverse {
+    Ok(Direction::Lang2ToLang1)
   } else {
-    string.into()
+    Ok(Direction::Lang1ToLang2)
+  }
+}
+
+/// Retrieve the configured names of language 1 and language 2.
+fn languages(config: &config::Config) -> Result<Option<(String, String)>> {
+  match config.get_strings("languages")?.as_deref() {
+    Some([lang1, lang2]) => Ok(Some((lang1.clone(), lang2.clone()))),
+    Some(_) => Err(Error::Error(
+      "Configuration value languages must list exactly two names".to_string()
+    )),
+    None => Ok(None),
+  }
+}
+
+/// Retrieve the configured policy for merging the user's entries with
+/// duplicates in the dictionary.
+fn merge_policy(config: &config::Config) -> Result<Merge> {
+  match config.get_string("user-entries")? {
+    Some(name) => Merge::from_name(name),
+    None => Ok(Merge::default()),
   }
 }
 
-fn handle<F>(mut cursor: sqlite::Cursor, callback: &mut F) -> Result<()>
+/// Retrieve the configured weights for ordering results by relevance.
+fn rank_weights(config: &config::Config) -> Result<rank::Weights> {
+  Ok(rank::Weights {
+    usage: config.get_number("rank.usage-weight")?.unwrap_or(1.0),
+    length: config.get_number("rank.length-weight")?.unwrap_or(0.1),
+  })
+}
+
+/// Read the contents of a file.
+fn read_file(path: &str) -> Result<String> {
+  fs::read_to_string(path).map_err(|e| Error::Io(format!("Failed to read {}", path), e))
+}
+
+/// Write the given contents to a file.
+fn write_file<C>(path: &str, content: C) -> Result<()>
 where
-  F: FnMut(&str, &str, &str) -> Result<()>,
+  C: AsRef<[u8]>,
 {
-  while let Some(row) = cursor.next()? {
-    let src_term = row[0].as_string().ok_or_else(|| Error::Error(format!(
-      "Invalid first column in result: {:?}",
-      row
-    )))?;
-    let dst_term = row[1].as_string().ok_or_else(|| Error::Error(format!(
-      "Invalid second column in result: {:?}",
-      row
-    )))?;
-    let type_ = row[2].as_string().ok_or_else(|| Error::Error(format!(
-      "Invalid third column in result: {:?}",
-      row
-    )))?;
-    callback(&normalize(src_term), &normalize(dst_term), type_)?;
+  fs::write(path, content).map_err(|e| Error::Io(format!("Failed to write {}", path), e))
+}
+
+/// Describe how the term to translate is transformed before matching,
+/// as a list of (step, result) pairs.
+fn normalization(args: &Args, db: &path::Path,
+                 directions: &[Direction]) -> Result<Vec<(&'static str, String)>> {
+  let quote = |terms: &[String]| {
+    terms.iter().map(|t| format!("\"{}\"", t)).collect::<Vec<_>>().join(", ")
+  };
+  let off = |option: &str| format!("(not requested, see --{})", option);
+  let term = &args.term;
+
+  let mut steps = vec![
+    ("input", quote(slice::from_ref(&args.input))),
+    ("trimmed", quote(slice::from_ref(term))),
+  ];
+  if args.options.case_sensitive {
+    steps.push(("case-folded", "(skipped due to --case-sensitive)".to_string()));
+  } else {
+    steps.push(("case-folded", quote(&[term.to_lowercase()])));
   }
-  Ok(())
+  if args.options.ignore_accents {
+    steps.push(("unaccented", quote(&[like::unaccent(term)])));
+  } else {
+    steps.push(("unaccented", off("ignore-accents")));
+  }
+
+  let variants = if args.transliterate {
+    translit::variants(term)
+  } else {
+    vec![term.clone()]
+  };
+  if args.transliterate {
+    steps.push(("transliterated", quote(&variants)));
+  } else {
+    steps.push(("transliterated", off("transliterate")));
+  }
+  if args.stem {
+    steps.push(("stemmed", quote(&search_terms(args, term))));
+  } else {
+    steps.push(("stemmed", off("stem")));
+  }
+
+  // Compounds are only split up if nothing else matched, but we report
+  // how they would be split regardless.
+  let mut decompounded = "(not a known compound)".to_string();
+  if !term.contains(' ') {
+    let connection = open(db)?;
+    for direction in directions {
+      let (src_col, _) = columns(direction);
+      let words = compound::load_words(&connection, src_col)?;
+      if let Some(parts) = compound::split(term, &words) {
+        let texts = parts.iter().map(|p| p.text.as_str()).collect::<Vec<_>>();
+        decompounded = texts.join(" + ");
+        break;
+      }
+    }
+  }
+  steps.push(("decompounded", decompounded));
+  Ok(steps)
+}
+
+fn search_terms(args: &Args, term: &str) -> Vec<String> {
+  let mut terms = if args.transliterate {
+    translit::variants(term)
+  } else {
+    vec![term.to_string()]
+  };
+  if args.stem {
+    let mut stemmed = Vec::new();
+    for candidate in terms.iter().flat_map(|t| stem::candidates(t)) {
+      if !stemmed.contains(&candidate) {
+        stemmed.push(candidate);
+      }
+    }
+    terms = stemmed;
+  }
+  terms
+}
+
+/// Replace the `--all-dicts` option in the arguments of the translate
+/// command with one selecting the database registered under the given
+/// name.
+fn dict_arguments(argv: &[String], name: &str) -> Vec<String> {
+  // Option parsing stops at "--", so anything following it is a word.
+  let end = argv.iter().position(|a| a == "--").unwrap_or(argv.len());
+  let mut args = vec!["--dict".to_string(), name.to_string()];
+  args.extend(argv[..end].iter().filter(|a| *a != "--all-dicts").cloned());
+  args.extend_from_slice(&argv[end..]);
+  args
 }
 
-fn open(db: &path::Path) -> Result<sqlite::Connection> {
-  // Note that sqlite::open by default creates the database if it does
-  // not exist. That is not a desired behavior. So we catch cases where
-  // the database does not exist in advance.
-  if !db.exists() {
-    let path = db.to_str().ok_or_else(|| Error::Error(
-      "No database given".to_string()
-    ))?;
-    Err(Error::Error(format!("Database {} not found", path).to_string()))
+/// Look up the given term using all registered databases, returning
+/// the number of results found in all of them.
+fn look_up_all(argv: &[String], matches: &getopts::Matches, out: &mut Output) -> CommandResult<usize> {
+  if matches.opt_present("dict") {
+    return Err(Error::Error("--dict and --all-dicts are mutually exclusive".to_string()).into());
+  }
+  let databases = config::Config::load()?.databases();
+  if databases.is_empty() {
+    return Err(Error::Error("No dictionaries are configured".to_string()).into());
+  }
+
+  // Merely checking for existence prints nothing, so there is nothing
+  // to group either.
+  let exists = matches.opt_present("e");
+  let mut found = 0;
+  let mut failed = 0;
+  for (i, (name, _)) in databases.iter().enumerate() {
+    if !exists {
+      if i > 0 {
+        let _ = writeln!(out);
+      }
+      let _ = writeln!(out, "==> {} <==", name);
+    }
+    match look_up(&dict_arguments(argv, name), out) {
+      Ok(count) => found += count,
+      Err(e) => {
+        eprintln!("{}: {}", name, e);
+        failed += 1;
+      },
+    }
+  }
+
+  if failed > 0 {
+    Err(Error::Error(format!("Lookup failed in {} of {} dictionaries", failed, databases.len())).into())
   } else {
-    let connection = sqlite::open(db)?;
-    Ok(connection)
+    Ok(found)
+  }
+}
+
+/// Check whether an argument refers to an alias, e.g., "@de".
+fn is_alias(arg: &str) -> bool {
+  arg.len() > 1 && arg.starts_with('@')
+}
+
+/// Expand the aliases among the arguments of the translate command into
+/// the options they stand for.
+///
+/// Arguments following `--` are taken literally, allowing for terms
+/// starting with '@'.
+fn expand_aliases(argv: &[String]) -> Result<Vec<String>> {
+  let end = argv.iter().position(|a| a == "--").unwrap_or(argv.len());
+  if !argv[..end].iter().any(|a| is_alias(a)) {
+    return Ok(argv.to_vec())
+  }
+
+  let config = config::Config::load()?;
+  let mut args = Vec::with_capacity(argv.len());
+  for arg in &argv[..end] {
+    if is_alias(arg) {
+      let expanded = config.alias(&arg[1..])?;
+      debug!("Expanded {} to {:?}", arg, expanded);
+      args.extend(expanded);
+    } else {
+      args.push(arg.clone());
+    }
+  }
+  args.extend(argv[end..].iter().cloned());
+  Ok(args)
+}
+
+/// Replace the options (or `-` argument) requesting a batch of terms in
+/// the arguments of the translate command with the term to look up.
+fn batch_arguments(argv: &[String], term: &str) -> Vec<String> {
+  let end = argv.iter().position(|a| a == "--").unwrap_or(argv.len());
+  let mut args = Vec::with_capacity(argv.len() + 2);
+  let mut options = argv[..end].iter();
+  while let Some(arg) = options.next() {
+    match arg.as_str() {
+      "--stdin" | "-" => (),
+      "--from-file" | "--jobs" => {
+        let _ = options.next();
+      },
+      _ if arg.starts_with("--from-file=") || arg.starts_with("--jobs=") => (),
+      _ => args.push(arg.clone()),
+    }
+  }
+  // The term follows all options, so that it is never mistaken for
+  // one.
+  args.push("--".to_string());
+  args.extend(argv[end..].iter().skip(1).cloned());
+  args.push(term.to_string());
+  args
+}
+
+/// Split the arguments of the translate command into the options (along
+/// with their arguments) and the free arguments.
+fn split_arguments(argv: &[String]) -> (Vec<String>, Vec<String>) {
+  let opts = translate_options();
+  let mut options = Vec::new();
+  let mut free = Vec::new();
+  let mut args = argv.iter();
+  while let Some(arg) = args.next() {
+    if arg == "--" {
+      free.extend(args.cloned());
+      break
+    }
+    if arg.starts_with('-') && arg.len() > 1 {
+      // An option takes an argument if it consumes the one following
+      // it.
+      let takes_arg = opts
+        .parse([arg.as_str(), ""])
+        .map(|matches| matches.free.is_empty())
+        .unwrap_or(false);
+      options.push(arg.clone());
+      if takes_arg {
+        options.extend(args.next().cloned());
+      }
+    } else {
+      free.push(arg.clone());
+    }
   }
+  (options, free)
 }
 
-fn translate<F, S>(db: &path::Path, to_translate: S,
-                   direction: &Direction, mut callback: F) -> Result<()>
+/// Determine the tuning of database connections, with the command line
+/// taking precedence over the configuration.
+fn connection_tuning(matches: &getopts::Matches, config: &config::Config) -> Result<tuning::Tuning> {
+  // Not every command offers all of the options.
+  let size = |option: &str, key: &str| -> Result<Option<u64>> {
+    match matches.opt_defined(option).then(|| matches.opt_str(option)).flatten() {
+      Some(size) => size
+        .parse::<u64>()
+        .map(Some)
+        .map_err(|_| Error::Error(format!("Invalid size for --{}: {}", option, size))),
+      None => match config.get_number(key)? {
+        Some(size) if size >= 0.0 && size.fract() == 0.0 => Ok(Some(size as u64)),
+        Some(size) => Err(Error::Error(format!("Invalid size for {}: {}", key, size))),
+        None => Ok(None),
+      },
+    }
+  };
+
+  let mut tuning = tuning::Tuning::default();
+  if let Some(cache_size) = size("cache-size", "sqlite.cache-size")? {
+    tuning.cache_size = cache_size;
+  }
+  if let Some(mmap_size) = size("mmap-size", "sqlite.mmap-size")? {
+    tuning.mmap_size = mmap_size;
+  }
+  if let Some(temp_store) = config.get_string("sqlite.temp-store")? {
+    tuning.temp_store = temp_store.parse()?;
+  }
+  tuning.in_memory = matches.opt_defined("in-memory") && matches.opt_present("in-memory")
+    || config.get_bool("sqlite.in-memory")?.unwrap_or(false);
+  Ok(tuning)
+}
+
+/// Retrieve the encoding to print the output of the translate command
+/// in.
+fn output_encoding(matches: &getopts::Matches) -> Result<encoding::Encoding> {
+  match matches.opt_str("output-encoding") {
+    Some(name) => encoding::Encoding::from_name(&name),
+    None => Ok(encoding::Encoding::default()),
+  }
+}
+
+/// Look up each line read from standard input or from a file, or each
+/// of the words given, as a term of its own.
+fn translate_batch(argv: &[String], matches: &getopts::Matches) -> CommandResult<i32> {
+  let file = matches.opt_str("from-file");
+  let each = matches.opt_present("each");
+  if file.is_some() && matches.opt_present("stdin") {
+    return Err(Error::Error("--stdin and --from-file are mutually exclusive".to_string()).into());
+  }
+  if each && (file.is_some() || matches.opt_present("stdin") || matches.free.iter().any(|a| a == "-")) {
+    return Err(Error::Error("--each cannot be combined with --stdin or --from-file".to_string()).into());
+  }
+  // Merely checking for existence prints nothing, so there is nothing
+  // to group either.
+  let exists = matches.opt_present("e");
+  let named = matches.opt_present("dict") || matches.opt_present("all-dicts");
+
+  // The words to look up each are removed from the arguments used for
+  // every lookup, keeping only the database.
+  let (argv, words, lacks_db) = if each {
+    let (options, mut free) = split_arguments(argv);
+    let db = if named || lacks_database(&free) {
+      None
+    } else {
+      Some(free.remove(0))
+    };
+    let lacks_db = db.is_none();
+    let options = options.into_iter().filter(|a| a != "--each");
+    (options.chain(db).collect::<Vec<_>>(), Some(free), lacks_db)
+  } else {
+    (argv.to_vec(), None, matches.free.iter().all(|a| a == "-"))
+  };
+  let argv = &argv[..];
+
+  // A missing database is reported once and not for every term.
+  if !named && lacks_db {
+    let _ = locate::database()?;
+  }
+
+  // The progress of lookups performed in parallel cannot be shown
+  // sensibly.
+  SHOW_PROGRESS.store(false, atomic::Ordering::Relaxed);
+  // Scanning the dictionary for alternatives to every term without a
+  // translation would dominate the time taken for a long list. The
+  // summary lists those terms instead.
+  SUGGEST.store(false, atomic::Ordering::Relaxed);
+  // Caching the results of every term would rewrite the cache each
+  // time and evict those of interactive lookups.
+  CACHE.store(false, atomic::Ordering::Relaxed);
+  let encoding = output_encoding(matches)?;
+  let jobs = match matches.opt_str("jobs") {
+    Some(jobs) => jobs
+      .parse::<usize>()
+      .ok()
+      .filter(|jobs| *jobs > 0)
+      .ok_or_else(|| Error::Error(format!("Invalid number of jobs: {}", jobs)))?,
+    None => 1,
+  };
+
+  let lines: Box<dyn Iterator<Item = Result<String>>> = match (words, &file) {
+    (Some(words), _) => Box::new(words.into_iter().map(Ok)),
+    (None, Some(path)) => Box::new(read_file(path)?.lines().map(|l| Ok(l.to_string())).collect::<Vec<_>>().into_iter()),
+    (None, None) => Box::new(io::stdin().lock().lines().map(|line| {
+      line.map_err(|e| Error::Io("Failed to read standard input".to_string(), e))
+    })),
+  };
+  let mut terms = lines.filter_map(|line| match line {
+    Ok(ref line) if line.trim().is_empty() => None,
+    Ok(line) => Some(Ok(line.trim().to_string())),
+    Err(e) => Some(Err(e)),
+  });
+
+  let mut code = 0;
+  let mut total = 0;
+  let mut missing = Vec::new();
+  let mut report = |term: &str, out: String, result: CommandResult<usize>| {
+    if !exists {
+      if total > 0 {
+        encoding.print("\n");
+      }
+      encoding.print(&format!("==> {} <==\n", term));
+    }
+    encoding.print(&out);
+    total += 1;
+    match result {
+      Ok(0) => missing.push(term.to_string()),
+      Ok(_) => (),
+      Err(e) => {
+        eprintln!("{}: {}", term, e);
+        code = 1;
+      },
+    }
+  };
+
+  if jobs == 1 {
+    // Terms are looked up as they are read, so that results show up
+    // right away when reading from an interactive source.
+    sharing_connections(|| -> Result<()> {
+      for term in terms {
+        let term = term?;
+        let mut out = Output::Collect(String::new());
+        let result = look_up(&batch_arguments(argv, &term), &mut out);
+        report(&term, out.into_collected(), result);
+      }
+      Ok(())
+    })?;
+  } else {
+    let terms = terms.by_ref().collect::<Result<Vec<_>>>()?;
+    look_up_parallel(argv, &terms, jobs, report);
+  }
+
+  // Vetting a word list is what a file is for, so we summarize which
+  // of its terms lack a translation.
+  if file.is_some() {
+    let mut summary = String::new();
+    if total > 0 && !exists {
+      summary.push('\n');
+    }
+    if missing.is_empty() {
+      let _ = writeln!(summary, "All {} terms have translations", total);
+    } else {
+      let _ = writeln!(summary, "No translation for {} of {} terms:", missing.len(), total);
+      for term in &missing {
+        let _ = writeln!(summary, "  {}", term);
+      }
+    }
+    encoding.print(&summary);
+  }
+  if !missing.is_empty() && (exists || file.is_some()) {
+    code = 1;
+  }
+  Ok(code)
+}
+
+/// Look up each of the given terms using a number of jobs in parallel,
+/// passing the output and outcome of each lookup to `report` in the
+/// order of the terms.
+///
+/// Each job uses connections of its own.
+fn look_up_parallel<F>(argv: &[String], terms: &[String], jobs: usize, mut report: F)
 where
-  F: FnMut(&str, &str, &str) -> Result<()>,
-  S: Into<String>,
+  F: FnMut(&str, String, CommandResult<usize>),
 {
-  let (src_col, dst_col) = match *direction {
-    Direction::Lang1ToLang2 => (TERM1_COL, TERM2_COL),
-    Direction::Lang2ToLang1 => (TERM2_COL, TERM1_COL),
+  let next = sync::atomic::AtomicUsize::new(0);
+  let (sender, receiver) = sync::mpsc::channel();
+
+  thread::scope(|scope| {
+    for _ in 0..cmp::min(jobs, terms.len()) {
+      let sender = sender.clone();
+      let next = &next;
+      scope.spawn(move || sharing_connections(|| loop {
+        let i = next.fetch_add(1, sync::atomic::Ordering::Relaxed);
+        if i >= terms.len() {
+          break
+        }
+        let mut out = Output::Collect(String::new());
+        let result = look_up(&batch_arguments(argv, &terms[i]), &mut out);
+        if sender.send((i, out.into_collected(), result)).is_err() {
+          break
+        }
+      }));
+    }
+    drop(sender);
+
+    // Lookups finish in any order, so we hold back the ones finishing
+    // ahead of those of earlier terms.
+    let mut pending = HashMap::new();
+    let mut i = 0;
+    for (j, out, result) in receiver {
+      pending.insert(j, (out, result));
+      while let Some((out, result)) = pending.remove(&i) {
+        report(&terms[i], out, result);
+        i += 1;
+      }
+    }
+  });
+}
+
+/// Translate the given term.
+///
+/// The function returns the exit code the program should terminate
+/// with.
+fn translate_term(argv: &[String]) -> CommandResult<i32> {
+  let argv = &expand_aliases(argv)?;
+  // When checking for existence or asking for the first translation,
+  // the absence of results is signaled by the exit code.
+  let (required, encoding) = match translate_options().parse(argv) {
+    Ok(ref matches) if matches.opt_present("stdin") ||
+                       matches.opt_present("from-file") ||
+                       matches.opt_present("each") ||
+                       matches.free.iter().any(|a| a == "-") => {
+      return translate_batch(argv, matches)
+    },
+    Ok(matches) => (
+      matches.opt_present("e") ||
+        matches.opt_present("first") ||
+        matches.opt_str("format").as_deref() == Some("first"),
+      output_encoding(&matches)?,
+    ),
+    // Malformed arguments are reported when parsing them.
+    Err(_) => (false, encoding::Encoding::default()),
   };
+
+  let found = look_up(argv, &mut Output::Print(encoding))?;
+  Ok(if required && found == 0 { 1 } else { 0 })
+}
+
+/// Warn if the entries of the dictionary are older than the given
+/// number of days. Databases lacking metadata are not judged.
+fn warn_stale(db: &path::Path, days: u64) -> Result<()> {
+  match metadata::age(&*open(db)?)? {
+    Some(age) if age > days as f64 => {
+      eprintln!("Warning: The dictionary is {} days old; consider updating it", age as u64)
+    },
+    Some(_) => (),
+    None => info!("Age of {} is unknown as it lacks metadata", db.display()),
+  }
+  Ok(())
+}
+
+/// The interval in between updates of the number of entries found.
+const PROGRESS_INTERVAL: time::Duration = time::Duration::from_millis(100);
+
+/// Whether to show the progress of lookups.
+static SHOW_PROGRESS: atomic::AtomicBool = atomic::AtomicBool::new(true);
+
+/// Whether to look for alternatives to terms without translations.
+static SUGGEST: atomic::AtomicBool = atomic::AtomicBool::new(true);
+
+/// Whether to cache the results of lookups.
+static CACHE: atomic::AtomicBool = atomic::AtomicBool::new(true);
+
+/// Whether we checked the age of the dictionary already, so as to warn
+/// only once when performing many lookups.
+static CHECKED_STALE: atomic::AtomicBool = atomic::AtomicBool::new(false);
+
+
+/// The destination of the output of a lookup.
+enum Output {
+  /// The output is collected, so that lookups can happen in parallel.
+  Collect(String),
+  /// The output is printed right away, in the given encoding, so that
+  /// entries can be shown as they are found.
+  Print(encoding::Encoding),
+}
+
+impl Output {
+  /// Check whether the output is printed right away.
+  fn prints(&self) -> bool {
+    matches!(*self, Output::Print(_))
+  }
+
+  /// Retrieve the output collected.
+  fn into_collected(self) -> String {
+    match self {
+      Output::Collect(text) => text,
+      Output::Print(_) => String::new(),
+    }
+  }
+}
+
+impl fmt::Write for Output {
+  fn write_str(&mut self, s: &str) -> fmt::Result {
+    match *self {
+      Output::Collect(ref mut text) => text.push_str(s),
+      Output::Print(encoding) => encoding.print(s),
+    }
+    Ok(())
+  }
+}
+
+
+/// A live count of the entries found by a lookup, shown on stderr
+/// once the lookup takes a noticeable amount of time, so that broad
+/// lookups do not appear to hang.
+///
+/// The count is shown by a thread of its own, as SQLite may take a
+/// while to sort the entries before handing out the first one.
+struct Progress {
+  /// The number of entries found so far.
+  count: sync::Arc<atomic::AtomicUsize>,
+  /// The thread showing the count along with the means of stopping
+  /// it, if the count is shown at all.
+  shower: Option<(sync::mpsc::Sender<()>, thread::JoinHandle<()>)>,
+}
+
+impl Progress {
+  /// Create the progress of a lookup about to start, shown only if
+  /// stderr is a terminal.
+  fn new() -> Progress {
+    let count = sync::Arc::new(atomic::AtomicUsize::new(0));
+    let enabled = SHOW_PROGRESS.load(atomic::Ordering::Relaxed)
+      && unsafe { libc::isatty(libc::STDERR_FILENO) != 0 };
+    let shower = if enabled {
+      let (stop, stopped) = sync::mpsc::channel();
+      let count = count.clone();
+      let thread = thread::spawn(move || {
+        let mut shown = false;
+        while let Err(sync::mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(PROGRESS_INTERVAL) {
+          match count.load(atomic::Ordering::Relaxed) {
+            0 => eprint!("\rSearching..."),
+            count => eprint!("\rFound {} entries...", count),
+          }
+          shown = true;
+        }
+        if shown {
+          eprint!("\r\x1b[K");
+        }
+      });
+      Some((stop, thread))
+    } else {
+      None
+    };
+    Progress { count, shower }
+  }
+
+  /// Account for another entry found.
+  fn add(&self) {
+    self.count.fetch_add(1, atomic::Ordering::Relaxed);
+  }
+
+  /// Stop showing the count, erasing it.
+  fn finish(&mut self) {
+    if let Some((stop, thread)) = self.shower.take() {
+      drop(stop);
+      let _ = thread.join();
+    }
+  }
+}
+
+impl Drop for Progress {
+  fn drop(&mut self) {
+    // The count is erased once the results are about to be shown.
+    self.finish()
+  }
+}
+
+
+/// Add the principal forms of the terms of verbs and nouns to the
+/// matches, if the database has inflection tables.
+fn add_inflections(db: &path::Path, matches: &mut [results::Match]) -> Result<()> {
   let connection = open(db)?;
-  // Note that for some reason some terms in the database do not have a
-  // proper type associated with them. We make this fact a little more
-  // explicit by replacing the empty string. Note that it is important
-  // to properly handle this problem at the level of SQL. We sort by the
-  // type column and if we perform the replacement afterwards we mess up
-  // the order because the empty string '' is sorted before all other
-  // strings.
-  let select = format!(
-    "SELECT {src},{dst}, \
-       CASE {typ} WHEN '' \
-         THEN 'unknown' \
-         ELSE entry_type \
-       END AS __type__, \
-       {use} \
-     FROM {tbl}",
-    src = src_col, dst = dst_col,
-    typ = TYPE_COL, tbl = SEARCH_TBL, use = USAGE_COL,
-  );
-  // Note that the database contains some elements with strings
-  // containing multiple white spaces in succession. As of now we only
-  // support two spaces and will merge them into a single one. Do note
-  // though that the entire (current) data set was checked and it was
-  // found that only square braces ever appear with two spaces in front
-  // of them.
-  let where1 = format!(
-    "WHERE {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           ({src} LIKE ? AND __type__='verb') OR \
-           ({src} LIKE ? AND __type__='verb')",
-    src = src_col,
-  );
-  let where2 = format!(
-    "WHERE {src} LIKE ? OR \
-           {src} LIKE ? OR \
-           {src} LIKE ?",
-    src = src_col,
-  );
-  // We order by type first and then by the number of uses. The reason
-  // is that we first want to print all the translations for a
-  // particular type sorted by the number of uses before moving on to
-  // the next type.
-  let order = format!(
-    "ORDER BY __type__ ASC, \
-             {use} DESC, \
-             {src} ASC",
-    src = src_col, use = USAGE_COL,
-  );
+  let inflections = match inflect::Inflections::detect(&connection)? {
+    Some(inflections) => inflections,
+    None => {
+      eprintln!("Warning: The database lacks inflection tables");
+      return Ok(())
+    },
+  };
 
-  let query =
-    format!(
-    "{select} {where1} \
-     UNION \
-     {select} {where2} \
-     {order}",
-    select = select, where1 = where1, where2 = where2, order = order,
-  );
+  let inflected = [entry::Type::Verb, entry::Type::Noun];
+  for match_ in matches.iter_mut().filter(|m| m.entry.types.iter().any(|t| inflected.contains(t))) {
+    // Which of the two terms is listed depends on the database, so we
+    // try both.
+    for term in &[&match_.entry.source, &match_.entry.target] {
+      let forms = inflections.principal_forms(&connection, &term.headword)?;
+      if !forms.is_empty() {
+        match_.inflections = forms;
+        break
+      }
+    }
+  }
+  Ok(())
+}
 
-  let to_translate = to_translate.into();
-  let mut cursor = connection.prepare(query)?.cursor();
-  cursor.bind(&[
-    vec![sqlite::Value::String(to_translate.clone())],
-    include!("permutations.in"),
-    vec![
-      sqlite::Value::String(
-        "to ".to_string() + &to_translate
-      ),
-      sqlite::Value::String(
-        "to ".to_string() + &to_translate + " %"
-      ),
-      sqlite::Value::String(
-        to_translate.clone() + " %"
-      ),
-      sqlite::Value::String(
-        "% ".to_string() + &to_translate
-      ),
-      sqlite::Value::String(
-        "% ".to_string() + &to_translate + " %"
-      ),
-    ],
-  ]
-   .concat())?;
-
-  handle(cursor, &mut callback)
-}
-
-fn usage(opts: &getopts::Options) -> String {
-  let program = env::args().nth(0).unwrap_or_else(|| "dictcc-cli".to_string());
-  let usage = format!("Usage: {} [options] [<database>] [<word>...]", program);
-  opts.usage(&usage)
+/// Convert the results of a lookup, along with the number of entries
+/// filtered out, into the form they are cached in.
+fn to_cached(results: &results::Results, omitted: usize) -> json::Value {
+  json::Value::Object(vec![
+    ("omitted".to_string(), json::Value::Number(omitted as f64)),
+    ("results".to_string(), results.to_json()),
+  ])
 }
 
-/// Parse the program's arguments and return a (database, term) tuple.
-fn parse_arguments() -> Result<(String, String, Direction)> {
-  let argv: Vec<String> = env::args().collect();
+/// Parse the entries found by a lookup, along with the number of
+/// entries filtered out, as cached by `to_cached`.
+fn from_cached(cached: &str) -> Option<(Vec<results::Match>, usize)> {
+  let value = json::parse(cached).ok()?;
+  let omitted = match value.get("omitted") {
+    Some(&json::Value::Number(omitted)) => omitted as usize,
+    _ => return None,
+  };
+  let results = results::Results::from_json(value.get("results")?).ok()?;
+  Some((results.entries, omitted))
+}
+
+/// Look up the given term, returning the number of results found.
+fn look_up(argv: &[String], out: &mut Output) -> CommandResult<usize> {
+  if let Ok(matches) = translate_options().parse(argv) {
+    if matches.opt_present("all-dicts") {
+      return look_up_all(argv, &matches, out);
+    }
+  }
+
+  let mut args = parse_arguments(argv)?;
+  if !args.subjects.is_empty() {
+    let subjects = subjects::subjects(&*open(path::Path::new(&args.database))?)?;
+    for subject in &args.subjects {
+      args.options.subjects.extend(subjects::resolve(&subjects, subject)?);
+    }
+  }
+  if let Some(days) = args.warn_stale {
+    if !CHECKED_STALE.swap(true, atomic::Ordering::Relaxed) {
+      warn_stale(path::Path::new(&args.database), days)?;
+    }
+  }
+  let Args { ref database, ref term, ref direction, ref filter, ref options, .. } = args;
+
+  #[cfg(feature = "online")]
+  {
+    if let Some(ref config) = args.mt {
+      if mt::is_sentence(term) {
+        let _ = writeln!(out, "{}", mt::translate(config, term)?);
+        return Ok(1);
+      }
+    }
+  }
+
+  let db = path::Path::new(database);
+  // Terms containing operators are treated as a boolean query, unless
+  // the user asked for an exact phrase or for individual words.
+  let is_query = !args.any && !options.phrase && !options.all_words && query::is_query(term);
+  info!("Matching {}", if args.any {
+    "each word on its own"
+  } else if is_query {
+    "a boolean query"
+  } else if options.all_words {
+    "all words in any order"
+  } else if options.phrase {
+    "an exact phrase"
+  } else {
+    "the term"
+  });
+  let conditions = |direction: &Direction| -> Result<(Vec<String>, Vec<sqlite::Value>)> {
+    if args.any {
+      let mut conds = Vec::new();
+      let mut binds = Vec::new();
+      for word in &args.words {
+        let (word_conds, word_binds) = term_query(&search_terms(&args, word), direction, options);
+        conds.extend(word_conds);
+        binds.extend(word_binds);
+      }
+      Ok((conds, binds))
+    } else if is_query {
+      let (cond, binds) = boolean_query(&query::parse(term)?, direction, options, |term| {
+        search_terms(&args, term)
+      });
+      Ok((vec![cond], binds))
+    } else {
+      Ok(term_query(&search_terms(&args, term), direction, options))
+    }
+  };
+
+  let detected;
+  let direction = if args.auto {
+    detected = detect_direction(db, term, conditions)?;
+    let (lang1, lang2) = match args.languages {
+      Some((ref lang1, ref lang2)) => (lang1.as_str(), lang2.as_str()),
+      None => ("lang1", "lang2"),
+    };
+    match detected {
+      Direction::Lang1ToLang2 => eprintln!("Translating from {} to {}", lang1, lang2),
+      Direction::Lang2ToLang1 => eprintln!("Translating from {} to {}", lang2, lang1),
+    }
+    &detected
+  } else {
+    direction
+  };
+  let both = [Direction::Lang1ToLang2, Direction::Lang2ToLang1];
+  let directions = if args.both {
+    &both[..]
+  } else {
+    slice::from_ref(direction)
+  };
+
+  if args.show_normalization {
+    for (step, result) in normalization(&args, db, directions)? {
+      eprintln!("{:>14}: {}", step, result);
+    }
+  }
+
+  // Filtering happens on the results, so we can only take the short
+  // cut of checking for any match if no filter is active. It does not
+  // tell how many matches there are, though.
+  if args.exists && filter.is_empty() {
+    for direction in directions {
+      let (conds, binds) = conditions(direction)?;
+      if exists(db, direction, &conds, &binds)? {
+        return Ok(1);
+      }
+    }
+    return Ok(0);
+  }
+
+  // Entries whose source term is (one of) the search term(s) are exact
+  // matches, all others are phrases merely containing them. The terms
+  // of a boolean query are not classified.
+  let terms = search_terms(&args, term);
+  debug!("Searching for {:?}", terms);
+  let word_terms = args.words.iter().map(|w| search_terms(&args, w)).collect::<Vec<_>>();
+  let related = |src_term: &str, terms: &[String]| {
+    let exact = |t: &String| if options.ignore_accents {
+      rank::classify(&like::unaccent(src_term), &like::unaccent(t)) == rank::Match::Exact
+    } else {
+      rank::classify(src_term, t) == rank::Match::Exact
+    };
+    !is_query && !terms.iter().any(exact)
+  };
+
+  let thresholds = if args.bands {
+    Some(band::thresholds(db)?)
+  } else {
+    None
+  };
+  // Lookups are commonly repeated, e.g., when watching the clipboard,
+  // so we cache the results of recent ones. The key has to capture
+  // everything influencing the results. Lookups whose execution is to
+  // be shown are always performed, as are those in the midst of a
+  // transaction, whose changes are not reflected in the versions of
+  // the databases yet.
+  let cache = args.cache && CACHE.load(atomic::Ordering::Relaxed);
+  let cache_key = if cache && !options.explain && !in_transaction() {
+    Some(format!("{:?}", (
+      term, &terms, &word_terms, directions, args.any, is_query, options, filter, &args.languages,
+      args.bands, args.inflections, overlay::fingerprint(),
+    )))
+  } else {
+    None
+  };
+  let cached = cache_key
+    .as_ref()
+    .and_then(|key| cache::query_results(db, key))
+    .and_then(|cached| from_cached(&cached));
+  let from_cache = cached.is_some();
+
+  // Entries are printed as they are found if they can be rendered one
+  // by one, i.e., if they are not divided into sections and nothing is
+  // added to them later on. All entries of a boolean query are shown
+  // in a single section.
+  let stream = out.prints() && !from_cache && !args.exists && !args.inflections && match args.format {
+    results::Format::Tsv => true,
+    results::Format::Plain => is_query,
+    _ => false,
+  };
+
+  let mut omitted = 0;
+  let mut entries = Vec::new();
+  let mut progress = Progress::new();
+  if let Some((cached, cached_omitted)) = cached {
+    info!("Using cached results");
+    entries = cached;
+    omitted = cached_omitted;
+  } else {
+    for direction in directions {
+      // When searching in both directions we label each result with the
+      // direction it was found in, using the names of the languages if
+      // configured.
+      let label = match (args.both, &args.languages, direction) {
+        (false, _, _) => None,
+        (true, &Some((ref l1, ref l2)), &Direction::Lang1ToLang2) => Some(format!("{}→{}", l1, l2)),
+        (true, &Some((ref l1, ref l2)), &Direction::Lang2ToLang1) => Some(format!("{}→{}", l2, l1)),
+        (true, &None, &Direction::Lang1ToLang2) => Some("1→2".to_string()),
+        (true, &None, &Direction::Lang2ToLang1) => Some("2→1".to_string()),
+      };
+      let mut record = |word: Option<usize>, entry: &Entry| {
+        progress.add();
+        if filter.admits(&entry.source.text, &entry.target.text) {
+          let terms = word.map_or(&terms, |i| &word_terms[i]);
+          let found = results::Match {
+            direction: label.clone(),
+            word: word.map(|i| args.words[i].clone()),
+            entry: entry.clone(),
+            related: related(&entry.source.text, terms),
+            band: thresholds.map(|t| t.band(entry.usage)),
+            inflections: Vec::new(),
+          };
+          if stream {
+            progress.finish();
+            let line = match args.format {
+              results::Format::Tsv => found.to_tsv(),
+              _ => found.to_plain(),
+            };
+            let _ = writeln!(out, "{}", line);
+          }
+          entries.push(found);
+        } else {
+          omitted += 1;
+        }
+        Ok(())
+      };
+      if args.any {
+        let expand = |word: &str| search_terms(&args, word);
+        translate_words(db, &args.words, direction, options, expand, |i, entry: &Entry| {
+          record(Some(i), entry)
+        })?;
+      } else if is_query {
+        let expr = query::parse(term)?;
+        let expand = |term: &str| search_terms(&args, term);
+        translate_query(db, &expr, direction, options, expand, |entry: &Entry| record(None, entry))?;
+      } else {
+        translate_with(db, &terms, direction, options, |entry: &Entry| record(None, entry))?;
+      }
+    }
+  }
+
+  drop(progress);
+
+  if args.inflections && !from_cache {
+    add_inflections(db, &mut entries)?;
+  }
+
+  let found = entries.len();
+  let results = results::Results {
+    query: term.clone(),
+    entries,
+  };
+  if let Some(ref key) = cache_key {
+    if !from_cache {
+      cache::cache_query_results(db, key, &to_cached(&results, omitted).to_string());
+    }
+  }
+  if let Some(ref path) = args.save_results {
+    write_file(path, results.render(results::Format::Json))?;
+  }
+
+  if args.exists {
+    return Ok(found);
+  }
+  if !stream {
+    let _ = out.write_str(&results.render(args.format));
+  }
+
+  args.hooks.run(hooks::Event::Lookup, &results)?;
+  if found == 0 {
+    args.hooks.run(hooks::Event::NoResult, &results)?;
+  }
+
+  // If all translations got filtered out there is no point in looking
+  // for alternatives. Nor do we have room for them on a single line or
+  // in machine readable output. Alternatives are only looked for if the
+  // term is to be found as a whole.
+  let single = !is_query && !args.any;
+  let suggest = SUGGEST.load(atomic::Ordering::Relaxed);
+  let terse = match args.format {
+    results::Format::OneLine | results::Format::First => true,
+    results::Format::Tsv | results::Format::Json => true,
+    results::Format::Plain | results::Format::Compact => false,
+  };
+  if found == 0 && omitted == 0 && single && suggest && !terse {
+    let connection = open(db)?;
+
+    // A single word without any translations may be a compound that
+    // the dictionary does not know about as a whole. In that case we
+    // provide the translations of its constituents.
+    if !term.contains(' ') {
+      for direction in directions {
+        let (src_col, _) = columns(direction);
+        let words = compound::load_words(&connection, src_col)?;
+        if let Some(parts) = compound::split(term, &words) {
+          let texts = parts.iter().map(|p| p.text.as_str()).collect::<Vec<_>>();
+          eprintln!("No translations found. Compound of: {}", texts.join(" + "));
+          for part in parts {
+            let _ = writeln!(out, "{}:", part.text);
+            translate(db, &[&part.word], direction, |entry: &Entry| {
+              if filter.admits(&entry.source.text, &entry.target.text) {
+                let _ = writeln!(out, "  {} ({}): {}", entry.source, entry.type_name(), entry.target);
+              }
+              Ok(())
+            })?;
+          }
+          return Ok(0);
+        }
+      }
+    }
+
+    let mut suggestions = Vec::new();
+    for direction in directions {
+      let (src_col, _) = columns(direction);
+      for suggestion in suggest::suggest(&connection, src_col, term, suggest::MAX_SUGGESTIONS)? {
+        if !suggestions.contains(&suggestion) {
+          suggestions.push(suggestion);
+        }
+      }
+    }
+    if !suggestions.is_empty() {
+      eprintln!("No translations found. Did you mean:");
+      for suggestion in suggestions.into_iter().take(suggest::MAX_SUGGESTIONS) {
+        eprintln!("  {}", suggestion);
+      }
+    }
+  }
+  Ok(found)
+}
+
+/// Run the commands of a script, sharing database connections between
+/// them.
+fn run_script(args: &[String]) -> CommandResult<i32> {
+  const SYNOPSES: &[&str] = &["script <script>"];
   let mut opts = getopts::Options::new();
-  opts.optflag("r", "reverse", "Perform reverse lookup, i.e., instead \
-                                from mapping from lang1 to lang2 map \
-                                from lang2 to lang1");
-  opts.optflag("h", "help", "Print the program's help");
+  let matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.len() != 1 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
 
-  let matches = opts.parse(&argv[1..])?;
-  if matches.free.len() < 2 {
-    return Err(Error::Error(usage(&opts)));
+  // We check the entire script upfront, so that a mistake does not
+  // surface only after some of the commands have run.
+  let path = &matches.free[0];
+  let lines = script::parse(&read_file(path)?).map_err(|e| Error::Error(format!("{}: {}", path, e)))?;
+  let mut commands = Vec::with_capacity(lines.len());
+  for line in &lines {
+    let command = match find_command(&line.args[0]) {
+      Some(command) if command.name == "script" => {
+        return Err(Error::Error(format!("{}:{}: Scripts cannot run scripts", path, line.number)).into())
+      },
+      // VACUUM cannot run inside of the script's transaction.
+      Some(command) if command.name == "compact" => {
+        return Err(Error::Error(format!("{}:{}: Scripts cannot compact databases", path, line.number)).into())
+      },
+      Some(command) => (command, &line.args[1..]),
+      None => (find_command("translate").unwrap(), &line.args[..]),
+    };
+    commands.push(command);
+  }
+
+  // The changes the script makes are applied together or, if one of
+  // its commands fails, not at all. Only commands reading databases
+  // require the changes before them to be committed, as they would
+  // not see them otherwise.
+  let code = sharing_connections(|| {
+    atomically(|| {
+      let mut code = 0;
+      for (i, (line, (command, args))) in lines.iter().zip(commands).enumerate() {
+        // Each command's output is preceded by a header naming it.
+        if i > 0 {
+          println!();
+        }
+        println!("==> {} <==", line.text);
+        if !command.modifies() {
+          commit()?;
+        }
+        match (command.run)(args) {
+          Ok(0) => (),
+          Ok(_) => code = 1,
+          Err(CommandError::Help(help)) => print!("{}", help),
+          Err(e) => return Err(Error::Error(format!("{}:{}: {}", path, line.number, e))),
+        }
+      }
+      Ok(code)
+    })
+  })?;
+  Ok(code)
+}
+
+/// Print the help of the program or of a command.
+fn help(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["help [<command>]"];
+  let mut opts = getopts::Options::new();
+  let matches = parse_options(&mut opts, args, SYNOPSES)?;
+  match matches.free.len() {
+    0 => translate_term(&["--help".to_string()]).map(|_| ()),
+    1 => match find_command(&matches.free[0]) {
+      Some(command) => (command.run)(&["--help".to_string()]).map(|_| ()),
+      None => Err(Error::Error(format!("Unknown command: {}", matches.free[0])).into()),
+    },
+    _ => Err(Error::Error(usage(&opts, SYNOPSES)).into()),
+  }
+}
+
+
+/// Print the headwords starting with a prefix, for completing the term
+/// to translate in shells.
+fn complete_term(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["__complete-term [options] [<database>] <prefix>"];
+  let mut opts = getopts::Options::new();
+  opts.optflag("r", "reverse", "Complete terms to translate in reverse");
+
+  let mut matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.len() == 1 {
+    default_database(&mut matches.free)?;
   }
-  let direction = if matches.opt_present("r") {
+  if matches.free.len() != 2 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+
+  // We complete the terms the translate command would look up, so we
+  // follow its choice of direction.
+  let db = path::Path::new(&matches.free[0]);
+  let mut config = config::Config::load()?;
+  config.select_database(db);
+  let german = orient::german_column(db)?;
+  let reverse = config.get_bool("reverse")?.unwrap_or(false) != (german == TERM2_COL);
+  let direction = if matches.opt_present("r") != reverse {
     Direction::Lang2ToLang1
   } else {
     Direction::Lang1ToLang2
   };
-  // We treat all arguments past the database path itself as words to
-  // search for (in that order, with a single space in between them).
-  Ok((matches.free[0].clone(), matches.free[1..].join(" "), direction))
+
+  let (src_col, _) = columns(&direction);
+  let prefix = &matches.free[1];
+  for headword in suggest::complete(&*open(db)?, src_col, prefix, suggest::MAX_COMPLETIONS)? {
+    println!("{}", headword);
+  }
+  Ok(())
 }
 
-fn run_() -> Result<()> {
-  let (database, term, direction) = parse_arguments()?;
-  let db = path::Path::new(&database);
-  let callback = |src_term: &str, dst_term: &str, type_: &str| {
-    println!("{} ({}): {}", src_term, type_, dst_term);
-    Ok(())
-  };
+/// Print the completion script for a shell.
+fn completions(args: &[String]) -> CommandResult<()> {
+  const SYNOPSES: &[&str] = &["completions bash|zsh|fish"];
+  let mut opts = getopts::Options::new();
+  let matches = parse_options(&mut opts, args, SYNOPSES)?;
+  if matches.free.len() != 1 {
+    return Err(Error::Error(usage(&opts, SYNOPSES)).into());
+  }
+
+  let shell = completion::Shell::from_name(&matches.free[0])?;
+  let mut commands = Vec::with_capacity(COMMANDS.len());
+  for command in COMMANDS.iter().filter(|c| !c.is_hidden()) {
+    // The options are extracted from the help of each command.
+    let options = match (command.run)(&["--help".to_string()]) {
+      Err(CommandError::Help(help)) => completion::parse_options(&help),
+      _ => Vec::new(),
+    };
+    commands.push(completion::Command {
+      name: command.name.to_string(),
+      summary: command.summary.to_string(),
+      options,
+    });
+  }
+  print!("{}", completion::script(shell, &commands));
+  Ok(())
+}
+
+
+/// A command of the program.
+struct Command {
+  /// The name the command is invoked by.
+  name: &'static str,
+  /// A one-line description of the command.
+  summary: &'static str,
+  /// The function implementing the command, returning the exit code.
+  run: fn(&[String]) -> CommandResult<i32>,
+}
+
+impl Command {
+  /// Check whether the command is an implementation detail (of shell
+  /// completion), which is not listed as available.
+  fn is_hidden(&self) -> bool {
+    self.name.starts_with("__")
+  }
+
+  /// Check whether the command only modifies databases, as opposed to
+  /// reading from them.
+  fn modifies(&self) -> bool {
+    matches!(
+      self.name,
+      "add" | "analyze" | "hide" | "import" | "import-corpus" | "migrate" | "optimize" | "remove"
+        | "unhide" | "update"
+    )
+  }
+}
+
+/// The commands of the program.
+const COMMANDS: &[Command] = &[
+  Command {
+    name: "__complete-term",
+    summary: "Print the headwords starting with a prefix",
+    run: |args| complete_term(args).map(|_| 0),
+  },
+  Command {
+    name: "add",
+    summary: "Add an entry of your own, included in every lookup",
+    run: |args| commands::add_entry(args).map(|_| 0),
+  },
+  Command {
+    name: "analyze",
+    summary: "Gather statistics on a database for planning lookups",
+    run: |args| commands::analyze_database(args).map(|_| 0),
+  },
+  Command {
+    name: "annotate",
+    summary: "Tag each word of a document with the language it is in",
+    run: |args| commands::annotate_document(args).map(|_| 0),
+  },
+  Command {
+    name: "check",
+    summary: "Check a database for problems that may affect lookups",
+    run: |args| commands::check_database(args).map(|_| 0),
+  },
+  Command {
+    name: "check-terms",
+    summary: "Check a translated document against a glossary",
+    run: |args| commands::check_terms(args).map(|_| 0),
+  },
+  Command {
+    name: "clear-cache",
+    summary: "Remove cached results and facts about databases",
+    run: |args| commands::clear_cache(args).map(|_| 0),
+  },
+  Command {
+    name: "collocations",
+    summary: "List the words frequently used together with a word",
+    run: |args| commands::collocations(args).map(|_| 0),
+  },
+  Command {
+    name: "compact",
+    summary: "Rebuild a database to reclaim the space it wastes",
+    run: |args| commands::compact_database(args).map(|_| 0),
+  },
+  Command {
+    name: "completions",
+    summary: "Print the completion script for a shell",
+    run: |args| completions(args).map(|_| 0),
+  },
+  Command {
+    name: "concord",
+    summary: "List the sentence pairs of the corpus containing a word",
+    run: |args| commands::concord(args).map(|_| 0),
+  },
+  Command {
+    name: "config",
+    summary: "Show and edit the configuration",
+    run: |args| commands::configure(args).map(|_| 0),
+  },
+  Command {
+    name: "coverage",
+    summary: "Report how well the dictionary covers a list of words",
+    run: |args| commands::report_coverage(args).map(|_| 0),
+  },
+  Command {
+    name: "export",
+    summary: "Export the dictionary for use with other dictionary programs",
+    run: |args| commands::export_dictionary(args).map(|_| 0),
+  },
+  Command {
+    name: "export-hunspell",
+    summary: "Export one side of the dictionary as a Hunspell dictionary",
+    run: |args| commands::export_hunspell(args).map(|_| 0),
+  },
+  Command {
+    name: "fetch",
+    summary: "Download a dictionary and install it as the default database",
+    run: |args| commands::fetch_database(args).map(|_| 0),
+  },
+  Command {
+    name: "help",
+    summary: "Print the help of the program or of a command",
+    run: |args| help(args).map(|_| 0),
+  },
+  Command {
+    name: "hidden",
+    summary: "List the entries hidden from lookups",
+    run: |args| commands::list_hidden(args).map(|_| 0),
+  },
+  Command {
+    name: "hide",
+    summary: "Hide an entry from all future lookups",
+    run: |args| commands::hide_entry(args).map(|_| 0),
+  },
+  Command {
+    name: "import",
+    summary: "Create a database from a vocabulary export of dict.cc",
+    run: |args| commands::import_export(args).map(|_| 0),
+  },
+  Command {
+    name: "import-corpus",
+    summary: "Import sentence pairs into the corpus of a database",
+    run: |args| commands::import_corpus(args).map(|_| 0),
+  },
+  Command {
+    name: "list-dicts",
+    summary: "List the databases registered in the configuration",
+    run: |args| commands::list_dicts(args).map(|_| 0),
+  },
+  Command {
+    name: "migrate",
+    summary: "Convert the full-text index of a database to FTS5",
+    run: |args| commands::migrate_database(args).map(|_| 0),
+  },
+  Command {
+    name: "optimize",
+    summary: "Add indexes to a database speeding up common lookups",
+    run: |args| commands::optimize_database(args).map(|_| 0),
+  },
+  Command {
+    name: "remove",
+    summary: "Remove an entry of your own added before",
+    run: |args| commands::remove_entry(args).map(|_| 0),
+  },
+  Command {
+    name: "render",
+    summary: "Render results read from a file or from standard input",
+    run: |args| commands::render(args).map(|_| 0),
+  },
+  Command {
+    name: "replay",
+    summary: "Render results saved earlier",
+    run: |args| commands::replay(args).map(|_| 0),
+  },
+  Command {
+    name: "script",
+    summary: "Run the commands listed in a file, one per line",
+    run: run_script,
+  },
+  Command {
+    name: "serve",
+    summary: "Answer lookups over HTTP",
+    run: |args| commands::serve_http(args).map(|_| 0),
+  },
+  Command {
+    name: "spellcheck",
+    summary: "Flag the words of a document not in the dictionary",
+    run: |args| commands::spellcheck(args).map(|_| 0),
+  },
+  Command {
+    name: "stats",
+    summary: "Print statistics about a database",
+    run: |args| commands::print_stats(args).map(|_| 0),
+  },
+  Command {
+    name: "subjects",
+    summary: "List the subject areas along with their number of entries",
+    run: |args| commands::list_subjects(args).map(|_| 0),
+  },
+  Command {
+    name: "translate",
+    summary: "Translate a term (the default command)",
+    run: translate_term,
+  },
+  Command {
+    name: "types",
+    summary: "List the types of entries along with sample terms",
+    run: |args| commands::list_types(args).map(|_| 0),
+  },
+  Command {
+    name: "unhide",
+    summary: "Show an entry hidden before in lookups again",
+    run: |args| commands::unhide_entry(args).map(|_| 0),
+  },
+  Command {
+    name: "update",
+    summary: "Update a database with a newer vocabulary export of dict.cc",
+    run: |args| commands::update_database(args).map(|_| 0),
+  },
+];
+
+/// Find the command with the given name.
+fn find_command(name: &str) -> Option<&'static Command> {
+  COMMANDS.iter().find(|command| command.name == name)
+}
 
-  translate(db, term, &direction, callback)
+/// Describe the available commands, for inclusion in the help.
+fn commands() -> String {
+  let visible = || COMMANDS.iter().filter(|c| !c.is_hidden());
+  let width = visible().map(|c| c.name.len()).max().unwrap_or(0);
+  let commands = visible()
+    .map(|c| format!("    {:width$}  {}\n", c.name, c.summary, width = width))
+    .collect::<String>();
+  format!("Commands:\n{}", commands)
+}
+
+fn run_() -> CommandResult<i32> {
+  let argv: Vec<String> = env::args().collect();
+  #[cfg(feature = "sqlcipher")]
+  cipher::set(cipher::default_key());
+
+  // Without a command we translate, so that the database can be
+  // provided right away.
+  match argv.get(1).and_then(|name| find_command(name)) {
+    Some(command) => (command.run)(&argv[2..]),
+    None => translate_term(&argv[1..]),
+  }
 }
 
 fn run() -> i32 {
   match run_() {
-    Ok(_) => 0,
+    Ok(code) => code,
+    Err(CommandError::Help(help)) => {
+      print!("{}", help);
+      0
+    },
     Err(e) => {
       eprintln!("{}", e);
       1
@@ -326,237 +2036,228 @@ fn main() {
 mod tests {
   use super::*;
 
-  #[test]
-  fn fail_db_not_found() {
-    let db = path::Path::new("./test/does_not_exist.db");
-    let callback = |_: &str, _: &str, _: &str| {
-      assert!(false);
-      Err(Error::Error("unreachable".to_string()))
-    };
+  use std::rc;
 
-    let err = translate(db, "", &Direction::Lang2ToLang1, callback).unwrap_err();
-    match err {
-      Error::Error(x) => assert_eq!(x, "Database ./test/does_not_exist.db not found"),
-      _ => panic!("Unexpected error: {}", err),
+  #[test]
+  fn command_help() {
+    for command in COMMANDS {
+      match (command.run)(&["--help".to_string()]) {
+        Err(CommandError::Help(help)) => assert!(help.contains("Usage:"), "{}", help),
+        _ => panic!("{} did not print its help", command.name),
+      }
+    }
+    match translate_term(&["-h".to_string()]) {
+      Err(CommandError::Help(help)) => assert!(help.contains("Commands:\n")),
+      _ => panic!("translate did not print the program's help"),
     }
   }
 
   #[test]
-  fn no_results() {
-    let db = path::Path::new("./test/test.db");
-    let callback = |_: &str, _: &str, _: &str| {
-      assert!(false);
-      Err(Error::Error("unreachable".to_string()))
+  fn run_scripts() {
+    let path = env::temp_dir().join(format!("dictcc-cli-script-{}", process::id()));
+    let script = |content: &str| -> CommandResult<i32> {
+      fs::write(&path, content).unwrap();
+      run_script(&[path.to_string_lossy().into_owned()])
     };
 
-    // We attempt translation of a word that has no translations. We
-    // expect no errors.
-    translate(db, "awordthatdoesnotexist", &Direction::Lang2ToLang1, callback).unwrap();
-  }
+    assert_eq!(script("test/test.db -e Zorn\ntypes test/test.db\n").unwrap(), 0);
+    assert_eq!(script("test/test.db -e Zorn\ntest/test.db -e Blitz\n").unwrap(), 1);
+    assert!(script("script other.txt\n").is_err());
+    assert!(script("test/test.db 'Zorn\n").is_err());
+    assert!(script("compact test/test.db\n").is_err());
 
-  fn collect_translations_dir<S>(to_translate: S,
-                                 direction: &Direction)
-                                 -> Vec<(String, String, String)>
-  where
-    S: Into<String>,
-  {
-    let mut found = Vec::new();
-    let db = path::Path::new("./test/test.db");
-    {
-      let callback = |src_term: &str, dst_term: &str, type_: &str| {
-        found.push((src_term.to_string(), type_.to_string(), dst_term.to_string()));
+    // A failing command undoes the changes of the ones before it.
+    let db = env::temp_dir().join(format!("dictcc-cli-script-{}.db", process::id()));
+    let export = env::temp_dir().join(format!("dictcc-cli-script-{}.txt", process::id()));
+    import::create(&db, "Bank {f}\tbank\tnoun\t\n").unwrap();
+    fs::write(&export, "Bank {f}\tbank\tnoun\t\nHaus {n}\thouse\tnoun\t\n").unwrap();
+    let found = || {
+      let mut found = 0;
+      translate_with(&db, &["house"], &Direction::Lang2ToLang1, &Options::default(), |_| {
+        found += 1;
         Ok(())
-      };
+      }).unwrap();
+      found
+    };
+    let content = format!("update {0} {1}\nupdate {0} {1}.missing\n", db.display(), export.display());
+    assert!(script(&content).is_err());
+    assert_eq!(found(), 0);
+    assert_eq!(script(&format!("update {} {}\n", db.display(), export.display())).unwrap(), 0);
+    assert_eq!(found(), 1);
 
-      translate(db, to_translate, direction, callback).unwrap();
-    }
-    found
-  }
+    // Lookups see the changes of the commands before them, as do
+    // lookups of the user's entries.
+    fs::remove_file(&db).unwrap();
+    import::create(&db, "Bank {f}\tbank\tnoun\t\n").unwrap();
+    let user = env::temp_dir().join(format!("dictcc-cli-script-{}-user.db", process::id()));
+    env::set_var(overlay::ENV_VAR, &user);
+    let content = format!(
+      "update {0} {1}\ntranslate -e -r {0} house\nadd Wut fury\ntranslate -e -r {0} fury\n",
+      db.display(), export.display(),
+    );
+    assert_eq!(script(&content).unwrap(), 0);
+    // Changes after the last lookup are still undone by a failure.
+    let content = format!("translate -r {0} house\nremove Wut fury\nupdate {0} {1}.missing\n",
+                          db.display(), export.display());
+    assert!(script(&content).is_err());
+    let mut found = Vec::new();
+    translate_with(&db, &["fury"], &Direction::Lang2ToLang1, &Options::default(), |entry| {
+      found.push(entry.target.text.clone());
+      Ok(())
+    }).unwrap();
+    assert_eq!(found, vec!["Wut"]);
+    env::remove_var(overlay::ENV_VAR);
+    overlay::set(None);
+    fs::remove_file(&user).unwrap();
+    fs::remove_file(&db).unwrap();
+    fs::remove_file(&export).unwrap();
+    fs::remove_file(&path).unwrap();
 
-  fn collect_translations<S>(to_translate: S) -> Vec<(String, String, String)>
-  where
-    S: Into<String>,
-  {
-    collect_translations_dir(to_translate, &Direction::Lang2ToLang1)
+    // Connections are only shared while running a script.
+    let db = path::Path::new("./test/test.db");
+    assert!(!rc::Rc::ptr_eq(&open(db).unwrap(), &open(db).unwrap()));
   }
 
   #[test]
-  fn inject_malicious_sql() {
-    // By injecting a condition that is always true we would effectively
-    // dump the entire table's contents, if the code were prone to SQL
-    // injection.
-    let code = format!("' OR 1=1 OR {src}='", src = TERM2_COL);
-    let found = collect_translations(code);
-    assert_eq!(found, vec![]);
-  }
+  fn tuning_overrides() {
+    let tuning = |args: &[&str], settings: &[&str]| -> Result<tuning::Tuning> {
+      let matches = translate_options().parse(args).unwrap();
+      let mut config = config::parse("").unwrap();
+      for setting in settings {
+        config.set_from(setting, config::Origin::Cli).unwrap();
+      }
+      connection_tuning(&matches, &config)
+    };
 
-  #[test]
-  fn translate_nauseating() {
-    let found = collect_translations("nauseating");
-    assert_eq!(
-      found,
-      vec![
-        ("nauseating".to_string(), "adj".to_string(), "ekelerregend".to_string()),
-        ("nauseating".to_string(), "adj".to_string(), "widerlich".to_string()),
-      ]
-    );
+    assert_eq!(tuning(&[], &[]).unwrap(), tuning::Tuning::default());
+    let tuned = tuning(&["--mmap-size", "0"], &["sqlite.mmap-size=4096", "sqlite.cache-size=1024"]).unwrap();
+    assert_eq!(tuned.mmap_size, 0);
+    assert_eq!(tuned.cache_size, 1024);
+    let tuned = tuning(&[], &["sqlite.temp-store=\"file\""]).unwrap();
+    assert_eq!(tuned.temp_store, tuning::TempStore::File);
+    assert!(tuning(&["--in-memory"], &[]).unwrap().in_memory);
+    assert!(tuning(&[], &["sqlite.in-memory=true"]).unwrap().in_memory);
+    assert!(tuning(&["--cache-size", "-1"], &[]).is_err());
+    assert!(tuning(&[], &["sqlite.mmap-size=1.5"]).is_err());
+    assert!(tuning(&[], &["sqlite.temp-store=\"disk\""]).is_err());
   }
 
   #[test]
-  fn translate_surefire() {
-    let found = collect_translations("surefire");
-    assert_eq!(
-      found,
-      vec![
-        ("surefire [coll.]".to_string(), "adj".to_string(), "todsicher [ugs.]".to_string()),
-      ]
-    );
+  fn cached_results() {
+    let results = results::Results {
+      query: "Zorn".to_string(),
+      entries: vec![results::Match {
+        direction: None,
+        word: None,
+        entry: Entry::new("Zorn {m}", "anger", "noun", 30),
+        related: false,
+        band: None,
+        inflections: vec!["Zorn".to_string(), "Zorns".to_string()],
+      }],
+    };
+    let cached = to_cached(&results, 2).to_string();
+    assert!(!cached.contains(['\t', '\n']));
+    assert_eq!(from_cached(&cached), Some((results.entries, 2)));
+    assert_eq!(from_cached("{}"), None);
+    assert_eq!(from_cached("[1"), None);
   }
 
   #[test]
-  fn translate_dorky() {
-    let found = collect_translations("dorky");
-    assert_eq!(
-      found,
-      vec![
-        ("dorky [coll.]".to_string(), "adj".to_string(), "bekloppt [ugs.]".to_string()),
-        ("dorky [coll.]".to_string(), "adj".to_string(), "idiotisch".to_string()),
-        ("dorky [coll.]".to_string(), "adj".to_string(), "deppert [österr.] [südd.]".to_string()),
-      ]
-    );
+  fn omitted_database() {
+    let free = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+    assert!(lacks_database(&free(&["Zorn"])));
+    assert!(lacks_database(&free(&["to", "subjugate"])));
+    assert!(lacks_database(&free(&["./test/test.db"])));
+    assert!(!lacks_database(&free(&["./test/test.db", "Zorn"])));
+    assert!(!lacks_database(&free(&["dictcc-fr.db", "amour"])));
+    assert!(!lacks_database(&[]));
   }
 
   #[test]
-  fn translate_subjugate() {
-    let found = collect_translations("subjugate");
+  fn batch_arguments_term() {
+    let argv = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
     assert_eq!(
-      found,
-      vec![
-        ("to subjugate".to_string(), "verb".to_string(), "unterwerfen".to_string()),
-        ("to subjugate".to_string(), "verb".to_string(), "bezwingen".to_string()),
-        ("to subjugate".to_string(), "verb".to_string(), "unterjochen".to_string()),
-        (
-          "to subjugate sb./sth.".to_string(),
-          "verb".to_string(),
-          "jdn./etw. knechten [geh.] [pej.] [unterwerfen]".to_string()
-        ),
-      ]
+      batch_arguments(&argv(&["--stdin", "-r", "test.db"]), "-x"),
+      argv(&["-r", "test.db", "--", "-x"]),
     );
-  }
-
-  #[test]
-  fn translate_love() {
-    let found = collect_translations("love");
     assert_eq!(
-      found,
-      vec![
-        ("love".to_string(), "noun".to_string(), "Liebe {f}".to_string()),
-        ("love".to_string(), "unknown".to_string(), "null [beim Tennis]".to_string()),
-      ]
+      batch_arguments(&argv(&["test.db", "-"]), "Zorn"),
+      argv(&["test.db", "--", "Zorn"]),
     );
-  }
-
-  #[test]
-  fn translate_christmas() {
-    let found = collect_translations("christmas");
     assert_eq!(
-      found,
-      vec![
-        ("Christmas".to_string(), "noun".to_string(), "Weihnachten {n}".to_string()),
-      ]
+      batch_arguments(&argv(&["--stdin", "--", "-.db"]), "Zorn"),
+      argv(&["--", "-.db", "Zorn"]),
     );
-  }
-
-  #[test]
-  fn translate_wherewithals() {
-    let found = collect_translations("wherewithals");
     assert_eq!(
-      found,
-      vec![
-        ("wherewithals {pl}".to_string(), "noun".to_string(), "Nötiges {n}".to_string()),
-      ]
+      batch_arguments(&argv(&["--from-file", "words.txt", "-b", "--jobs", "4", "--jobs=2"]), "Zorn"),
+      argv(&["-b", "--", "Zorn"]),
     );
   }
 
   #[test]
-  fn translate_statistics() {
-    let found = collect_translations("statistics");
+  fn split_options_and_words() {
+    let argv = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
     assert_eq!(
-      found,
-      vec![
-        (
-          "statistics {pl} [science that collects and interprets numerical data] [treated as sg.] \
-           <stats>"
-          .to_string(),
-          "noun".to_string(),
-          "Statistik {f}".to_string()
-        ),
-        ("statistics".to_string(), "noun".to_string(), "Statistiken {pl}".to_string()),
-      ]
+      split_arguments(&argv(&["test.db", "cat", "--subject", "sport", "-rb", "dog", "--each"])),
+      (argv(&["--subject", "sport", "-rb", "--each"]), argv(&["test.db", "cat", "dog"])),
     );
-  }
-
-  #[test]
-  fn translate_contents() {
-    let found = collect_translations("contents");
     assert_eq!(
-      found,
-      vec![
-        ("contents {pl} <cont.>".to_string(), "noun".to_string(), "Inhalt {m} <Inh.>".to_string()),
-      ]
+      split_arguments(&argv(&["--set=a=b", "--jobs", "2", "-", "--", "-x"])),
+      (argv(&["--set=a=b", "--jobs", "2"]), argv(&["-", "-x"])),
     );
   }
 
   #[test]
-  fn translate_sulfur() {
-    let found = collect_translations("sulfur");
-    assert_eq!(
-      found,
-      vec![
-        ("sulfur <S> [Am.]".to_string(), "noun".to_string(), "Schwefel {m} <S>".to_string()),
-      ]
-    );
+  fn parallel_lookups() {
+    let argv = vec!["./test/test.db".to_string()];
+    let terms = ["Zorn", "Blitz", "Wut", "Ärger", "Zorn"]
+      .iter()
+      .map(|t| t.to_string())
+      .collect::<Vec<_>>();
+    let mut reported = Vec::new();
+    look_up_parallel(&argv, &terms, 3, |term, out, result| {
+      reported.push((term.to_string(), out, result.unwrap()))
+    });
+
+    let order = reported.iter().map(|(term, _, _)| term.as_str()).collect::<Vec<_>>();
+    assert_eq!(order, vec!["Zorn", "Blitz", "Wut", "Ärger", "Zorn"]);
+    assert_eq!(reported[0].1, "Zorn {m} (noun): anger\n");
+    assert_eq!(reported[1].2, 0);
+    assert!(reported[3].2 > 0);
+    assert_eq!(reported[4].1, reported[0].1);
   }
 
   #[test]
-  fn translate_poor() {
-    let found = collect_translations("poor");
-    assert_eq!(
-      found,
-      vec![
-        (
-          "the poor {pl}".to_string(),
-          "noun".to_string(),
-          "Arme {pl} [arme Leute als Klasse]".to_string()
-        ),
-      ]
-    );
+  fn both_directions() {
+    let look_up = |args: &[&str]| {
+      let argv = args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+      let mut out = Output::Collect(String::new());
+      let found = look_up(&argv, &mut out).unwrap();
+      (found, out.into_collected())
+    };
+
+    let (found, out) = look_up(&["--both", "--no-cache", "--format", "tsv", "./test/test.db", "Zorn OR anger"]);
+    assert_eq!(found, 6);
+    let lines = out.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "1→2\tZorn {m}\tnoun\tanger");
+    assert_eq!(lines[1], "2→1\tanger\tnoun\tZorn {m}");
+    assert!(lines[2..].iter().all(|line| line.starts_with("2→1\tanger\t")));
+
+    let (found, out) = look_up(&["--both", "--no-cache", "./test/test.db", "Zorn"]);
+    assert_eq!(found, 1);
+    assert_eq!(out, "1→2 Zorn {m} (noun): anger\n");
   }
 
   #[test]
-  fn translate_inhalt() {
-    let found = collect_translations_dir("inhalt", &Direction::Lang1ToLang2);
+  fn all_dicts_arguments() {
+    let argv = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
     assert_eq!(
-      found,
-      vec![
-        ("Inhalt {m} <Inh.>".to_string(), "noun".to_string(), "contents {pl} <cont.>".to_string()),
-      ]
+      dict_arguments(&argv(&["-r", "--all-dicts", "dorky"]), "en"),
+      argv(&["--dict", "en", "-r", "dorky"]),
     );
-  }
-
-  #[test]
-  fn translate_aerger() {
-    // Note that a failure of this test case may be caused by a sqlite
-    // library without ICU support being used. Such a library does not
-    // treat Unicode characters in a case-insensitive manner, causing
-    // fewer matches to be found.
-    let found = collect_translations_dir("Ärger", &Direction::Lang1ToLang2);
     assert_eq!(
-      found,
-      vec![
-        ("ärger".to_string(), "adj adv".to_string(), "worse".to_string()),
-        ("Ärger {m}".to_string(), "noun".to_string(), "anger".to_string()),
-      ]
+      dict_arguments(&argv(&["--all-dicts", "--", "--all-dicts"]), "en"),
+      argv(&["--dict", "en", "--", "--all-dicts"]),
     );
   }
 }
@@ -0,0 +1,316 @@
+// memory.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+
+//! An in-memory index of dictionary entries.
+//!
+//! The [`Index`] backend holds all entries in memory and matches them
+//! without involving SQLite, following the same rules the database
+//! applies. That makes lookups independent of the disk once loaded and
+//! allows for serving entries that do not stem from a database at all.
+
+use std::cmp::Ordering;
+
+use backend::Backend;
+use like::like_cased;
+use like::unaccent;
+use pattern;
+use pattern::ESCAPE;
+use rank::score;
+#[cfg(feature = "sqlite")]
+use read_row;
+#[cfg(feature = "sqlite")]
+use select_clause;
+use term::comments_contain;
+use term::contains_phrase;
+use term::headword;
+#[cfg(feature = "sqlite")]
+use Database;
+use Direction;
+use Entry;
+use Error;
+use Options;
+use Result;
+
+
+/// Check whether a text matches a LIKE pattern as generated by the
+/// `pattern` module.
+fn matches(pattern: &str, text: &str, options: &Options) -> bool {
+  like_cased(pattern, text, Some(ESCAPE), options.case_sensitive)
+}
+
+/// The patterns matching a term, generated once per lookup rather than
+/// for each entry.
+#[derive(Default)]
+struct Patterns {
+  /// The patterns matching the term itself, possibly annotated.
+  exact: Vec<String>,
+  /// The patterns matching the term as a verb.
+  verb: Vec<String>,
+  /// The patterns matching the term as part of a phrase.
+  in_phrase: Vec<String>,
+}
+
+impl Patterns {
+  /// Generate the patterns needed for matching a term as dictated by
+  /// `options`.
+  fn new(term: &str, options: &Options) -> Patterns {
+    // Phrases and sets of words are not matched by means of patterns.
+    if options.phrase || options.all_words {
+      return Patterns::default()
+    }
+    Patterns {
+      exact: pattern::patterns(term),
+      verb: pattern::verb(term),
+      in_phrase: if options.exact {
+        Vec::new()
+      } else {
+        pattern::in_phrase(term)
+      },
+    }
+  }
+}
+
+/// Check whether a source term of an entry with the given type matches
+/// `term`, with the given patterns, mirroring the conditions built by
+/// `term_query`.
+fn matches_term(source: &str, type_: &str, term: &str, patterns: &Patterns,
+                options: &Options) -> bool {
+  if options.phrase {
+    return contains_phrase(source, term, options.case_sensitive)
+      || options.include_comments && comments_contain(source, term);
+  }
+
+  if options.all_words {
+    let text = if options.include_comments {
+      source.to_string()
+    } else {
+      headword(source)
+    };
+    let mut words = term.split_whitespace().peekable();
+    return words.peek().is_some()
+      && words.all(|word| matches(&pattern::containing(word), &text, options));
+  }
+
+  let exact = patterns.exact.iter().any(|p| matches(p, source, options))
+    || type_ == "verb" && patterns.verb.iter().any(|p| matches(p, source, options));
+  if exact || options.exact {
+    return exact;
+  }
+
+  let headword = headword(source);
+  patterns.in_phrase.iter().any(|p| matches(p, &headword, options))
+    || options.include_comments && comments_contain(source, term)
+}
+
+
+/// An index of dictionary entries held in memory.
+#[derive(Clone, Debug, Default)]
+pub struct Index {
+  /// The entries, translating from language 1 to language 2.
+  entries: Vec<Entry>,
+}
+
+impl Index {
+  /// Create an index of the given entries, each translating from
+  /// language 1 to language 2.
+  pub fn new(entries: Vec<Entry>) -> Index {
+    Index { entries }
+  }
+
+  /// Load all entries of a database into memory.
+  #[cfg(feature = "sqlite")]
+  pub fn load(database: &Database) -> Result<Index> {
+    let query = select_clause(&Direction::Lang1ToLang2);
+    let mut entries = Vec::new();
+    database.execute(&query, &[], &Options::default(), |row| {
+      entries.push(read_row(row)?);
+      Ok(())
+    })?;
+    Ok(Index::new(entries))
+  }
+
+  /// Retrieve the number of entries in the index.
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  /// Check whether the index is empty.
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+}
+
+impl Backend for Index {
+  fn lookup(&self, terms: &[String], direction: &Direction, options: &Options,
+            limit: Option<usize>, callback: &mut dyn FnMut(&Entry) -> Result<()>) -> Result<()> {
+    if !options.subjects.is_empty() {
+      return Err(Error::Error("Subjects are not supported by the in-memory index".to_string()));
+    }
+
+    let accents = |string: &str| if options.ignore_accents {
+      unaccent(string)
+    } else {
+      string.to_string()
+    };
+    let terms = terms.iter().map(|t| accents(t)).collect::<Vec<_>>();
+    let patterns = terms.iter().map(|t| Patterns::new(t, options)).collect::<Vec<_>>();
+
+    let mut found = Vec::new();
+    for entry in &self.entries {
+      let entry = match *direction {
+        Direction::Lang1ToLang2 => entry.clone(),
+        Direction::Lang2ToLang1 => Entry {
+          source: entry.target.clone(),
+          target: entry.source.clone(),
+          types: entry.types.clone(),
+          usage: entry.usage,
+          user: entry.user,
+          id: entry.id,
+        },
+      };
+      if !options.types.is_empty() && !entry.types.iter().any(|t| options.types.contains(t)) {
+        continue;
+      }
+
+      let source = accents(&entry.source.text);
+      let type_ = entry.type_name();
+      let mut terms = terms.iter().zip(&patterns);
+      if terms.any(|(term, patterns)| matches_term(&source, &type_, term, patterns, options)) {
+        found.push(entry);
+      }
+    }
+
+    // Entries are ordered the way the database orders them.
+    let position = |entry: &Entry| {
+      let type_ = entry.type_name();
+      options.type_order.iter().position(|t| *t == type_).unwrap_or(options.type_order.len())
+    };
+    found.sort_by(|entry1, entry2| {
+      let order = match options.rank {
+        Some(ref query) => {
+          let score1 = score(&entry1.source.text, query, entry1.usage, &options.weights);
+          let score2 = score(&entry2.source.text, query, entry2.usage, &options.weights);
+          score2.partial_cmp(&score1).unwrap_or(Ordering::Equal)
+        },
+        None => position(entry1)
+          .cmp(&position(entry2))
+          .then_with(|| entry1.type_name().cmp(&entry2.type_name())),
+      };
+      order
+        .then_with(|| entry2.usage.cmp(&entry1.usage))
+        .then_with(|| options.collation.compare(&entry1.source.text, &entry2.source.text))
+    });
+
+    for entry in found.iter().take(options.cap(limit).unwrap_or(usize::MAX)) {
+      callback(entry)?;
+    }
+    Ok(())
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[cfg(feature = "sqlite")]
+  use std::path;
+
+  #[cfg(feature = "sqlite")]
+  use entry::Type;
+  use Query;
+
+  fn collect<B>(query: &Query, backend: &B) -> Vec<Entry>
+  where
+    B: Backend,
+  {
+    let mut found = Vec::new();
+    query
+      .run_on(backend, |entry| {
+        found.push(entry.clone());
+        Ok(())
+      })
+      .unwrap();
+    found
+  }
+
+  /// Check that an index can be built from entries read elsewhere,
+  /// e.g., by a JavaScript host.
+  #[test]
+  fn match_entries() {
+    let index = Index::new(vec![
+      Entry::new("Ärger {m}", "anger", "noun", 40),
+      Entry::new("ärgerlich", "annoying", "adj", 20),
+      Entry::new("Zorn {m}", "anger", "noun", 30),
+    ]);
+    assert_eq!(index.len(), 3);
+
+    let query = Query::new("anger").direction(Direction::Lang2ToLang1);
+    let found = collect(&query, &index);
+    let found = found.iter().map(|e| e.target.text.as_str()).collect::<Vec<_>>();
+    assert_eq!(found, vec!["Ärger {m}", "Zorn {m}"]);
+
+    let query = Query::new("Arger").ignore_accents(true);
+    let found = collect(&query, &index);
+    let found = found.iter().map(|e| e.target.text.as_str()).collect::<Vec<_>>();
+    assert_eq!(found, vec!["anger"]);
+  }
+
+  /// Check that the index reports the very entries the database does.
+  #[test]
+  #[cfg(feature = "sqlite")]
+  fn match_like_database() {
+    let database = Database::open(path::Path::new("./test/test.db")).unwrap();
+    let index = Index::load(&database).unwrap();
+    assert_eq!(index.len(), 29);
+
+    let queries = vec![
+      Query::new("anger"),
+      Query::new("dorky"),
+      Query::new("subjugate"),
+      Query::new("poor").exact(true),
+      Query::new("statistics").include_comments(true),
+      Query::new("treated as").include_comments(true),
+      Query::new("worse").types([Type::Adverb]),
+      Query::new("the poor").phrase(true),
+      Query::new("numerical data science").all_words(true).include_comments(true),
+      Query::with_terms(&["love", "Christmas"]).limit(2),
+      Query::new("anger").rank("anger"),
+      Query::new("Love").case_sensitive(true),
+    ];
+    for query in queries {
+      for direction in &[Direction::Lang1ToLang2, Direction::Lang2ToLang1] {
+        let query = query.clone().direction(*direction);
+        assert_eq!(collect(&query, &index), collect(&query, &database), "{:?}", query);
+      }
+    }
+
+    let query = Query::new("Arger").direction(Direction::Lang1ToLang2).ignore_accents(true);
+    let found = collect(&query, &index);
+    let found = found.iter().map(|e| e.target.text.as_str()).collect::<Vec<_>>();
+    assert_eq!(found, vec!["worse", "anger"]);
+  }
+
+  #[test]
+  fn unsupported_subjects() {
+    let query = Query::new("anger").subjects(vec![1]);
+    assert!(query.run_on(&Index::default(), |_| Ok(())).is_err());
+  }
+}
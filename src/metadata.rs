@@ -0,0 +1,203 @@
+// metadata.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Metadata describing the origin of a database's entries.
+//!
+//! Databases created from a vocabulary export record in a `metadata`
+//! table when they were imported and last updated, along with the
+//! description and the creation date the export states in its header,
+//! i.e., the comment lines it starts with. The first of them describes
+//! the export (e.g., "DE-EN vocabulary database compiled by dict.cc"),
+//! while the one starting with "Date and time" contains the date.
+//!
+//! Dates are stored in the form SQLite's date functions understand,
+//! which we use for all calculations on them. The databases of the
+//! dict.cc app lack the table.
+
+use sqlite;
+
+use check::table_sql;
+use Result;
+
+/// The name of the table containing the metadata.
+const METADATA_TBL: &str = "metadata";
+/// The key of the date the database was created at (in UTC).
+pub const IMPORTED: &str = "imported";
+/// The key of the date the database was last updated at (in UTC).
+pub const UPDATED: &str = "updated";
+/// The key of the description of the export.
+pub const SOURCE: &str = "source";
+/// The key of the date the export was created at.
+pub const VERSION: &str = "version";
+/// All keys, in the order to present them in.
+const KEYS: [&str; 4] = [SOURCE, VERSION, IMPORTED, UPDATED];
+
+
+/// Retrieve a human readable label for a key.
+pub fn label(key: &str) -> &str {
+  match key {
+    SOURCE => "Source",
+    VERSION => "Version",
+    IMPORTED => "Imported",
+    UPDATED => "Updated",
+    _ => key,
+  }
+}
+
+/// Extract the metadata contained in the header of an export, i.e.,
+/// the comment lines it starts with.
+pub fn header(content: &str) -> Vec<(&'static str, String)> {
+  let mut metadata = Vec::new();
+  let comments = content
+    .lines()
+    .take_while(|line| line.starts_with('#'))
+    .map(|line| line.trim_start_matches('#').trim());
+  for (i, comment) in comments.enumerate() {
+    let mut fields = comment.splitn(2, '\t').map(str::trim);
+    let key = fields.next().unwrap_or("");
+    let value = fields.next().unwrap_or("");
+    if i == 0 {
+      // The first line describes the export as a whole.
+      let source = [key, value].iter().filter(|s| !s.is_empty()).cloned().collect::<Vec<_>>();
+      metadata.push((SOURCE, source.join(" ")));
+    } else if key.eq_ignore_ascii_case("Date and time") && !value.is_empty() {
+      metadata.push((VERSION, value.to_string()));
+    }
+  }
+  metadata
+}
+
+/// Record metadata along with the current date under the given key
+/// (`IMPORTED` or `UPDATED`), replacing existing values.
+pub fn record(connection: &sqlite::Connection,
+              metadata: &[(&str, String)],
+              event: &str) -> Result<()> {
+  connection.execute(format!(
+    "CREATE TABLE IF NOT EXISTS {tbl}(key TEXT PRIMARY KEY NOT NULL, value TEXT NOT NULL)",
+    tbl = METADATA_TBL,
+  ))?;
+
+  let insert = format!("INSERT OR REPLACE INTO {} VALUES (?, ?)", METADATA_TBL);
+  let mut statement = connection.prepare(insert)?;
+  for (key, value) in metadata {
+    statement.reset()?;
+    statement.bind(1, *key)?;
+    statement.bind(2, value.as_str())?;
+    statement.next()?;
+  }
+
+  let mut statement = connection.prepare(format!(
+    "INSERT OR REPLACE INTO {} VALUES (?, datetime('now'))",
+    METADATA_TBL,
+  ))?;
+  statement.bind(1, event)?;
+  statement.next()?;
+  Ok(())
+}
+
+/// Load the metadata of a database, in presentation order. Databases
+/// without metadata yield none.
+pub fn load(connection: &sqlite::Connection) -> Result<Vec<(String, String)>> {
+  if table_sql(connection, METADATA_TBL)?.is_none() {
+    return Ok(Vec::new())
+  }
+
+  let select = format!("SELECT key, value FROM {}", METADATA_TBL);
+  let mut cursor = connection.prepare(select)?.cursor();
+  let mut metadata = Vec::new();
+  while let Some(row) = cursor.next()? {
+    if let (Some(key), Some(value)) = (row[0].as_string(), row[1].as_string()) {
+      metadata.push((key.to_string(), value.to_string()));
+    }
+  }
+  let position = |key: &str| KEYS.iter().position(|k| *k == key).unwrap_or(KEYS.len());
+  metadata.sort_by(|(key1, _), (key2, _)| position(key1).cmp(&position(key2)).then(key1.cmp(key2)));
+  Ok(metadata)
+}
+
+/// Determine the age of the entries of a database in days, if known.
+///
+/// The age is based on the date the export was created at, falling
+/// back to the date it was last imported.
+pub fn age(connection: &sqlite::Connection) -> Result<Option<f64>> {
+  if table_sql(connection, METADATA_TBL)?.is_none() {
+    return Ok(None)
+  }
+
+  let select = format!(
+    "SELECT julianday('now') - COALESCE(\
+       (SELECT julianday(value) FROM {tbl} WHERE key = '{version}'), \
+       (SELECT julianday(value) FROM {tbl} WHERE key = '{updated}'), \
+       (SELECT julianday(value) FROM {tbl} WHERE key = '{imported}'))",
+    tbl = METADATA_TBL, version = VERSION, updated = UPDATED, imported = IMPORTED,
+  );
+  let mut cursor = connection.prepare(select)?.cursor();
+  Ok(cursor.next()?.and_then(|row| row[0].as_float()))
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const HEADER: &str = "\
+# DE-EN vocabulary database\tcompiled by dict.cc
+# Date and time\t2024-03-01 01:23
+# License\tPersonal use only
+Zorn {m}\tanger\tnoun\t
+# Not part of the header
+";
+
+
+  #[test]
+  fn parse_header() {
+    assert_eq!(header(HEADER), vec![
+      (SOURCE, "DE-EN vocabulary database compiled by dict.cc".to_string()),
+      (VERSION, "2024-03-01 01:23".to_string()),
+    ]);
+    assert_eq!(header("Zorn {m}\tanger\tnoun\t\n"), vec![]);
+  }
+
+  #[test]
+  fn record_metadata() {
+    let connection = sqlite::open(":memory:").unwrap();
+    assert_eq!(load(&connection).unwrap(), vec![]);
+    assert_eq!(age(&connection).unwrap(), None);
+
+    record(&connection, &[], IMPORTED).unwrap();
+    let metadata = load(&connection).unwrap();
+    assert_eq!(metadata.len(), 1);
+    assert_eq!(metadata[0].0, IMPORTED);
+    assert!(age(&connection).unwrap().unwrap() < 1.0);
+
+    record(&connection, &header(HEADER), UPDATED).unwrap();
+    let keys = load(&connection)
+      .unwrap()
+      .into_iter()
+      .map(|(key, _)| key)
+      .collect::<Vec<_>>();
+    assert_eq!(keys, vec![SOURCE, VERSION, IMPORTED, UPDATED]);
+    // The age is that of the export.
+    assert!(age(&connection).unwrap().unwrap() > 365.0);
+
+    // An unrecognized date is not taken into account.
+    record(&connection, &[(VERSION, "March 2024".to_string())], UPDATED).unwrap();
+    assert!(age(&connection).unwrap().unwrap() < 1.0);
+  }
+}
@@ -0,0 +1,260 @@
+// mt.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Passthrough of sentences to a machine translation service.
+//!
+//! The dict.cc database only covers words and phrases. Sentences are
+//! instead handed to a (configured) online machine translation service.
+//! We use `curl` for the actual HTTP transfer so as to not require a
+//! TLS stack of our own.
+
+use std::env;
+use std::io::Write;
+use std::process;
+
+use json;
+use Error;
+use Result;
+
+/// The environment variable containing the API key of the service.
+pub const KEY_VAR: &str = "DICTCC_MT_KEY";
+
+/// The minimum number of words for a query to be considered a sentence.
+const SENTENCE_MIN_WORDS: usize = 5;
+
+
+/// The supported machine translation services.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Service {
+  /// The DeepL API.
+  DeepL,
+  /// A LibreTranslate instance.
+  LibreTranslate,
+}
+
+impl Service {
+  /// Parse a service from its name.
+  pub fn from_name(name: &str) -> Result<Service> {
+    match name.to_lowercase().as_str() {
+      "deepl" => Ok(Service::DeepL),
+      "libretranslate" => Ok(Service::LibreTranslate),
+      _ => Err(Error::Error(format!("Unsupported translation service: {}", name))),
+    }
+  }
+
+  /// The endpoint used if none was specified explicitly.
+  fn default_url(self) -> &'static str {
+    match self {
+      Service::DeepL => "https://api-free.deepl.com/v2/translate",
+      Service::LibreTranslate => "http://localhost:5000/translate",
+    }
+  }
+}
+
+
+/// The configuration of a machine translation service.
+#[derive(Debug)]
+pub struct Config {
+  /// The service to use.
+  pub service: Service,
+  /// The endpoint to send requests to.
+  pub url: Option<String>,
+  /// The language code of the source language.
+  pub source: String,
+  /// The language code of the target language.
+  pub target: String,
+}
+
+/// Parse a language pair in the form `<lang1>:<lang2>`.
+pub fn parse_languages(languages: &str) -> Result<(String, String)> {
+  let mut parts = languages.splitn(2, ':');
+  match (parts.next(), parts.next()) {
+    (Some(l1), Some(l2)) if !l1.is_empty() && !l2.is_empty() => {
+      Ok((l1.to_string(), l2.to_string()))
+    },
+    _ => Err(Error::Error(format!("Invalid language pair: {}", languages))),
+  }
+}
+
+
+/// Check whether a query looks like a sentence as opposed to a word
+/// or a short phrase.
+pub fn is_sentence(query: &str) -> bool {
+  let words = query.split_whitespace().count();
+  let terminated = query.trim_end().ends_with(['.', '!', '?']);
+
+  words >= SENTENCE_MIN_WORDS || (words >= 2 && terminated)
+}
+
+/// Quote a value for use in a `curl` configuration file.
+fn quote(value: &str) -> String {
+  let mut quoted = String::with_capacity(value.len() + 2);
+  quoted.push('"');
+  for c in value.chars() {
+    match c {
+      '"' => quoted.push_str("\\\""),
+      '\\' => quoted.push_str("\\\\"),
+      '\n' => quoted.push_str("\\n"),
+      '\r' => quoted.push_str("\\r"),
+      '\t' => quoted.push_str("\\t"),
+      c => quoted.push(c),
+    }
+  }
+  quoted.push('"');
+  quoted
+}
+
+/// POST a request to the given URL using `curl`.
+///
+/// The headers and the body are passed as configuration on stdin
+/// instead of as arguments, so that secrets such as API keys do not
+/// show up in the process list.
+fn curl(url: &str, headers: &[String], body: &str) -> Result<String> {
+  let mut config = String::new();
+  for header in headers {
+    config.push_str(&format!("header = {}\n", quote(header)));
+  }
+  config.push_str(&format!("data-raw = {}\n", quote(body)));
+
+  let mut child = process::Command::new("curl")
+    .arg("--silent")
+    .arg("--show-error")
+    .arg("--fail")
+    .arg("--request")
+    .arg("POST")
+    .arg("--config")
+    .arg("-")
+    .arg("--")
+    .arg(url)
+    .stdin(process::Stdio::piped())
+    .stdout(process::Stdio::piped())
+    .stderr(process::Stdio::piped())
+    .spawn()
+    .map_err(|e| Error::Io("Failed to run curl".to_string(), e))?;
+
+  child
+    .stdin
+    .take()
+    .expect("stdin not captured")
+    .write_all(config.as_bytes())
+    .map_err(|e| Error::Io("Failed to send request".to_string(), e))?;
+
+  let output = child
+    .wait_with_output()
+    .map_err(|e| Error::Io("Failed to run curl".to_string(), e))?;
+  if !output.status.success() {
+    let err = String::from_utf8_lossy(&output.stderr);
+    return Err(Error::Error(format!("Translation request failed: {}", err.trim())));
+  }
+  String::from_utf8(output.stdout)
+    .map_err(|_| Error::Error("Translation response is not valid UTF-8".to_string()))
+}
+
+/// Extract the translation from a service's response.
+fn parse_response(service: Service, response: &str) -> Result<String> {
+  let value = json::parse(response)?;
+  let text = match service {
+    Service::DeepL => value
+      .get("translations")
+      .and_then(|t| t.as_array())
+      .and_then(|t| t.first())
+      .and_then(|t| t.get("text")),
+    Service::LibreTranslate => value.get("translatedText"),
+  };
+
+  text
+    .and_then(|t| t.as_str())
+    .map(|t| t.to_string())
+    .ok_or_else(|| Error::Error(format!("Unexpected translation response: {}", response)))
+}
+
+/// Translate the given text using the configured service.
+pub fn translate(config: &Config, text: &str) -> Result<String> {
+  let key = env::var(KEY_VAR).ok();
+  let url = config.url.as_deref().unwrap_or_else(|| config.service.default_url());
+  let mut headers = vec!["Content-Type: application/json".to_string()];
+
+  let body = match config.service {
+    Service::DeepL => {
+      let key = key.ok_or_else(|| Error::Error(format!(
+        "DeepL requires an API key in the {} environment variable", KEY_VAR
+      )))?;
+      headers.push(format!("Authorization: DeepL-Auth-Key {}", key));
+      json::Value::Object(vec![
+        ("text".to_string(), json::Value::Array(vec![text.into()])),
+        ("source_lang".to_string(), config.source.to_uppercase().into()),
+        ("target_lang".to_string(), config.target.to_uppercase().into()),
+      ])
+    },
+    Service::LibreTranslate => {
+      let mut members = vec![
+        ("q".to_string(), text.into()),
+        ("source".to_string(), config.source.to_lowercase().into()),
+        ("target".to_string(), config.target.to_lowercase().into()),
+        ("format".to_string(), "text".into()),
+      ];
+      if let Some(key) = key {
+        members.push(("api_key".to_string(), key.into()));
+      }
+      json::Value::Object(members)
+    },
+  };
+
+  let response = curl(url, &headers, &body.to_string())?;
+  parse_response(config.service, &response)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn sentence_detection() {
+    assert!(!is_sentence("love"));
+    assert!(!is_sentence("to give up"));
+    assert!(!is_sentence("Hallo."));
+    assert!(is_sentence("Wie geht es dir?"));
+    assert!(is_sentence("I would like to order a coffee"));
+  }
+
+  #[test]
+  fn language_pair() {
+    assert_eq!(parse_languages("de:en").unwrap(), ("de".to_string(), "en".to_string()));
+    assert!(parse_languages("de").is_err());
+    assert!(parse_languages(":en").is_err());
+  }
+
+  #[test]
+  fn response_parsing() {
+    let deepl = r#"{"translations":[{"detected_source_language":"DE","text":"How are you?"}]}"#;
+    assert_eq!(parse_response(Service::DeepL, deepl).unwrap(), "How are you?");
+
+    let libre = r#"{"translatedText":"How are you?"}"#;
+    assert_eq!(parse_response(Service::LibreTranslate, libre).unwrap(), "How are you?");
+    assert!(parse_response(Service::LibreTranslate, deepl).is_err());
+  }
+
+  #[test]
+  fn config_quoting() {
+    assert_eq!(quote("Authorization: DeepL-Auth-Key k3y"), r#""Authorization: DeepL-Auth-Key k3y""#);
+    assert_eq!(quote(r#"{"text":["a\"b"]}"#), r#""{\"text\":[\"a\\\"b\"]}""#);
+    assert_eq!(quote("a\nb\tc"), r#""a\nb\tc""#);
+  }
+}
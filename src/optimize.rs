@@ -0,0 +1,365 @@
+// optimize.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Supplementary indexes speeding up common lookups.
+//!
+//! The search table of a dict.cc database is a full-text table, which
+//! is of no help for matching terms as a whole or by prefix, so both
+//! require checking every entry. The `optimize` command adds a plain
+//! table with the headwords of all terms, i.e., the terms stripped of
+//! annotations, along with their case folded version indexed for
+//! equality and prefix search. Lookups of exact matches and the
+//! completion of terms use it when present.
+//!
+//! The table is not updated along with the search table, so it has to
+//! be rebuilt after modifying the latter.
+//!
+//! The `analyze` command gathers the statistics SQLite's query planner
+//! bases its choice of indexes on. Without them, the planner has to
+//! guess how selective an index is.
+//!
+//! The `compact` command rebuilds the database file, getting rid of the
+//! free pages and fragmentation an import or update leaves behind, and
+//! optionally merges the segments of the full-text index.
+
+use sqlite;
+
+use fulltext::FullText;
+use term::headword;
+use transaction;
+use Error;
+use Result;
+use SEARCH_TBL;
+use TERM1_COL;
+use TERM2_COL;
+
+/// The name of the table containing the headwords.
+pub const HEADWORDS_TBL: &str = "headwords";
+/// The name of the index over the case folded headwords.
+const HEADWORDS_IDX: &str = "headwords_key";
+/// The largest character, used as the upper bound of a prefix range.
+const MAX_CHAR: char = '\u{10ffff}';
+
+
+/// Fold the case of a string the way our LIKE implementation does.
+pub fn fold(string: &str) -> String {
+  string.chars().flat_map(char::to_lowercase).collect()
+}
+
+/// Retrieve the number identifying a term column in the headwords
+/// table.
+pub fn column_id(column: &str) -> i64 {
+  if column == TERM1_COL {
+    1
+  } else {
+    debug_assert_eq!(column, TERM2_COL);
+    2
+  }
+}
+
+/// Retrieve the bounds of the range of keys starting with a prefix.
+pub fn prefix_range(prefix: &str) -> (String, String) {
+  (prefix.to_string(), format!("{}{}", prefix, MAX_CHAR))
+}
+
+/// Check whether the database contains the headwords table.
+pub fn exists(connection: &sqlite::Connection) -> Result<bool> {
+  let mut statement = connection.prepare(
+    "SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND name=?"
+  )?;
+  statement.bind(1, HEADWORDS_TBL)?;
+  statement.next()?;
+  Ok(statement.read::<i64>(0)? > 0)
+}
+
+/// Fill the headwords table with the given connection, which is
+/// expected to be inside of a transaction.
+pub fn fill(connection: &sqlite::Connection) -> Result<usize> {
+  connection.execute(format!(
+    "DROP TABLE IF EXISTS {tbl};\
+     CREATE TABLE {tbl} (id INTEGER NOT NULL, col INTEGER NOT NULL, \
+                         headword TEXT NOT NULL, key TEXT NOT NULL);",
+    tbl = HEADWORDS_TBL,
+  ))?;
+
+  let mut select = connection
+    .prepare(format!(
+      "SELECT rowid, {term1}, {term2} FROM {tbl}",
+      term1 = TERM1_COL, term2 = TERM2_COL, tbl = SEARCH_TBL,
+    ))?
+    .cursor();
+  let mut insert = connection.prepare(format!(
+    "INSERT INTO {tbl} (id, col, headword, key) VALUES (?, ?, ?, ?)",
+    tbl = HEADWORDS_TBL,
+  ))?;
+
+  let mut count = 0;
+  while let Some(row) = select.next()? {
+    let id = row[0].as_integer().ok_or_else(|| Error::InvalidSchema(format!(
+      "Invalid row id in result: {:?}",
+      row
+    )))?;
+    for (index, column) in [TERM1_COL, TERM2_COL].iter().enumerate() {
+      let term = match row[index + 1].as_string() {
+        Some(term) => term,
+        None => continue,
+      };
+      let headword = headword(term);
+      insert.reset()?;
+      insert.bind(1, id)?;
+      insert.bind(2, column_id(column))?;
+      insert.bind(3, headword.as_str())?;
+      insert.bind(4, fold(&headword).as_str())?;
+      insert.next()?;
+      count += 1;
+    }
+  }
+
+  connection.execute(format!(
+    "CREATE INDEX {idx} ON {tbl} (col, key); ANALYZE {tbl};",
+    idx = HEADWORDS_IDX, tbl = HEADWORDS_TBL,
+  ))?;
+  Ok(count)
+}
+
+/// Build (or rebuild) the headwords table, returning the number of
+/// headwords indexed.
+pub fn build(connection: &sqlite::Connection) -> Result<usize> {
+  transaction(connection, || fill(connection))
+}
+
+/// Check whether the database contains statistics for the query
+/// planner.
+pub fn has_statistics(connection: &sqlite::Connection) -> Result<bool> {
+  let mut statement = connection.prepare(
+    "SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND name='sqlite_stat1'"
+  )?;
+  statement.next()?;
+  if statement.read::<i64>(0)? == 0 {
+    return Ok(false)
+  }
+
+  let mut statement = connection.prepare("SELECT COUNT(*) FROM sqlite_stat1")?;
+  statement.next()?;
+  Ok(statement.read::<i64>(0)? > 0)
+}
+
+/// Gather statistics on all tables and indexes for the query planner,
+/// returning the number of those covered.
+pub fn analyze(connection: &sqlite::Connection) -> Result<usize> {
+  connection.execute("ANALYZE; PRAGMA optimize;")?;
+  let mut statement = connection.prepare("SELECT COUNT(*) FROM sqlite_stat1")?;
+  statement.next()?;
+  Ok(statement.read::<i64>(0)? as usize)
+}
+
+/// Retrieve the size of the database in bytes.
+fn size(connection: &sqlite::Connection) -> Result<u64> {
+  let mut statement = connection.prepare(
+    "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+  )?;
+  statement.next()?;
+  Ok(statement.read::<i64>(0)? as u64)
+}
+
+/// Compact the database, merging the segments of the full-text index
+/// into one beforehand if `index` is set, returning the size of the
+/// database before and after in bytes.
+pub fn compact(connection: &sqlite::Connection, index: bool) -> Result<(u64, u64)> {
+  let before = size(connection)?;
+  if index {
+    if FullText::detect(connection)?.is_none() {
+      return Err(Error::Error("Database lacks a full-text index to optimize".to_string()))
+    }
+    connection.execute(format!(
+      "INSERT INTO {tbl}({tbl}) VALUES('optimize')",
+      tbl = SEARCH_TBL,
+    ))?;
+  }
+  // VACUUM rebuilds the database from scratch, which requires up to
+  // twice its size on disk.
+  connection.execute("VACUUM")?;
+  Ok((before, size(connection)?))
+}
+
+/// Remove the headwords table, if present.
+pub fn remove(connection: &sqlite::Connection) -> Result<()> {
+  connection.execute(format!("DROP TABLE IF EXISTS {}", HEADWORDS_TBL))?;
+  Ok(())
+}
+
+/// Retrieve up to `count` distinct headwords starting with a prefix
+/// (ignoring case), sorted alphabetically.
+pub fn complete(connection: &sqlite::Connection,
+                column: &str,
+                prefix: &str,
+                count: usize) -> Result<Vec<String>> {
+  let select = format!(
+    "SELECT DISTINCT headword FROM {tbl} WHERE col = ? AND key BETWEEN ? AND ? LIMIT ?",
+    tbl = HEADWORDS_TBL,
+  );
+  let (lower, upper) = prefix_range(&fold(prefix));
+  let mut cursor = connection.prepare(select)?.cursor();
+  cursor.bind(&[
+    sqlite::Value::Integer(column_id(column)),
+    sqlite::Value::String(lower),
+    sqlite::Value::String(upper),
+    sqlite::Value::Integer(count as i64),
+  ])?;
+
+  let mut headwords = Vec::new();
+  while let Some(row) = cursor.next()? {
+    let headword = row[0].as_string().ok_or_else(|| Error::InvalidSchema(format!(
+      "Invalid column in result: {:?}",
+      row
+    )))?;
+    headwords.push(headword.to_string());
+  }
+  headwords.sort();
+  Ok(headwords)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::env;
+  use std::fs;
+  use std::path;
+  use std::process;
+
+  use database;
+  use explain::explain;
+  use open_writable;
+  use suggest;
+  use translate_with;
+  use Direction;
+  use Options;
+
+
+  /// Copy the test database to a temporary location and build the
+  /// headwords table in it.
+  fn optimized_db(name: &str) -> path::PathBuf {
+    let db = env::temp_dir().join(format!("dictcc-cli-{}-{}.db", name, process::id()));
+    fs::copy("./test/test.db", &db).unwrap();
+    let connection = open_writable(&db).unwrap();
+    assert!(!exists(&connection).unwrap());
+    assert_eq!(build(&connection).unwrap(), 58);
+    assert!(exists(&connection).unwrap());
+    db
+  }
+
+  #[test]
+  fn gather_statistics() {
+    let db = optimized_db("analyze");
+    let connection = open_writable(&db).unwrap();
+    // Building the headwords table gathers statistics on it.
+    assert!(has_statistics(&connection).unwrap());
+    assert_eq!(analyze(&connection).unwrap(), 4);
+    assert!(has_statistics(&connection).unwrap());
+
+    // Plans making use of an index point out missing statistics.
+    let query = "SELECT id FROM headwords WHERE col = 1 AND key = 'zorn'";
+    assert!(!explain(&connection, query, &[]).unwrap().contains("Note:"));
+    connection.execute("DELETE FROM sqlite_stat1").unwrap();
+    assert!(!has_statistics(&connection).unwrap());
+    assert!(explain(&connection, query, &[]).unwrap().contains("Note:"));
+    fs::remove_file(&db).unwrap();
+
+    let connection = open_writable(path::Path::new("./test/test.db")).unwrap();
+    assert!(!has_statistics(&connection).unwrap());
+  }
+
+  #[test]
+  fn compact_database() {
+    let db = optimized_db("compact");
+    let connection = open_writable(&db).unwrap();
+    // Dropping a table leaves its pages behind.
+    remove(&connection).unwrap();
+    let (before, after) = compact(&connection, true).unwrap();
+    assert!(after < before, "{} >= {}", after, before);
+    assert_eq!(compact(&connection, false).unwrap(), (after, after));
+    drop(connection);
+
+    let mut found = Vec::new();
+    translate_with(&db, &["Zorn"], &Direction::Lang1ToLang2, &Options::default(), |entry| {
+      found.push(entry.target.text.clone());
+      Ok(())
+    })
+    .unwrap();
+    assert_eq!(found, vec!["anger"]);
+    fs::remove_file(&db).unwrap();
+  }
+
+  #[test]
+  fn fold_case() {
+    assert_eq!(fold("Ärger"), "ärger");
+    assert_eq!(fold("to give UP"), "to give up");
+  }
+
+  #[test]
+  fn complete_headwords() {
+    let db = optimized_db("complete");
+    let connection = open_writable(&db).unwrap();
+    let optimized = complete(&connection, TERM1_COL, "ä", 10).unwrap();
+    remove(&connection).unwrap();
+    assert!(!exists(&connection).unwrap());
+    let plain = suggest::complete(&connection, TERM1_COL, "ä", 10).unwrap();
+    assert!(!optimized.is_empty());
+    assert_eq!(optimized, plain);
+    fs::remove_file(&db).unwrap();
+  }
+
+  #[test]
+  fn exact_lookups() {
+    let db = optimized_db("exact");
+    let options = Options {
+      exact: true,
+      ..Default::default()
+    };
+    let lookup = |db: &path::Path, term: &str, direction: Direction| {
+      let mut found = Vec::new();
+      translate_with(db, &[term], &direction, &options, |e| {
+        found.push(e.clone());
+        Ok(())
+      }).unwrap();
+      found
+    };
+
+    let terms = [
+      ("Zorn", Direction::Lang1ToLang2),
+      ("zorn", Direction::Lang1ToLang2),
+      ("ärger", Direction::Lang1ToLang2),
+      ("Statistik", Direction::Lang1ToLang2),
+      ("Stein", Direction::Lang1ToLang2),
+      ("subjugate", Direction::Lang2ToLang1),
+      ("dorky", Direction::Lang2ToLang1),
+      ("the poor", Direction::Lang2ToLang1),
+    ];
+    for &(term, direction) in &terms {
+      let optimized = lookup(&db, term, direction);
+      let plain = lookup(path::Path::new("./test/test.db"), term, direction);
+      assert_eq!(optimized, plain, "{}", term);
+    }
+    assert!(database(&db).unwrap().headwords());
+    assert_eq!(lookup(&db, "subjugate", Direction::Lang2ToLang1).len(), 4);
+    fs::remove_file(&db).unwrap();
+  }
+}
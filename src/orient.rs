@@ -0,0 +1,59 @@
+// orient.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Detection of the orientation of a database.
+//!
+//! Databases extracted from different versions of the dict.cc app do
+//! not agree on which column holds the German terms. We sample the
+//! entries of a database to find out the first time it is used and
+//! cache the result in `$XDG_CACHE_HOME/dictcc-cli/orientation`.
+
+use std::path;
+
+use cache;
+use open;
+use spell;
+use Result;
+use TERM1_COL;
+use TERM2_COL;
+
+/// The name of the file caching database orientations.
+const CACHE_FILE: &str = "orientation";
+
+
+/// Determine which of the two term columns of a database contains the
+/// German terms, consulting the cache first.
+pub fn german_column(db: &path::Path) -> Result<&'static str> {
+  let compute = || spell::german_column(&*open(db)?);
+  let parse = |column: &str| [TERM1_COL, TERM2_COL].iter().find(|c| **c == column).cloned();
+  cache::cached(CACHE_FILE, db, compute, parse)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn detect_orientation() {
+    let db = path::Path::new("./test/test.db");
+    assert_eq!(german_column(db).unwrap(), TERM1_COL);
+    assert!(german_column(path::Path::new("./test/does_not_exist.db")).is_err());
+  }
+}
@@ -0,0 +1,483 @@
+// overlay.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Personal entries of the user, kept apart from the dictionary.
+//!
+//! Entries added by the user, e.g., own translations or corrections,
+//! are stored in a database of their own, so that the one of dict.cc is
+//! never modified. It resides in `$XDG_DATA_HOME/dictcc-cli/user.db`
+//! (defaulting to `~/.local/share/dictcc-cli/user.db`) unless the
+//! `DICTCC_USER_DB` environment variable names another one.
+//!
+//! When a database is opened for lookups, the user database (if set and
+//! existing) is attached to the connection and lookups select the
+//! entries matching in it as well. Its table has the columns of the
+//! search table the conditions of lookups refer to. The ids of user
+//! entries are reported negated, which tells them apart from the
+//! entries of the dictionary.
+//!
+//! The user database also records the entries the user never wants to
+//! see, by id or by term, which lookups filter out.
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path;
+use std::rc;
+use std::sync;
+use std::time;
+
+use sqlite;
+
+use normalize;
+use transactional;
+use Error;
+use Result;
+
+/// The environment variable naming the user database.
+pub const ENV_VAR: &str = "DICTCC_USER_DB";
+/// The table containing the user's entries, as attached.
+pub const TABLE: &str = "user.entries";
+/// The table containing the entries hidden by the user, as attached.
+pub const HIDDEN_TABLE: &str = "user.hidden";
+/// The name the user database is attached under.
+const SCHEMA: &str = "user";
+/// The usage of user entries, on par with the most common entries of
+/// dict.cc.
+const USAGE: i64 = 45;
+
+/// The user database attached to connections opened for lookups.
+static CURRENT: sync::RwLock<Option<path::PathBuf>> = sync::RwLock::new(None);
+
+
+/// An entry hidden from lookups.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Hidden {
+  /// The entry with the given id, as reported by lookups, i.e., an
+  /// entry of the dictionary or, if negative, one of the user's.
+  Id(i64),
+  /// All entries with the given term, or a term with it as headword,
+  /// in either language.
+  Term(String),
+}
+
+impl Hidden {
+  /// Parse an entry to hide, given either as id (optionally prefixed
+  /// by '#') or as term.
+  pub fn parse(arg: &str) -> Result<Hidden> {
+    let arg = arg.trim();
+    match arg.trim_start_matches('#').parse::<i64>() {
+      Ok(id) if id != 0 => Ok(Hidden::Id(id)),
+      Ok(_) => Err(Error::Error(format!("Invalid entry id: {}", arg))),
+      Err(_) if arg.is_empty() => Err(Error::Error("Hiding requires an id or term".to_string())),
+      Err(_) => Ok(Hidden::Term(normalize(arg).into_owned())),
+    }
+  }
+}
+
+impl fmt::Display for Hidden {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match *self {
+      Hidden::Id(id) => write!(f, "#{}", id),
+      Hidden::Term(ref term) => write!(f, "{}", term),
+    }
+  }
+}
+
+
+/// Retrieve the path of the user database to use by default.
+pub fn default_path() -> Option<path::PathBuf> {
+  match env::var_os(ENV_VAR) {
+    Some(ref db) if !db.is_empty() => return Some(path::PathBuf::from(db)),
+    _ => (),
+  }
+  let dir = match env::var_os("XDG_DATA_HOME") {
+    Some(ref dir) if !dir.is_empty() => path::PathBuf::from(dir),
+    _ => path::PathBuf::from(env::var_os("HOME")?).join(".local").join("share"),
+  };
+  Some(dir.join("dictcc-cli").join("user.db"))
+}
+
+/// Set the user database attached to connections opened for lookups
+/// from now on, if any.
+pub fn set(db: Option<path::PathBuf>) {
+  *CURRENT.write().unwrap_or_else(|e| e.into_inner()) = db;
+}
+
+/// Retrieve the user database attached to connections opened for
+/// lookups, if any.
+pub fn current() -> Option<path::PathBuf> {
+  CURRENT.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Describe the state of the current user database, so that cached
+/// results can be told apart from those of lookups performed before it
+/// changed.
+pub fn fingerprint() -> Option<(path::PathBuf, Option<(time::SystemTime, u64)>)> {
+  let db = current()?;
+  let version = fs::metadata(&db).and_then(|m| Ok((m.modified()?, m.len()))).ok();
+  Some((db, version))
+}
+
+/// Attach a user database to a connection, if it exists, returning
+/// whether it got attached.
+pub fn attach(connection: &sqlite::Connection, db: &path::Path) -> Result<bool> {
+  if !db.is_file() {
+    return Ok(false)
+  }
+
+  // The user database is never encrypted. That has to be spelled out
+  // for SQLCipher not to apply the key of the main database to it;
+  // SQLite ignores the clause.
+  let mut statement = connection.prepare(format!("ATTACH DATABASE ? AS {} KEY ''", SCHEMA))?;
+  statement.bind(1, db.to_string_lossy().as_ref())?;
+  statement.next()?;
+  info!("Attached user database {}", db.display());
+  Ok(true)
+}
+
+/// Check whether a user database with the tables of the user's entries
+/// and of the hidden ones is attached to a connection.
+pub fn attached(connection: &sqlite::Connection) -> Result<bool> {
+  let mut cursor = connection.prepare("PRAGMA database_list")?.cursor();
+  let mut attached = false;
+  while let Some(row) = cursor.next()? {
+    attached |= row[1].as_string() == Some(SCHEMA);
+  }
+  if !attached {
+    return Ok(false)
+  }
+
+  let mut statement = connection.prepare(format!(
+    "SELECT COUNT(*) FROM {}.sqlite_master \
+     WHERE type = 'table' AND name IN ('entries', 'hidden')",
+    SCHEMA,
+  ))?;
+  statement.next()?;
+  Ok(statement.read::<i64>(0)? == 2)
+}
+
+/// Open the user database for modification, creating it if necessary.
+///
+/// Within `atomically` the connection takes part in its transaction.
+pub fn open(db: &path::Path) -> Result<rc::Rc<sqlite::Connection>> {
+  if let Some(dir) = db.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+    fs::create_dir_all(dir)
+      .map_err(|e| Error::Io(format!("Failed to create {}", dir.display()), e))?;
+  }
+  transactional(db, || {
+    let connection = sqlite::open(db)?;
+    connection.execute(
+      "CREATE TABLE IF NOT EXISTS entries(\
+         term1 TEXT NOT NULL, term2 TEXT NOT NULL, entry_type TEXT NOT NULL DEFAULT '', \
+         vt_usage INTEGER NOT NULL, subj_ids TEXT NOT NULL DEFAULT '');\
+       CREATE TABLE IF NOT EXISTS hidden(id INTEGER UNIQUE, term TEXT UNIQUE)",
+    )?;
+    Ok(connection)
+  })
+}
+
+/// Add an entry, returning its id.
+pub fn add(connection: &sqlite::Connection, term1: &str, term2: &str,
+           entry_type: &str) -> Result<i64> {
+  let (term1, term2) = (normalize(term1.trim()), normalize(term2.trim()));
+  if term1.is_empty() || term2.is_empty() {
+    return Err(Error::Error("Entries require both terms".to_string()))
+  }
+
+  let mut statement = connection.prepare(
+    "INSERT INTO entries(term1, term2, entry_type, vt_usage) VALUES (?, ?, ?, ?)",
+  )?;
+  statement.bind(1, term1.as_ref())?;
+  statement.bind(2, term2.as_ref())?;
+  statement.bind(3, normalize(entry_type.trim()).as_ref())?;
+  statement.bind(4, USAGE)?;
+  statement.next()?;
+
+  let mut statement = connection.prepare("SELECT last_insert_rowid()")?;
+  statement.next()?;
+  Ok(statement.read::<i64>(0)?)
+}
+
+/// Remove the entries with the given terms, returning their number.
+pub fn remove(connection: &sqlite::Connection, term1: &str, term2: &str) -> Result<usize> {
+  let mut statement = connection.prepare("DELETE FROM entries WHERE term1 = ? AND term2 = ?")?;
+  statement.bind(1, normalize(term1.trim()).as_ref())?;
+  statement.bind(2, normalize(term2.trim()).as_ref())?;
+  statement.next()?;
+  changes(connection)
+}
+
+
+/// Hide an entry from lookups, returning whether it was not hidden
+/// before.
+pub fn hide(connection: &sqlite::Connection, hidden: &Hidden) -> Result<bool> {
+  let mut statement = match *hidden {
+    Hidden::Id(id) => {
+      let mut statement = connection.prepare("INSERT OR IGNORE INTO hidden(id) VALUES (?)")?;
+      statement.bind(1, id)?;
+      statement
+    },
+    Hidden::Term(ref term) => {
+      let mut statement = connection.prepare("INSERT OR IGNORE INTO hidden(term) VALUES (?)")?;
+      statement.bind(1, term.as_str())?;
+      statement
+    },
+  };
+  statement.next()?;
+  Ok(changes(connection)? > 0)
+}
+
+/// Show an entry hidden before again, returning whether it was hidden.
+pub fn unhide(connection: &sqlite::Connection, hidden: &Hidden) -> Result<bool> {
+  let mut statement = match *hidden {
+    Hidden::Id(id) => {
+      let mut statement = connection.prepare("DELETE FROM hidden WHERE id = ?")?;
+      statement.bind(1, id)?;
+      statement
+    },
+    Hidden::Term(ref term) => {
+      let mut statement = connection.prepare("DELETE FROM hidden WHERE term = ?")?;
+      statement.bind(1, term.as_str())?;
+      statement
+    },
+  };
+  statement.next()?;
+  Ok(changes(connection)? > 0)
+}
+
+/// Retrieve the entries hidden from lookups, in the order they got
+/// hidden in.
+pub fn hidden(connection: &sqlite::Connection) -> Result<Vec<Hidden>> {
+  let mut cursor = connection.prepare("SELECT id, term FROM hidden ORDER BY rowid")?.cursor();
+  let mut hidden = Vec::new();
+  while let Some(row) = cursor.next()? {
+    match (row[0].as_integer(), row[1].as_string()) {
+      (Some(id), _) => hidden.push(Hidden::Id(id)),
+      (None, Some(term)) => hidden.push(Hidden::Term(term.to_string())),
+      (None, None) => (),
+    }
+  }
+  Ok(hidden)
+}
+
+/// Retrieve the number of rows changed by the last statement.
+fn changes(connection: &sqlite::Connection) -> Result<usize> {
+  let mut statement = connection.prepare("SELECT changes()")?;
+  statement.next()?;
+  Ok(statement.read::<i64>(0)? as usize)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::process;
+
+  use open_read_only;
+  use read_row;
+  use term_select;
+  use Direction;
+  use Merge;
+  use Options;
+  use Tuning;
+
+  fn user_db(name: &str) -> path::PathBuf {
+    env::temp_dir()
+      .join(format!("dictcc-cli-overlay-{}-{}", name, process::id()))
+      .join("user.db")
+  }
+
+
+  #[test]
+  fn add_remove_entries() {
+    let db = user_db("add");
+    let connection = open(&db).unwrap();
+    assert_eq!(add(&connection, " Zorn  {m}", "fury ", "noun").unwrap(), 1);
+    assert_eq!(add(&connection, "Zorn {m}", "rage", "").unwrap(), 2);
+    assert!(add(&connection, "Zorn {m}", " ", "").is_err());
+
+    assert_eq!(remove(&connection, "Zorn {m}", "wrath").unwrap(), 0);
+    assert_eq!(remove(&connection, "Zorn {m}", "fury").unwrap(), 1);
+    let mut statement = connection.prepare("SELECT term1, term2, vt_usage FROM entries").unwrap();
+    statement.next().unwrap();
+    assert_eq!(statement.read::<String>(0).unwrap(), "Zorn {m}");
+    assert_eq!(statement.read::<String>(1).unwrap(), "rage");
+    assert_eq!(statement.read::<i64>(2).unwrap(), USAGE);
+    drop(statement);
+    drop(connection);
+    fs::remove_dir_all(db.parent().unwrap()).unwrap();
+  }
+
+  #[test]
+  fn parse_hidden() {
+    assert_eq!(Hidden::parse("41203").unwrap(), Hidden::Id(41203));
+    assert_eq!(Hidden::parse("#41203").unwrap(), Hidden::Id(41203));
+    assert_eq!(Hidden::parse(" Zorn  {m} ").unwrap(), Hidden::Term("Zorn {m}".to_string()));
+    assert_eq!(Hidden::parse("#1a").unwrap(), Hidden::Term("#1a".to_string()));
+    assert_eq!(Hidden::parse("#-2").unwrap(), Hidden::Id(-2));
+    assert_eq!(Hidden::Id(-2).to_string(), "#-2");
+    assert!(Hidden::parse("0").is_err());
+    assert!(Hidden::parse(" ").is_err());
+    assert_eq!(Hidden::Id(41203).to_string(), "#41203");
+  }
+
+  #[test]
+  fn hide_unhide_entries() {
+    let db = user_db("hide");
+    let connection = open(&db).unwrap();
+    let zorn = Hidden::Term("Zorn".to_string());
+    assert!(hide(&connection, &Hidden::Id(42)).unwrap());
+    assert!(hide(&connection, &zorn).unwrap());
+    assert!(!hide(&connection, &zorn).unwrap());
+    assert_eq!(hidden(&connection).unwrap(), vec![Hidden::Id(42), zorn.clone()]);
+
+    assert!(unhide(&connection, &Hidden::Id(42)).unwrap());
+    assert!(!unhide(&connection, &Hidden::Id(42)).unwrap());
+    assert_eq!(hidden(&connection).unwrap(), vec![zorn]);
+    drop(connection);
+    fs::remove_dir_all(db.parent().unwrap()).unwrap();
+  }
+
+  /// Check that lookups find the user's entries along with those of the
+  /// dictionary, marked as such.
+  #[test]
+  fn look_up_user_entries() {
+    let db = user_db("lookup");
+    let connection = open(&db).unwrap();
+    add(&connection, "Zorn {m}", "fury", "noun").unwrap();
+    drop(connection);
+    let hide_ = |hidden: Hidden| hide(&open(&db).unwrap(), &hidden).unwrap();
+
+    let connection = open_read_only(path::Path::new("./test/test.db"), &Tuning::default()).unwrap();
+    assert!(!attached(&connection).unwrap());
+    assert!(!attach(&connection, &db.with_file_name("missing.db")).unwrap());
+    assert!(attach(&connection, &db).unwrap());
+    assert!(attached(&connection).unwrap());
+
+    let lookup = |term: &str, direction: &Direction| {
+      let options = Options::default();
+      let (query, binds) = term_select(&[term], direction, &options, None, false, true).unwrap();
+      let mut cursor = connection.prepare(query).unwrap().cursor();
+      cursor.bind(&binds).unwrap();
+      let mut found = Vec::new();
+      while let Some(row) = cursor.next().unwrap() {
+        let entry = read_row(row).unwrap();
+        found.push((entry.source.text, entry.target.text, entry.user));
+      }
+      found.sort();
+      found.dedup();
+      found
+    };
+
+    let found = lookup("Zorn", &Direction::Lang1ToLang2);
+    assert!(found.contains(&("Zorn {m}".to_string(), "anger".to_string(), false)), "{:?}", found);
+    assert!(found.contains(&("Zorn {m}".to_string(), "fury".to_string(), true)), "{:?}", found);
+    assert_eq!(
+      lookup("fury", &Direction::Lang2ToLang1),
+      vec![("fury".to_string(), "Zorn {m}".to_string(), true)]
+    );
+
+    // Entries can be hidden by id as well as by term, in either
+    // language and with or without annotations.
+    let ids = |term: &str, direction: &Direction| {
+      let (query, binds) = term_select(&[term], direction, &Options::default(), None, false, true)
+        .unwrap();
+      let mut cursor = connection.prepare(query).unwrap().cursor();
+      cursor.bind(&binds).unwrap();
+      let mut ids = Vec::new();
+      while let Some(row) = cursor.next().unwrap() {
+        ids.extend(read_row(row).unwrap().id);
+      }
+      ids
+    };
+    let anger = ids("anger", &Direction::Lang2ToLang1);
+    assert!(!anger.is_empty());
+    hide_(Hidden::Id(anger[0]));
+    assert!(!ids("anger", &Direction::Lang2ToLang1).contains(&anger[0]));
+    // The user's entries are hidden by their negative ids.
+    assert_eq!(ids("fury", &Direction::Lang2ToLang1), vec![-1]);
+    hide_(Hidden::Id(-1));
+    assert_eq!(lookup("fury", &Direction::Lang2ToLang1), vec![]);
+    hide_(Hidden::Term("fury".to_string()));
+    assert_eq!(lookup("fury", &Direction::Lang2ToLang1), vec![]);
+    hide_(Hidden::Term("Zorn".to_string()));
+    assert_eq!(lookup("Zorn", &Direction::Lang1ToLang2), vec![]);
+    drop(connection);
+    fs::remove_dir_all(db.parent().unwrap()).unwrap();
+  }
+
+  /// Check that user entries duplicating ones of the dictionary are
+  /// merged with them as configured.
+  #[test]
+  fn merge_user_entries() {
+    let db = user_db("merge");
+    let connection = open(&db).unwrap();
+    add(&connection, "Zorn [fig.]", "anger", "").unwrap();
+    add(&connection, "Zorn {m}", "fury", "noun").unwrap();
+    drop(connection);
+
+    let connection = open_read_only(path::Path::new("./test/test.db"), &Tuning::default()).unwrap();
+    assert!(attach(&connection, &db).unwrap());
+
+    let lookup = |term: &str, direction: &Direction, merge: Merge| {
+      let options = Options {
+        merge,
+        ..Default::default()
+      };
+      let (query, binds) = term_select(&[term], direction, &options, None, false, true).unwrap();
+      let mut cursor = connection.prepare(query).unwrap().cursor();
+      cursor.bind(&binds).unwrap();
+      let mut found = Vec::new();
+      while let Some(row) = cursor.next().unwrap() {
+        let entry = read_row(row).unwrap();
+        found.push((entry.source.text, entry.target.text, entry.types[0].name().to_string(), entry.user));
+      }
+      found.sort();
+      found.dedup();
+      found
+    };
+    let entry = |source: &str, target: &str, type_: &str, user: bool| {
+      (source.to_string(), target.to_string(), type_.to_string(), user)
+    };
+
+    let fury = entry("Zorn {m}", "fury", "noun", true);
+    assert_eq!(lookup("Zorn", &Direction::Lang1ToLang2, Merge::PreferUser), vec![
+      entry("Zorn [fig.]", "anger", "unknown", true),
+      fury.clone(),
+    ]);
+    assert_eq!(lookup("Zorn", &Direction::Lang1ToLang2, Merge::PreferMain), vec![
+      entry("Zorn {m}", "anger", "noun", false),
+      fury.clone(),
+    ]);
+    assert_eq!(lookup("Zorn", &Direction::Lang1ToLang2, Merge::Combine), vec![
+      fury,
+      entry("Zorn {m} [fig.]", "anger", "noun", false),
+    ]);
+    let found = lookup("anger", &Direction::Lang2ToLang1, Merge::Combine);
+    assert!(found.contains(&entry("anger", "Zorn {m} [fig.]", "noun", false)), "{:?}", found);
+    assert!(found.iter().all(|(_, _, _, user)| !user), "{:?}", found);
+
+    assert_eq!(Merge::from_name("combine").unwrap(), Merge::Combine);
+    assert!(Merge::from_name("both").is_err());
+    drop(connection);
+    fs::remove_dir_all(db.parent().unwrap()).unwrap();
+  }
+}
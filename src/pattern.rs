@@ -0,0 +1,257 @@
+// pattern.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Generation of the LIKE patterns matching a term along with its
+//! annotations.
+//!
+//! Terms in the database may carry annotations (`[...]`, `{...}`, and
+//! `<...>`) following any of their words, e.g., "to give [sth.] up" or
+//! "Zorn {m}". The patterns depend on the number of words of the term,
+//! as each word is followed by a position annotations may appear in.
+//!
+//! The term provided by the user is always matched literally, i.e., any
+//! '%' and '_' it contains are escaped using `ESCAPE`. Patterns have to
+//! be used with an `ESCAPE` clause accordingly.
+//!
+//! As the number of patterns grows with the number of words, queries
+//! do not use them directly but go through `matches`, which is
+//! registered as an SQL function.
+
+use std::sync::OnceLock;
+
+#[cfg(feature = "sqlite")]
+use like::like_cased;
+
+/// The character escaping wildcards in the patterns we generate.
+pub const ESCAPE: char = '\\';
+/// The annotations that may follow a term, as LIKE patterns.
+const ANNOTATIONS: [&str; 3] = ["[%]", "{%}", "<%>"];
+
+
+/// Escape the LIKE wildcards in a string (as well as the escape
+/// character itself), for the string to be matched literally.
+pub fn escape(string: &str) -> String {
+  let mut escaped = String::with_capacity(string.len());
+  for c in string.chars() {
+    if c == '%' || c == '_' || c == ESCAPE {
+      escaped.push(ESCAPE);
+    }
+    escaped.push(c);
+  }
+  escaped
+}
+
+/// Generate all permutations of the given length of the annotations.
+fn permutations(length: usize) -> Vec<Vec<&'static str>> {
+  if length == 0 {
+    return vec![Vec::new()];
+  }
+
+  let mut result = Vec::new();
+  for permutation in permutations(length - 1) {
+    for annotation in &ANNOTATIONS {
+      if !permutation.contains(annotation) {
+        let mut permutation = permutation.clone();
+        permutation.push(annotation);
+        result.push(permutation);
+      }
+    }
+  }
+  result
+}
+
+/// Generate the suffixes of trailing annotations, i.e., all
+/// permutations of up to all annotations.
+///
+/// Note that the database contains some elements with strings containing
+/// multiple white spaces in succession. It was found that only square
+/// braces ever appear with two spaces in front of them, so we cover
+/// these as well.
+///
+/// The suffixes are the same for every term, so they are generated only
+/// once.
+fn suffixes() -> &'static [String] {
+  static SUFFIXES: OnceLock<Vec<String>> = OnceLock::new();
+
+  SUFFIXES.get_or_init(|| {
+    let mut suffixes = Vec::new();
+    for length in 1..=ANNOTATIONS.len() {
+      for permutation in permutations(length) {
+        let suffix = permutation.iter().map(|a| format!(" {}", a)).collect::<String>();
+        suffixes.push(suffix.replace(" [", "  ["));
+        suffixes.push(suffix);
+      }
+    }
+    suffixes.dedup();
+    suffixes
+  })
+}
+
+/// Generate the LIKE patterns matching the given term, possibly
+/// annotated.
+///
+/// The first pattern is the term itself. Annotations may follow the
+/// term as a whole and, for terms consisting of multiple words, a
+/// single annotation may additionally appear between any two words.
+pub fn patterns(term: &str) -> Vec<String> {
+  let term = escape(term);
+  let words = term.split(' ').collect::<Vec<_>>();
+  let mut bases = vec![term.clone()];
+  for i in 1..words.len() {
+    for annotation in &ANNOTATIONS {
+      bases.push(format!("{} {} {}", words[..i].join(" "), annotation, words[i..].join(" ")));
+    }
+  }
+
+  let suffixes = suffixes();
+  let mut patterns = Vec::with_capacity(bases.len() * (suffixes.len() + 1));
+  for base in bases {
+    let annotated = suffixes.iter().map(|suffix| format!("{}{}", base, suffix)).collect::<Vec<_>>();
+    patterns.push(base);
+    patterns.extend(annotated);
+  }
+  patterns
+}
+
+/// Check whether `text` is the given term, possibly annotated, i.e.,
+/// whether it matches any of the term's `patterns`.
+#[cfg(feature = "sqlite")]
+pub fn matches(text: &str, term: &str, case_sensitive: bool) -> bool {
+  // Every pattern starts with the term's first word, which rules out
+  // most texts without generating the patterns.
+  let first = term.split(' ').next().unwrap_or("");
+  if !like_cased(&format!("{}%", escape(first)), text, Some(ESCAPE), case_sensitive) {
+    return false
+  }
+
+  patterns(term)
+    .iter()
+    .any(|pattern| like_cased(pattern, text, Some(ESCAPE), case_sensitive))
+}
+
+/// Generate the patterns matching the term as an English verb, i.e.,
+/// preceded by "to" and possibly followed by more words.
+pub fn verb(term: &str) -> Vec<String> {
+  let term = escape(term);
+  vec![format!("to {}", term), format!("to {} %", term)]
+}
+
+/// Generate the patterns matching the term as (a sequence of) whole
+/// words in a longer phrase.
+pub fn in_phrase(term: &str) -> Vec<String> {
+  let term = escape(term);
+  vec![format!("{} %", term), format!("% {}", term), format!("% {} %", term)]
+}
+
+/// Generate the pattern matching any text containing the given string.
+pub fn containing(string: &str) -> String {
+  format!("%{}%", escape(string))
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use like::like;
+  use like::like_to_glob;
+
+  #[test]
+  fn generate_patterns() {
+    let found = patterns("Zorn");
+    assert_eq!(found.len(), 27);
+    assert_eq!(found[0], "Zorn");
+    assert!(found.contains(&"Zorn  [%] {%}".to_string()));
+    assert!(found.contains(&"Zorn <%> {%} [%]".to_string()));
+    assert!(found.contains(&"Zorn <%> {%}  [%]".to_string()));
+
+    // Each of the three positions between words allows for any of the
+    // three annotations.
+    let found = patterns("to give up on");
+    assert_eq!(found.len(), 27 * 10);
+    assert!(found.contains(&"to give [%] up on".to_string()));
+    assert!(found.contains(&"to give up {%} on [%]".to_string()));
+  }
+
+  #[test]
+  #[cfg(feature = "sqlite")]
+  fn match_annotated() {
+    assert!(matches("Zorn {m}", "zorn", false));
+    assert!(!matches("Zorn {m}", "zorn", true));
+    assert!(matches("to give [sth.] up", "to give up", false));
+    assert!(matches("to give up  [coll.] <gu>", "to give up", false));
+    assert!(!matches("to give up on", "to give up", false));
+    assert!(!matches("Zornausbruch", "Zorn", false));
+    assert!(matches("100% {adj}", "100%", false));
+    assert!(!matches("1000 {adj}", "100%", false));
+
+    // The number of words is not limited.
+    let words = (0..20).map(|i| format!("w{}", i)).collect::<Vec<_>>();
+    let text = format!("{} [x] {} {{y}}", words[..7].join(" "), words[7..].join(" "));
+    assert!(matches(&text, &words.join(" "), false));
+    assert!(!matches(&text, &words[1..].join(" "), false));
+  }
+
+  #[test]
+  fn escape_wildcards() {
+    assert_eq!(escape("100%"), "100\\%");
+    assert_eq!(escape("a_b\\c"), "a\\_b\\\\c");
+    assert_eq!(patterns("5%")[1], "5\\%  [%]");
+    assert_eq!(containing("_"), "%\\_%");
+    assert!(like(&containing("10%"), "über 10% mehr", Some(ESCAPE)));
+    assert!(!like(&containing("10%"), "über 100 mehr", Some(ESCAPE)));
+    assert!(like(&verb("a_b")[1], "to a_b sth.", Some(ESCAPE)));
+    assert!(!like(&verb("a_b")[1], "to axb sth.", Some(ESCAPE)));
+  }
+
+  /// Check with a large number of pseudo-random strings that escaped
+  /// strings only ever match themselves.
+  #[test]
+  fn fuzz_escape() {
+    const ALPHABET: [char; 10] = ['a', 'B', 'ä', ' ', '%', '_', '\\', '[', '*', '?'];
+
+    // A simple xorshift generator, for deterministic results.
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut random = || {
+      state ^= state << 13;
+      state ^= state >> 7;
+      state ^= state << 17;
+      state
+    };
+    let mut string = || {
+      let len = random() % 6;
+      (0..len).map(|_| ALPHABET[(random() % ALPHABET.len() as u64) as usize]).collect::<String>()
+    };
+
+    for _ in 0..10000 {
+      let (s, t) = (string(), string());
+      let escaped = escape(&s);
+      assert!(like(&escaped, &s, Some(ESCAPE)), "{:?} does not match itself", s);
+      assert_eq!(
+        like(&escaped, &t, Some(ESCAPE)),
+        s.to_lowercase() == t.to_lowercase(),
+        "{:?} vs. {:?}", s, t
+      );
+
+      let glob = like_to_glob(&escaped, Some(ESCAPE));
+      let chars = glob.replace("[*]", "*").replace("[?]", "?").replace("[[]", "[");
+      assert_eq!(chars, s, "{:?} converted to {:?}", s, glob);
+    }
+  }
+}
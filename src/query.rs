@@ -0,0 +1,348 @@
+// query.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! A small boolean query language.
+//!
+//! Queries consist of terms combined with the (upper case) operators
+//! `AND`, `OR`, and `NOT`, e.g., `set NOT "set up"`. Consecutive words
+//! not separated by an operator form a single term, while words
+//! enclosed in double quotes form a phrase. `NOT` binds tightest,
+//! followed by `AND`, followed by `OR`; `a NOT b` is short for
+//! `a AND NOT b`. Parentheses can be used for grouping, up to a
+//! maximum nesting depth.
+
+#[cfg(feature = "sqlite")]
+use sqlite;
+
+use Error;
+use Result;
+
+/// The maximum depth of the expressions we parse, so that neither
+/// parsing nor anything working on the expression recursively
+/// exhausts the stack.
+const MAX_DEPTH: usize = 128;
+
+/// A token of a query.
+#[derive(Debug, PartialEq)]
+enum Token {
+  Word(String),
+  Phrase(String),
+  And,
+  Or,
+  Not,
+  Open,
+  Close,
+}
+
+/// A parsed query.
+#[derive(Debug, PartialEq)]
+pub enum Expr {
+  /// A term, matched the same way a plain search term is.
+  Term(String),
+  /// A phrase that has to appear as a whole.
+  Phrase(String),
+  /// Both expressions have to match.
+  And(Box<Expr>, Box<Expr>),
+  /// Either expression has to match.
+  Or(Box<Expr>, Box<Expr>),
+  /// The expression must not match.
+  Not(Box<Expr>),
+}
+
+#[cfg(feature = "sqlite")]
+impl Expr {
+  /// Compile the expression into an SQL condition along with the
+  /// parameters to bind to it.
+  ///
+  /// The conditions for terms and phrases are provided by `leaf`.
+  pub fn compile<F>(&self, leaf: &mut F) -> (String, Vec<sqlite::Value>)
+  where
+    F: FnMut(&Expr) -> (String, Vec<sqlite::Value>),
+  {
+    let binary = |lhs: &Expr, rhs: &Expr, op: &str, leaf: &mut F| {
+      let (cond1, mut binds1) = lhs.compile(leaf);
+      let (cond2, binds2) = rhs.compile(leaf);
+      binds1.extend(binds2);
+      (format!("({}) {} ({})", cond1, op, cond2), binds1)
+    };
+
+    match *self {
+      Expr::Term(_) | Expr::Phrase(_) => leaf(self),
+      Expr::And(ref lhs, ref rhs) => binary(lhs, rhs, "AND", leaf),
+      Expr::Or(ref lhs, ref rhs) => binary(lhs, rhs, "OR", leaf),
+      Expr::Not(ref expr) => {
+        let (cond, binds) = expr.compile(leaf);
+        (format!("NOT ({})", cond), binds)
+      },
+    }
+  }
+}
+
+
+/// Split a query into tokens.
+fn tokenize(query: &str) -> Result<Vec<Token>> {
+  let mut tokens = Vec::new();
+  let mut chars = query.chars().peekable();
+
+  while let Some(&c) = chars.peek() {
+    match c {
+      c if c.is_whitespace() => {
+        chars.next();
+      },
+      '(' | ')' => {
+        chars.next();
+        tokens.push(if c == '(' { Token::Open } else { Token::Close });
+      },
+      '"' => {
+        chars.next();
+        let phrase = chars.by_ref().take_while(|&c| c != '"').collect::<String>();
+        let phrase = phrase.split_whitespace().collect::<Vec<_>>().join(" ");
+        if phrase.is_empty() {
+          return Err(Error::QuerySyntax("Empty phrase in query".to_string()));
+        }
+        tokens.push(Token::Phrase(phrase));
+      },
+      _ => {
+        let mut word = String::new();
+        while let Some(&c) = chars.peek() {
+          if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+            break;
+          }
+          word.push(c);
+          chars.next();
+        }
+        tokens.push(match word.as_str() {
+          "AND" => Token::And,
+          "OR" => Token::Or,
+          "NOT" => Token::Not,
+          _ => Token::Word(word),
+        });
+      },
+    }
+  }
+  Ok(tokens)
+}
+
+/// Check whether a term makes use of the query language, i.e., whether
+/// it contains any operators.
+pub fn is_query(term: &str) -> bool {
+  match tokenize(term) {
+    Ok(tokens) => tokens.iter().any(|t| *t == Token::And || *t == Token::Or || *t == Token::Not),
+    Err(_) => false,
+  }
+}
+
+
+/// A recursive descent parser for queries.
+struct Parser {
+  tokens: Vec<Token>,
+  pos: usize,
+  /// The depth of the expression currently being parsed.
+  depth: usize,
+}
+
+impl Parser {
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.pos)
+  }
+
+  /// Descend one level deeper into the expression, failing if that
+  /// exceeds the maximum depth.
+  fn descend(&mut self) -> Result<()> {
+    self.depth += 1;
+    if self.depth > MAX_DEPTH {
+      return Err(Error::QuerySyntax("Query is nested too deeply".to_string()))
+    }
+    Ok(())
+  }
+
+  fn parse_or(&mut self) -> Result<Expr> {
+    // Chained operators nest the expressions they combine, too.
+    let depth = self.depth;
+    let mut expr = self.parse_and()?;
+    while let Some(&Token::Or) = self.peek() {
+      self.pos += 1;
+      self.descend()?;
+      expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+    }
+    self.depth = depth;
+    Ok(expr)
+  }
+
+  fn parse_and(&mut self) -> Result<Expr> {
+    let depth = self.depth;
+    let mut expr = self.parse_unary()?;
+    loop {
+      match self.peek() {
+        Some(&Token::And) => {
+          self.pos += 1;
+          self.descend()?;
+          expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        },
+        // A NOT following an expression negates the right hand side of
+        // an implicit AND.
+        Some(&Token::Not) => {
+          self.descend()?;
+          expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        },
+        _ => break,
+      }
+    }
+    self.depth = depth;
+    Ok(expr)
+  }
+
+  fn parse_unary(&mut self) -> Result<Expr> {
+    match self.peek() {
+      Some(&Token::Not) => {
+        self.pos += 1;
+        self.descend()?;
+        let expr = Expr::Not(Box::new(self.parse_unary()?));
+        self.depth -= 1;
+        Ok(expr)
+      },
+      _ => self.parse_primary(),
+    }
+  }
+
+  fn parse_primary(&mut self) -> Result<Expr> {
+    match self.tokens.get(self.pos) {
+      Some(&Token::Open) => {
+        self.pos += 1;
+        self.descend()?;
+        let expr = self.parse_or()?;
+        self.depth -= 1;
+        match self.peek() {
+          Some(&Token::Close) => {
+            self.pos += 1;
+            Ok(expr)
+          },
+          _ => Err(Error::QuerySyntax("Missing closing parenthesis in query".to_string())),
+        }
+      },
+      Some(Token::Phrase(phrase)) => {
+        let phrase = phrase.clone();
+        self.pos += 1;
+        Ok(Expr::Phrase(phrase))
+      },
+      Some(&Token::Word(_)) => {
+        let mut words = Vec::new();
+        while let Some(Token::Word(word)) = self.tokens.get(self.pos) {
+          words.push(word.clone());
+          self.pos += 1;
+        }
+        Ok(Expr::Term(words.join(" ")))
+      },
+      Some(token) => Err(Error::QuerySyntax(format!("Unexpected {:?} in query", token))),
+      None => Err(Error::QuerySyntax("Unexpected end of query".to_string())),
+    }
+  }
+}
+
+/// Parse a query.
+pub fn parse(query: &str) -> Result<Expr> {
+  let mut parser = Parser {
+    tokens: tokenize(query)?,
+    pos: 0,
+    depth: 0,
+  };
+  let expr = parser.parse_or()?;
+  match parser.peek() {
+    None => Ok(expr),
+    Some(token) => Err(Error::QuerySyntax(format!("Unexpected {:?} in query", token))),
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn term(term: &str) -> Box<Expr> {
+    Box::new(Expr::Term(term.to_string()))
+  }
+
+  #[test]
+  fn detect_queries() {
+    assert!(is_query("set NOT \"set up\""));
+    assert!(is_query("anger OR rage"));
+    assert!(!is_query("give up"));
+    assert!(!is_query("not or and"));
+  }
+
+  #[test]
+  fn parse_queries() {
+    assert_eq!(
+      parse("set NOT \"set  up\"").unwrap(),
+      Expr::And(term("set"), Box::new(Expr::Not(Box::new(Expr::Phrase("set up".to_string())))))
+    );
+    assert_eq!(
+      parse("a OR b AND give up").unwrap(),
+      Expr::Or(term("a"), Box::new(Expr::And(term("b"), term("give up"))))
+    );
+    assert_eq!(
+      parse("(a OR b) AND c").unwrap(),
+      Expr::And(Box::new(Expr::Or(term("a"), term("b"))), term("c"))
+    );
+  }
+
+  #[test]
+  fn parse_invalid() {
+    assert!(parse("a AND").is_err());
+    assert!(parse("(a OR b").is_err());
+    assert!(parse("a )").is_err());
+    assert!(parse("a \"\"").is_err());
+  }
+
+  /// Check that deeply nested queries are rejected instead of
+  /// exhausting the stack.
+  #[test]
+  fn parse_nested() {
+    let nested = |depth: usize| format!("{}a{}", "(".repeat(depth), ")".repeat(depth));
+    assert!(parse(&nested(MAX_DEPTH)).is_ok());
+
+    for query in &[
+      nested(100_000),
+      "NOT ".repeat(100_000) + "a",
+      "a AND ".repeat(MAX_DEPTH + 1) + "a",
+      "a OR ".repeat(MAX_DEPTH + 1) + "a",
+    ] {
+      match parse(query) {
+        Err(Error::QuerySyntax(ref err)) => assert_eq!(err, "Query is nested too deeply"),
+        result => panic!("Unexpected result: {:?}", result),
+      }
+    }
+  }
+
+  #[test]
+  #[cfg(feature = "sqlite")]
+  fn compile_query() {
+    let expr = parse("a OR NOT \"b c\"").unwrap();
+    let (cond, binds) = expr.compile(&mut |leaf| match *leaf {
+      Expr::Term(ref t) => ("term = ?".to_string(), vec![sqlite::Value::String(t.clone())]),
+      Expr::Phrase(ref p) => ("phrase = ?".to_string(), vec![sqlite::Value::String(p.clone())]),
+      _ => unreachable!(),
+    });
+    assert_eq!(cond, "(term = ?) OR (NOT (phrase = ?))");
+    assert_eq!(
+      binds,
+      vec![sqlite::Value::String("a".to_string()), sqlite::Value::String("b c".to_string())]
+    );
+  }
+}
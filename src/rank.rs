@@ -0,0 +1,130 @@
+// rank.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Scoring of results by their relevance to a query.
+
+use term::find_word;
+use term::headword;
+
+/// The score awarded per level of match quality. It is chosen such that
+/// the quality of a match always outweighs usage and length (for
+/// reasonable weights of the latter).
+const LEVEL_WEIGHT: f64 = 100.0;
+
+
+/// The weights of the factors influencing the score of a term within
+/// the same quality of match.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Weights {
+  /// The weight of the (logarithmic) number of uses.
+  pub usage: f64,
+  /// The penalty per character the term is longer than the query.
+  pub length: f64,
+}
+
+impl Default for Weights {
+  fn default() -> Weights {
+    Weights {
+      usage: 1.0,
+      length: 0.1,
+    }
+  }
+}
+
+
+/// How well a term matches a query, from worst to best.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Match {
+  /// The query does not appear in the term at all.
+  None,
+  /// The query appears only as part of a word of the term.
+  Partial,
+  /// The query appears as a whole word (or sequence of words) in a
+  /// longer phrase.
+  Word,
+  /// The term (without its annotations) is the query.
+  Exact,
+}
+
+/// Determine how well a term matches a query. Case is ignored, as is
+/// the "to" preceding English verbs.
+pub fn classify(term: &str, query: &str) -> Match {
+  let term = headword(term).to_lowercase();
+  let query = query.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+
+  if term == query || term.strip_prefix("to ") == Some(query.as_str()) {
+    Match::Exact
+  } else if !find_word(&term, &query).is_empty() {
+    Match::Word
+  } else if term.contains(&query) {
+    Match::Partial
+  } else {
+    Match::None
+  }
+}
+
+/// Score a term with respect to a query. Higher scores denote more
+/// relevant terms.
+///
+/// The quality of the match dominates. Within the same quality, more
+/// commonly used terms score higher while longer terms score lower, as
+/// weighted by `weights`.
+pub fn score(term: &str, query: &str, usage: i64, weights: &Weights) -> f64 {
+  let level = classify(term, query) as u8 as f64;
+  let extra = headword(term).chars().count().saturating_sub(query.chars().count());
+  level * LEVEL_WEIGHT + weights.usage * (1.0 + usage.max(0) as f64).ln()
+    - weights.length * extra as f64
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn classify_matches() {
+    assert_eq!(classify("Ärger {m}", "ärger"), Match::Exact);
+    assert_eq!(classify("to subjugate sb./sth.", "subjugate"), Match::Word);
+    assert_eq!(classify("to subjugate", "subjugate"), Match::Exact);
+    assert_eq!(classify("Liebesärger {m}", "Ärger"), Match::Partial);
+    assert_eq!(classify("Zorn {m}", "Ärger"), Match::None);
+  }
+
+  #[test]
+  fn score_terms() {
+    let w = Weights::default();
+    assert!(score("Ärger {m}", "Ärger", 0, &w) > score("Ärger machen", "Ärger", 1000, &w));
+    assert!(score("Ärger machen", "Ärger", 0, &w) > score("Liebesärger", "Ärger", 1000, &w));
+    assert!(score("Zorn", "Zorn", 20, &w) > score("Zorn", "Zorn", 10, &w));
+    assert!(score("jdm. Ärger machen", "Ärger", 10, &w) < score("Ärger machen", "Ärger", 10, &w));
+  }
+
+  #[test]
+  fn score_weighted() {
+    let w = Weights::default();
+    assert!(score("jdm. Ärger machen", "Ärger", 10, &w) > score("viel Ärger", "Ärger", 0, &w));
+
+    // With length weighing more heavily, the shorter phrase wins.
+    let w = Weights {
+      usage: 1.0,
+      length: 1.0,
+    };
+    assert!(score("jdm. Ärger machen", "Ärger", 10, &w) < score("viel Ärger", "Ärger", 0, &w));
+  }
+}
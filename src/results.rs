@@ -0,0 +1,527 @@
+// results.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Structured results of a lookup.
+//!
+//! Results can be saved to a file (as JSON) and rendered again later on
+//! in one of several formats, without access to the database.
+//!
+//! Entries are divided into exact matches and related phrases, i.e.,
+//! entries in which the query is only part of a longer phrase. The
+//! human readable format lists them in separate sections.
+
+use std::fmt::Write;
+
+use band::Band;
+use entry::Entry;
+use json;
+use json::Value;
+use Error;
+use Result;
+
+
+/// A format to render results in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+  /// The human readable format used for regular lookups.
+  Plain,
+  /// Single line pairs of terms without types, for tight spaces.
+  Compact,
+  /// Only the best translation, without any annotations, e.g., for
+  /// status bars.
+  OneLine,
+  /// Only the single best translation overall, e.g., for scripts
+  /// needing exactly one answer.
+  First,
+  /// Tab separated values, one entry per line.
+  Tsv,
+  /// A JSON document, as saved to a file.
+  Json,
+}
+
+impl Format {
+  /// Parse a format from its name.
+  pub fn from_name(name: &str) -> Result<Format> {
+    match name.to_lowercase().as_str() {
+      "plain" => Ok(Format::Plain),
+      "compact" => Ok(Format::Compact),
+      "one-line" => Ok(Format::OneLine),
+      "first" => Ok(Format::First),
+      "tsv" => Ok(Format::Tsv),
+      "json" => Ok(Format::Json),
+      _ => Err(Error::Error(format!("Unsupported format: {}", name))),
+    }
+  }
+}
+
+
+/// A single dictionary entry found by a lookup, along with the
+/// circumstances it was found in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match {
+  /// The direction the entry was found in ("1→2" or "2→1"), if the
+  /// lookup covered both directions.
+  pub direction: Option<String>,
+  /// The query word the entry was found for, if the words of the query
+  /// were looked up individually.
+  pub word: Option<String>,
+  /// The entry itself.
+  pub entry: Entry,
+  /// Whether the entry is merely related to the query, as opposed to
+  /// being an exact match.
+  pub related: bool,
+  /// The band of usage of the entry, if requested.
+  pub band: Option<Band>,
+  /// The principal forms of the entry's term, if requested and known.
+  pub inflections: Vec<String>,
+}
+
+impl Match {
+  /// Render the entry in the human readable format.
+  pub fn to_plain(&self) -> String {
+    let label = match self.direction {
+      Some(ref direction) => format!("{} ", direction),
+      None => String::new(),
+    };
+    format!(
+      "{}{}{}{} ({}): {}{}",
+      self.badge(),
+      self.word_label(),
+      label,
+      self.entry.source,
+      self.entry.type_name(),
+      self.entry.target,
+      self.user_label(),
+    )
+  }
+
+  /// Render the entry in the compact format, with the arrow pointing
+  /// from the source to the destination term.
+  pub fn to_compact(&self) -> String {
+    format!(
+      "{}{}{} → {}{}",
+      self.badge(),
+      self.word_label(),
+      self.entry.source,
+      self.entry.target,
+      self.user_label(),
+    )
+  }
+
+  /// Render the entry as a line of tab separated values.
+  pub fn to_tsv(&self) -> String {
+    // The query word is only known if words were looked up
+    // individually, in which case it is reported last.
+    let word = self.word.as_ref().map(|w| format!("\t{}", w)).unwrap_or_default();
+    format!(
+      "{}\t{}\t{}\t{}{}",
+      self.direction.as_deref().unwrap_or(""),
+      self.entry.source,
+      self.entry.type_name(),
+      self.entry.target,
+      word,
+    )
+  }
+
+  /// Retrieve the badge representing the band of usage of the entry,
+  /// if any.
+  fn badge(&self) -> String {
+    match self.band {
+      Some(band) => format!("{} ", band.badge()),
+      None => String::new(),
+    }
+  }
+
+  /// Retrieve the label identifying the query word the entry belongs
+  /// to, if any.
+  fn word_label(&self) -> String {
+    match self.word {
+      Some(ref word) => format!("[{}] ", word),
+      None => String::new(),
+    }
+  }
+
+  /// Retrieve the label marking the entry as one of the user's own, if
+  /// it is.
+  fn user_label(&self) -> &'static str {
+    if self.entry.user {
+      " (user entry)"
+    } else {
+      ""
+    }
+  }
+
+  /// Write the principal forms of the entry's term, if any, on a line
+  /// of their own below the entry, indented by `indent`.
+  fn write_inflections(&self, output: &mut String, indent: &str) {
+    if !self.inflections.is_empty() {
+      let _ = writeln!(output, "{}  ↳ {}", indent, self.inflections.join(", "));
+    }
+  }
+
+  fn to_json(&self) -> Value {
+    let optional = |value: &Option<String>| match *value {
+      Some(ref value) => Value::from(value.as_str()),
+      None => Value::Null,
+    };
+    Value::Object(vec![
+      ("direction".to_string(), optional(&self.direction)),
+      ("word".to_string(), optional(&self.word)),
+      ("source".to_string(), Value::from(self.entry.source.text.as_str())),
+      ("target".to_string(), Value::from(self.entry.target.text.as_str())),
+      ("type".to_string(), Value::from(self.entry.type_name())),
+      ("usage".to_string(), Value::Number(self.entry.usage as f64)),
+      ("id".to_string(), self.entry.id.map_or(Value::Null, |id| Value::Number(id as f64))),
+      ("related".to_string(), Value::Bool(self.related)),
+      ("user".to_string(), Value::Bool(self.entry.user)),
+      ("band".to_string(), self.band.map_or(Value::Null, |b| Value::from(b.name()))),
+      (
+        "inflections".to_string(),
+        Value::Array(self.inflections.iter().map(|f| Value::from(f.as_str())).collect()),
+      ),
+    ])
+  }
+
+  fn from_json(value: &Value) -> Result<Match> {
+    let string = |key: &str| {
+      value
+        .get(key)
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+        .ok_or_else(|| Error::Error(format!("Invalid entry: missing {}", key)))
+    };
+    let optional = |key: &str| value.get(key).and_then(|v| v.as_str()).map(|s| s.to_string());
+    // Results saved by earlier versions lack the usage.
+    let usage = match value.get("usage") {
+      Some(&Value::Number(usage)) => usage as i64,
+      _ => 0,
+    };
+    let mut entry = Entry::new(&string("source")?, &string("target")?, &string("type")?, usage);
+    entry.user = value.get("user") == Some(&Value::Bool(true));
+    entry.id = match value.get("id") {
+      Some(&Value::Number(id)) => Some(id as i64),
+      _ => None,
+    };
+    Ok(Match {
+      direction: optional("direction"),
+      word: optional("word"),
+      entry,
+      related: value.get("related") == Some(&Value::Bool(true)),
+      band: optional("band").map(|b| Band::from_name(&b)).transpose()?,
+      inflections: value
+        .get("inflections")
+        .and_then(|v| v.as_array())
+        .map(|forms| forms.iter().filter_map(|f| f.as_str()).map(|f| f.to_string()).collect())
+        .unwrap_or_default(),
+    })
+  }
+}
+
+
+/// The results of a lookup.
+#[derive(Debug, Default, PartialEq)]
+pub struct Results {
+  /// The query as provided by the user.
+  pub query: String,
+  /// The entries found.
+  pub entries: Vec<Match>,
+}
+
+impl Results {
+  /// Convert the results into their JSON representation.
+  pub fn to_json(&self) -> Value {
+    Value::Object(vec![
+      ("query".to_string(), Value::from(self.query.as_str())),
+      ("entries".to_string(), Value::Array(self.entries.iter().map(Match::to_json).collect())),
+    ])
+  }
+
+  /// Parse results from their JSON representation.
+  pub fn from_json(value: &Value) -> Result<Results> {
+    let query = value
+      .get("query")
+      .and_then(|q| q.as_str())
+      .ok_or_else(|| Error::Error("Invalid results: missing query".to_string()))?;
+    let entries = value
+      .get("entries")
+      .and_then(|e| e.as_array())
+      .ok_or_else(|| Error::Error("Invalid results: missing entries".to_string()))?;
+
+    Ok(Results {
+      query: query.to_string(),
+      entries: entries.iter().map(Match::from_json).collect::<Result<_>>()?,
+    })
+  }
+
+  /// Parse results as saved to a file.
+  pub fn parse(content: &str) -> Result<Results> {
+    Results::from_json(&json::parse(content.trim())?)
+  }
+
+  /// Render the results in the given format.
+  pub fn render(&self, format: Format) -> String {
+    let mut output = String::new();
+    match format {
+      Format::Plain => {
+        let (related, exact) = self.entries.iter().partition::<Vec<_>, _>(|e| e.related);
+        // Sections are only called out as such if there is anything
+        // beyond exact matches.
+        if related.is_empty() {
+          for entry in exact {
+            let _ = writeln!(output, "{}", entry.to_plain());
+            entry.write_inflections(&mut output, "");
+          }
+        } else {
+          for (title, entries) in &[("Exact matches", exact), ("Related phrases", related)] {
+            if entries.is_empty() {
+              continue;
+            }
+            if !output.is_empty() {
+              output.push('\n');
+            }
+            let _ = writeln!(output, "{}:", title);
+            for entry in entries {
+              let _ = writeln!(output, "  {}", entry.to_plain());
+              entry.write_inflections(&mut output, "  ");
+            }
+          }
+        }
+      },
+      Format::Compact => {
+        let (related, exact) = self.entries.iter().partition::<Vec<_>, _>(|e| e.related);
+        for entry in exact.into_iter().chain(related) {
+          let _ = writeln!(output, "{}", entry.to_compact());
+        }
+      },
+      Format::OneLine | Format::First => {
+        // Entries are ordered by relevance within their section, so the
+        // first exact match is the best translation. Words looked up
+        // individually get a line each, unless only the single best
+        // translation overall is of interest.
+        let word = |entry: &Match| match format {
+          Format::First => None,
+          _ => entry.word.clone(),
+        };
+        let mut words = Vec::new();
+        for entry in &self.entries {
+          if !words.contains(&word(entry)) {
+            words.push(word(entry));
+          }
+        }
+        for w in words {
+          let mut entries = self.entries.iter().filter(|e| word(e) == w);
+          let best = entries.clone().find(|e| !e.related).or_else(|| entries.next());
+          if let Some(entry) = best {
+            let _ = writeln!(output, "{}", entry.entry.target.headword);
+          }
+        }
+      },
+      Format::Tsv => {
+        for entry in &self.entries {
+          let _ = writeln!(output, "{}", entry.to_tsv());
+        }
+      },
+      Format::Json => {
+        let _ = writeln!(output, "{}", self.to_json());
+      },
+    }
+    output
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::fs;
+
+  fn results() -> Results {
+    Results {
+      query: "dorky".to_string(),
+      entries: vec![
+        Match {
+          direction: None,
+          word: None,
+          entry: Entry::new("dorky [coll.]", "bekloppt [ugs.]", "adj", 12),
+          related: false,
+          band: None,
+          inflections: Vec::new(),
+        },
+        Match {
+          direction: Some("2→1".to_string()),
+          word: None,
+          entry: Entry::new("dorky [coll.]", "idiotisch", "adj", 3),
+          related: false,
+          band: None,
+          inflections: Vec::new(),
+        },
+      ],
+    }
+  }
+
+  #[test]
+  fn json_round_trip() {
+    let results = results();
+    let json = json::parse(&results.to_json().to_string()).unwrap();
+    assert_eq!(Results::from_json(&json).unwrap(), results);
+    assert!(Results::from_json(&json::parse("{\"query\": \"x\"}").unwrap()).is_err());
+  }
+
+  #[test]
+  fn render_formats() {
+    let results = results();
+    assert_eq!(
+      results.render(Format::Plain),
+      "dorky [coll.] (adj): bekloppt [ugs.]\n2→1 dorky [coll.] (adj): idiotisch\n"
+    );
+    assert_eq!(
+      results.render(Format::Tsv),
+      "\tdorky [coll.]\tadj\tbekloppt [ugs.]\n2→1\tdorky [coll.]\tadj\tidiotisch\n"
+    );
+    assert_eq!(
+      results.render(Format::Compact),
+      "dorky [coll.] → bekloppt [ugs.]\ndorky [coll.] → idiotisch\n"
+    );
+    assert_eq!(results.render(Format::OneLine), "bekloppt\n");
+    assert!(Format::from_name("xml").is_err());
+  }
+
+  #[test]
+  fn render_sections() {
+    let mut results = results();
+    results.entries[0].related = true;
+    assert_eq!(
+      results.render(Format::Plain),
+      "Exact matches:\n  2→1 dorky [coll.] (adj): idiotisch\n\n\
+       Related phrases:\n  dorky [coll.] (adj): bekloppt [ugs.]\n"
+    );
+
+    results.entries[1].related = true;
+    assert_eq!(
+      results.render(Format::Plain),
+      "Related phrases:\n  dorky [coll.] (adj): bekloppt [ugs.]\n  2→1 dorky [coll.] (adj): idiotisch\n"
+    );
+  }
+
+  #[test]
+  fn render_words() {
+    let mut results = results();
+    results.entries[0].word = Some("dorky".to_string());
+    results.entries[1].word = Some("daft".to_string());
+    results.entries[1].direction = None;
+    assert_eq!(
+      results.render(Format::Plain),
+      "[dorky] dorky [coll.] (adj): bekloppt [ugs.]\n[daft] dorky [coll.] (adj): idiotisch\n"
+    );
+    assert_eq!(
+      results.render(Format::Tsv),
+      "\tdorky [coll.]\tadj\tbekloppt [ugs.]\tdorky\n\tdorky [coll.]\tadj\tidiotisch\tdaft\n"
+    );
+    assert_eq!(results.render(Format::OneLine), "bekloppt\nidiotisch\n");
+    assert_eq!(results.render(Format::First), "bekloppt\n");
+
+    results.entries[0].related = true;
+    assert_eq!(results.render(Format::First), "idiotisch\n");
+  }
+
+  #[test]
+  fn render_bands() {
+    let mut results = results();
+    results.entries[0].band = Some(Band::Rare);
+    results.entries[1].band = Some(Band::VeryCommon);
+    assert_eq!(
+      results.render(Format::Plain),
+      "●○○ dorky [coll.] (adj): bekloppt [ugs.]\n●●● 2→1 dorky [coll.] (adj): idiotisch\n"
+    );
+    assert_eq!(
+      results.render(Format::Compact),
+      "●○○ dorky [coll.] → bekloppt [ugs.]\n●●● dorky [coll.] → idiotisch\n"
+    );
+
+    let json = json::parse(&results.to_json().to_string()).unwrap();
+    assert_eq!(json.get("entries").unwrap().as_array().unwrap()[1].get("band"), Some(&Value::from("very common")));
+    assert_eq!(Results::from_json(&json).unwrap(), results);
+  }
+
+  #[test]
+  fn render_user_entries() {
+    let mut results = results();
+    results.entries[1].entry.user = true;
+    results.entries[1].entry.id = Some(-3);
+    assert_eq!(
+      results.render(Format::Plain),
+      "dorky [coll.] (adj): bekloppt [ugs.]\n2→1 dorky [coll.] (adj): idiotisch (user entry)\n"
+    );
+    assert_eq!(
+      results.render(Format::Compact),
+      "dorky [coll.] → bekloppt [ugs.]\ndorky [coll.] → idiotisch (user entry)\n"
+    );
+
+    let json = json::parse(&results.to_json().to_string()).unwrap();
+    assert_eq!(json.get("entries").unwrap().as_array().unwrap()[1].get("user"), Some(&Value::Bool(true)));
+    assert_eq!(Results::from_json(&json).unwrap(), results);
+  }
+
+  #[test]
+  fn render_inflections() {
+    let mut results = results();
+    results.entries[0].inflections = vec!["bekloppt".to_string(), "bekloppter".to_string()];
+    assert_eq!(
+      results.render(Format::Plain),
+      "dorky [coll.] (adj): bekloppt [ugs.]\n  ↳ bekloppt, bekloppter\n\
+       2→1 dorky [coll.] (adj): idiotisch\n"
+    );
+
+    results.entries[1].related = true;
+    assert_eq!(
+      results.render(Format::Plain),
+      "Exact matches:\n  dorky [coll.] (adj): bekloppt [ugs.]\n    ↳ bekloppt, bekloppter\n\n\
+       Related phrases:\n  2→1 dorky [coll.] (adj): idiotisch\n"
+    );
+    assert_eq!(
+      results.render(Format::Compact),
+      "dorky [coll.] → bekloppt [ugs.]\ndorky [coll.] → idiotisch\n"
+    );
+
+    let json = json::parse(&results.to_json().to_string()).unwrap();
+    assert_eq!(Results::from_json(&json).unwrap(), results);
+  }
+
+  /// Check the rendering of the results in `test/golden/input.json`
+  /// against the expected output stored alongside.
+  #[test]
+  fn render_golden() {
+    let input = fs::read_to_string("./test/golden/input.json").unwrap();
+    let results = Results::parse(&input).unwrap();
+
+    let formats = [
+      (Format::Plain, "txt"),
+      (Format::Compact, "compact"),
+      (Format::OneLine, "one-line"),
+      (Format::First, "first"),
+      (Format::Tsv, "tsv"),
+      (Format::Json, "json"),
+    ];
+    for &(format, extension) in &formats {
+      let expected = fs::read_to_string(format!("./test/golden/expected.{}", extension)).unwrap();
+      assert_eq!(results.render(format), expected, "format: {:?}", format);
+    }
+  }
+}
@@ -0,0 +1,264 @@
+// schema.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Detection of the layout of a database.
+//!
+//! Queries expect the entries in a table named `main_ft`, with the
+//! terms in columns `term1` and `term2` and their type and usage in
+//! `entry_type` and `vt_usage`. Databases of other versions of the
+//! dict.cc app or created by other means do not necessarily agree on
+//! these names. When opening a database for lookups, we probe its
+//! tables and, if the entries are stored differently, map them onto
+//! the expected layout by means of a temporary view shadowing the
+//! search table, so that all queries work unchanged.
+
+use sqlite;
+
+use subjects::table_columns;
+use Result;
+use SEARCH_TBL;
+use SUBJ_COL;
+use TERM1_COL;
+use TERM2_COL;
+use TYPE_COL;
+use USAGE_COL;
+
+/// Candidates for the table containing the entries, in order of
+/// preference. Other tables are considered afterwards.
+const TABLES: [&str; 5] = ["main_ft", "main", "entries", "translations", "vocabulary"];
+/// Candidates for the column containing the term in language 1.
+const TERM1_COLS: [&str; 5] = ["term1", "term_1", "lang1", "word1", "text1"];
+/// Candidates for the column containing the term in language 2.
+const TERM2_COLS: [&str; 5] = ["term2", "term_2", "lang2", "word2", "text2"];
+/// Candidates for the column containing the type of an entry.
+const TYPE_COLS: [&str; 5] = ["entry_type", "type", "word_class", "wordclass", "pos"];
+/// Candidates for the column containing the usage of an entry.
+const USAGE_COLS: [&str; 4] = ["vt_usage", "usage", "frequency", "popularity"];
+/// Candidates for the column containing the subjects of an entry.
+const SUBJ_COLS: [&str; 3] = ["subj_ids", "subject_ids", "subjects"];
+
+
+/// The layout of the table containing the entries.
+#[derive(Debug, PartialEq)]
+pub struct Schema {
+  /// The name of the table.
+  pub table: String,
+  /// The column containing the term in language 1.
+  pub term1: &'static str,
+  /// The column containing the term in language 2.
+  pub term2: &'static str,
+  /// The column containing the type of an entry, if any.
+  pub entry_type: Option<&'static str>,
+  /// The column containing the usage of an entry, if any.
+  pub usage: Option<&'static str>,
+  /// The column containing the subjects of an entry, if any.
+  pub subj_ids: Option<&'static str>,
+}
+
+impl Schema {
+  /// Probe the tables of a database for the one containing the
+  /// entries.
+  pub fn detect(connection: &sqlite::Connection) -> Result<Option<Schema>> {
+    let mut cursor = connection
+      .prepare("SELECT name, sql FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'")?
+      .cursor();
+    let mut tables = Vec::new();
+    let mut virtual_tables = Vec::new();
+    while let Some(row) = cursor.next()? {
+      if let Some(name) = row[0].as_string() {
+        let sql = row[1].as_string().unwrap_or("").to_lowercase();
+        if sql.starts_with("create virtual table") {
+          virtual_tables.push(name.to_string());
+        }
+        tables.push(name.to_string());
+      }
+    }
+
+    // Full-text tables are backed by tables of their own, named after
+    // them, which are of no interest.
+    tables.retain(|t| !virtual_tables.iter().any(|v| t.starts_with(&format!("{}_", v))));
+    tables.sort_by_key(|t| TABLES.iter().position(|c| c == t).unwrap_or(TABLES.len()));
+
+    for table in tables {
+      let columns = table_columns(connection, &table)?;
+      let find = |candidates: &[&'static str]| {
+        candidates.iter().find(|c| columns.iter().any(|col| col == *c)).cloned()
+      };
+
+      if let (Some(term1), Some(term2)) = (find(&TERM1_COLS), find(&TERM2_COLS)) {
+        return Ok(Some(Schema {
+          entry_type: find(&TYPE_COLS),
+          usage: find(&USAGE_COLS),
+          subj_ids: find(&SUBJ_COLS),
+          table,
+          term1,
+          term2,
+        }))
+      }
+    }
+    Ok(None)
+  }
+
+  /// Check whether the layout is the one queries expect. The subjects
+  /// are optional, as only few commands rely on them.
+  pub fn is_expected(&self) -> bool {
+    self.table == SEARCH_TBL &&
+    self.term1 == TERM1_COL &&
+    self.term2 == TERM2_COL &&
+    self.entry_type == Some(TYPE_COL) &&
+    self.usage == Some(USAGE_COL)
+  }
+
+  /// Build the statement creating a view mapping the table onto the
+  /// expected layout.
+  fn view(&self) -> String {
+    format!(
+      "CREATE TEMP VIEW {search} AS SELECT \
+         rowid AS rowid, \
+         {term1} AS {term1_col}, \
+         {term2} AS {term2_col}, \
+         {typ} AS {type_col}, \
+         {use} AS {usage_col}, \
+         {subj} AS {subj_col} \
+       FROM main.{table}",
+      search = SEARCH_TBL,
+      term1 = quote(self.term1), term1_col = TERM1_COL,
+      term2 = quote(self.term2), term2_col = TERM2_COL,
+      typ = self.entry_type.map(quote).unwrap_or_else(|| "''".to_string()), type_col = TYPE_COL,
+      use = self.usage.map(quote).unwrap_or_else(|| "0".to_string()), usage_col = USAGE_COL,
+      subj = self.subj_ids.map(quote).unwrap_or_else(|| "''".to_string()), subj_col = SUBJ_COL,
+      table = quote(&self.table),
+    )
+  }
+}
+
+
+/// Quote an identifier for use in SQL.
+fn quote(identifier: &str) -> String {
+  format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Make the entries of a database accessible under the expected layout,
+/// if they are stored differently. The connection must not have been
+/// restricted to reading yet.
+pub fn adapt(connection: &sqlite::Connection) -> Result<()> {
+  match Schema::detect(connection)? {
+    Some(ref schema) if !schema.is_expected() => {
+      info!("Mapping table {} onto the expected layout: {:?}", schema.table, schema);
+      connection.execute(schema.view())?;
+    },
+    Some(_) => (),
+    // Lookups will report the missing table.
+    None => debug!("No table with entries found"),
+  }
+  Ok(())
+}
+
+/// Check whether the search table of a connection is a view created by
+/// `adapt`.
+pub fn is_adapted(connection: &sqlite::Connection) -> Result<bool> {
+  let mut statement = connection.prepare(
+    "SELECT COUNT(*) FROM sqlite_temp_master WHERE type = 'view' AND name = ?"
+  )?;
+  statement.bind(1, SEARCH_TBL)?;
+  statement.next()?;
+  Ok(statement.read::<i64>(0)? > 0)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::env;
+  use std::fs;
+  use std::path;
+  use std::process;
+
+  use open;
+  use translate_with;
+  use Database;
+  use Direction;
+  use Options;
+
+
+  /// Create a database with the given schema and content.
+  fn create_db(name: &str, sql: &str) -> path::PathBuf {
+    let db = env::temp_dir().join(format!("dictcc-cli-schema-{}-{}.db", name, process::id()));
+    let _ = fs::remove_file(&db);
+    sqlite::open(&db).unwrap().execute(sql).unwrap();
+    db
+  }
+
+  /// Look up a term in a database, retrieving the entries found.
+  fn lookup(db: &path::Path, term: &str) -> Vec<(String, String, String, i64)> {
+    let mut found = Vec::new();
+    translate_with(db, &[term], &Direction::Lang1ToLang2, &Options::default(), |e| {
+      let types = e.types.iter().map(|t| t.name()).collect::<Vec<_>>().join(" ");
+      found.push((e.source.to_string(), e.target.to_string(), types, e.usage));
+      Ok(())
+    }).unwrap();
+    found
+  }
+
+  #[test]
+  fn detect_expected_schema() {
+    let connection = open(path::Path::new("./test/test.db")).unwrap();
+    let schema = Schema::detect(&connection).unwrap().unwrap();
+    assert_eq!(schema.table, "main_ft");
+    assert!(schema.is_expected());
+    assert!(!is_adapted(&connection).unwrap());
+    assert!(Database::open(path::Path::new("./test/test.db")).unwrap().fulltext());
+  }
+
+  #[test]
+  fn detect_other_schemas() {
+    let db = create_db(
+      "fts4",
+      "CREATE VIRTUAL TABLE vocabulary USING fts4(lang1, lang2, word_class, frequency);\
+       INSERT INTO vocabulary VALUES ('Zorn {m}', 'anger', 'noun', 42);\
+       INSERT INTO vocabulary VALUES ('Ärger {m}', 'anger', 'noun', 41);",
+    );
+    let connection = sqlite::open(&db).unwrap();
+    assert_eq!(Schema::detect(&connection).unwrap(), Some(Schema {
+      table: "vocabulary".to_string(),
+      term1: "lang1",
+      term2: "lang2",
+      entry_type: Some("word_class"),
+      usage: Some("frequency"),
+      subj_ids: None,
+    }));
+    assert_eq!(lookup(&db, "Zorn"), vec![
+      ("Zorn {m}".to_string(), "anger".to_string(), "noun".to_string(), 42),
+    ]);
+    // The view cannot make use of the full-text index.
+    assert!(!Database::open(&db).unwrap().fulltext());
+    fs::remove_file(&db).unwrap();
+
+    let db = create_db(
+      "plain",
+      "CREATE TABLE entries (id INTEGER PRIMARY KEY, word1 VARCHAR, word2 VARCHAR);\
+       INSERT INTO entries (word1, word2) VALUES ('Liebe {f}', 'love');",
+    );
+    assert_eq!(lookup(&db, "Liebe"), vec![
+      ("Liebe {f}".to_string(), "love".to_string(), "unknown".to_string(), 0),
+    ]);
+    fs::remove_file(&db).unwrap();
+  }
+}
@@ -0,0 +1,133 @@
+// script.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Parsing of scripts running a sequence of commands.
+//!
+//! A script contains one command per line, written just like the
+//! arguments to the program, e.g., "translate dictcc-lp1.db Zorn".
+//! Words are separated by white space, unless enclosed in single or
+//! double quotes or escaped with a backslash. Empty lines and lines
+//! starting with '#' are ignored.
+
+use Error;
+use Result;
+
+
+/// A command of a script.
+#[derive(Debug, PartialEq)]
+pub struct Line {
+  /// The (1-based) number of the line the command is on.
+  pub number: usize,
+  /// The line itself, as written.
+  pub text: String,
+  /// The arguments the line consists of, including the command.
+  pub args: Vec<String>,
+}
+
+
+/// Split a line into words, honoring quotes and escapes.
+fn split(line: &str) -> Result<Vec<String>> {
+  let mut words = Vec::new();
+  let mut word = None;
+  let mut quote = None;
+  let mut chars = line.chars();
+
+  while let Some(c) = chars.next() {
+    match (quote, c) {
+      (Some(q), c) if c == q => quote = None,
+      (Some('"'), '\\') | (None, '\\') => match chars.next() {
+        Some(c) => word.get_or_insert_with(String::new).push(c),
+        None => return Err(Error::Error("Trailing backslash".to_string())),
+      },
+      (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+      (None, '"') | (None, '\'') => {
+        quote = Some(c);
+        word.get_or_insert_with(String::new);
+      },
+      (None, c) if c.is_whitespace() => words.extend(word.take()),
+      (None, c) => word.get_or_insert_with(String::new).push(c),
+    }
+  }
+
+  if let Some(quote) = quote {
+    return Err(Error::Error(format!("Unterminated {} quote", quote)));
+  }
+  words.extend(word);
+  Ok(words)
+}
+
+/// Parse a script into the commands it consists of.
+pub fn parse(content: &str) -> Result<Vec<Line>> {
+  let mut lines = Vec::new();
+  for (i, text) in content.lines().enumerate() {
+    let trimmed = text.trim();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+      continue;
+    }
+
+    let args = split(trimmed).map_err(|e| Error::Error(format!("Line {}: {}", i + 1, e)))?;
+    lines.push(Line {
+      number: i + 1,
+      text: trimmed.to_string(),
+      args,
+    });
+  }
+  Ok(lines)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn split_words() {
+    assert_eq!(split("translate db Zorn").unwrap(), vec!["translate", "db", "Zorn"]);
+    assert_eq!(split("  a   b ").unwrap(), vec!["a", "b"]);
+    assert_eq!(split(r#"db '"the poor"'"#).unwrap(), vec!["db", "\"the poor\""]);
+    assert_eq!(split(r#"db "a \"b\"" c\ d ''"#).unwrap(), vec!["db", "a \"b\"", "c d", ""]);
+    assert_eq!(split(r"'a\b'").unwrap(), vec![r"a\b"]);
+    assert!(split("'a").is_err());
+    assert!(split("a\\").is_err());
+  }
+
+  #[test]
+  fn parse_script() {
+    let script = "# Look up some words.\n\ntranslate db Zorn\n  types db\n";
+    let lines = parse(script).unwrap();
+    assert_eq!(
+      lines,
+      vec![
+        Line {
+          number: 3,
+          text: "translate db Zorn".to_string(),
+          args: vec!["translate".to_string(), "db".to_string(), "Zorn".to_string()],
+        },
+        Line {
+          number: 4,
+          text: "types db".to_string(),
+          args: vec!["types".to_string(), "db".to_string()],
+        },
+      ]
+    );
+
+    let err = parse("a\nb 'c").unwrap_err();
+    assert_eq!(err.to_string(), "Line 2: Unterminated ' quote");
+  }
+}
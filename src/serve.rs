@@ -0,0 +1,723 @@
+// serve.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+//! A minimal HTTP server exposing lookups to other programs.
+//!
+//! The `serve` command answers requests such as `GET
+//! /translate?q=Zorn&format=json`, so that GUIs or browser extensions
+//! can query the offline dictionary. Only what such an API needs of
+//! HTTP/1.1 is implemented: each connection carries a single request,
+//! request bodies are ignored, and the connection is closed after the
+//! response.
+//!
+//! Requests are read by a thread of their own per connection, which
+//! gives up once the request is not complete within `REQUEST_DEADLINE`,
+//! so that slow clients cannot hold up others. Complete requests are
+//! answered by a fixed number of worker threads, each of which keeps
+//! its database connections open across requests (see
+//! `sharing_connections`). Lookups made this way are performed with
+//! settings fixed at startup (see `Lookups`), and neither run hooks nor
+//! look for alternatives to terms without translations. Upon SIGINT or
+//! SIGTERM the server stops accepting connections and shuts down once
+//! the requests in flight are answered.
+
+use std::io;
+use std::io::BufRead;
+use std::io::Read;
+use std::io::Write;
+use std::net;
+use std::path;
+use std::sync;
+use std::sync::atomic;
+use std::sync::mpsc;
+use std::thread;
+use std::time;
+
+use libc;
+
+use like;
+use query;
+use rank;
+use results;
+use term;
+use detect_direction;
+use sharing_connections;
+use term_query;
+use translate_query;
+use translate_with;
+use Direction;
+use Entry;
+use Error;
+use Options;
+use Result;
+
+/// The maximum size of a request line along with its headers.
+const MAX_REQUEST: u64 = 8 * 1024;
+/// How long a client may take to send its request in total.
+const REQUEST_DEADLINE: time::Duration = time::Duration::from_secs(5);
+/// The maximum number of connections whose requests are read at a
+/// time. Further connections are turned away.
+const MAX_PENDING: usize = 64;
+/// The maximum number of results a lookup reports.
+pub const MAX_RESULTS: usize = 200;
+/// How often to check whether to shut down while no connections come
+/// in.
+const POLL_INTERVAL: time::Duration = time::Duration::from_millis(100);
+
+/// Whether a signal asked the server to shut down.
+static SHUTDOWN: atomic::AtomicBool = atomic::AtomicBool::new(false);
+
+
+/// A request received by the server.
+#[derive(Debug, PartialEq)]
+pub struct Request {
+  /// The method, e.g., "GET".
+  pub method: String,
+  /// The path of the target, without the query string.
+  pub path: String,
+  /// The decoded parameters of the query string, in order.
+  pub params: Vec<(String, String)>,
+}
+
+impl Request {
+  /// Read a request, i.e., its request line and headers.
+  pub fn read<R>(reader: R) -> Result<Request>
+  where
+    R: Read,
+  {
+    let mut reader = io::BufReader::new(reader.take(MAX_REQUEST));
+    let mut line = String::new();
+    let _ = reader
+      .read_line(&mut line)
+      .map_err(|e| Error::Io("Failed to read request".to_string(), e))?;
+    let request = Self::parse(line.trim_end())?;
+
+    // The headers are of no interest, but a request is only complete
+    // once all of them are read.
+    loop {
+      line.clear();
+      match reader.read_line(&mut line) {
+        Ok(0) => return Err(Error::Error("Incomplete request".to_string())),
+        Ok(_) if line.trim_end().is_empty() => return Ok(request),
+        Ok(_) => (),
+        Err(e) => return Err(Error::Io("Failed to read request".to_string(), e)),
+      }
+    }
+  }
+
+  /// Parse a request line, e.g., "GET /translate?q=Zorn HTTP/1.1".
+  pub fn parse(line: &str) -> Result<Request> {
+    let invalid = || Error::Error(format!("Invalid request line: {}", line));
+    let mut parts = line.split(' ');
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+      (Some(method), Some(target), Some(version), None) => (method, target, version),
+      _ => return Err(invalid()),
+    };
+    if method.is_empty() || !target.starts_with('/') || !version.starts_with("HTTP/1.") {
+      return Err(invalid())
+    }
+
+    let (path, query) = match target.find('?') {
+      Some(i) => (&target[..i], &target[i + 1..]),
+      None => (target, ""),
+    };
+    let params = query
+      .split('&')
+      .filter(|param| !param.is_empty())
+      .map(|param| {
+        let (name, value) = match param.find('=') {
+          Some(i) => (&param[..i], &param[i + 1..]),
+          None => (param, ""),
+        };
+        Ok((decode(name)?, decode(value)?))
+      })
+      .collect::<Result<Vec<_>>>()?;
+
+    Ok(Request {
+      method: method.to_string(),
+      path: decode(path)?,
+      params,
+    })
+  }
+
+  /// Retrieve the value of the first parameter with the given name.
+  pub fn param(&self, name: &str) -> Option<&str> {
+    self
+      .params
+      .iter()
+      .find(|(n, _)| n == name)
+      .map(|(_, value)| value.as_str())
+  }
+}
+
+
+/// A response to a request.
+#[derive(Debug, PartialEq)]
+pub struct Response {
+  /// The status code.
+  pub status: u16,
+  /// The type of the content of the body.
+  pub content_type: &'static str,
+  /// The body.
+  pub body: String,
+}
+
+impl Response {
+  /// Create a response with a body of plain text.
+  pub fn text(status: u16, body: String) -> Response {
+    Response {
+      status,
+      content_type: "text/plain; charset=utf-8",
+      body,
+    }
+  }
+
+  /// Create a response with a JSON body.
+  pub fn json(status: u16, body: String) -> Response {
+    Response {
+      status,
+      content_type: "application/json",
+      body,
+    }
+  }
+
+  /// Write the response, including its status line and headers.
+  ///
+  /// Web pages and browser extensions from `allow_origin` are allowed
+  /// to read the response.
+  pub fn write_to<W>(&self, mut writer: W, allow_origin: Option<&str>) -> io::Result<()>
+  where
+    W: Write,
+  {
+    write!(
+      writer,
+      "HTTP/1.1 {} {}\r\n\
+       Content-Type: {}\r\n\
+       Content-Length: {}\r\n",
+      self.status, reason(self.status), self.content_type, self.body.len(),
+    )?;
+    if let Some(origin) = allow_origin {
+      write!(writer, "Access-Control-Allow-Origin: {}\r\nVary: Origin\r\n", origin)?;
+    }
+    write!(writer, "Connection: close\r\n\r\n{}", self.body)?;
+    writer.flush()
+  }
+}
+
+
+/// The settings lookups are performed with, determined once when the
+/// server starts.
+#[derive(Clone, Debug)]
+pub struct Lookups {
+  /// The database to look up terms in.
+  pub database: path::PathBuf,
+  /// The direction to translate in unless requested otherwise.
+  pub direction: Direction,
+  /// The names of language 1 and language 2, for labeling the results
+  /// of lookups in both directions.
+  pub languages: Option<(String, String)>,
+  /// The options to look up terms with.
+  pub options: Options,
+}
+
+impl Lookups {
+  /// Answer a request by looking up the term it contains.
+  ///
+  /// `GET /translate` takes the term in `q`, the direction in `dir`
+  /// (`forward`, the default, `reverse`, `both`, or `auto`), and the
+  /// format of the results in `format` (`json` by default).
+  pub fn answer(&self, request: &Request) -> Response {
+    if request.method != "GET" {
+      return Response::text(405, "Only GET requests are supported\n".to_string())
+    }
+    if request.path != "/translate" {
+      return Response::text(404, format!("No such resource: {}\n", request.path))
+    }
+
+    let term = match request.param("q") {
+      Some(term) if !term.trim().is_empty() => term,
+      _ => return Response::text(400, "Missing parameter: q\n".to_string()),
+    };
+    let (forward, reverse) = match self.direction {
+      Direction::Lang1ToLang2 => (Direction::Lang1ToLang2, Direction::Lang2ToLang1),
+      Direction::Lang2ToLang1 => (Direction::Lang2ToLang1, Direction::Lang1ToLang2),
+    };
+    let directions = match request.param("dir").unwrap_or("forward") {
+      "forward" => Some(vec![forward]),
+      "reverse" => Some(vec![reverse]),
+      "both" => Some(vec![forward, reverse]),
+      "auto" => None,
+      dir => return Response::text(400, format!("Invalid direction: {}\n", dir)),
+    };
+    let format = match results::Format::from_name(request.param("format").unwrap_or("json")) {
+      Ok(format) => format,
+      Err(err) => return Response::text(400, format!("{}\n", err)),
+    };
+
+    match self.look_up(term, directions, format) {
+      Ok(output) if format == results::Format::Json => Response::json(200, output),
+      Ok(output) => Response::text(200, output),
+      Err(err @ Error::QuerySyntax(_)) => Response::text(400, format!("{}\n", err)),
+      Err(err) => {
+        warn!("Failed to look up {:?}: {}", term, err);
+        Response::text(500, "Lookup failed\n".to_string())
+      },
+    }
+  }
+
+  /// Look up a term, rendering the results in the given format.
+  ///
+  /// The direction is detected if no directions are given.
+  fn look_up(&self, input: &str, directions: Option<Vec<Direction>>,
+             format: results::Format) -> Result<String> {
+    // The term is normalized the way the translate command does it.
+    let term = input.split_whitespace().collect::<Vec<_>>().join(" ");
+    let term = match term::strip_punctuation(&term) {
+      stripped if !stripped.is_empty() => stripped.to_string(),
+      _ => term,
+    };
+    let mut options = self.options.clone();
+    let term = match term::unquote(&term) {
+      Some(phrase) => {
+        options.phrase = true;
+        phrase.to_string()
+      },
+      None => term,
+    };
+    if let results::Format::OneLine | results::Format::First = format {
+      options.rank = Some(term.clone());
+    }
+    let is_query = !options.phrase && query::is_query(&term);
+    let expr = if is_query {
+      Some(query::parse(&term)?)
+    } else {
+      None
+    };
+
+    let db = self.database.as_path();
+    let terms = [term.as_str()];
+    let (directions, labeled) = match directions {
+      Some(directions) => {
+        let labeled = directions.len() > 1;
+        (directions, labeled)
+      },
+      None => {
+        let detected = detect_direction(db, &term, |direction: &Direction| {
+          Ok(term_query(&terms, direction, &options))
+        })?;
+        (vec![detected], false)
+      },
+    };
+
+    let mut entries = Vec::new();
+    for direction in &directions {
+      let label = if labeled {
+        Some(self.label(direction))
+      } else {
+        None
+      };
+      let mut record = |entry: &Entry| {
+        let exact = if options.ignore_accents {
+          rank::classify(&like::unaccent(&entry.source.text), &like::unaccent(&term))
+        } else {
+          rank::classify(&entry.source.text, &term)
+        };
+        entries.push(results::Match {
+          direction: label.clone(),
+          word: None,
+          entry: entry.clone(),
+          related: !is_query && exact != rank::Match::Exact,
+          band: None,
+          inflections: Vec::new(),
+        });
+        Ok(())
+      };
+      match expr {
+        Some(ref expr) => {
+          let expand = |term: &str| vec![term.to_string()];
+          translate_query(db, expr, direction, &options, expand, &mut record)?
+        },
+        None => translate_with(db, &terms, direction, &options, &mut record)?,
+      }
+    }
+
+    let results = results::Results {
+      query: term.clone(),
+      entries,
+    };
+    Ok(results.render(format))
+  }
+
+  /// Label results found translating in the given direction.
+  fn label(&self, direction: &Direction) -> String {
+    match (&self.languages, direction) {
+      (Some((l1, l2)), Direction::Lang1ToLang2) => format!("{}→{}", l1, l2),
+      (Some((l1, l2)), Direction::Lang2ToLang1) => format!("{}→{}", l2, l1),
+      (None, Direction::Lang1ToLang2) => "1→2".to_string(),
+      (None, Direction::Lang2ToLang1) => "2→1".to_string(),
+    }
+  }
+}
+
+
+/// Retrieve the reason phrase of a status code.
+fn reason(status: u16) -> &'static str {
+  match status {
+    200 => "OK",
+    400 => "Bad Request",
+    404 => "Not Found",
+    405 => "Method Not Allowed",
+    408 => "Request Timeout",
+    _ => "Internal Server Error",
+  }
+}
+
+/// Decode a percent-encoded component of a URL, with '+' denoting a
+/// space.
+fn decode(component: &str) -> Result<String> {
+  let invalid = || Error::Error(format!("Invalid encoding: {}", component));
+  let bytes = component.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    match bytes[i] {
+      b'%' => {
+        let hex = bytes.get(i + 1..i + 3).ok_or_else(invalid)?;
+        let hex = std::str::from_utf8(hex).map_err(|_| invalid())?;
+        decoded.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+        i += 3;
+      },
+      b'+' => {
+        decoded.push(b' ');
+        i += 1;
+      },
+      byte => {
+        decoded.push(byte);
+        i += 1;
+      },
+    }
+  }
+  String::from_utf8(decoded).map_err(|_| invalid())
+}
+
+extern "C" fn request_shutdown(_: libc::c_int) {
+  SHUTDOWN.store(true, atomic::Ordering::SeqCst);
+}
+
+/// Make SIGINT and SIGTERM request the shut down of the server instead
+/// of terminating the program, returning the flag they set.
+pub fn shutdown_on_signal() -> &'static atomic::AtomicBool {
+  let handler = request_shutdown as extern "C" fn(libc::c_int) as libc::sighandler_t;
+  unsafe {
+    libc::signal(libc::SIGINT, handler);
+    libc::signal(libc::SIGTERM, handler);
+  }
+  &SHUTDOWN
+}
+
+
+/// A stream of data that has to arrive by a deadline, irrespective of
+/// how it is split up.
+struct Deadline<'s> {
+  stream: &'s net::TcpStream,
+  deadline: time::Instant,
+}
+
+impl<'s> Read for Deadline<'s> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let now = time::Instant::now();
+    if now >= self.deadline {
+      return Err(io::Error::new(io::ErrorKind::TimedOut, "request not received in time"))
+    }
+    self.stream.set_read_timeout(Some(self.deadline - now))?;
+    self.stream.read(buf)
+  }
+}
+
+
+/// A connection whose request was received, waiting to be answered.
+struct Pending {
+  stream: net::TcpStream,
+  peer: String,
+  start: time::Instant,
+  request: Result<Request>,
+}
+
+/// Read the request of a connection.
+fn receive(stream: net::TcpStream, start: time::Instant) -> Pending {
+  let peer = stream
+    .peer_addr()
+    .map(|addr| addr.to_string())
+    .unwrap_or_else(|_| "-".to_string());
+  let request = stream
+    .set_nonblocking(false)
+    .map_err(|e| Error::Io("Failed to configure connection".to_string(), e))
+    .and_then(|()| {
+      Request::read(Deadline {
+        stream: &stream,
+        deadline: start + REQUEST_DEADLINE,
+      })
+    });
+  Pending {
+    stream,
+    peer,
+    start,
+    request,
+  }
+}
+
+/// Answer a connection whose request was received.
+fn answer<H>(pending: Pending, allow_origin: Option<&str>, handler: &H)
+where
+  H: Fn(&Request) -> Response,
+{
+  let Pending { stream, peer, start, request } = pending;
+  let (line, response) = match request {
+    Ok(request) => {
+      let line = format!("{} {}", request.method, request.path);
+      (line, handler(&request))
+    },
+    Err(Error::Io(_, ref e)) if e.kind() == io::ErrorKind::TimedOut
+                                || e.kind() == io::ErrorKind::WouldBlock => {
+      ("-".to_string(), Response::text(408, "Request not received in time\n".to_string()))
+    },
+    Err(err) => ("-".to_string(), Response::text(400, format!("{}\n", err))),
+  };
+  let _ = stream.set_write_timeout(Some(REQUEST_DEADLINE));
+  if let Err(err) = response.write_to(&stream, allow_origin) {
+    warn!("Failed to respond to {}: {}", peer, err);
+  }
+  info!("{} \"{}\" {} {:.1?}", peer, line, response.status, start.elapsed());
+}
+
+/// Serve the connections coming in on a listener with the given number
+/// of worker threads, until `shutdown` is set.
+///
+/// Each request is answered with the response `handler` creates for
+/// it. Web pages and browser extensions from `allow_origin` (which may
+/// be `*` for any) are allowed to read responses.
+pub fn serve<H>(listener: net::TcpListener, workers: usize, allow_origin: Option<&str>,
+                shutdown: &atomic::AtomicBool, handler: H) -> Result<()>
+where
+  H: Fn(&Request) -> Response + Sync,
+{
+  // We poll for connections, so that we notice when to shut down.
+  listener
+    .set_nonblocking(true)
+    .map_err(|e| Error::Io("Failed to configure listener".to_string(), e))?;
+
+  let (sender, receiver) = mpsc::channel::<Pending>();
+  let receiver = sync::Mutex::new(receiver);
+  let receiving = atomic::AtomicUsize::new(0);
+  thread::scope(|scope| {
+    for _ in 0..workers.max(1) {
+      let receiver = &receiver;
+      let handler = &handler;
+      scope.spawn(move || sharing_connections(|| loop {
+        let pending = match receiver.lock().unwrap_or_else(|e| e.into_inner()).recv() {
+          Ok(pending) => pending,
+          Err(_) => break,
+        };
+        answer(pending, allow_origin, handler);
+      }));
+    }
+
+    let result = loop {
+      if shutdown.load(atomic::Ordering::SeqCst) {
+        break Ok(())
+      }
+      match listener.accept() {
+        Ok((stream, peer)) => {
+          if receiving.load(atomic::Ordering::SeqCst) >= MAX_PENDING {
+            warn!("Too many pending requests; turning away {}", peer);
+            continue
+          }
+          // Requests are read separately from the workers, so that a
+          // client sending its request slowly does not keep a worker
+          // from answering others.
+          let start = time::Instant::now();
+          let _ = receiving.fetch_add(1, atomic::Ordering::SeqCst);
+          let sender = sender.clone();
+          let receiving = &receiving;
+          let _ = scope.spawn(move || {
+            let pending = receive(stream, start);
+            let _ = receiving.fetch_sub(1, atomic::Ordering::SeqCst);
+            let _ = sender.send(pending);
+          });
+        },
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+        Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+        Err(e) => break Err(Error::Io("Failed to accept connection".to_string(), e)),
+      }
+    };
+    // The workers answer the requests received so far and then
+    // terminate.
+    drop(sender);
+    result
+  })
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::net::TcpStream;
+
+
+  /// Check that we can parse request lines.
+  #[test]
+  fn parse_request() {
+    let request = Request::parse("GET /translate?q=Zorn+M%C3%BCller&dir=reverse&x HTTP/1.1").unwrap();
+    assert_eq!(request.method, "GET");
+    assert_eq!(request.path, "/translate");
+    assert_eq!(request.param("q"), Some("Zorn Müller"));
+    assert_eq!(request.param("dir"), Some("reverse"));
+    assert_eq!(request.param("x"), Some(""));
+    assert_eq!(request.param("format"), None);
+
+    let request = Request::parse("GET / HTTP/1.0").unwrap();
+    assert_eq!(request.path, "/");
+    assert!(request.params.is_empty());
+
+    assert!(Request::parse("GET /translate").is_err());
+    assert!(Request::parse("GET translate HTTP/1.1").is_err());
+    assert!(Request::parse("GET /translate HTTP/2 x").is_err());
+    assert!(Request::parse("GET /translate?q=%C3 HTTP/1.1").is_err());
+    assert!(Request::parse("GET /translate?q=%z1 HTTP/1.1").is_err());
+    assert!(Request::parse("GET /translate?q=%4 HTTP/1.1").is_err());
+  }
+
+  /// Check that reading a request consumes its headers.
+  #[test]
+  fn read_request() {
+    let data = b"GET /translate?q=a HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    let request = Request::read(&data[..]).unwrap();
+    assert_eq!(request.param("q"), Some("a"));
+
+    let data = b"GET /translate?q=a HTTP/1.1\r\nHost: localhost\r\n";
+    assert!(Request::read(&data[..]).is_err());
+  }
+
+  /// Check that responses are written as expected.
+  #[test]
+  fn write_response() {
+    let mut buffer = Vec::new();
+    let response = Response::json(200, "{}".to_string());
+    let () = response.write_to(&mut buffer, None).unwrap();
+    let expected = "HTTP/1.1 200 OK\r\n\
+                    Content-Type: application/json\r\n\
+                    Content-Length: 2\r\n\
+                    Connection: close\r\n\
+                    \r\n\
+                    {}";
+    assert_eq!(String::from_utf8(buffer).unwrap(), expected);
+
+    let mut buffer = Vec::new();
+    let () = response.write_to(&mut buffer, Some("moz-extension://dictcc")).unwrap();
+    let response = String::from_utf8(buffer).unwrap();
+    assert!(response.contains("\r\nAccess-Control-Allow-Origin: moz-extension://dictcc\r\n"));
+  }
+
+  /// Check that lookups are answered as expected.
+  #[test]
+  fn answer_lookups() {
+    let lookups = Lookups {
+      database: path::PathBuf::from("./test/test.db"),
+      direction: Direction::Lang1ToLang2,
+      languages: None,
+      options: Options::default(),
+    };
+    let answer = |line: &str| {
+      let request = Request::parse(&format!("{} HTTP/1.1", line)).unwrap();
+      lookups.answer(&request)
+    };
+
+    let response = answer("GET /translate?q=Zorn");
+    assert_eq!(response.status, 200);
+    assert_eq!(response.content_type, "application/json");
+    assert!(response.body.starts_with(r#"{"query":"Zorn","entries":[{"#), "{}", response.body);
+
+    let response = answer("GET /translate?q=anger&dir=reverse&format=first");
+    assert_eq!((response.status, response.body.as_str()), (200, "Zorn\n"));
+
+    let response = answer("GET /translate?q=Zorn&dir=both&format=plain");
+    assert_eq!((response.status, response.body.as_str()), (200, "1→2 Zorn {m} (noun): anger\n"));
+
+    let response = answer("GET /translate?q=Zorn+OR+Wut&format=tsv");
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body.lines().count(), 2, "{}", response.body);
+
+    // Terms without translations are not followed up on.
+    let response = answer("GET /translate?q=Zornesfalte&format=plain");
+    assert_eq!((response.status, response.body.as_str()), (200, ""));
+
+    assert_eq!(answer("GET /translate").status, 400);
+    assert_eq!(answer("GET /translate?q=Zorn&dir=up").status, 400);
+    assert_eq!(answer("GET /translate?q=Zorn&format=xml").status, 400);
+    assert_eq!(answer("GET /translate?q=Zorn+OR+(Wut").status, 400);
+    let nested = format!("a+AND+{}Zorn{}+AND+b", "(".repeat(3000), ")".repeat(3000));
+    assert_eq!(answer(&format!("GET /translate?q={}", nested)).status, 400);
+    assert_eq!(answer("GET /lookup?q=Zorn").status, 404);
+    assert_eq!(answer("POST /translate?q=Zorn").status, 405);
+  }
+
+  /// Check that the server answers requests and shuts down once asked
+  /// to.
+  #[test]
+  fn serve_requests() {
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = atomic::AtomicBool::new(false);
+
+    thread::scope(|scope| {
+      let server = scope.spawn(|| {
+        serve(listener, 1, None, &shutdown, |request| {
+          let body = request.param("q").unwrap_or("").to_uppercase();
+          Response::text(200, body)
+        })
+      });
+
+      // Clients that do not send their request hold up neither the
+      // single worker nor anybody else.
+      let idle = (0..4).map(|_| TcpStream::connect(addr).unwrap()).collect::<Vec<_>>();
+
+      for (request, expected) in &[
+        ("GET /translate?q=zorn HTTP/1.1\r\n\r\n", "HTTP/1.1 200 OK"),
+        ("nonsense\r\n\r\n", "HTTP/1.1 400 Bad Request"),
+      ] {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let () = stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with(expected), "{}", response);
+      }
+
+      let mut stream = TcpStream::connect(addr).unwrap();
+      let () = stream.write_all(b"GET /?q=zorn HTTP/1.1\r\n\r\n").unwrap();
+      let mut response = String::new();
+      let _ = stream.read_to_string(&mut response).unwrap();
+      assert!(response.ends_with("\r\n\r\nZORN"), "{}", response);
+      assert!(!response.contains("Access-Control-Allow-Origin"), "{}", response);
+      drop(idle);
+
+      shutdown.store(true, atomic::Ordering::SeqCst);
+      let () = server.join().unwrap().unwrap();
+    });
+  }
+}
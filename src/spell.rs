@@ -0,0 +1,186 @@
+// spell.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! A simple spellchecker backed by the words of one side of the
+//! dictionary.
+
+use std::collections::HashSet;
+
+use sqlite;
+
+use compound;
+use stem;
+use suggest;
+use Result;
+use SEARCH_TBL;
+use TERM1_COL;
+use TERM2_COL;
+
+/// The number of rows we sample for figuring out which column contains
+/// the German terms.
+const SAMPLE_SIZE: usize = 1000;
+
+
+/// A word not found in the dictionary.
+#[derive(Debug, PartialEq)]
+pub struct Misspelling {
+  /// The (1-based) line the word appeared in.
+  pub line: usize,
+  /// The word as it appeared in the text.
+  pub word: String,
+  /// Known words similar to the misspelled one.
+  pub suggestions: Vec<String>,
+}
+
+
+/// Determine which of the two term columns contains German terms, by
+/// checking which one uses more umlauts and 'ß'.
+pub fn german_column(connection: &sqlite::Connection) -> Result<&'static str> {
+  let select = format!(
+    "SELECT {t1}, {t2} FROM {tbl} LIMIT {n}",
+    t1 = TERM1_COL, t2 = TERM2_COL, tbl = SEARCH_TBL, n = SAMPLE_SIZE,
+  );
+  let mut cursor = connection.prepare(select)?.cursor();
+  let count = |text: Option<&str>| {
+    text.map(|t| t.chars().filter(|c| "äöüÄÖÜß".contains(*c)).count()).unwrap_or(0)
+  };
+
+  let (mut count1, mut count2) = (0, 0);
+  while let Some(row) = cursor.next()? {
+    count1 += count(row[0].as_string());
+    count2 += count(row[1].as_string());
+  }
+  Ok(if count2 > count1 { TERM2_COL } else { TERM1_COL })
+}
+
+
+/// Split a text into the words to check, as (line, word) pairs.
+///
+/// Words containing digits are skipped, as are single characters.
+fn words(text: &str) -> Vec<(usize, &str)> {
+  text
+    .lines()
+    .enumerate()
+    .flat_map(|(i, line)| {
+      line
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() > 1 && !w.chars().any(|c| c.is_numeric()))
+        .map(move |w| (i + 1, w))
+    })
+    .collect()
+}
+
+
+/// A spellchecker for one side of the dictionary.
+pub struct Checker {
+  /// The (lower case) words known to the dictionary.
+  words: HashSet<String>,
+  /// The stems of all known words.
+  stems: HashSet<String>,
+}
+
+impl Checker {
+  /// Create a spellchecker using the words in the given column.
+  pub fn new(connection: &sqlite::Connection, column: &str) -> Result<Checker> {
+    let words = compound::load_words(connection, column)?;
+    let stems = words.iter().flat_map(|w| stem::candidates(w).into_iter().skip(1)).collect();
+    Ok(Checker {
+      words,
+      stems,
+    })
+  }
+
+  /// Check whether a word is known, possibly in inflected form or as
+  /// part of a compound.
+  pub fn is_known(&self, word: &str) -> bool {
+    let lower = word.to_lowercase();
+    self.words.contains(&lower) ||
+      stem::candidates(&lower).iter().any(|s| self.stems.contains(s) || self.words.contains(s)) ||
+      compound::split(word, &self.words).is_some()
+  }
+
+  /// Check a text, reporting all words not known to the dictionary
+  /// along with at most `count` suggestions each.
+  pub fn check(&self, text: &str, count: usize) -> Vec<Misspelling> {
+    words(text)
+      .into_iter()
+      .filter(|&(_, word)| !self.is_known(word))
+      .map(|(line, word)| Misspelling {
+        line,
+        word: word.to_string(),
+        suggestions: suggest::closest(&self.words, word, count),
+      })
+      .collect()
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::path;
+
+  use open;
+
+  #[test]
+  fn split_words() {
+    assert_eq!(
+      words("Die Liebe, 2 Mal!\nE-Mail"),
+      vec![(1, "Die"), (1, "Liebe"), (1, "Mal"), (2, "Mail")]
+    );
+  }
+
+  #[test]
+  fn detect_german() {
+    let connection = open(path::Path::new("./test/test.db")).unwrap();
+    assert_eq!(german_column(&connection).unwrap(), TERM1_COL);
+  }
+
+  #[test]
+  fn spellcheck_text() {
+    let connection = open(path::Path::new("./test/test.db")).unwrap();
+    let checker = Checker::new(&connection, TERM1_COL).unwrap();
+    assert!(checker.is_known("Liebe"));
+    assert!(checker.is_known("Statistiken"));
+    assert!(checker.is_known("Liebesärger"));
+
+    let found = checker.check("Liebe und Zorn\nWut und Ärgr", 3);
+    assert_eq!(
+      found,
+      vec![
+        Misspelling {
+          line: 1,
+          word: "und".to_string(),
+          suggestions: vec![],
+        },
+        Misspelling {
+          line: 2,
+          word: "und".to_string(),
+          suggestions: vec![],
+        },
+        Misspelling {
+          line: 2,
+          word: "Ärgr".to_string(),
+          suggestions: vec!["ärger".to_string()],
+        },
+      ]
+    );
+  }
+}
@@ -0,0 +1,211 @@
+// stats.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Statistics about a database.
+//!
+//! The numbers of entries overall, per type, and per subject, along
+//! with the size of the database, the search facilities available
+//! for it, and its metadata, help confirm that an import worked and
+//! comparing different versions of a database.
+
+use std::fmt;
+
+use sqlite;
+
+use check::table_sql;
+use metadata;
+use subjects;
+use types;
+use Result;
+use SEARCH_TBL;
+use TERM1_COL;
+
+
+/// Statistics about a database.
+#[derive(Debug)]
+pub struct Stats {
+  /// The number of entries.
+  pub entries: usize,
+  /// The number of entries per type, most common first.
+  pub types: Vec<(String, usize)>,
+  /// The number of entries per subject, most common first.
+  pub subjects: Vec<(String, usize)>,
+  /// The size of the database in bytes.
+  pub size: u64,
+  /// The version of the full-text search module of the search table
+  /// (e.g., "FTS3"), if it is a full-text table.
+  pub fts: Option<String>,
+  /// Whether the SQLite library was built with ICU support.
+  pub icu: bool,
+  /// The metadata of the database, as (key, value) pairs.
+  pub metadata: Vec<(String, String)>,
+}
+
+impl Stats {
+  /// Gather the statistics of the database behind a connection.
+  pub fn gather(connection: &sqlite::Connection) -> Result<Stats> {
+    let types = types::types(connection, TERM1_COL, 0)?
+      .into_iter()
+      .map(|t| (t.name, t.count))
+      .collect::<Vec<_>>();
+    let entries = types.iter().map(|(_, count)| count).sum();
+
+    let mut subjects = subjects::subjects(connection)?
+      .into_iter()
+      .map(|s| {
+        let id = s.id;
+        (s.name.unwrap_or_else(|| format!("#{}", id)), s.count)
+      })
+      .collect::<Vec<_>>();
+    subjects.sort_by(|(name1, count1), (name2, count2)| {
+      count2.cmp(count1).then_with(|| name1.cmp(name2))
+    });
+
+    let fts = table_sql(connection, SEARCH_TBL)?.and_then(|sql| {
+      let sql = sql.to_lowercase();
+      ["fts3", "fts4", "fts5"]
+        .iter()
+        .find(|module| sql.contains(&format!("using {}", module)))
+        .map(|module| module.to_uppercase())
+    });
+
+    let mut statement = connection.prepare(
+      "SELECT page_count * page_size, sqlite_compileoption_used('ENABLE_ICU') \
+       FROM pragma_page_count(), pragma_page_size()",
+    )?;
+    statement.next()?;
+
+    Ok(Stats {
+      entries,
+      types,
+      subjects,
+      size: statement.read::<i64>(0)? as u64,
+      fts,
+      icu: statement.read::<i64>(1)? != 0,
+      metadata: metadata::load(connection)?,
+    })
+  }
+}
+
+/// Format a size in bytes in a human readable way, e.g., "1.5 MiB".
+pub fn format_size(size: u64) -> String {
+  let (size, unit) = if size >= 1024 * 1024 {
+    (size as f64 / (1024.0 * 1024.0), "MiB")
+  } else {
+    (size as f64 / 1024.0, "KiB")
+  };
+  format!("{:.1} {}", size, unit)
+}
+
+impl fmt::Display for Stats {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let yes_no = |flag| if flag { "yes" } else { "no" };
+
+    writeln!(f, "Entries: {}", self.entries)?;
+    writeln!(f, "Size: {} ({} bytes)", format_size(self.size), self.size)?;
+    writeln!(f, "Full-text search: {}", self.fts.as_deref().unwrap_or("none"))?;
+    writeln!(f, "ICU support: {}", yes_no(self.icu))?;
+    for (key, value) in &self.metadata {
+      match key.as_str() {
+        metadata::IMPORTED | metadata::UPDATED => {
+          writeln!(f, "{}: {} UTC", metadata::label(key), value)?
+        },
+        _ => writeln!(f, "{}: {}", metadata::label(key), value)?,
+      }
+    }
+    writeln!(f, "Types:")?;
+    for (name, count) in &self.types {
+      writeln!(f, "  {}: {}", name, count)?;
+    }
+    if !self.subjects.is_empty() {
+      writeln!(f, "Subjects:")?;
+      for (name, count) in &self.subjects {
+        writeln!(f, "  {}: {}", name, count)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::path;
+
+  use open;
+
+  /// Create an in-memory database with a couple of entries and
+  /// subjects.
+  fn subject_db() -> sqlite::Connection {
+    let connection = sqlite::open(":memory:").unwrap();
+    connection
+      .execute(
+        "CREATE TABLE main_ft (id INTEGER, term1 VARCHAR, term2 VARCHAR, \
+                               subj_ids VARCHAR, entry_type VARCHAR, vt_usage INTEGER); \
+         INSERT INTO main_ft VALUES (1, 'Herz {n}', 'heart', ',1,2,', 'noun', 40); \
+         INSERT INTO main_ft VALUES (2, 'Vertrag {m}', 'contract', ',3,', 'noun', 35); \
+         INSERT INTO main_ft VALUES (3, 'Kardiologie {f}', 'cardiology', ',2,', 'noun', 20); \
+         INSERT INTO main_ft VALUES (4, 'Liebe {f}', 'love', '', 'noun', 41); \
+         INSERT INTO main_ft VALUES (5, 'Sonde {f}', 'probe', ',9,', 'noun', 25); \
+         CREATE TABLE subjects (id INTEGER, name VARCHAR, parent_id INTEGER); \
+         INSERT INTO subjects VALUES (1, 'med.', 0); \
+         INSERT INTO subjects VALUES (2, 'cardio.', 1); \
+         INSERT INTO subjects VALUES (3, 'law', 0); \
+         INSERT INTO subjects VALUES (4, 'zool.', 0);",
+      )
+      .unwrap();
+    connection
+  }
+
+  #[test]
+  fn gather_stats() {
+    let connection = open(path::Path::new("./test/test.db")).unwrap();
+    let stats = Stats::gather(&connection).unwrap();
+    assert_eq!(stats.entries, 29);
+    assert_eq!(stats.types[0], ("noun".to_string(), 15));
+    assert_eq!(stats.types.iter().map(|(_, count)| count).sum::<usize>(), 29);
+    assert_eq!(stats.fts.as_deref(), Some("FTS3"));
+    assert!(stats.size > 0);
+
+    let text = stats.to_string();
+    let mut lines = text.lines();
+    assert_eq!(lines.next(), Some("Entries: 29"));
+    assert!(lines.next().unwrap().starts_with("Size: "));
+    assert_eq!(lines.next(), Some("Full-text search: FTS3"));
+    assert!(lines.next().unwrap().starts_with("ICU support: "));
+    assert_eq!(lines.next(), Some("Types:"));
+    assert_eq!(lines.next(), Some("  noun: 15"));
+  }
+
+  #[test]
+  fn gather_subject_stats() {
+    let connection = subject_db();
+    let stats = Stats::gather(&connection).unwrap();
+    assert_eq!(stats.fts, None);
+    assert_eq!(stats.subjects, vec![
+      ("cardio.".to_string(), 2),
+      ("#9".to_string(), 1),
+      ("law".to_string(), 1),
+      ("med.".to_string(), 1),
+      ("zool.".to_string(), 0),
+    ]);
+  }
+}
@@ -0,0 +1,78 @@
+// stem.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Generation of stemmed candidates for a query.
+
+use rust_stemmers::Algorithm;
+use rust_stemmers::Stemmer;
+
+/// The stemming algorithms we try. We do not know which language a
+/// query is in and so we just apply all of them.
+const ALGORITHMS: [Algorithm; 2] = [Algorithm::English, Algorithm::German];
+
+
+/// Generate the terms to search for when looking up `term` in a
+/// stemming-aware manner.
+///
+/// The first candidate is always the term itself. It is followed by
+/// the variants with each word reduced to its stem, for each of the
+/// supported languages. Duplicates are removed.
+pub fn candidates(term: &str) -> Vec<String> {
+  let mut candidates = vec![term.to_string()];
+
+  for algorithm in &ALGORITHMS {
+    let stemmer = Stemmer::create(*algorithm);
+    let stemmed = term
+      .split_whitespace()
+      .map(|word| stemmer.stem(&word.to_lowercase()).into_owned())
+      .collect::<Vec<_>>()
+      .join(" ");
+
+    if !stemmed.is_empty() && !candidates.iter().any(|c| c.to_lowercase() == stemmed) {
+      candidates.push(stemmed);
+    }
+  }
+  candidates
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn stem_english() {
+    let found = candidates("running");
+    assert_eq!(found[0], "running");
+    assert!(found.contains(&"run".to_string()));
+  }
+
+  #[test]
+  fn stem_german() {
+    let found = candidates("Häuser");
+    assert_eq!(found[0], "Häuser");
+    assert!(found.contains(&"haus".to_string()));
+  }
+
+  #[test]
+  fn stem_phrase() {
+    let found = candidates("giving up");
+    assert!(found.contains(&"give up".to_string()));
+  }
+}
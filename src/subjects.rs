@@ -0,0 +1,302 @@
+// subjects.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Access to the subject areas entries are associated with.
+//!
+//! Each entry references the subjects it belongs to by means of a comma
+//! separated list of ids (e.g., ",6,89,") in its `subj_ids` column. The
+//! names of the subjects (and their hierarchy) are stored in a separate
+//! table, the layout of which differs between database versions. It is
+//! not present in all databases at all.
+
+use std::collections::BTreeMap;
+
+use sqlite;
+
+use Error;
+use Result;
+use SEARCH_TBL;
+use SUBJ_COL;
+
+/// The name of the table containing the subjects.
+const SUBJECTS_TBL: &str = "subjects";
+/// Candidates for the column containing a subject's id.
+const ID_COLS: [&str; 3] = ["id", "subj_id", "subject_id"];
+/// Candidates for the column containing a subject's name.
+const NAME_COLS: [&str; 5] = ["name", "subject", "abbrev", "short_name", "title"];
+/// Candidates for the column containing the id of a subject's parent.
+const PARENT_COLS: [&str; 3] = ["parent", "parent_id", "super_id"];
+
+
+/// A subject area.
+#[derive(Debug, PartialEq)]
+pub struct Subject {
+  /// The subject's id, as referenced by entries.
+  pub id: i64,
+  /// The subject's name, if known.
+  pub name: Option<String>,
+  /// The id of the subject's parent, if any.
+  pub parent: Option<i64>,
+  /// The number of entries associated with the subject.
+  pub count: usize,
+}
+
+
+/// Parse a list of subject ids in the form ",6,89,".
+pub fn parse_ids(ids: &str) -> Vec<i64> {
+  ids.split(',').filter_map(|id| id.trim().parse().ok()).collect()
+}
+
+/// Retrieve the names of the columns of a table, in lower case.
+pub fn table_columns(connection: &sqlite::Connection, table: &str) -> Result<Vec<String>> {
+  let mut columns = Vec::new();
+  let mut cursor = connection.prepare(format!("PRAGMA table_info({})", table))?.cursor();
+  while let Some(row) = cursor.next()? {
+    if let Some(name) = row[1].as_string() {
+      columns.push(name.to_lowercase());
+    }
+  }
+  Ok(columns)
+}
+
+/// Find the first of the given candidates in a list of columns.
+fn find_column(columns: &[String], candidates: &[&'static str]) -> Option<&'static str> {
+  candidates.iter().find(|c| columns.iter().any(|col| col == *c)).cloned()
+}
+
+/// Load the subjects from the subjects table, if there is one.
+fn load_names(connection: &sqlite::Connection) -> Result<BTreeMap<i64, Subject>> {
+  let mut subjects = BTreeMap::new();
+  let columns = table_columns(connection, SUBJECTS_TBL)?;
+  let id_col = match find_column(&columns, &ID_COLS) {
+    Some(id_col) => id_col,
+    None => return Ok(subjects),
+  };
+  let name_col = find_column(&columns, &NAME_COLS).unwrap_or("NULL");
+  let parent_col = find_column(&columns, &PARENT_COLS).unwrap_or("NULL");
+
+  let select = format!(
+    "SELECT {id}, {name}, {parent} FROM {tbl}",
+    id = id_col, name = name_col, parent = parent_col, tbl = SUBJECTS_TBL,
+  );
+  let mut cursor = connection.prepare(select)?.cursor();
+  while let Some(row) = cursor.next()? {
+    let id = row[0].as_integer().ok_or_else(|| Error::InvalidSchema(format!(
+      "Invalid subject id: {:?}",
+      row
+    )))?;
+    // Top-level subjects may reference a parent of 0 or themselves.
+    let parent = row[2].as_integer().filter(|&p| p > 0 && p != id);
+    subjects.insert(id, Subject {
+      id,
+      name: row[1].as_string().map(|n| n.to_string()),
+      parent,
+      count: 0,
+    });
+  }
+  Ok(subjects)
+}
+
+/// Retrieve all subjects along with the number of entries associated
+/// with each, ordered by id.
+pub fn subjects(connection: &sqlite::Connection) -> Result<Vec<Subject>> {
+  let mut subjects = load_names(connection)?;
+
+  let select = format!("SELECT {subj} FROM {tbl}", subj = SUBJ_COL, tbl = SEARCH_TBL);
+  let mut cursor = connection.prepare(select)?.cursor();
+  while let Some(row) = cursor.next()? {
+    if let Some(ids) = row[0].as_string() {
+      for id in parse_ids(ids) {
+        subjects
+          .entry(id)
+          .or_insert_with(|| Subject {
+            id,
+            name: None,
+            parent: None,
+            count: 0,
+          })
+          .count += 1;
+      }
+    }
+  }
+  Ok(subjects.into_values().collect())
+}
+
+/// Arrange subjects in a hierarchy, returning (depth, subject) pairs in
+/// depth-first order.
+pub fn tree(subjects: &[Subject]) -> Vec<(usize, &Subject)> {
+  fn visit<'s>(subjects: &'s [Subject],
+               parent: Option<i64>,
+               depth: usize,
+               result: &mut Vec<(usize, &'s Subject)>) {
+    for subject in subjects.iter().filter(|s| s.parent == parent) {
+      result.push((depth, subject));
+      visit(subjects, Some(subject.id), depth + 1, result);
+    }
+  }
+
+  let mut result = Vec::with_capacity(subjects.len());
+  visit(subjects, None, 0, &mut result);
+
+  // Subjects whose parent is unknown are treated as top-level ones.
+  let orphans = subjects
+    .iter()
+    .filter(|s| s.parent.is_some_and(|p| !subjects.iter().any(|o| o.id == p)));
+  for root in orphans {
+    result.push((0, root));
+    visit(subjects, Some(root.id), 1, &mut result);
+  }
+  result
+}
+
+/// Resolve a subject given by name (with or without trailing period,
+/// ignoring case) or id into the ids of the subject itself and all
+/// subjects below it in the hierarchy.
+pub fn resolve(subjects: &[Subject], subject: &str) -> Result<Vec<i64>> {
+  let normalize = |name: &str| name.trim().trim_end_matches('.').to_lowercase();
+  let wanted = normalize(subject);
+  let found = subjects.iter().find(|s| {
+    s.name.as_ref().is_some_and(|n| normalize(n) == wanted) ||
+      wanted.parse() == Ok(s.id)
+  });
+
+  let root = match found {
+    Some(root) => root,
+    None => {
+      let known = subjects
+        .iter()
+        .filter_map(|s| s.name.as_deref())
+        .collect::<Vec<_>>();
+      let error = if known.is_empty() {
+        format!("Unknown subject: {}", subject)
+      } else {
+        format!("Unknown subject: {} (known subjects: {})", subject, known.join(", "))
+      };
+      return Err(Error::Error(error));
+    },
+  };
+
+  let mut ids = vec![root.id];
+  let mut i = 0;
+  while i < ids.len() {
+    let parent = ids[i];
+    ids.extend(subjects.iter().filter(|s| s.parent == Some(parent)).map(|s| s.id));
+    i += 1;
+  }
+  Ok(ids)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Create an in-memory database with a couple of entries and
+  /// subjects.
+  fn subject_db() -> sqlite::Connection {
+    let connection = sqlite::open(":memory:").unwrap();
+    connection
+      .execute(
+        "CREATE TABLE main_ft (id INTEGER, term1 VARCHAR, term2 VARCHAR, \
+                               subj_ids VARCHAR, entry_type VARCHAR, vt_usage INTEGER); \
+         INSERT INTO main_ft VALUES (1, 'Herz {n}', 'heart', ',1,2,', 'noun', 40); \
+         INSERT INTO main_ft VALUES (2, 'Vertrag {m}', 'contract', ',3,', 'noun', 35); \
+         INSERT INTO main_ft VALUES (3, 'Kardiologie {f}', 'cardiology', ',2,', 'noun', 20); \
+         INSERT INTO main_ft VALUES (4, 'Liebe {f}', 'love', '', 'noun', 41); \
+         INSERT INTO main_ft VALUES (5, 'Sonde {f}', 'probe', ',9,', 'noun', 25); \
+         CREATE TABLE subjects (id INTEGER, name VARCHAR, parent_id INTEGER); \
+         INSERT INTO subjects VALUES (1, 'med.', 0); \
+         INSERT INTO subjects VALUES (2, 'cardio.', 1); \
+         INSERT INTO subjects VALUES (3, 'law', 0); \
+         INSERT INTO subjects VALUES (4, 'zool.', 0);",
+      )
+      .unwrap();
+    connection
+  }
+
+  #[test]
+  fn parse_subject_ids() {
+    assert_eq!(parse_ids(",6,89,"), vec![6, 89]);
+    assert_eq!(parse_ids(""), Vec::<i64>::new());
+  }
+
+  #[test]
+  fn list_subjects() {
+    let connection = subject_db();
+    let found = subjects(&connection).unwrap();
+    let found = found
+      .iter()
+      .map(|s| (s.id, s.name.clone(), s.count))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      found,
+      vec![
+        (1, Some("med.".to_string()), 1),
+        (2, Some("cardio.".to_string()), 2),
+        (3, Some("law".to_string()), 1),
+        (4, Some("zool.".to_string()), 0),
+        (9, None, 1),
+      ]
+    );
+  }
+
+  #[test]
+  fn subject_tree() {
+    let connection = subject_db();
+    let found = subjects(&connection).unwrap();
+    let tree = tree(&found)
+      .into_iter()
+      .map(|(depth, s)| (depth, s.id))
+      .collect::<Vec<_>>();
+    assert_eq!(tree, vec![(0, 1), (1, 2), (0, 3), (0, 4), (0, 9)]);
+  }
+
+  #[test]
+  fn resolve_subjects() {
+    let connection = subject_db();
+    let found = subjects(&connection).unwrap();
+    assert_eq!(resolve(&found, "med").unwrap(), vec![1, 2]);
+    assert_eq!(resolve(&found, "Cardio.").unwrap(), vec![2]);
+    assert_eq!(resolve(&found, "9").unwrap(), vec![9]);
+    assert!(resolve(&found, "chem").is_err());
+  }
+
+  #[test]
+  fn subjects_without_table() {
+    let connection = sqlite::open(":memory:").unwrap();
+    connection
+      .execute(
+        "CREATE TABLE main_ft (id INTEGER, term1 VARCHAR, term2 VARCHAR, subj_ids VARCHAR); \
+         INSERT INTO main_ft VALUES (1, 'Herz {n}', 'heart', ',5,');",
+      )
+      .unwrap();
+    let found = subjects(&connection).unwrap();
+    assert_eq!(
+      found,
+      vec![
+        Subject {
+          id: 5,
+          name: None,
+          parent: None,
+          count: 1,
+        },
+      ]
+    );
+  }
+}
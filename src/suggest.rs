@@ -0,0 +1,258 @@
+// suggest.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Suggestions for terms similar to a query that did not produce any
+//! translations.
+
+use std::cmp;
+use std::collections::HashMap;
+
+use sqlite;
+
+use optimize;
+use pattern;
+use term::headword;
+use Error;
+use Result;
+use SEARCH_TBL;
+
+/// The maximum number of suggestions we provide.
+pub const MAX_SUGGESTIONS: usize = 5;
+/// The maximum number of completions we provide.
+pub const MAX_COMPLETIONS: usize = 50;
+
+
+/// Calculate the Levenshtein distance between two strings.
+fn distance(a: &[char], b: &[char]) -> usize {
+  let mut prev = (0..=b.len()).collect::<Vec<_>>();
+  let mut cur = vec![0; b.len() + 1];
+
+  for (i, ca) in a.iter().enumerate() {
+    cur[0] = i + 1;
+    for (j, cb) in b.iter().enumerate() {
+      let cost = if ca == cb { 0 } else { 1 };
+      cur[j + 1] = cmp::min(cmp::min(prev[j + 1] + 1, cur[j] + 1), prev[j] + cost);
+    }
+    prev.copy_from_slice(&cur);
+  }
+  prev[b.len()]
+}
+
+/// Determine the number of characters two strings have in common at
+/// their beginning.
+fn common_prefix(a: &[char], b: &[char]) -> usize {
+  a.iter().zip(b.iter()).take_while(|&(x, y)| x == y).count()
+}
+
+
+#[derive(Debug)]
+struct Candidate {
+  term: String,
+  distance: usize,
+  prefix: usize,
+}
+
+/// Find the terms closest to `query` among the given ones.
+///
+/// A term is considered close if its edit distance to the query is
+/// small (relative to the query's length) or if it starts with the
+/// query. Candidates are ranked by their edit distance first and the
+/// length of the prefix they share with the query second. At most
+/// `count` suggestions are returned.
+pub fn closest<I, S>(terms: I, query: &str, count: usize) -> Vec<String>
+where
+  I: IntoIterator<Item = S>,
+  S: AsRef<str>,
+{
+  let query = query.to_lowercase().chars().collect::<Vec<_>>();
+  if query.is_empty() {
+    return Vec::new();
+  }
+
+  let max_distance = cmp::max(1, query.len() / 3);
+  let mut candidates = HashMap::<String, Candidate>::new();
+
+  for term in terms {
+    let term = term.as_ref();
+    let lower = term.to_lowercase();
+    if candidates.contains_key(&lower) {
+      continue;
+    }
+
+    let chars = lower.chars().collect::<Vec<_>>();
+    let is_prefix = query.len() >= 3 && chars.starts_with(&query);
+    // Terms of vastly different length can never be within the
+    // maximum distance, so skip the comparatively expensive distance
+    // calculation for them.
+    let length_diff = cmp::max(chars.len(), query.len()) - cmp::min(chars.len(), query.len());
+    if length_diff > max_distance && !is_prefix {
+      continue;
+    }
+
+    let distance = distance(&query, &chars);
+    if distance == 0 || (distance > max_distance && !is_prefix) {
+      continue;
+    }
+
+    let prefix = common_prefix(&query, &chars);
+    candidates.insert(lower, Candidate {
+      term: term.to_string(),
+      distance,
+      prefix,
+    });
+  }
+
+  let mut candidates = candidates.into_values().collect::<Vec<_>>();
+  candidates.sort_by(|x, y| {
+    x.distance.cmp(&y.distance)
+      .then_with(|| y.prefix.cmp(&x.prefix))
+      .then_with(|| x.term.cmp(&y.term))
+  });
+  candidates.into_iter().take(count).map(|c| c.term).collect()
+}
+
+/// Scan the given column for terms close to `query`.
+///
+/// See `closest` for what qualifies as close.
+pub fn suggest(connection: &sqlite::Connection,
+               column: &str,
+               query: &str,
+               count: usize) -> Result<Vec<String>> {
+  let select = format!("SELECT DISTINCT {col} FROM {tbl}", col = column, tbl = SEARCH_TBL);
+  let mut cursor = connection.prepare(select)?.cursor();
+  let mut terms = Vec::new();
+
+  while let Some(row) = cursor.next()? {
+    let term = row[0].as_string().ok_or_else(|| Error::InvalidSchema(format!(
+      "Invalid column in result: {:?}",
+      row
+    )))?;
+    terms.push(headword(term));
+  }
+  Ok(closest(terms, query, count))
+}
+
+/// Find the headwords in the given column starting with `prefix`, for
+/// completing a term being typed.
+///
+/// At most `count` headwords are returned, sorted alphabetically. As
+/// we stop reading once we have enough, which headwords make it is
+/// up to the database. The headwords table (see `optimize`) is used if
+/// present.
+pub fn complete(connection: &sqlite::Connection,
+                column: &str,
+                prefix: &str,
+                count: usize) -> Result<Vec<String>> {
+  if optimize::exists(connection)? {
+    return optimize::complete(connection, column, prefix, count)
+  }
+
+  let select = format!(
+    "SELECT {col} FROM {tbl} WHERE {col} LIKE ? ESCAPE '{esc}'",
+    col = column, tbl = SEARCH_TBL, esc = pattern::ESCAPE,
+  );
+  let mut cursor = connection.prepare(select)?.cursor();
+  cursor.bind(&[sqlite::Value::String(format!("{}%", pattern::escape(prefix)))])?;
+
+  // Annotations in front of the actual words may make a term match
+  // without its headword starting with the prefix.
+  let lower = prefix.to_lowercase();
+  let mut headwords = Vec::<String>::new();
+  while headwords.len() < count {
+    let row = match cursor.next()? {
+      Some(row) => row,
+      None => break,
+    };
+    let term = row[0].as_string().ok_or_else(|| Error::InvalidSchema(format!(
+      "Invalid column in result: {:?}",
+      row
+    )))?;
+    let headword = headword(term);
+    if headword.to_lowercase().starts_with(&lower) && !headwords.contains(&headword) {
+      headwords.push(headword);
+    }
+  }
+  headwords.sort();
+  Ok(headwords)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::path;
+
+  use open;
+  use TERM2_COL;
+
+  fn chars(s: &str) -> Vec<char> {
+    s.chars().collect()
+  }
+
+  #[test]
+  fn levenshtein_distance() {
+    assert_eq!(distance(&chars(""), &chars("")), 0);
+    assert_eq!(distance(&chars("love"), &chars("love")), 0);
+    assert_eq!(distance(&chars("love"), &chars("lvoe")), 2);
+    assert_eq!(distance(&chars("kitten"), &chars("sitting")), 3);
+    assert_eq!(distance(&chars(""), &chars("abc")), 3);
+  }
+
+  #[test]
+  fn suggest_typo() {
+    let connection = open(path::Path::new("./test/test.db")).unwrap();
+    let found = suggest(&connection, TERM2_COL, "nauseatng", MAX_SUGGESTIONS).unwrap();
+    assert_eq!(found, vec!["nauseating".to_string()]);
+  }
+
+  #[test]
+  fn suggest_prefix() {
+    let connection = open(path::Path::new("./test/test.db")).unwrap();
+    let found = suggest(&connection, TERM2_COL, "statis", MAX_SUGGESTIONS).unwrap();
+    assert_eq!(found, vec!["statistics".to_string()]);
+  }
+
+  #[test]
+  fn closest_words() {
+    let words = ["haus", "maus", "hase", "haustür"];
+    assert_eq!(closest(words, "hause", 2), vec!["haus", "hase"]);
+    assert_eq!(closest(words, "haus", MAX_SUGGESTIONS), vec!["maus", "haustür"]);
+  }
+
+  #[test]
+  fn complete_prefix() {
+    let connection = open(path::Path::new("./test/test.db")).unwrap();
+    let found = complete(&connection, TERM2_COL, "STAT", MAX_COMPLETIONS).unwrap();
+    assert_eq!(found, vec!["statistics".to_string()]);
+    let found = complete(&connection, TERM2_COL, "to sub", MAX_COMPLETIONS).unwrap();
+    assert_eq!(found, vec!["to subjugate".to_string(), "to subjugate sb./sth.".to_string()]);
+    let found = complete(&connection, TERM2_COL, "%", MAX_COMPLETIONS).unwrap();
+    assert_eq!(found, Vec::<String>::new());
+    let found = complete(&connection, TERM2_COL, "", 2).unwrap();
+    assert_eq!(found.len(), 2);
+  }
+
+  #[test]
+  fn suggest_nothing() {
+    let connection = open(path::Path::new("./test/test.db")).unwrap();
+    let found = suggest(&connection, TERM2_COL, "xyzzy", MAX_SUGGESTIONS).unwrap();
+    assert_eq!(found, Vec::<String>::new());
+  }
+}
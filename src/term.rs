@@ -0,0 +1,409 @@
+// term.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Helpers for working with the terms as stored in the database.
+
+/// Characters that may precede a term copied from running text.
+const LEADING: &[char] = &['„', '“', '”', '‚', '‘', '’', '«', '»', '‹', '›', '\'', '¡', '¿'];
+/// Characters that may follow a term copied from running text.
+const TRAILING: &[char] = &[
+  '.', ',', ';', ':', '!', '?', '…', '“', '”', '‘', '’', '«', '»', '‹', '›', '\'',
+];
+/// Pairs of opening and closing brackets.
+const BRACKETS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}')];
+/// Pairs of brackets enclosing annotations.
+const ANNOTATIONS: &[(char, char)] = &[('[', ']'), ('{', '}'), ('<', '>')];
+/// Abbreviations whose trailing period is part of dictionary terms.
+const ABBREVIATIONS: &[&str] = &[
+  "sb.", "sth.", "jd.", "jdn.", "jdm.", "jds.", "etw.", "etc.", "usw.", "vs.",
+];
+
+
+/// Strip all annotations from a term, leaving only the actual words.
+///
+/// Terms in the database carry annotations in the form of comments
+/// (`[...]`), gender and plural tags (`{...}`), and abbreviations
+/// (`<...>`). This function removes all of them and merges the
+/// remaining white spaces, e.g., "Inhalt {m} <Inh.>" becomes "Inhalt".
+pub fn headword(term: &str) -> String {
+  let mut result = String::with_capacity(term.len());
+  let mut depth = 0usize;
+
+  for c in term.chars() {
+    match c {
+      '[' | '{' | '<' => depth += 1,
+      ']' | '}' | '>' if depth > 0 => depth -= 1,
+      _ if depth == 0 => result.push(c),
+      _ => (),
+    }
+  }
+  result.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Check whether the annotations of a term are well formed, i.e., that
+/// every bracket opening one is closed by the matching bracket.
+pub fn annotations_balanced(term: &str) -> bool {
+  let mut open = Vec::new();
+  for c in term.chars() {
+    if let Some(&(_, close)) = ANNOTATIONS.iter().find(|(o, _)| *o == c) {
+      open.push(close);
+    } else if ANNOTATIONS.iter().any(|(_, close)| *close == c) && open.pop() != Some(c) {
+      return false
+    }
+  }
+  open.is_empty()
+}
+
+/// Add the annotations of `other` that `term` lacks to it, e.g.,
+/// "Zorn {m}" and "Zorn [fig.]" combine to "Zorn {m} [fig.]".
+pub fn combine_annotations(term: &str, other: &str) -> String {
+  let mut result = term.to_string();
+  let mut depth = 0usize;
+  let mut start = 0;
+
+  for (i, c) in other.char_indices() {
+    if ANNOTATIONS.iter().any(|(open, _)| *open == c) {
+      if depth == 0 {
+        start = i;
+      }
+      depth += 1;
+    } else if depth > 0 && ANNOTATIONS.iter().any(|(_, close)| *close == c) {
+      depth -= 1;
+      let annotation = &other[start..i + c.len_utf8()];
+      if depth == 0 && !term.contains(annotation) {
+        result.push(' ');
+        result.push_str(annotation);
+      }
+    }
+  }
+  result
+}
+
+/// Find all occurrences of `word` in `text` that are delimited by word
+/// boundaries, returning their byte offsets.
+pub fn find_word(text: &str, word: &str) -> Vec<usize> {
+  let mut found = Vec::new();
+  if word.is_empty() {
+    return found;
+  }
+
+  for (start, _) in text.match_indices(word) {
+    let end = start + word.len();
+    let before = text[..start].chars().next_back();
+    let after = text[end..].chars().next();
+    let boundary = |c: Option<char>| c.map(|c| !c.is_alphanumeric()).unwrap_or(true);
+
+    if boundary(before) && boundary(after) {
+      found.push(start);
+    }
+  }
+  found
+}
+
+/// Retrieve the phrase enclosed in double quotes, e.g., "give up" for
+/// `"give up"`, or `None` if the term is not quoted as a whole.
+pub fn unquote(term: &str) -> Option<&str> {
+  let term = term.trim();
+  if term.len() >= 2 && term.starts_with('"') && term.ends_with('"') {
+    let inner = &term[1..term.len() - 1];
+    if !inner.contains('"') {
+      return Some(inner.trim());
+    }
+  }
+  None
+}
+
+/// Strip the punctuation, quotes, and brackets surrounding a term as
+/// copied from running text, e.g., "Haus" for `“Haus,”`.
+///
+/// Double quotes (denoting a phrase) are retained, as are the periods
+/// of abbreviations such as "sb." and brackets enclosing only part of
+/// the term.
+pub fn strip_punctuation(term: &str) -> &str {
+  let mut term = term.trim();
+  loop {
+    let stripped = term
+      .trim_start_matches(LEADING)
+      .trim_end_matches(|c| c != '.' && TRAILING.contains(&c))
+      .trim();
+    let stripped = match stripped.strip_suffix('.') {
+      Some(rest) if !is_abbreviation(stripped) => rest.trim_end(),
+      _ => stripped,
+    };
+    let stripped = strip_brackets(stripped);
+    if stripped.len() == term.len() {
+      return term;
+    }
+    term = stripped;
+  }
+}
+
+/// Check whether the last word of a term is an abbreviation ending in a
+/// period, such as "sb." or "z.B.".
+fn is_abbreviation(term: &str) -> bool {
+  let word = term.rsplit(char::is_whitespace).next().unwrap_or(term);
+  let stem = &word[..word.len() - 1];
+  // Abbreviations such as "z.B." consist of several periods, but each
+  // follows a letter, unlike in an ellipsis.
+  let dotted = stem.contains('.') && stem.chars().next_back().is_some_and(char::is_alphanumeric);
+  dotted || ABBREVIATIONS.iter().any(|a| a.eq_ignore_ascii_case(word))
+}
+
+/// Strip a bracket that is not matched within the term from either end,
+/// or a pair of brackets enclosing the term as a whole.
+fn strip_brackets(term: &str) -> &str {
+  for &(open, close) in BRACKETS {
+    let unmatched = |c: char| term.matches(open).count() != term.matches(close).count() &&
+      term.matches(c).count() == 1;
+    if term.starts_with(open) && (unmatched(open) || encloses(term, open, close)) {
+      return term[open.len_utf8()..].trim_start();
+    }
+    if term.ends_with(close) && unmatched(close) {
+      return term[..term.len() - close.len_utf8()].trim_end();
+    }
+  }
+  term
+}
+
+/// Check whether the bracket opening a term is closed only at its end.
+fn encloses(term: &str, open: char, close: char) -> bool {
+  let mut depth = 0usize;
+  for (i, c) in term.char_indices() {
+    if c == open {
+      depth += 1;
+    } else if c == close {
+      depth = depth.saturating_sub(1);
+      if depth == 0 {
+        return i + c.len_utf8() == term.len();
+      }
+    }
+  }
+  false
+}
+
+/// Check whether the headword of `term` contains `phrase` as a whole,
+/// i.e., delimited by word boundaries. The amount of white space in
+/// between words is ignored, as is case unless `case_sensitive` is set.
+pub fn contains_phrase(term: &str, phrase: &str, case_sensitive: bool) -> bool {
+  let phrase = phrase.split_whitespace().collect::<Vec<_>>().join(" ");
+  if case_sensitive {
+    !find_word(&headword(term), &phrase).is_empty()
+  } else {
+    !find_word(&headword(term).to_lowercase(), &phrase.to_lowercase()).is_empty()
+  }
+}
+
+/// Retrieve the comments (`[...]`) and abbreviations (`<...>`) of a
+/// term, without the enclosing brackets. Gender and plural tags are
+/// not included.
+pub fn comments(term: &str) -> Vec<&str> {
+  let mut comments = Vec::new();
+  let mut open = None;
+
+  for (i, c) in term.char_indices() {
+    match (open, c) {
+      (None, '[') | (None, '<') => open = Some((i + 1, if c == '[' { ']' } else { '>' })),
+      (Some((start, close)), c) if c == close => {
+        comments.push(&term[start..i]);
+        open = None;
+      },
+      _ => (),
+    }
+  }
+  comments
+}
+
+/// Check whether any of the comments of `term` (as retrieved by
+/// `comments`) contains `phrase` as a whole, ignoring case.
+pub fn comments_contain(term: &str, phrase: &str) -> bool {
+  let phrase = phrase.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+  comments(term)
+    .iter()
+    .any(|comment| !find_word(&comment.to_lowercase(), &phrase).is_empty())
+}
+
+/// Wrap all occurrences of the given words in `text` in `start` and
+/// `end` markers. Words are matched case-insensitively and only on word
+/// boundaries.
+pub fn highlight<S>(text: &str, words: &[S], start: &str, end: &str) -> String
+where
+  S: AsRef<str>,
+{
+  // Lower casing a string may change the length of the byte
+  // representation of individual characters. So we keep track of where
+  // each byte in the lower case version originates from.
+  let mut lower = String::with_capacity(text.len());
+  let mut offsets = Vec::with_capacity(text.len() + 1);
+  for (i, c) in text.char_indices() {
+    for l in c.to_lowercase() {
+      lower.push(l);
+      offsets.extend((0..l.len_utf8()).map(|_| i));
+    }
+  }
+  offsets.push(text.len());
+
+  let mut ranges = words
+    .iter()
+    .flat_map(|word| {
+      let word = word.as_ref().to_lowercase();
+      find_word(&lower, &word)
+        .into_iter()
+        .map(|i| (offsets[i], offsets[i + word.len()]))
+        .collect::<Vec<_>>()
+    })
+    .collect::<Vec<_>>();
+  ranges.sort();
+
+  let mut result = String::with_capacity(text.len());
+  let mut last = 0;
+  for (begin, finish) in ranges {
+    // Skip ranges overlapping with one we already highlighted.
+    if begin < last {
+      continue;
+    }
+    result.push_str(&text[last..begin]);
+    result.push_str(start);
+    result.push_str(&text[begin..finish]);
+    result.push_str(end);
+    last = finish;
+  }
+  result.push_str(&text[last..]);
+  result
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn headword_plain() {
+    assert_eq!(headword("nauseating"), "nauseating");
+    assert_eq!(headword("to subjugate"), "to subjugate");
+  }
+
+  #[test]
+  fn headword_annotated() {
+    assert_eq!(headword("Inhalt {m} <Inh.>"), "Inhalt");
+    assert_eq!(headword("dorky  [coll.]"), "dorky");
+    assert_eq!(
+      headword("jdn./etw. knechten [geh.] [pej.] [unterwerfen]"),
+      "jdn./etw. knechten"
+    );
+    assert_eq!(headword("the poor {pl}"), "the poor");
+  }
+
+  #[test]
+  fn balanced_annotations() {
+    assert!(annotations_balanced("Inhalt {m} <Inh.>"));
+    assert!(annotations_balanced("to give [sth. {pl}] up"));
+    assert!(annotations_balanced("Liebe"));
+    assert!(!annotations_balanced("Inhalt {m <Inh.>"));
+    assert!(!annotations_balanced("sulfur <S] [Am.>"));
+    assert!(!annotations_balanced("null beim Tennis]"));
+  }
+
+  #[test]
+  fn combined_annotations() {
+    assert_eq!(combine_annotations("Zorn {m}", "Zorn [fig.]"), "Zorn {m} [fig.]");
+    assert_eq!(combine_annotations("Zorn {m}", "Zorn {m}"), "Zorn {m}");
+    assert_eq!(combine_annotations("anger", "anger [über etw. {acc}] <A>"), "anger [über etw. {acc}] <A>");
+    assert_eq!(combine_annotations("anger", "anger"), "anger");
+  }
+
+  #[test]
+  fn find_word_boundaries() {
+    assert_eq!(find_word("give up, give in", "give"), vec![0, 9]);
+    assert_eq!(find_word("forgive", "give"), Vec::<usize>::new());
+    assert_eq!(find_word("Ärger über Ärger", "Ärger"), vec![0, 13]);
+    assert_eq!(find_word("anything", ""), Vec::<usize>::new());
+  }
+
+  #[test]
+  fn unquote_phrase() {
+    assert_eq!(unquote("\"give up\""), Some("give up"));
+    assert_eq!(unquote(" \" give up \" "), Some("give up"));
+    assert_eq!(unquote("give up"), None);
+    assert_eq!(unquote("\""), None);
+    assert_eq!(unquote("\"a\" OR \"b\""), None);
+  }
+
+  #[test]
+  fn strip_copied_punctuation() {
+    assert_eq!(strip_punctuation("“Haus,”"), "Haus");
+    assert_eq!(strip_punctuation("„Haus“"), "Haus");
+    assert_eq!(strip_punctuation("«Zorn»!"), "Zorn");
+    assert_eq!(strip_punctuation(" Ärger… "), "Ärger");
+    assert_eq!(strip_punctuation("(Wut)."), "Wut");
+    assert_eq!(strip_punctuation("Wut)"), "Wut");
+    assert_eq!(strip_punctuation("[Wut"), "Wut");
+    assert_eq!(strip_punctuation("Haus."), "Haus");
+    assert_eq!(strip_punctuation("Haus. "), "Haus");
+    assert_eq!(strip_punctuation("¿qué?"), "qué");
+    assert_eq!(strip_punctuation("'Zorn'"), "Zorn");
+    assert_eq!(strip_punctuation("Haus..."), "Haus");
+  }
+
+  #[test]
+  fn strip_punctuation_retained() {
+    assert_eq!(strip_punctuation("to subjugate sb."), "to subjugate sb.");
+    assert_eq!(strip_punctuation("jdn./etw."), "jdn./etw.");
+    assert_eq!(strip_punctuation("z.B."), "z.B.");
+    assert_eq!(strip_punctuation("\"the poor\""), "\"the poor\"");
+    assert_eq!(strip_punctuation("(a OR b) AND (c)"), "(a OR b) AND (c)");
+    assert_eq!(strip_punctuation("Zorn {m}"), "Zorn {m}");
+    assert_eq!(strip_punctuation("..."), "");
+    assert_eq!(strip_punctuation(""), "");
+  }
+
+  #[test]
+  fn phrase_boundaries() {
+    assert!(contains_phrase("to give up sth. [quit]", "give up", false));
+    assert!(contains_phrase("to give [sth.] up", "GIVE  UP", false));
+    assert!(!contains_phrase("to give [sth.] up", "GIVE  UP", true));
+    assert!(contains_phrase("to give [sth.] up", "give  up", true));
+    assert!(!contains_phrase("to give upkeep", "give up", false));
+    assert!(!contains_phrase("to forgive up", "give up", false));
+  }
+
+  #[test]
+  fn find_comments() {
+    assert_eq!(comments("sulfur <S> [Am.]"), vec!["S", "Am."]);
+    assert_eq!(comments("Inhalt {m} <Inh.>"), vec!["Inh."]);
+    assert_eq!(comments("Liebe {f}"), Vec::<&str>::new());
+
+    assert!(comments_contain("sulfur <S> [Am.]", "s"));
+    assert!(comments_contain("null [beim Tennis]", "tennis"));
+    assert!(!comments_contain("null [beim Tennis]", "null"));
+    assert!(!comments_contain("Liebe {f}", "f"));
+  }
+
+  #[test]
+  fn highlight_words() {
+    assert_eq!(
+      highlight("Die Liebe ist blind, liebe Leute.", &["liebe"], "*", "*"),
+      "Die *Liebe* ist blind, *liebe* Leute."
+    );
+    assert_eq!(
+      highlight("ÄRGER über Ärger", &["ärger", "über ärger"], "[", "]"),
+      "[ÄRGER] [über Ärger]"
+    );
+    assert_eq!(highlight("nothing", &["else"], "[", "]"), "nothing");
+  }
+}
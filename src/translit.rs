@@ -0,0 +1,120 @@
+// translit.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Handling of ASCII transliterations of German umlauts and 'ß'.
+
+/// The maximum number of transliterated character sequences in a term
+/// we consider. Each of them doubles the number of variants.
+const MAX_POSITIONS: usize = 5;
+
+/// Transliterations of the form (lower case ASCII, lower case original,
+/// upper case original).
+const TRANSLITERATIONS: [(&str, &str, &str); 4] = [
+  ("ae", "ä", "Ä"),
+  ("oe", "ö", "Ö"),
+  ("ue", "ü", "Ü"),
+  ("ss", "ß", "ß"),
+];
+
+
+/// Find the positions of all transliterated sequences in a term, as
+/// (byte offset, replacement) pairs.
+fn positions(term: &str) -> Vec<(usize, &'static str)> {
+  let lower = term.to_ascii_lowercase();
+  let bytes = lower.as_bytes();
+  let mut positions = Vec::new();
+  let mut i = 0;
+
+  while i + 1 < bytes.len() && positions.len() < MAX_POSITIONS {
+    // Note that we work on bytes here, as the term may contain
+    // arbitrary non-ASCII characters.
+    let pair = &bytes[i..i + 2];
+    let found = TRANSLITERATIONS.iter().find(|&&(ascii, _, _)| ascii.as_bytes() == pair);
+    // A 'ue' following a 'q' or an 'e' (as in "Quelle" or "neue") is
+    // pretty much never a transliterated 'ü'.
+    let excluded = pair == b"ue" && i > 0 && (bytes[i - 1] == b'q' || bytes[i - 1] == b'e');
+
+    match found {
+      Some(&(_, lower, upper)) if !excluded => {
+        let upper_case = term.as_bytes()[i].is_ascii_uppercase();
+        positions.push((i, if upper_case { upper } else { lower }));
+        i += 2;
+      },
+      _ => i += 1,
+    }
+  }
+  positions
+}
+
+/// Generate all variants of a term with ASCII transliterations replaced
+/// by the umlauts (or 'ß') they may stand for.
+///
+/// The term itself is always the first variant.
+pub fn variants(term: &str) -> Vec<String> {
+  // Note that transliterations are plain ASCII and so we can safely
+  // slice the term at their byte offsets.
+  let positions = positions(term);
+  let mut variants = Vec::with_capacity(1 << positions.len());
+
+  for mask in 0..(1usize << positions.len()) {
+    let mut variant = String::with_capacity(term.len());
+    let mut last = 0;
+    for (bit, &(offset, replacement)) in positions.iter().enumerate() {
+      if mask & (1 << bit) != 0 {
+        variant.push_str(&term[last..offset]);
+        variant.push_str(replacement);
+        last = offset + 2;
+      }
+    }
+    variant.push_str(&term[last..]);
+    variants.push(variant);
+  }
+  variants
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn transliterate_umlauts() {
+    assert_eq!(variants("aerger"), vec!["aerger", "ärger"]);
+    assert_eq!(variants("Aerger"), vec!["Aerger", "Ärger"]);
+    assert_eq!(variants("gruen"), vec!["gruen", "grün"]);
+    assert_eq!(variants("Strasse"), vec!["Strasse", "Straße"]);
+    assert_eq!(variants("Übergroesse"), vec!["Übergroesse", "Übergrösse", "Übergroeße", "Übergröße"]);
+  }
+
+  #[test]
+  fn transliterate_multiple() {
+    assert_eq!(
+      variants("Gruesse"),
+      vec!["Gruesse", "Grüsse", "Grueße", "Grüße"]
+    );
+  }
+
+  #[test]
+  fn transliterate_nothing() {
+    assert_eq!(variants("love"), vec!["love"]);
+    assert_eq!(variants("Quelle"), vec!["Quelle"]);
+    assert_eq!(variants("neue"), vec!["neue"]);
+    assert_eq!(variants(""), vec![""]);
+  }
+}
@@ -0,0 +1,203 @@
+// tuning.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Performance tuning of database connections.
+//!
+//! The defaults of SQLite are geared towards small databases and
+//! memory constrained environments. With a dict.cc database of about a
+//! million rows that makes the first lookup noticeably slow, especially
+//! on spinning disks, so we apply somewhat more generous settings when
+//! opening a database.
+
+use std::str;
+use std::sync;
+
+use Error;
+use Result;
+
+/// The default size of the page cache, in KiB.
+const CACHE_SIZE: u64 = 16 * 1024;
+/// The default maximum number of bytes of the database to memory map.
+const MMAP_SIZE: u64 = 256 * 1024 * 1024;
+
+/// The tuning applied to connections opened without explicit tuning.
+static CURRENT: sync::RwLock<Option<Tuning>> = sync::RwLock::new(None);
+
+
+/// Where temporary tables and indices are stored.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TempStore {
+  /// The compile time default of the SQLite library.
+  Default,
+  /// Store them in files.
+  File,
+  /// Keep them in memory.
+  Memory,
+}
+
+impl TempStore {
+  /// Retrieve the value of the `temp_store` PRAGMA.
+  fn pragma(self) -> u8 {
+    match self {
+      TempStore::Default => 0,
+      TempStore::File => 1,
+      TempStore::Memory => 2,
+    }
+  }
+}
+
+impl str::FromStr for TempStore {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<TempStore> {
+    match s {
+      "default" => Ok(TempStore::Default),
+      "file" => Ok(TempStore::File),
+      "memory" => Ok(TempStore::Memory),
+      _ => Err(Error::Error(format!("Invalid temporary storage: {}", s))),
+    }
+  }
+}
+
+
+/// The PRAGMAs applied to database connections upon opening.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tuning {
+  /// The size of the page cache, in KiB.
+  pub cache_size: u64,
+  /// Where temporary tables and indices are stored.
+  pub temp_store: TempStore,
+  /// The maximum number of bytes of the database to access through
+  /// memory mapped I/O, with zero disabling it.
+  pub mmap_size: u64,
+  /// Whether to load the entire database into memory upon opening,
+  /// trading memory for faster repeated lookups.
+  pub in_memory: bool,
+  /// Whether to open the database as immutable, sparing SQLite from
+  /// locking it and from checking for changes made by others. Only
+  /// safe if it is not modified for as long as the connection is open.
+  pub immutable: bool,
+}
+
+impl Tuning {
+  /// Apply the tuning to a connection.
+  pub fn apply(&self, connection: &sqlite::Connection) -> Result<()> {
+    // A negative cache size is interpreted as KiB, not pages.
+    let sql = format!(
+      "PRAGMA cache_size = -{}; PRAGMA temp_store = {}; PRAGMA mmap_size = {};",
+      self.cache_size,
+      self.temp_store.pragma(),
+      self.mmap_size,
+    );
+    debug!("Tuning connection: {}", sql);
+    connection.execute(sql)?;
+    Ok(())
+  }
+}
+
+impl Default for Tuning {
+  fn default() -> Tuning {
+    Tuning {
+      cache_size: CACHE_SIZE,
+      temp_store: TempStore::Memory,
+      mmap_size: MMAP_SIZE,
+      in_memory: false,
+      immutable: false,
+    }
+  }
+}
+
+
+/// Set the tuning applied to connections opened by `Database::open`
+/// from now on.
+pub fn set(tuning: Tuning) {
+  *CURRENT.write().unwrap_or_else(|e| e.into_inner()) = Some(tuning);
+}
+
+/// Retrieve the tuning applied to connections opened by
+/// `Database::open`.
+pub fn current() -> Tuning {
+  CURRENT.read().unwrap_or_else(|e| e.into_inner()).unwrap_or_default()
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::path;
+
+  use open_read_only;
+
+
+  #[test]
+  fn parse_temp_store() {
+    assert_eq!("memory".parse::<TempStore>().unwrap(), TempStore::Memory);
+    assert_eq!("file".parse::<TempStore>().unwrap(), TempStore::File);
+    assert_eq!("default".parse::<TempStore>().unwrap(), TempStore::Default);
+    assert!("disk".parse::<TempStore>().is_err());
+  }
+
+  #[test]
+  fn apply_tuning() {
+    let pragma = |connection: &sqlite::Connection, name: &str| {
+      let mut statement = connection.prepare(format!("PRAGMA {}", name)).unwrap();
+      statement.next().unwrap();
+      statement.read::<i64>(0).unwrap()
+    };
+
+    let tuning = Tuning {
+      cache_size: 4096,
+      temp_store: TempStore::File,
+      mmap_size: 0,
+      in_memory: false,
+      immutable: true,
+    };
+    let connection = open_read_only(path::Path::new("./test/test.db"), &tuning).unwrap();
+    assert_eq!(pragma(&connection, "cache_size"), -4096);
+    assert_eq!(pragma(&connection, "temp_store"), 1);
+    assert_eq!(pragma(&connection, "mmap_size"), 0);
+
+    let connection = open_read_only(path::Path::new("./test/test.db"), &Tuning::default()).unwrap();
+    assert_eq!(pragma(&connection, "cache_size"), -(CACHE_SIZE as i64));
+    assert_eq!(pragma(&connection, "temp_store"), 2);
+  }
+
+  #[test]
+  fn load_into_memory() {
+    let tuning = Tuning {
+      in_memory: true,
+      ..Default::default()
+    };
+    let connection = open_read_only(path::Path::new("./test/test.db"), &tuning).unwrap();
+    let mut statement = connection.prepare("SELECT file FROM pragma_database_list WHERE name = 'main'").unwrap();
+    statement.next().unwrap();
+    assert_eq!(statement.read::<String>(0).unwrap(), "");
+    drop(statement);
+
+    let database = ::Database::open_with(path::Path::new("./test/test.db"), &tuning).unwrap();
+    let mut entries = Vec::new();
+    database.translate(&["Zorn"], &::Direction::Lang1ToLang2, |e| {
+      entries.push(e.clone());
+      Ok(())
+    }).unwrap();
+    assert!(!entries.is_empty());
+    assert!(database.fulltext());
+  }
+}
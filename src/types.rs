@@ -0,0 +1,120 @@
+// types.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Statistics about the types (e.g., "noun" or "adj adv") of entries.
+
+use sqlite;
+
+use Error;
+use Result;
+use SEARCH_TBL;
+use TYPE_COL;
+use USAGE_COL;
+
+/// The name we report entries without a type under.
+pub const UNKNOWN_TYPE: &str = "unknown";
+
+
+/// An entry type along with some statistics.
+#[derive(Debug, PartialEq)]
+pub struct Type {
+  /// The name of the type, `UNKNOWN_TYPE` for entries without one.
+  pub name: String,
+  /// The number of entries of this type.
+  pub count: usize,
+  /// The most used terms of this type.
+  pub samples: Vec<String>,
+}
+
+
+/// Retrieve all entry types, ordered by the number of entries of each,
+/// along with up to `samples` sample terms taken from `column`.
+pub fn types(connection: &sqlite::Connection,
+             column: &str,
+             samples: usize) -> Result<Vec<Type>> {
+  let select = format!(
+    "SELECT {typ}, COUNT(*) AS count FROM {tbl} \
+     GROUP BY {typ} \
+     ORDER BY count DESC, {typ} ASC",
+    typ = TYPE_COL, tbl = SEARCH_TBL,
+  );
+  let mut cursor = connection.prepare(select)?.cursor();
+  let mut found = Vec::new();
+  while let Some(row) = cursor.next()? {
+    match (row[0].as_string(), row[1].as_integer()) {
+      (Some(type_), Some(count)) => found.push((type_.to_string(), count as usize)),
+      _ => return Err(Error::InvalidSchema(format!("Invalid type in result: {:?}", row))),
+    }
+  }
+
+  let select = format!(
+    "SELECT {col} FROM {tbl} WHERE {typ} = ? ORDER BY {use} DESC, {col} ASC LIMIT ?",
+    col = column, tbl = SEARCH_TBL, typ = TYPE_COL, use = USAGE_COL,
+  );
+  let mut statement = connection.prepare(select)?;
+  let mut types = Vec::with_capacity(found.len());
+  for (type_, count) in found {
+    statement.reset()?;
+    statement.bind(1, type_.as_str())?;
+    statement.bind(2, samples as i64)?;
+
+    let mut terms = Vec::with_capacity(samples);
+    while let sqlite::State::Row = statement.next()? {
+      terms.push(statement.read::<String>(0)?);
+    }
+
+    // Some entries do not have a proper type associated with them. We
+    // report them the same way translations do.
+    let name = if type_.is_empty() {
+      UNKNOWN_TYPE.to_string()
+    } else {
+      type_
+    };
+    types.push(Type {
+      name,
+      count,
+      samples: terms,
+    });
+  }
+  Ok(types)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::path;
+
+  use open;
+  use TERM2_COL;
+
+  #[test]
+  fn list_types() {
+    let connection = open(path::Path::new("./test/test.db")).unwrap();
+    let found = types(&connection, TERM2_COL, 2).unwrap();
+    let names = found.iter().map(|t| (t.name.as_str(), t.count)).collect::<Vec<_>>();
+    assert_eq!(
+      names,
+      vec![("noun", 15), ("adj", 6), ("verb", 4), ("adj adv", 3), ("unknown", 1)]
+    );
+    assert_eq!(found[4].samples, vec!["love"]);
+    assert_eq!(found[0].samples.len(), 2);
+  }
+}
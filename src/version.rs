@@ -0,0 +1,104 @@
+// version.rs
+
+// *************************************************************************
+// * Copyright (C) 2018 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Version and build information.
+//!
+//! Besides the version of the program, the SQLite library it is linked
+//! against matters for how terms are matched: ICU support changes how
+//! the case of non-ASCII characters is folded and FTS5 support decides
+//! which full-text search facilities are available.
+
+use std::fmt;
+
+use sqlite;
+
+use Result;
+
+
+/// Information about the program and the SQLite library in use.
+#[derive(Debug)]
+pub struct Version {
+  /// The version of the program.
+  version: &'static str,
+  /// The commit the program was built from, if known.
+  commit: Option<&'static str>,
+  /// The version of the SQLite library.
+  sqlite: String,
+  /// Whether the SQLite library was built with ICU support.
+  icu: bool,
+  /// Whether the SQLite library was built with FTS5 support.
+  fts5: bool,
+}
+
+impl Version {
+  /// Gather the version information, inspecting the SQLite library.
+  pub fn detect() -> Result<Version> {
+    let connection = sqlite::open(":memory:")?;
+    let mut statement = connection.prepare(
+      "SELECT sqlite_version(), \
+              sqlite_compileoption_used('ENABLE_ICU'), \
+              sqlite_compileoption_used('ENABLE_FTS5')",
+    )?;
+    statement.next()?;
+
+    Ok(Version {
+      version: env!("CARGO_PKG_VERSION"),
+      commit: option_env!("DICTCC_CLI_COMMIT"),
+      sqlite: statement.read::<String>(0)?,
+      icu: statement.read::<i64>(1)? != 0,
+      fts5: statement.read::<i64>(2)? != 0,
+    })
+  }
+}
+
+impl fmt::Display for Version {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let yes_no = |flag| if flag { "yes" } else { "no" };
+
+    write!(f, "dictcc-cli {}", self.version)?;
+    if let Some(commit) = self.commit {
+      write!(f, " (commit {})", commit)?;
+    }
+    writeln!(f)?;
+    writeln!(f, "SQLite {}", self.sqlite)?;
+    writeln!(f, "  ICU support: {}", yes_no(self.icu))?;
+    writeln!(f, "  FTS5 support: {}", yes_no(self.fts5))
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn detect_version() {
+    let version = Version::detect().unwrap();
+    assert_eq!(version.version, env!("CARGO_PKG_VERSION"));
+    assert!(version.sqlite.starts_with("3."), "{}", version.sqlite);
+
+    let text = version.to_string();
+    let mut lines = text.lines();
+    assert!(lines.next().unwrap().starts_with("dictcc-cli "));
+    assert_eq!(lines.next(), Some(&*format!("SQLite {}", version.sqlite)));
+    assert!(lines.next().unwrap().starts_with("  ICU support: "));
+    assert!(lines.next().unwrap().starts_with("  FTS5 support: "));
+    assert_eq!(lines.next(), None);
+  }
+}
@@ -40,9 +40,10 @@ DELETE FROM main_ft WHERE NOT (
 );
 DROP INDEX sw_term4search;
 DROP TABLE subjects;
-DROP TABLE main_ft_segments;
-DROP TABLE main_ft_segdir;
 DROP TABLE singlewords;
+/* Keep the full-text index but shrink it to what is left. */
+INSERT INTO main_ft(main_ft) VALUES('optimize');
+VACUUM;
 /* Check that a bunch of words are still in there. */
 SELECT * from main_ft;
 /* Check that our required query still works. */